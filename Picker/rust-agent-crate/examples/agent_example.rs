@@ -46,6 +46,12 @@ async fn test_tool_invocation(agent_runner: &SimpleAgentRunner) -> Result<(), Bo
                 println!("输入: {}", action.tool_input);
                 println!("日志: {}", action.log);
             },
+            AgentOutput::Actions(actions) => {
+                for action in actions {
+                    println!("工具: {}", action.tool);
+                    println!("输入: {}", action.tool_input);
+                }
+            },
             AgentOutput::Finish(finish) => {
                 println!("完成结果: {:?}", finish.return_values);
             }
@@ -69,6 +75,12 @@ async fn test_direct_completion(agent_runner: &SimpleAgentRunner) -> Result<(),
                 println!("输入: {}", action.tool_input);
                 println!("日志: {}", action.log);
             },
+            AgentOutput::Actions(actions) => {
+                for action in actions {
+                    println!("工具: {}", action.tool);
+                    println!("输入: {}", action.tool_input);
+                }
+            },
             AgentOutput::Finish(finish) => {
                 println!("完成结果: {:?}", finish.return_values);
             }
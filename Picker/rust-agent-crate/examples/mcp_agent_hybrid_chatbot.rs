@@ -39,10 +39,10 @@ async fn main() {
     
     // 添加本地自定义工具定义
     mcp_client.add_tools(vec![
-        McpTool {
-            name: "get_local_time".to_string(),
-            description: "Get the current local time and date. For example: 'What time is it?'".to_string(),
-        },
+        McpTool::new(
+            "get_local_time".to_string(),
+            "Get the current local time and date. For example: 'What time is it?'".to_string(),
+        ),
     ]);
     
     // 注册本地工具处理器
@@ -88,7 +88,8 @@ async fn main() {
         "data".into(),           // 组合记忆数据存储目录
         200,           // 摘要阈值（token数量）
         10          // 保留的最近消息数量
-    ).await.expect("Failed to create CompositeMemory");
+    ).await.expect("Failed to create CompositeMemory")
+    .with_chat_model(Arc::new(model.clone()));
 
     // 创建Agent实例
     let client_arc: Arc<dyn McpClient> = Arc::new(mcp_client);
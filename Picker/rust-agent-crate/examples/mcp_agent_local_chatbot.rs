@@ -1,6 +1,6 @@
 // 基于MCP的AI Agent聊天机器人示例
 use std::path::PathBuf;
-use rust_agent::{run_agent, OpenAIChatModel, McpClient, SimpleMcpClient, McpTool, McpAgent, SimpleMemory, BaseMemory, CompositeMemory};
+use rust_agent::{run_agent, OpenAIChatModel, McpClient, SimpleMcpClient, McpTool, McpAgent, SimpleMemory, BaseMemory, CompositeMemory, CachedChatModel, CacheMode, DiskResponseCache, ModelRegistry};
 use std::sync::Arc;
 use std::collections::HashMap;
 use chrono;
@@ -56,20 +56,26 @@ async fn main() {
     
     // 添加自定义工具
     mcp_client.add_tools(vec![
-        McpTool {
-            name: "get_weather".to_string(),
-            description: format!(
-                "Get weather information for a specified city. For example: 'What's the weather like in Beijing?'.
-                The parameter request body you should extract is: '\"parameters\": {{ \"city\": \"{}\" }}'",
-                "city".to_string()),
-        },
-        McpTool {
-            name: "simple_calculate".to_string(),
-            description: format!(
-                "Execute simple mathematical calculations. For example: 'What is 9.11 plus 9.8?'.
-                The parameter request body you should extract is: '\"parameters\": {{ \"expression\": \"{}\" }}'",
-                "expression".to_string()),
-        },
+        McpTool::new(
+            "get_weather".to_string(),
+            "Get weather information for a specified city. For example: 'What's the weather like in Beijing?'".to_string(),
+        ).with_input_schema(json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string", "description": "City name"}
+            },
+            "required": ["city"]
+        })),
+        McpTool::new(
+            "simple_calculate".to_string(),
+            "Execute simple mathematical calculations. For example: 'What is 9.11 plus 9.8?'".to_string(),
+        ).with_input_schema(json!({
+            "type": "object",
+            "properties": {
+                "expression": {"type": "string", "description": "Mathematical expression, e.g. 9.11 + 9.8"}
+            },
+            "required": ["expression"]
+        })),
     ]);
     
     // 注册自定义工具处理器
@@ -109,6 +115,16 @@ async fn main() {
     
     let client_arc: Arc<dyn McpClient> = Arc::new(mcp_client);
     
+    // 可选的分词器词表：TIKTOKEN_DIR 目录下的 cl100k_base.tiktoken / o200k_base.tiktoken 用于精确计算 token
+    // 未配置时使用字符估算，摘要阈值和上下文窗口裁剪都基于此计数
+    let mut model_registry = ModelRegistry::new();
+    if let Ok(tiktoken_dir) = std::env::var("TIKTOKEN_DIR") {
+        model_registry = model_registry.with_encodings_from_dir(&tiktoken_dir)
+            .expect("Failed to load tokenizer vocabularies");
+    }
+    let tokenizer = model_registry.tokenizer_for(model.model_name().map_or("unknown", |name| name.as_str()));
+    info!("Using tokenizer: {}", tokenizer.name());
+
     // 根据配置创建不同类型的记忆模块实例
     let memory: Box<dyn BaseMemory> = match memory_type.as_str() {
         "simple" => {
@@ -123,7 +139,9 @@ async fn main() {
                 PathBuf::from("./data/memory"),
                 summary_threshold,
                 recent_messages_count,
-            ).await.expect("Failed to create composite memory");
+            ).await.expect("Failed to create composite memory")
+            .with_tokenizer(tokenizer)
+            .with_chat_model(Arc::new(model.clone()));
             
             Box::new(memory)
         },
@@ -141,8 +159,20 @@ async fn main() {
         user_system_prompt,
         model.clone(),
         memory
-    );
+    ).with_model_registry(model_registry);
     
+    // 可选的响应缓存：LLM_CACHE_DIR 指定目录，LLM_CACHE_MODE 为 read_through / record / replay
+    // replay 模式只使用录制的结果，可在 CI 中无网络运行（工具结果需保持确定，否则缓存键会变化）
+    if let Ok(cache_dir) = std::env::var("LLM_CACHE_DIR") {
+        let cache_mode: CacheMode = std::env::var("LLM_CACHE_MODE").ok()
+            .and_then(|mode| mode.parse().ok())
+            .unwrap_or_default();
+        info!("Using response cache: {} ({:?})", cache_dir, cache_mode);
+        let cached_model = CachedChatModel::new(Arc::new(model.clone()), Arc::new(DiskResponseCache::new(cache_dir)))
+            .with_mode(cache_mode);
+        agent = agent.with_chat_model(Arc::new(cached_model));
+    }
+
     // 尝试从MCP服务器自动获取工具并添加到Agent
     if let Err(e) = agent.auto_add_tools().await {
         error!("Failed to auto add tools from MCP server: {}", e);
@@ -161,14 +191,14 @@ async fn main() {
         error!("Failed to get tools from MCP server: {}", e);
         // 返回本地工具列表
         vec![
-            McpTool {
-                name: "get_weather".to_string(),
-                description: "Get the weather information for a specified city. For example: 'What's the weather like in Beijing?'".to_string(),
-            },
-            McpTool {
-                name: "simple_calculate".to_string(),
-                description: "Perform simple mathematical calculations. For example: 'What is 9.11 plus 9.8?'".to_string(),
-            },
+            McpTool::new(
+                "get_weather".to_string(),
+                "Get the weather information for a specified city. For example: 'What's the weather like in Beijing?'".to_string(),
+            ),
+            McpTool::new(
+                "simple_calculate".to_string(),
+                "Perform simple mathematical calculations. For example: 'What is 9.11 plus 9.8?'".to_string(),
+            ),
         ]
     });
     
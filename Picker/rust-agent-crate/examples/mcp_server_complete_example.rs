@@ -28,6 +28,17 @@ impl Tool for WeatherTool {
         &self.description
     }
     
+    // 参数的JSON Schema，通过tools/list以inputSchema返回给客户端
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string", "description": "City name, e.g. Beijing"}
+            },
+            "required": ["city"]
+        })
+    }
+    
     fn invoke(&self, input: &str) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + '_>> {
         // 解析输入参数，客户端可能发送JSON格式的参数
         let city = match serde_json::from_str::<serde_json::Value>(input) {
@@ -88,6 +99,16 @@ impl Tool for CalculatorTool {
         &self.description
     }
     
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "expression": {"type": "string", "description": "Mathematical expression, e.g. 15.5 + 24.3"}
+            },
+            "required": ["expression"]
+        })
+    }
+    
     fn invoke(&self, input: &str) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + '_>> {
         // 解析输入参数，客户端可能发送不同格式的参数
         let expression = match serde_json::from_str::<serde_json::Value>(input) {
@@ -0,0 +1,73 @@
+// stdio传输示例：同一个程序既可作为MCP服务器子进程运行，也可作为启动它的客户端
+// 运行：cargo run --example mcp_stdio_example
+use rust_agent::{McpClient, McpServer, SimpleMcpServer, StdioMcpClient, Tool};
+use anyhow::Error;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// 返回大写文本的工具
+struct UppercaseTool;
+
+impl Tool for UppercaseTool {
+    fn name(&self) -> &str {
+        "uppercase"
+    }
+
+    fn description(&self) -> &str {
+        "Convert the text to upper case"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"]
+        })
+    }
+
+    fn invoke(&self, input: &str) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + '_>> {
+        let text = serde_json::from_str::<serde_json::Value>(input)
+            .ok()
+            .and_then(|args| args.get("text").and_then(|t| t.as_str()).map(|t| t.to_string()))
+            .unwrap_or_else(|| input.to_string());
+        Box::pin(async move { Ok(text.to_uppercase()) })
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    // 日志输出到stderr，stdout只用于协议消息
+    env_logger::init();
+
+    if std::env::args().any(|arg| arg == "--serve") {
+        // 子进程：通过stdin/stdout提供MCP服务，stdin关闭时退出
+        let server = SimpleMcpServer::new().with_server_info("uppercase-server", "0.1.0");
+        server.register_tool(Arc::new(UppercaseTool))?;
+        return server.serve_stdio().await;
+    }
+
+    // 父进程：以--serve参数启动自身作为MCP服务器
+    let exe = std::env::current_exe()?;
+    let mut client = StdioMcpClient::new(exe.to_string_lossy().to_string())
+        .with_arg("--serve")
+        .with_env("RUST_LOG", "info");
+    client.connect("").await?;
+    println!("Connected to {:?} (protocol {:?})", client.server_info(), client.protocol_version());
+
+    for tool in client.get_tools().await? {
+        println!("Tool: {} - {}", tool.name, tool.description);
+    }
+
+    let mut params = HashMap::new();
+    params.insert("text".to_string(), serde_json::json!("hello from stdio"));
+    println!("Result: {}", client.call_tool_result("uppercase", params).await?.to_observation());
+
+    client.disconnect().await?;
+    println!("Server stderr:\n{}", client.stderr_output().join("\n"));
+    Ok(())
+}
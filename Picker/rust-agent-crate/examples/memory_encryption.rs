@@ -0,0 +1,43 @@
+// Encrypt existing plaintext memory sessions or rotate the encryption key
+//
+// cargo run --example memory_encryption -- encrypt [data_dir]
+// cargo run --example memory_encryption -- rotate [data_dir]
+//
+// The key comes from MEMORY_ENCRYPTION_KEY (base64, 32 bytes) or MEMORY_ENCRYPTION_PASSPHRASE.
+// For rotate, the previous key comes from MEMORY_ENCRYPTION_OLD_KEY or MEMORY_ENCRYPTION_OLD_PASSPHRASE.
+use std::path::PathBuf;
+use anyhow::{Error, Result};
+use rust_agent::memory::{get_data_dir_from_env, reencrypt_data_dir, MemoryCipher};
+
+async fn old_cipher(data_dir: &std::path::Path) -> Result<MemoryCipher> {
+    if let Ok(key) = std::env::var("MEMORY_ENCRYPTION_OLD_KEY") {
+        return MemoryCipher::from_base64_key(&key);
+    }
+    let passphrase = std::env::var("MEMORY_ENCRYPTION_OLD_PASSPHRASE")
+        .map_err(|_| Error::msg("Set MEMORY_ENCRYPTION_OLD_KEY or MEMORY_ENCRYPTION_OLD_PASSPHRASE to rotate"))?;
+    MemoryCipher::from_passphrase(&passphrase, data_dir).await
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+    let data_dir = args.next().map(PathBuf::from).unwrap_or_else(get_data_dir_from_env);
+
+    let cipher = MemoryCipher::from_env(&data_dir).await?
+        .ok_or_else(|| Error::msg("Set MEMORY_ENCRYPTION_KEY or MEMORY_ENCRYPTION_PASSPHRASE"))?;
+    let cipher = match command.as_str() {
+        "encrypt" => cipher,
+        "rotate" => cipher.with_previous_key(old_cipher(&data_dir).await?),
+        _ => return Err(Error::msg("Usage: memory_encryption <encrypt|rotate> [data_dir]")),
+    };
+
+    let report = reencrypt_data_dir(&data_dir, &cipher).await?;
+    println!("Data directory: {}", data_dir.display());
+    println!("Key id: {}", cipher.key_id());
+    println!("Encrypted plaintext files: {}", report.encrypted.len());
+    println!("Re-encrypted files of previous keys: {}", report.rotated.len());
+    println!("Files already up to date: {}", report.unchanged.len());
+    Ok(())
+}
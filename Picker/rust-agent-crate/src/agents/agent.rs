@@ -1,7 +1,9 @@
 // Agent interface and related structure definitions
 use anyhow::Error;
 use std::collections::HashMap;
+use tokio::sync::mpsc;
 use crate::tools::{ExampleTool, Tool};
+use crate::callbacks::CallbackManager;
 use crate::core::Runnable;
 
 // Action executed by Agent (simplified)
@@ -11,6 +13,8 @@ pub struct AgentAction {
     pub tool_input: String,
     pub log: String,
     pub thought: Option<String>,
+    // Tool call ID assigned by the model when native tool calling is used
+    pub tool_call_id: Option<String>,
 }
 
 // Result when Agent completes execution (simplified)
@@ -19,10 +23,29 @@ pub struct AgentFinish {
     pub return_values: HashMap<String, String>,
 }
 
+impl AgentFinish {
+    /// Get the final answer ("answer" or "output" return value)
+    pub fn answer(&self) -> &str {
+        self.return_values.get("answer")
+            .or_else(|| self.return_values.get("output"))
+            .map(|s| s.as_str())
+            .unwrap_or("")
+    }
+}
+
+// Action taken by the Agent together with the observed tool output
+#[derive(Clone, Debug)]
+pub struct AgentStep {
+    pub action: AgentAction,
+    pub observation: String,
+}
+
 // Unified Agent output type
 #[derive(Clone, Debug)]
 pub enum AgentOutput {
     Action(AgentAction),
+    // Several actions requested at once (parallel tool calls), executed in order
+    Actions(Vec<AgentAction>),
     Finish(AgentFinish),
 }
 
@@ -48,6 +71,66 @@ pub trait Agent: Send + Sync {
         })
     }
     
+    // Execute with a callback manager whose tool runs become children of the current agent run (used by AgentExecutor)
+    // The default implementation reports the whole execution as one tool run
+    fn execute_with_callbacks<'a>(&'a self, action: &'a AgentAction, callbacks: &'a CallbackManager) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let run_manager = callbacks.on_tool_start(&action.tool, &action.tool_input);
+            match self.execute(action).await {
+                Ok(observation) => {
+                    run_manager.on_tool_end(&action.tool, &observation);
+                    Ok(observation)
+                },
+                Err(e) => {
+                    run_manager.on_tool_error(&action.tool, &e.to_string());
+                    Err(e)
+                },
+            }
+        })
+    }
+    
+    // Decide the next output based on the inputs and the steps taken so far
+    fn plan<'a>(&'a self, intermediate_steps: &'a [AgentStep], inputs: &'a HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, Error>> + Send + 'a>> {
+        let _intermediate_steps = intermediate_steps;
+        let _inputs = inputs;
+        Box::pin(async move {
+            Err(Error::msg("The agent does not implement the plan method"))
+        })
+    }
+    
+    // Plan with a callback manager whose runs become children of the current agent run (used by AgentExecutor)
+    fn plan_with_callbacks<'a>(&'a self, intermediate_steps: &'a [AgentStep], inputs: &'a HashMap<String, String>, callbacks: &'a CallbackManager) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, Error>> + Send + 'a>> {
+        let _callbacks = callbacks;
+        self.plan(intermediate_steps, inputs)
+    }
+    
+    // Plan with callbacks, sending the tokens of a final answer to the channel (used by AgentExecutor::stream)
+    // The default implementation sends the whole answer once the agent finished
+    fn stream_plan_with_callbacks<'a>(&'a self, intermediate_steps: &'a [AgentStep], inputs: &'a HashMap<String, String>, callbacks: &'a CallbackManager, tokens: &'a mpsc::Sender<String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let output = self.plan_with_callbacks(intermediate_steps, inputs, callbacks).await?;
+            if let AgentOutput::Finish(finish) = &output {
+                if tokens.send(finish.answer().to_string()).await.is_err() {
+                    return Err(Error::msg("Stream receiver has been dropped"));
+                }
+            }
+            Ok(output)
+        })
+    }
+    
+    // Save a final answer the agent did not plan itself, e.g. the answer of a run stopped by AgentExecutor
+    // Agents with memory store the turn, the default implementation does nothing
+    fn save_answer<'a>(&'a self, inputs: &'a HashMap<String, String>, answer: &'a str) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        let _inputs = inputs;
+        let _answer = answer;
+        Box::pin(async move {})
+    }
+    
+    // Callback manager attached to the agent
+    fn callback_manager(&self) -> CallbackManager {
+        CallbackManager::new()
+    }
+    
     // Clone agent instance
     fn clone_agent(&self) -> Box<dyn Agent>;
 }
@@ -122,6 +205,7 @@ impl Runnable<HashMap<String, String>, AgentOutput> for SimpleAgentRunner {
                     tool_input,
                     log: format!("Invoking tool: {}", tool_name),
                     thought: Some("Invoking tool".to_string()),
+                    tool_call_id: None,
                 }))
             } else {
                 // Otherwise return a simple completion result
@@ -0,0 +1,507 @@
+// Agent executor - runs the Action -> tool -> observation loop until the agent finishes
+use anyhow::Error;
+use futures::stream::Stream;
+use log::info;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use crate::agents::{Agent, AgentAction, AgentFinish, AgentOutput, AgentStep};
+use crate::callbacks::{CallbackManager, CallbackRunManager};
+use crate::core::Runnable;
+use crate::tools::find_matching_tool_index;
+
+// What to do when the executor stops before the agent finished
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EarlyStoppingMethod {
+    // Return a final answer built from the steps taken so far
+    #[default]
+    Force,
+    // Return an error
+    Error,
+}
+
+// Reason why the executor stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AgentStopReason {
+    // The agent returned AgentFinish
+    Finished,
+    // max_iterations was reached
+    MaxIterations,
+    // max_execution_time was exceeded
+    Timeout,
+    // The agent requested the same tool call with the same input again
+    RepeatedToolCall { tool: String, tool_input: String },
+}
+
+impl std::fmt::Display for AgentStopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentStopReason::Finished => write!(f, "agent finished"),
+            AgentStopReason::MaxIterations => write!(f, "iteration limit"),
+            AgentStopReason::Timeout => write!(f, "time limit"),
+            AgentStopReason::RepeatedToolCall { tool, .. } => write!(f, "repeated call of tool {}", tool),
+        }
+    }
+}
+
+// Result of an executor run
+#[derive(Clone, Debug)]
+pub struct AgentExecutorOutput {
+    pub return_values: HashMap<String, String>,
+    // Every action taken with its observation, in order
+    pub intermediate_steps: Vec<AgentStep>,
+    pub stop_reason: AgentStopReason,
+}
+
+impl AgentExecutorOutput {
+    /// Get the final answer ("answer" or "output" return value)
+    pub fn answer(&self) -> &str {
+        self.return_values.get("answer")
+            .or_else(|| self.return_values.get("output"))
+            .map(|s| s.as_str())
+            .unwrap_or("")
+    }
+}
+
+// Multi-step agent executor (aligned with langchain AgentExecutor)
+pub struct AgentExecutor {
+    agent: Arc<dyn Agent>,
+    max_iterations: Option<usize>,
+    max_execution_time: Option<Duration>,
+    early_stopping_method: EarlyStoppingMethod,
+    // Turn tool errors into observations instead of aborting the run
+    handle_tool_errors: bool,
+    callback_manager: CallbackManager,
+}
+
+impl AgentExecutor {
+    pub fn new(agent: impl Agent + 'static) -> Self {
+        Self::from_agent(Arc::new(agent))
+    }
+
+    pub fn from_agent(agent: Arc<dyn Agent>) -> Self {
+        Self {
+            agent,
+            max_iterations: Some(15),
+            max_execution_time: None,
+            early_stopping_method: EarlyStoppingMethod::Force,
+            handle_tool_errors: true,
+            callback_manager: CallbackManager::new(),
+        }
+    }
+
+    /// Set the maximum number of planning steps, None means unlimited
+    pub fn with_max_iterations(mut self, max_iterations: Option<usize>) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Set the wall-clock limit of a whole run, None means unlimited
+    pub fn with_max_execution_time(mut self, max_execution_time: Option<Duration>) -> Self {
+        self.max_execution_time = max_execution_time;
+        self
+    }
+
+    pub fn with_early_stopping_method(mut self, early_stopping_method: EarlyStoppingMethod) -> Self {
+        self.early_stopping_method = early_stopping_method;
+        self
+    }
+
+    pub fn with_handle_tool_errors(mut self, handle_tool_errors: bool) -> Self {
+        self.handle_tool_errors = handle_tool_errors;
+        self
+    }
+
+    /// Set the callback manager, combined with the callback manager of the agent
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+
+    pub fn agent(&self) -> &Arc<dyn Agent> {
+        &self.agent
+    }
+
+    /// Run the agent until it finishes or a limit is hit
+    pub async fn run(&self, inputs: &HashMap<String, String>) -> Result<AgentExecutorOutput, Error> {
+        self.run_with_tokens(inputs, None).await
+    }
+
+    /// Run the agent like `run`, streaming only the tokens of the final answer
+    /// The answer of a run stopped by a limit is sent as a single token, errors end the stream.
+    pub fn stream(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn Stream<Item = Result<String, Error>> + Send>> {
+        let executor = self.clone();
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let (token_sender, mut token_receiver) = mpsc::channel(64);
+            let forward_sender = sender.clone();
+            // Dropping the token receiver when the stream is dropped ends the run
+            let forward = async move {
+                while let Some(token) = token_receiver.recv().await {
+                    if forward_sender.send(Ok(token)).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            let (output, _) = tokio::join!(executor.run_with_tokens(&inputs, Some(token_sender)), forward);
+            match output {
+                Ok(output) if output.stop_reason != AgentStopReason::Finished => {
+                    let _ = sender.send(Ok(output.answer().to_string())).await;
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                },
+            }
+        });
+        Box::pin(ReceiverStream::new(receiver))
+    }
+
+    // Run with callbacks, the agent sends the tokens of its final answer when a token channel is given
+    async fn run_with_tokens(&self, inputs: &HashMap<String, String>, tokens: Option<mpsc::Sender<String>>) -> Result<AgentExecutorOutput, Error> {
+        let callbacks = self.callback_manager.merge(&self.agent.callback_manager());
+        let run_manager = callbacks.on_chain_start("AgentExecutor");
+
+        match self.run_steps(inputs, &run_manager, tokens.as_ref()).await {
+            Ok(output) => {
+                run_manager.on_agent_finish(&AgentFinish { return_values: output.return_values.clone() });
+                run_manager.on_chain_end("AgentExecutor");
+                Ok(output)
+            },
+            Err(e) => {
+                run_manager.on_chain_error("AgentExecutor", &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    // Action -> tool -> observation loop, model and tool runs are children of the executor run
+    async fn run_steps(&self, inputs: &HashMap<String, String>, run_manager: &CallbackRunManager, tokens: Option<&mpsc::Sender<String>>) -> Result<AgentExecutorOutput, Error> {
+        let child_callbacks = run_manager.get_child();
+        let deadline = self.max_execution_time.map(|timeout| Instant::now() + timeout);
+        let mut intermediate_steps: Vec<AgentStep> = Vec::new();
+        let mut iterations = 0;
+
+        loop {
+            if self.max_iterations.is_some_and(|max| iterations >= max) {
+                return self.stop(AgentStopReason::MaxIterations, inputs, intermediate_steps).await;
+            }
+            iterations += 1;
+
+            let plan = match tokens {
+                Some(tokens) => self.agent.stream_plan_with_callbacks(&intermediate_steps, inputs, &child_callbacks, tokens),
+                None => self.agent.plan_with_callbacks(&intermediate_steps, inputs, &child_callbacks),
+            };
+            let output = match Self::with_deadline(deadline, plan).await {
+                Some(output) => output?,
+                None => return self.stop(AgentStopReason::Timeout, inputs, intermediate_steps).await,
+            };
+
+            let actions = match output {
+                AgentOutput::Finish(finish) => {
+                    return Ok(AgentExecutorOutput {
+                        return_values: finish.return_values,
+                        intermediate_steps,
+                        stop_reason: AgentStopReason::Finished,
+                    });
+                },
+                AgentOutput::Action(action) => vec![action],
+                AgentOutput::Actions(actions) => actions,
+            };
+
+            // Parallel tool calls each get their own observation
+            for mut action in actions {
+                run_manager.on_agent_action(&action);
+
+                // Find the corresponding tool using fuzzy matching mechanism
+                let tools = self.agent.tools();
+                let observation = match find_matching_tool_index(&tools, &action.tool) {
+                    Some(matched_name) => {
+                        action.tool = matched_name;
+
+                        if Self::is_repeated_call(&intermediate_steps, &action) {
+                            let reason = AgentStopReason::RepeatedToolCall {
+                                tool: action.tool.clone(),
+                                tool_input: action.tool_input.clone(),
+                            };
+                            return self.stop(reason, inputs, intermediate_steps).await;
+                        }
+
+                        info!("Executing tool {} with input: {}", action.tool, action.tool_input);
+                        // The agent reports the tool run, tools with callbacks of their own fire every event once
+                        match Self::with_deadline(deadline, self.agent.execute_with_callbacks(&action, &child_callbacks)).await {
+                            Some(Ok(observation)) => observation,
+                            Some(Err(e)) if self.handle_tool_errors => format!("Error: {}", e),
+                            Some(Err(e)) => return Err(e),
+                            None => {
+                                child_callbacks.on_tool_start(&action.tool, &action.tool_input).on_tool_error(&action.tool, "Tool execution timed out");
+                                return self.stop(AgentStopReason::Timeout, inputs, intermediate_steps).await;
+                            },
+                        }
+                    },
+                    None => {
+                        let names: Vec<&str> = tools.iter().map(|t| t.name()).collect();
+                        let observation = format!("{} is not a valid tool, try one of [{}].", action.tool, names.join(", "));
+                        child_callbacks.on_tool_start(&action.tool, &action.tool_input).on_tool_error(&action.tool, &observation);
+                        observation
+                    },
+                };
+
+                intermediate_steps.push(AgentStep { action, observation });
+            }
+        }
+    }
+
+    // Await the future, returning None if the deadline passes first
+    async fn with_deadline<T>(deadline: Option<Instant>, future: impl std::future::Future<Output = T>) -> Option<T> {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+            None => Some(future.await),
+        }
+    }
+
+    // Check whether the same tool was already called with the same input
+    fn is_repeated_call(intermediate_steps: &[AgentStep], action: &AgentAction) -> bool {
+        intermediate_steps.iter().any(|step| {
+            step.action.tool == action.tool && step.action.tool_input.trim() == action.tool_input.trim()
+        })
+    }
+
+    // Handle a run that stopped before the agent finished, a forced answer is saved like a planned one
+    async fn stop(&self, stop_reason: AgentStopReason, inputs: &HashMap<String, String>, intermediate_steps: Vec<AgentStep>) -> Result<AgentExecutorOutput, Error> {
+        match self.early_stopping_method {
+            EarlyStoppingMethod::Error => Err(Error::msg(format!(
+                "Agent stopped due to {} after {} steps",
+                stop_reason,
+                intermediate_steps.len()
+            ))),
+            EarlyStoppingMethod::Force => {
+                let answer = match intermediate_steps.last() {
+                    Some(step) => format!(
+                        "Agent stopped due to {}. Last result of tool {}: {}",
+                        stop_reason, step.action.tool, step.observation
+                    ),
+                    None => format!("Agent stopped due to {}.", stop_reason),
+                };
+                self.agent.save_answer(inputs, &answer).await;
+                let mut return_values = HashMap::new();
+                return_values.insert("answer".to_string(), answer);
+
+                Ok(AgentExecutorOutput {
+                    return_values,
+                    intermediate_steps,
+                    stop_reason,
+                })
+            },
+        }
+    }
+}
+
+impl Clone for AgentExecutor {
+    fn clone(&self) -> Self {
+        Self {
+            agent: Arc::clone(&self.agent),
+            max_iterations: self.max_iterations,
+            max_execution_time: self.max_execution_time,
+            early_stopping_method: self.early_stopping_method,
+            handle_tool_errors: self.handle_tool_errors,
+            callback_manager: self.callback_manager.clone(),
+        }
+    }
+}
+
+impl Runnable<HashMap<String, String>, AgentExecutorOutput> for AgentExecutor {
+    fn invoke(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn std::future::Future<Output = Result<AgentExecutorOutput, Error>> + Send>> {
+        let executor = self.clone();
+        Box::pin(async move {
+            executor.run(&inputs).await
+        })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<HashMap<String, String>, AgentExecutorOutput> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callbacks::{CallbackHandler, RunInfo};
+    use crate::tools::{ExampleTool, Tool};
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    // Agent returning a fixed sequence of outputs, repeating the last one
+    struct ScriptedAgent {
+        outputs: Mutex<VecDeque<AgentOutput>>,
+    }
+
+    impl ScriptedAgent {
+        fn new(outputs: Vec<AgentOutput>) -> Self {
+            Self { outputs: Mutex::new(outputs.into()) }
+        }
+    }
+
+    impl Agent for ScriptedAgent {
+        fn tools(&self) -> Vec<Box<dyn Tool + Send + Sync>> {
+            vec![
+                Box::new(ExampleTool::new("get_weather".to_string(), "Get the weather".to_string())),
+                Box::new(ExampleTool::new("calculate".to_string(), "Calculate an expression".to_string())),
+            ]
+        }
+
+        fn plan<'a>(&'a self, _intermediate_steps: &'a [AgentStep], _inputs: &'a HashMap<String, String>) -> Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, Error>> + Send + 'a>> {
+            Box::pin(async move {
+                let mut outputs = self.outputs.lock().unwrap();
+                let output = if outputs.len() > 1 { outputs.pop_front() } else { outputs.front().cloned() };
+                output.ok_or_else(|| Error::msg("No scripted output"))
+            })
+        }
+
+        fn clone_agent(&self) -> Box<dyn Agent> {
+            Box::new(ScriptedAgent::new(self.outputs.lock().unwrap().iter().cloned().collect()))
+        }
+    }
+
+    fn action(tool: &str, tool_input: &str) -> AgentOutput {
+        AgentOutput::Action(AgentAction {
+            tool: tool.to_string(),
+            tool_input: tool_input.to_string(),
+            log: String::new(),
+            thought: None,
+            tool_call_id: None,
+        })
+    }
+
+    fn finish(answer: &str) -> AgentOutput {
+        let mut return_values = HashMap::new();
+        return_values.insert("answer".to_string(), answer.to_string());
+        AgentOutput::Finish(AgentFinish { return_values })
+    }
+
+    #[tokio::test]
+    async fn test_runs_multiple_tools_until_finish() {
+        let executor = AgentExecutor::new(ScriptedAgent::new(vec![
+            action("weather", "{\"city\":\"Paris\"}"),
+            action("calculate", "{\"expression\":\"1+1\"}"),
+            finish("done"),
+        ]));
+
+        let output = executor.run(&HashMap::new()).await.unwrap();
+        assert_eq!(output.answer(), "done");
+        assert_eq!(output.stop_reason, AgentStopReason::Finished);
+        assert_eq!(output.intermediate_steps.len(), 2);
+        // Fuzzy matched tool name is recorded in the trace
+        assert_eq!(output.intermediate_steps[0].action.tool, "get_weather");
+        assert_eq!(output.intermediate_steps[1].observation, "Tool calculate received input: {\"expression\":\"1+1\"}");
+    }
+
+    #[tokio::test]
+    async fn test_max_iterations_and_early_stopping() {
+        let outputs = || vec![
+            action("get_weather", "{\"city\":\"Paris\"}"),
+            action("get_weather", "{\"city\":\"Rome\"}"),
+            action("get_weather", "{\"city\":\"Oslo\"}"),
+        ];
+
+        let executor = AgentExecutor::new(ScriptedAgent::new(outputs())).with_max_iterations(Some(2));
+        let output = executor.run(&HashMap::new()).await.unwrap();
+        assert_eq!(output.stop_reason, AgentStopReason::MaxIterations);
+        assert_eq!(output.intermediate_steps.len(), 2);
+        assert!(output.answer().contains("Rome"));
+
+        let executor = AgentExecutor::new(ScriptedAgent::new(outputs()))
+            .with_max_iterations(Some(2))
+            .with_early_stopping_method(EarlyStoppingMethod::Error);
+        assert!(executor.run(&HashMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_call_detection() {
+        let executor = AgentExecutor::new(ScriptedAgent::new(vec![
+            action("get_weather", "{\"city\":\"Paris\"}"),
+        ]));
+
+        let output = executor.run(&HashMap::new()).await.unwrap();
+        assert_eq!(output.intermediate_steps.len(), 1);
+        assert_eq!(output.stop_reason, AgentStopReason::RepeatedToolCall {
+            tool: "get_weather".to_string(),
+            tool_input: "{\"city\":\"Paris\"}".to_string(),
+        });
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tool_becomes_observation() {
+        let executor = AgentExecutor::new(ScriptedAgent::new(vec![
+            action("translate", "{}"),
+            finish("done"),
+        ]));
+
+        let output = executor.run(&HashMap::new()).await.unwrap();
+        assert_eq!(output.intermediate_steps[0].observation, "translate is not a valid tool, try one of [get_weather, calculate].");
+    }
+
+    // Records (event, run_id, parent_run_id) of every callback
+    #[derive(Default)]
+    struct EventRecorder {
+        events: Mutex<Vec<(String, uuid::Uuid, Option<uuid::Uuid>)>>,
+    }
+
+    impl EventRecorder {
+        fn record(&self, event: &str, run: &RunInfo) {
+            self.events.lock().unwrap().push((event.to_string(), run.run_id, run.parent_run_id));
+        }
+    }
+
+    impl CallbackHandler for EventRecorder {
+        fn on_tool_start(&self, run: &RunInfo, _tool_name: &str, _input: &str) {
+            self.record("tool_start", run);
+        }
+
+        fn on_tool_end(&self, run: &RunInfo, _tool_name: &str, _output: &str) {
+            self.record("tool_end", run);
+        }
+
+        fn on_chain_start(&self, run: &RunInfo, _chain_name: &str) {
+            self.record("chain_start", run);
+        }
+
+        fn on_chain_end(&self, run: &RunInfo, _chain_name: &str) {
+            self.record("chain_end", run);
+        }
+
+        fn on_agent_action(&self, run: &RunInfo, _action: &AgentAction) {
+            self.record("agent_action", run);
+        }
+
+        fn on_agent_finish(&self, run: &RunInfo, _finish: &AgentFinish) {
+            self.record("agent_finish", run);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_fired_with_run_hierarchy() {
+        let recorder = Arc::new(EventRecorder::default());
+        let executor = AgentExecutor::new(ScriptedAgent::new(vec![
+            action("get_weather", "{\"city\":\"Paris\"}"),
+            finish("done"),
+        ])).with_callback_manager(CallbackManager::new().with_handler(recorder.clone()));
+
+        executor.run(&HashMap::new()).await.unwrap();
+
+        let events = recorder.events.lock().unwrap();
+        let names: Vec<&str> = events.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["chain_start", "agent_action", "tool_start", "tool_end", "agent_finish", "chain_end"]);
+
+        let chain_run_id = events[0].1;
+        assert_eq!(events[0].2, None);
+        // Tool run is a child of the executor run
+        assert_eq!(events[2].2, Some(chain_run_id));
+        assert_eq!(events[2].1, events[3].1);
+        assert_eq!(events[5].1, chain_run_id);
+    }
+}
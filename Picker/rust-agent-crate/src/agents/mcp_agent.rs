@@ -1,22 +1,61 @@
-use anyhow::anyhow;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::Arc;
+use futures::stream::{Stream, StreamExt};
 use log::info;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 
 use crate::{
-    Agent, AgentAction, AgentFinish, AgentOutput, BaseMemory, ModelChatMessage, ChatMessageContent, ChatModel,
-    McpClient, McpToolAdapter, OpenAIChatModel, Runnable, Tool, parse_model_output
+    Agent, AgentAction, AgentExecutor, AgentFinish, AgentOutput, AgentStep, BaseMemory, ModelChatMessage, ChatMessageContent, ChatModel,
+    ChatCompletion, ChatCompletionAccumulator, McpClient, McpToolAdapter, OpenAIChatModel, Runnable, Tool, ToolCall, ToolDefinition,
+    ModelRegistry, Tokenizer, find_matching_tool_index, parse_model_output, trim_messages
 };
+use crate::callbacks::CallbackManager;
+use crate::prompt::PromptTemplate;
 use serde_json::Value;
 
+/// Default ReAct prompt for models with native tool calling
+/// Available variables: {system_prompt}, {tool_descriptions}, {tool_names}
+pub const DEFAULT_REACT_PROMPT: &str = "{system_prompt}
+You are an AI assistant that follows the ReAct (Reasoning and Acting) framework.
+You should think step by step and decide whether to use tools based on user needs.
+You should carefully review and when confirming the use of the tool, if there are omissions, errors, or other issues with the parameters, you should reply and remind the user.
+When you need to use a tool, call it through the provided tools. When you don't need to use a tool, answer the user directly.
+        Please think carefully about whether the user's request requires a tool to be used, and only use tools when necessary.";
+
+/// Default ReAct prompt describing the JSON tool calling protocol, used for models without native tool calling
+pub const DEFAULT_REACT_JSON_PROMPT: &str = "{system_prompt}
+You are an AI assistant that follows the ReAct (Reasoning and Acting) framework.
+You should think step by step and decide whether to use tools based on user needs.
+You should carefully review and when confirming the use of the tool, if there are omissions, errors, or other issues with the parameters, you should reply and remind the user.
+Available tools:\n{tool_descriptions}\n\nWhen you need to use a tool, please respond in the following JSON format:
+            \n{{\"call_tool\": {{\"name\": \"Tool Name\", \"parameters\": {{\"parameter_name\": \"parameter_value\"}}}}}}
+        When you don't need to use a tool, please respond in the following JSON format:\n{{\"content\": \"Your answer\"}}
+        Please think carefully about whether the user's request requires a tool to be used, and only use tools when necessary.";
+
+// Variables provided by McpAgent when formatting the ReAct prompt
+const REACT_PROMPT_VARIABLES: [&str; 3] = ["system_prompt", "tool_descriptions", "tool_names"];
+
 /// McpAgent is an intelligent agent implementation based on MCP services
 /// It can connect to MCP servers, process user inputs, call tools, and generate responses
 pub struct McpAgent {
     client: Arc<dyn McpClient>,
-    tools: Vec<Box<dyn Tool + Send + Sync>>,
+    tools: Vec<Arc<dyn Tool + Send + Sync>>,
     system_prompt: String,
     openai_model: Option<OpenAIChatModel>,
+    // Any other chat model (router, cache, Anthropic, Ollama...), takes precedence over openai_model
+    chat_model: Option<Arc<dyn ChatModel>>,
     memory: Option<Box<dyn BaseMemory>>,
+    callback_manager: CallbackManager,
+    react_prompt: PromptTemplate,
+    react_json_prompt: PromptTemplate,
+    // Messages of an MCP prompt, sent after the chat history
+    prompt_messages: Vec<ModelChatMessage>,
+    // Context windows used to drop the oldest chat history that does not fit
+    model_registry: ModelRegistry,
+    // Overrides the tokenizer of the model registry
+    tokenizer: Option<Arc<dyn Tokenizer>>,
 }
 
 impl McpAgent {
@@ -27,10 +66,17 @@ impl McpAgent {
             tools: Vec::new(),
             system_prompt,
             openai_model: None, // Default to not setting OpenAI model
+            chat_model: None,
             memory: None, // Default to not setting memory module
+            callback_manager: CallbackManager::new(),
+            react_prompt: PromptTemplate::new(DEFAULT_REACT_PROMPT).expect("default ReAct prompt is valid"),
+            react_json_prompt: PromptTemplate::new(DEFAULT_REACT_JSON_PROMPT).expect("default ReAct prompt is valid"),
+            prompt_messages: Vec::new(),
+            model_registry: ModelRegistry::default(),
+            tokenizer: None,
         }
     }
-    
+
     /// Create a new McpAgent instance with specified OpenAIChatModel
     pub fn with_openai_model(client: Arc<dyn McpClient>, system_prompt: String, openai_model: OpenAIChatModel) -> Self {
         Self {
@@ -38,10 +84,17 @@ impl McpAgent {
             tools: Vec::new(),
             system_prompt,
             openai_model: Some(openai_model),
+            chat_model: None,
             memory: None, // Default to not setting memory module
+            callback_manager: CallbackManager::new(),
+            react_prompt: PromptTemplate::new(DEFAULT_REACT_PROMPT).expect("default ReAct prompt is valid"),
+            react_json_prompt: PromptTemplate::new(DEFAULT_REACT_JSON_PROMPT).expect("default ReAct prompt is valid"),
+            prompt_messages: Vec::new(),
+            model_registry: ModelRegistry::default(),
+            tokenizer: None,
         }
     }
-    
+
     /// Create a new McpAgent instance with specified memory module
     pub fn with_memory(client: Arc<dyn McpClient>, system_prompt: String, memory: Box<dyn BaseMemory>) -> Self {
         Self {
@@ -49,7 +102,14 @@ impl McpAgent {
             tools: Vec::new(),
             system_prompt,
             openai_model: None,
+            chat_model: None,
             memory: Some(memory),
+            callback_manager: CallbackManager::new(),
+            react_prompt: PromptTemplate::new(DEFAULT_REACT_PROMPT).expect("default ReAct prompt is valid"),
+            react_json_prompt: PromptTemplate::new(DEFAULT_REACT_JSON_PROMPT).expect("default ReAct prompt is valid"),
+            prompt_messages: Vec::new(),
+            model_registry: ModelRegistry::default(),
+            tokenizer: None,
         }
     }
 
@@ -60,10 +120,85 @@ impl McpAgent {
             tools: Vec::new(),
             system_prompt,
             openai_model: Some(openai_model),
+            chat_model: None,
             memory: Some(memory),
+            callback_manager: CallbackManager::new(),
+            react_prompt: PromptTemplate::new(DEFAULT_REACT_PROMPT).expect("default ReAct prompt is valid"),
+            react_json_prompt: PromptTemplate::new(DEFAULT_REACT_JSON_PROMPT).expect("default ReAct prompt is valid"),
+            prompt_messages: Vec::new(),
+            model_registry: ModelRegistry::default(),
+            tokenizer: None,
         }
     }
-    
+
+    /// Use any ChatModel, e.g. a RouterChatModel or a CachedChatModel, instead of the OpenAI model
+    /// Runs of the model become children of the agent run, through ChatModel::with_callbacks.
+    pub fn with_chat_model(mut self, model: Arc<dyn ChatModel>) -> Self {
+        self.chat_model = Some(model);
+        self
+    }
+
+    /// Use this registry to look up the context window and tokenizer of the model
+    /// The oldest chat history messages are dropped when the prompt does not fit the context window.
+    pub fn with_model_registry(mut self, registry: ModelRegistry) -> Self {
+        self.model_registry = registry;
+        self
+    }
+
+    /// Count prompt tokens with this tokenizer instead of the one from the model registry
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Attach a callback manager, notified of agent, model and tool events
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+
+    /// Override the ReAct prompt used with native tool calling
+    /// The template may use {system_prompt}, {tool_descriptions} and {tool_names}
+    pub fn with_react_prompt(mut self, prompt: PromptTemplate) -> Result<Self, anyhow::Error> {
+        Self::check_react_prompt(&prompt)?;
+        self.react_prompt = prompt;
+        Ok(self)
+    }
+
+    /// Override the ReAct prompt used when the model has no native tool calling
+    /// The template should describe the {"call_tool": ...} / {"content": ...} JSON protocol
+    pub fn with_react_json_prompt(mut self, prompt: PromptTemplate) -> Result<Self, anyhow::Error> {
+        Self::check_react_prompt(&prompt)?;
+        self.react_json_prompt = prompt;
+        Ok(self)
+    }
+
+    /// Send these messages after the chat history, for example the messages of an MCP prompt
+    /// The input may then be empty, the prompt messages describe the task.
+    pub fn with_prompt_messages(mut self, messages: Vec<ModelChatMessage>) -> Self {
+        self.prompt_messages = messages;
+        self
+    }
+
+    /// Fetch a prompt from the MCP server with prompts/get and use its messages (see with_prompt_messages)
+    pub async fn load_prompt(&mut self, name: &str, arguments: HashMap<String, String>) -> Result<(), anyhow::Error> {
+        let prompt = self.client.get_prompt(name, arguments).await?;
+        info!("MCP Client Get Prompt: {} ({} messages)", name, prompt.messages.len());
+        self.prompt_messages = prompt.to_chat_messages();
+        Ok(())
+    }
+
+    /// Check that the ReAct prompt only uses variables provided by the agent
+    fn check_react_prompt(prompt: &PromptTemplate) -> Result<(), anyhow::Error> {
+        let unknown: Vec<String> = prompt.input_variables().into_iter()
+            .filter(|name| !REACT_PROMPT_VARIABLES.contains(&name.as_str()))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow::anyhow!("ReAct prompt uses unknown variables: {:?}", unknown));
+        }
+        Ok(())
+    }
+
     /// Get a reference to the memory module
     pub fn get_memory(&self) -> Option<&Box<dyn BaseMemory>> {
         self.memory.as_ref()
@@ -71,15 +206,13 @@ impl McpAgent {
 
     /// Add a tool to the Agent
     pub fn add_tool(&mut self, tool: Box<dyn Tool + Send + Sync>) {
-        self.tools.push(tool);
+        self.tools.push(Arc::from(tool));
     }
-    
+
     /// Automatically get tools from MCP client and add them to the Agent
     /// This method gets all available tools from the MCP client and wraps them as McpToolAdapter before adding to the Agent
     /// Local tool registration and addition are handled by the caller
     pub async fn auto_add_tools(&mut self) -> Result<(), anyhow::Error> {
-        use crate::McpToolAdapter;
-        
         // Get tool list from MCP client
         let tools = self.client.get_tools().await?;
 
@@ -87,7 +220,7 @@ impl McpAgent {
         for tool in &tools {
             info!("MCP Client Get Tool: {} - {}", tool.name, tool.description);
         }
-        
+
         // Wrap each tool as McpToolAdapter and add to the Agent
         for tool in tools {
             let tool_adapter = McpToolAdapter::new(
@@ -96,434 +229,797 @@ impl McpAgent {
             );
             self.add_tool(Box::new(tool_adapter));
         }
-        
+
         Ok(())
     }
-}
 
-impl Agent for McpAgent {
-    fn tools(&self) -> Vec<Box<dyn Tool + Send + Sync>> {
-        // Return a cloned version of the tool list
-        // To solve the problem that Box<dyn Tool> cannot be directly cloned, we create new tool adapter instances
-        let mut cloned_tools: Vec<Box<dyn Tool + Send + Sync>> = Vec::new();
+    /// Build tool definitions for models with native tool calling
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|tool| {
+            ToolDefinition::new(
+                tool.name().to_string(),
+                tool.description().to_string(),
+                tool.input_schema(),
+            )
+        }).collect()
+    }
+
+    /// Build the system prompt, the JSON tool calling protocol is only described when the model lacks native tool calling
+    fn build_system_prompt(&self, native_tool_calling: bool) -> String {
+        if self.tools.is_empty() {
+            return self.system_prompt.clone();
+        }
 
-        // Since McpToolAdapter can be recreated through client and McpTool,
-        // we iterate through existing tools and create new adapter instances for each tool
+        let mut tool_descriptions = String::new();
         for tool in &self.tools {
-            // Check if the tool is of type McpToolAdapter
-            if let Some(mcp_tool_adapter) = tool.as_any().downcast_ref::<McpToolAdapter>() {
-                // Recreate McpToolAdapter instance
-                let cloned_adapter = McpToolAdapter::new(
-                    mcp_tool_adapter.get_client(),
-                    mcp_tool_adapter.get_mcp_tool(),
-                );
-                cloned_tools.push(Box::new(cloned_adapter));
+            tool_descriptions.push_str(&format!("- {}: {}\n", tool.name(), tool.description()));
+        }
+        let tool_names: Vec<&str> = self.tools.iter().map(|tool| tool.name()).collect();
+
+        let mut inputs = HashMap::new();
+        inputs.insert("system_prompt".to_string(), self.system_prompt.clone());
+        inputs.insert("tool_descriptions".to_string(), tool_descriptions);
+        inputs.insert("tool_names".to_string(), tool_names.join(", "));
+
+        let template = if native_tool_calling { &self.react_prompt } else { &self.react_json_prompt };
+        template.format(&inputs).unwrap_or_else(|e| {
+            log::warn!("Failed to format ReAct prompt: {}", e);
+            self.system_prompt.clone()
+        })
+    }
+
+    /// Get the configured model name, use default value if not available
+    fn configured_model_name(&self) -> String {
+        if let Some(model) = &self.chat_model {
+            return model.model_name().unwrap_or("unknown").to_string();
+        }
+        self.openai_model.as_ref()
+            .and_then(|model| model.model_name().map(|s| s.to_string()))
+            .unwrap_or("unknown".to_string())
+    }
+
+    /// Append the summary stored in the memory module to the system prompt
+    async fn append_summary(&self, system_prompt: String) -> String {
+        let mut enhanced_prompt = system_prompt;
+        if let Some(memory) = &self.memory {
+            // Try to get summary content from memory module
+            // Here we use downcast_ref to check if it's CompositeMemory type
+            if let Some(composite_memory) = memory.as_any().downcast_ref::<crate::memory::composite_memory::CompositeMemory>() {
+                // If it's CompositeMemory, call get_summary method to get summary
+                match composite_memory.get_summary().await {
+                    Ok(Some(summary)) => {
+                        // Append summary content to system prompt
+                        enhanced_prompt = format!("{}\n\nPrevious conversation summary: {}", enhanced_prompt, summary);
+                        log::info!("Summary appended to system prompt");
+                    },
+                    Ok(None) => {
+                        log::info!("No summary content found");
+                    },
+                    Err(e) => {
+                        log::warn!("Error getting summary: {}", e);
+                    }
+                }
             } else {
-                // For other types of tools, we skip or need to implement other cloning mechanisms
-                // Here we can add logs or error handling
-                info!(
-                    "Warning: Unable to clone non-McpToolAdapter type tool: {}",
-                    tool.name()
-                );
+                // If not CompositeMemory, try to get summary from memory variables
+                match memory.load_memory_variables(&HashMap::new()).await {
+                    Ok(memories) => {
+                        if let Some(summary_str) = memories.get("summary").and_then(|s| s.as_str()) {
+                            // Append summary content to system prompt
+                            enhanced_prompt = format!("{}\n\nPrevious conversation summary: {}", enhanced_prompt, summary_str);
+                            log::info!("Summary retrieved from memory variables and appended to system prompt");
+                        }
+                    },
+                    Err(e) => {
+                        log::warn!("Error getting summary from memory variables: {}", e);
+                    }
+                }
             }
         }
+        enhanced_prompt
+    }
 
-        cloned_tools
+    // Tool of an action, using fuzzy matching mechanism
+    fn find_tool(&self, tool_name: &str) -> Result<Arc<dyn Tool + Send + Sync>, anyhow::Error> {
+        find_matching_tool_index(&self.tools, tool_name)
+            .and_then(|matched_name| self.tools.iter().find(|t| t.name() == matched_name).cloned())
+            .ok_or_else(|| anyhow::anyhow!("Tool {} does not exist", tool_name))
     }
 
-    fn execute(
-        &self,
-        _action: &AgentAction,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>,
-    > {
-        Box::pin(async move {
-            // In practical applications, there should be a mechanism here to find and call tools
-            // Since we cannot clone the tool list, we simplify the implementation here
-            Err(anyhow!("Tool execution functionality is not implemented yet"))
-        })
+    // Memory variables appended to the system prompt with their titles, the keys come from the configured memories
+    fn context_section_keys(memory: &dyn BaseMemory) -> Vec<(String, &'static str)> {
+        const ENTITIES_TITLE: &str = "Known facts about the user and the mentioned entities:";
+        const RELEVANT_HISTORY_TITLE: &str = "Relevant messages from past conversations:";
+        let any = memory.as_any();
+        if let Some(composite) = any.downcast_ref::<crate::memory::CompositeMemory>() {
+            let mut keys = Vec::new();
+            if let Some(entities) = composite.entity_memory() {
+                keys.push((entities.memory_key().to_string(), ENTITIES_TITLE));
+            }
+            if let Some(long_term) = composite.long_term_memory() {
+                keys.push((long_term.memory_key().to_string(), RELEVANT_HISTORY_TITLE));
+            }
+            keys
+        } else if let Some(entities) = any.downcast_ref::<crate::memory::EntityMemory>() {
+            vec![(entities.memory_key().to_string(), ENTITIES_TITLE)]
+        } else if let Some(long_term) = any.downcast_ref::<crate::memory::VectorStoreRetrieverMemory>() {
+            vec![(long_term.memory_key().to_string(), RELEVANT_HISTORY_TITLE)]
+        } else {
+            // Other memories are expected to use the default keys
+            vec![
+                (crate::memory::DEFAULT_ENTITY_MEMORY_KEY.to_string(), ENTITIES_TITLE),
+                (crate::memory::DEFAULT_RETRIEVER_MEMORY_KEY.to_string(), RELEVANT_HISTORY_TITLE),
+            ]
+        }
     }
 
-    fn clone_agent(&self) -> Box<dyn Agent> {
-        // Create a new McpAgent instance, copy basic fields, but do not copy tools (simplified implementation)
-        let new_agent = McpAgent::new(
-            self.client.clone(),
-            self.system_prompt.clone(),
-        );
+    /// Load chat history messages and the system prompt sections (known entities, relevant past messages) from the memory module
+    async fn load_history_messages(&self, input_text: &str) -> (Vec<ModelChatMessage>, Vec<String>) {
+        let mut messages = Vec::new();
+        let mut context_sections = Vec::new();
+        let Some(memory) = &self.memory else {
+            return (messages, context_sections);
+        };
+
+        // The input is the query of long-term memory
+        let inputs = HashMap::from([("input".to_string(), Value::String(input_text.to_string()))]);
+        match memory.load_memory_variables(&inputs).await {
+            Ok(memories) => {
+                log::debug!("Loaded {} memory variables: {:?}", memories.len(), memories.keys().collect::<Vec<_>>());
+                // Known entities and relevant past messages are appended to the system prompt
+                for (key, title) in Self::context_section_keys(memory.as_ref()) {
+                    if let Some(text) = memories.get(&key).and_then(|value| value.as_str()).filter(|text| !text.trim().is_empty()) {
+                        context_sections.push(format!("{}\n{}", title, text));
+                    }
+                }
+                if let Some(Value::Array(messages_array)) = memories.get("chat_history") {
+                    for message in messages_array {
+                        if let Value::Object(msg_obj) = message {
+                            let role = msg_obj.get("role").and_then(|v| v.as_str()).unwrap_or("unknown");
+                            let content = msg_obj.get("content").and_then(|v| v.as_str()).unwrap_or("");
+
+                            // Skip empty content messages
+                            if content.trim().is_empty() {
+                                continue;
+                            }
+
+                            // Skip assistant messages containing complete history messages
+                            if role == "assistant" && content.contains("user:") && content.contains("assistant:") {
+                                continue;
+                            }
+
+                            match role {
+                                "human" | "user" => {
+                                    log::debug!("Loaded human message ({} chars)", content.len());
+                                    messages.push(ModelChatMessage::Human(ChatMessageContent {
+                                        content: content.to_string(),
+                                        name: None,
+                                        additional_kwargs: HashMap::new(),
+                                    }));
+                                },
+                                "ai" | "assistant" => {
+                                    log::debug!("Loaded AI message ({} chars)", content.len());
+                                    messages.push(ModelChatMessage::AIMessage(ChatMessageContent {
+                                        content: content.to_string(),
+                                        name: None,
+                                        additional_kwargs: HashMap::new(),
+                                    }));
+                                },
+                                "tool" => {
+                                    // Tool results are replayed as plain context, the originating tool call is not stored in memory
+                                    log::debug!("Loaded tool message ({} chars)", content.len());
+                                    messages.push(ModelChatMessage::Human(ChatMessageContent {
+                                        content: format!("[CUSTOMIZE_TOOL_RESULT] {}", content),
+                                        name: None,
+                                        additional_kwargs: HashMap::new(),
+                                    }));
+                                },
+                                _ => {
+                                    // Ignore messages with unknown roles
+                                    log::debug!("Skipped message with unknown role {}", role);
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            Err(e) => {
+                // If loading memory fails, log the error but continue execution
+                log::warn!("Failed to load memory variables: {}", e);
+            }
+        }
 
-        // Note: We do not copy tools here because Box<dyn Tool> cannot be directly cloned
-        Box::new(new_agent)
+        (messages, context_sections)
     }
-}
 
-impl Clone for McpAgent {
-    fn clone(&self) -> Self {
-        // Create a new McpAgent instance, but do not copy the tool list (simplified implementation)
-        Self {
-            client: Arc::clone(&self.client),
-            tools: Vec::new(), // Do not copy tools because Box<dyn Tool> cannot be directly cloned
-            system_prompt: self.system_prompt.clone(),
-            openai_model: self.openai_model.clone(), // Clone OpenAI model instance
-            memory: self.memory.clone(), // Clone memory module
+    /// Convert intermediate steps to messages, using tool messages when native tool calling is enabled
+    fn step_messages(intermediate_steps: &[AgentStep], native_tool_calling: bool) -> Vec<ModelChatMessage> {
+        let mut messages = Vec::new();
+        for (index, step) in intermediate_steps.iter().enumerate() {
+            let action = &step.action;
+            if native_tool_calling {
+                let tool_call = ToolCall {
+                    id: action.tool_call_id.clone().unwrap_or_else(|| format!("call_{}", index)),
+                    name: action.tool.clone(),
+                    arguments: action.tool_input.clone(),
+                };
+
+                let mut ai_kwargs = HashMap::new();
+                ai_kwargs.insert("tool_calls".to_string(), Value::Array(vec![tool_call.to_openai_value()]));
+                messages.push(ModelChatMessage::AIMessage(ChatMessageContent {
+                    content: action.log.clone(),
+                    name: None,
+                    additional_kwargs: ai_kwargs,
+                }));
+
+                let mut tool_kwargs = HashMap::new();
+                tool_kwargs.insert("tool_call_id".to_string(), Value::String(tool_call.id));
+                messages.push(ModelChatMessage::ToolMessage(ChatMessageContent {
+                    content: step.observation.clone(),
+                    name: None,
+                    additional_kwargs: tool_kwargs,
+                }));
+            } else {
+                // Replay the JSON protocol used in the system prompt
+                let parameters = serde_json::from_str::<Value>(&action.tool_input)
+                    .unwrap_or_else(|_| Value::String(action.tool_input.clone()));
+                let call = serde_json::json!({
+                    "call_tool": {
+                        "name": action.tool,
+                        "parameters": parameters
+                    }
+                });
+                messages.push(ModelChatMessage::AIMessage(ChatMessageContent {
+                    content: call.to_string(),
+                    name: None,
+                    additional_kwargs: HashMap::new(),
+                }));
+
+                let result = serde_json::json!({
+                    "tool": action.tool,
+                    "result": step.observation
+                });
+                messages.push(ModelChatMessage::Human(ChatMessageContent {
+                    content: format!("[CUSTOMIZE_TOOL_RESULT] {}", result),
+                    name: None,
+                    additional_kwargs: HashMap::new(),
+                }));
+            }
         }
+        messages
     }
-}
 
-impl Runnable<std::collections::HashMap<String, String>, AgentOutput> for McpAgent {
-    fn invoke(
-        &self,
-        input: std::collections::HashMap<String, String>,
-    ) -> Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, anyhow::Error>> + Send>> {
-        // Capture system prompt in advance
-        let system_prompt = self.system_prompt.clone();
-        let input_text = input
+    /// Save the user input and final answer to the memory module
+    async fn save_to_memory(&self, input_text: &str, content: &str) {
+        let Some(memory) = &self.memory else {
+            return;
+        };
+
+        // Without input the task is the last user message of the prompt
+        let input_text = if input_text.is_empty() {
+            self.prompt_messages.iter().rev()
+                .find_map(|message| match message {
+                    ModelChatMessage::Human(content) => Some(content.content.as_str()),
+                    _ => None,
+                })
+                .unwrap_or_default()
+        } else {
+            input_text
+        };
+
+        let mut inputs = HashMap::new();
+        inputs.insert("input".to_string(), Value::String(input_text.to_string()));
+
+        // Preprocess content, if it's JSON string format, extract the content field
+        let processed_content = if content.starts_with('"') && content.ends_with('"') {
+            // Try to parse as JSON string
+            match serde_json::from_str::<Value>(content) {
+                Ok(Value::String(s)) => s,
+                _ => content.to_string(),
+            }
+        } else if content.starts_with('{') && content.ends_with('}') {
+            // Try to parse as JSON object, if it's a JSON object, try to extract the content field
+            match serde_json::from_str::<Value>(content) {
+                Ok(json_obj) => json_obj.get("content")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| content.to_string()),
+                _ => content.to_string(),
+            }
+        } else {
+            content.to_string()
+        };
+
+        let mut outputs = HashMap::new();
+        outputs.insert("output".to_string(), Value::String(processed_content));
+
+        if let Err(e) = memory.save_context(&inputs, &outputs).await {
+            log::warn!("Failed to save context to memory: {}", e);
+        }
+    }
+
+    /// Trim the system and history messages so that the prompt fits the context window of the model
+    /// Models missing from the model registry are sent the full history.
+    fn fit_context_window(&self, model: &dyn ChatModel, messages: Vec<ModelChatMessage>, turn_messages: &[ModelChatMessage], native_tool_calling: bool) -> Vec<ModelChatMessage> {
+        let model_name = model.model_name().unwrap_or("unknown");
+        let Some(profile) = self.model_registry.profile(model_name) else {
+            return messages;
+        };
+        let tokenizer = self.tokenizer.clone().unwrap_or_else(|| self.model_registry.tokenizer_for(model_name));
+
+        // Tokens of the current turn and of the tool definitions sent along with the messages
+        let mut reserved = tokenizer.count_message_tokens(turn_messages);
+        if native_tool_calling {
+            for definition in self.tool_definitions() {
+                reserved += tokenizer.count_tokens(&definition.name)
+                    + tokenizer.count_tokens(&definition.description)
+                    + tokenizer.count_tokens(&definition.parameters.to_string());
+            }
+        }
+
+        let budget = profile.prompt_budget().saturating_sub(reserved);
+        let count = messages.len();
+        let messages = trim_messages(messages, budget, tokenizer.as_ref());
+        if messages.len() < count {
+            log::info!("Dropped {} history messages to fit the {} token context window of {} ({} tokenizer)",
+                count - messages.len(), profile.context_window, model_name, tokenizer.name());
+        }
+        messages
+    }
+
+    /// Build a finish output containing the answer and model name
+    fn finish(answer: String, model_name: String) -> AgentOutput {
+        let mut return_values = HashMap::new();
+        return_values.insert("answer".to_string(), answer);
+        return_values.insert("model".to_string(), model_name);
+        AgentOutput::Finish(AgentFinish { return_values })
+    }
+
+    /// Build the messages for the next model call, or return the output directly when no model call is needed
+    async fn prepare_plan(&self, intermediate_steps: &[AgentStep], inputs: &HashMap<String, String>, callbacks: &CallbackManager) -> PlanRequest {
+        let input_text = inputs
             .get("input")
             .cloned()
             .unwrap_or_default()
-            .to_string()
             .trim()
             .to_string();
 
-        // Capture tool descriptions in advance to avoid using self in async move
-        let tool_descriptions: String = if !self.tools.is_empty() {
-            let mut descriptions = String::new();
-            for tool in &self.tools {
-                descriptions.push_str(&format!("- {}: {}\n", tool.name(), tool.description()));
-            }
-            descriptions
-        } else {
-            String::new()
+        // Check if input is empty, prompt messages can carry the task on their own
+        if input_text.is_empty() && self.prompt_messages.is_empty() {
+            return PlanRequest::Finish(Self::finish("Please enter valid content".to_string(), self.configured_model_name()));
+        }
+
+        // Use the passed model instance
+        let model: Arc<dyn ChatModel> = match (&self.chat_model, &self.openai_model) {
+            (Some(model), _) => model.clone(),
+            (None, Some(model)) => Arc::new(model.clone()),
+            // If no model instance is provided, return an error
+            (None, None) => return PlanRequest::Finish(Self::finish("No OpenAI model provided".to_string(), "unknown".to_string())),
         };
+        // Model runs become children of the current agent run, handlers attached to the model are kept
+        let model = model.with_callbacks(callbacks).unwrap_or(model);
 
-        // Capture memory module in advance to avoid using self in async move
-        let memory_clone = self.memory.clone();
+        // Native tool calling replaces the JSON protocol described in the system prompt
+        let native_tool_calling = model.supports_tool_calling() && !self.tools.is_empty();
 
-        // Build enhanced system prompt using ReAct framework format
-        let enhanced_system_prompt = if !tool_descriptions.is_empty() {
-            format!("{}
-You are an AI assistant that follows the ReAct (Reasoning and Acting) framework. 
-You should think step by step and decide whether to use tools based on user needs.
-You should carefully review and when confirming the use of the tool, if there are omissions, errors, or other issues with the parameters, you should reply and remind the user.
-Available tools:\n{}\n\nWhen you need to use a tool, please respond in the following JSON format:
-            \n{{\"call_tool\": {{\"name\": \"Tool Name\", \"parameters\": {{\"parameter_name\": \"parameter_value\"}}}}}}
-        When you don't need to use a tool, please respond in the following JSON format:\n{{\"content\": \"Your answer\"}}
-        Please think carefully about whether the user's request requires a tool to be used, and only use tools when necessary.", 
-            system_prompt, tool_descriptions)
-        } else {
-            system_prompt
-        };
+        // Build message list
+        let mut messages = Vec::new();
 
-        // Capture OpenAI model instance in advance to avoid using self in async move
-        let openai_model_clone = self.openai_model.clone();
+        // If there is a memory module, load memory variables
+        let (history_messages, context_sections) = self.load_history_messages(&input_text).await;
 
-        Box::pin(async move {
-            // Check if input is empty
-            if input_text.is_empty() {
-                let mut return_values = std::collections::HashMap::new();
-                return_values.insert("answer".to_string(), "Please enter valid content".to_string());
-                // Get model name from OpenAI model, use default value if not available
-                let model_name = if let Some(ref openai_model) = openai_model_clone {
-                    openai_model.model_name().map(|s| s.to_string()).unwrap_or("unknown".to_string())
-                } else {
-                    "unknown".to_string()
-                };
-                return_values.insert("model".to_string(), model_name);
-                return Ok(AgentOutput::Finish(AgentFinish { return_values }));
+        // Add system message with summary content, known entities and relevant past messages appended
+        let mut system_prompt = self.append_summary(self.build_system_prompt(native_tool_calling)).await;
+        for section in context_sections {
+            system_prompt = format!("{}\n\n{}", system_prompt, section);
+        }
+        messages.push(ModelChatMessage::System(ChatMessageContent {
+            content: system_prompt,
+            name: None,
+            additional_kwargs: HashMap::new(),
+        }));
+
+        // Add the chat history to the message list
+        messages.extend(history_messages);
+
+        // Messages of the current turn, always sent
+        let mut turn_messages = Vec::new();
+
+        // Add the messages of the MCP prompt
+        turn_messages.extend(self.prompt_messages.iter().cloned());
+
+        // Add current user message
+        if !input_text.is_empty() {
+            turn_messages.push(ModelChatMessage::Human(ChatMessageContent {
+                content: input_text.clone(),
+                name: None,
+                additional_kwargs: HashMap::new(),
+            }));
+        }
+
+        // Add tool calls and observations from previous steps
+        turn_messages.extend(Self::step_messages(intermediate_steps, native_tool_calling));
+
+        // Drop the oldest chat history that does not fit the context window
+        let mut messages = self.fit_context_window(model.as_ref(), messages, &turn_messages, native_tool_calling);
+        messages.extend(turn_messages);
+
+        // Roles only, message contents may hold user data
+        let roles: Vec<&str> = messages.iter().map(|msg| match msg {
+            ModelChatMessage::System(_) => "system",
+            ModelChatMessage::Human(_) => "user",
+            ModelChatMessage::AIMessage(_) => "assistant",
+            ModelChatMessage::ToolMessage(_) => "tool",
+        }).collect();
+        log::debug!("Sending {} messages to model: {:?}", roles.len(), roles);
+
+        PlanRequest::Model {
+            model,
+            input_text,
+            messages,
+            native_tool_calling,
+        }
+    }
+
+    /// Parse the model response into the next action or the final answer
+    async fn handle_completion(&self, input_text: &str, result: Result<ChatCompletion, anyhow::Error>) -> AgentOutput {
+        let model_name = self.configured_model_name();
+        let completion = match result {
+            Ok(completion) => completion,
+            Err(e) => {
+                // Even if model call fails, save user message to memory
+                let answer = format!("Model invocation failed: {}", e);
+                self.save_to_memory(input_text, &answer).await;
+                return Self::finish(answer, model_name);
             }
+        };
 
-            // Use the passed OpenAI model instance or create a new instance
-            let model = if let Some(ref openai_model) = openai_model_clone {
-                // Use the passed OpenAI model instance
-                openai_model
+        // Parse model output
+        let (content, tool_calls) = match completion.message {
+            ModelChatMessage::AIMessage(content) => {
+                let tool_calls = content.tool_calls();
+                (content.content, tool_calls)
+            },
+            other => (format!("{},{:?}", "Non-AI message received", other), Vec::new()),
+        };
+
+        // Structured tool calls returned by the model take precedence, parallel calls are all executed
+        if !tool_calls.is_empty() {
+            let thought = if content.trim().is_empty() { None } else { Some(content.clone()) };
+            let mut actions: Vec<AgentAction> = tool_calls.into_iter().enumerate().map(|(index, tool_call)| AgentAction {
+                tool: tool_call.name,
+                tool_input: tool_call.arguments,
+                // The text of the response is kept with the first call only
+                log: if index == 0 { content.clone() } else { String::new() },
+                thought: if index == 0 { thought.clone() } else { None },
+                tool_call_id: Some(tool_call.id),
+            }).collect();
+            return if actions.len() == 1 {
+                AgentOutput::Action(actions.remove(0))
             } else {
-                // If no OpenAI model instance is provided, return an error
-                let mut return_values = std::collections::HashMap::new();
-                return_values.insert("answer".to_string(), "No OpenAI model provided".to_string());
-                return_values.insert("model".to_string(), "unknown".to_string());
-                return Ok(AgentOutput::Finish(AgentFinish { return_values }));
+                AgentOutput::Actions(actions)
             };
+        }
 
-            // Build message list
-            let mut messages = Vec::new();
-
-            // Get summary content and append to system prompt
-            let enhanced_system_prompt_with_summary = {
-                let mut enhanced_prompt = enhanced_system_prompt;
-                if let Some(memory) = &memory_clone {
-                    // Try to get summary content from memory module
-                    // Here we use downcast_ref to check if it's CompositeMemory type
-                    if let Some(composite_memory) = memory.as_any().downcast_ref::<crate::memory::composite_memory::CompositeMemory>() {
-                        // If it's CompositeMemory, call get_summary method to get summary
-                        match composite_memory.get_summary().await {
-                            Ok(Some(summary)) => {
-                                // Append summary content to system prompt
-                                enhanced_prompt = format!("{}\n\nPrevious conversation summary: {}", enhanced_prompt, summary);
-                                log::info!("Summary appended to system prompt");
-                            },
-                            Ok(None) => {
-                                log::info!("No summary content found");
-                            },
-                            Err(e) => {
-                                log::warn!("Error getting summary: {}", e);
-                            }
-                        }
-                    } else {
-                        // If not CompositeMemory, try to get summary from memory variables
-                        match memory.load_memory_variables(&std::collections::HashMap::new()).await {
-                            Ok(memories) => {
-                                if let Some(summary) = memories.get("summary") {
-                                    if let Some(summary_str) = summary.as_str() {
-                                        // Append summary content to system prompt
-                                        enhanced_prompt = format!("{}\n\nPrevious conversation summary: {}", enhanced_prompt, summary_str);
-                                        log::info!("Summary retrieved from memory variables and appended to system prompt");
-                                    }
-                                }
-                            },
-                            Err(e) => {
-                                log::warn!("Error getting summary from memory variables: {}", e);
-                            }
-                        }
-                    }
+        // Fall back to the JSON protocol for models without native tool calling
+        if let Ok(parsed_output) = parse_model_output(&content) {
+            if let AgentOutput::Action(action) = parsed_output {
+                return AgentOutput::Action(action);
+            }
+        } else if content.contains("call_tool") {
+            if let Ok(agent_action) = parse_tool_call_from_content(&content) {
+                return AgentOutput::Action(agent_action);
+            }
+        }
+
+        // Directly return the answer and save the finished turn to memory
+        self.save_to_memory(input_text, &content).await;
+        Self::finish(content, model_name)
+    }
+
+    /// Decide the next action based on the input and the intermediate steps
+    async fn plan_next(&self, intermediate_steps: &[AgentStep], inputs: &HashMap<String, String>, callbacks: &CallbackManager) -> Result<AgentOutput, anyhow::Error> {
+        let (model, input_text, messages, native_tool_calling) = match self.prepare_plan(intermediate_steps, inputs, callbacks).await {
+            PlanRequest::Finish(output) => return Ok(output),
+            PlanRequest::Model { model, input_text, messages, native_tool_calling } => (model, input_text, messages, native_tool_calling),
+        };
+
+        // Call the language model
+        let result = if native_tool_calling {
+            model.invoke_with_tools(messages, self.tool_definitions()).await
+        } else {
+            model.invoke(messages).await
+        };
+
+        Ok(self.handle_completion(&input_text, result).await)
+    }
+
+    /// Streaming version of plan_next, content tokens are sent to the sink
+    async fn stream_plan_next(
+        &self,
+        intermediate_steps: &[AgentStep],
+        inputs: &HashMap<String, String>,
+        callbacks: &CallbackManager,
+        sink: TokenSink<'_>,
+    ) -> Result<AgentOutput, anyhow::Error> {
+        let (model, input_text, messages, native_tool_calling) = match self.prepare_plan(intermediate_steps, inputs, callbacks).await {
+            PlanRequest::Finish(output) => {
+                if let (TokenSink::Answer(sender), AgentOutput::Finish(finish)) = (&sink, &output) {
+                    Self::send_answer_tokens(sender, vec![finish.answer().to_string()]).await?;
                 }
-                enhanced_prompt
-            };
+                return Ok(output);
+            },
+            PlanRequest::Model { model, input_text, messages, native_tool_calling } => (model, input_text, messages, native_tool_calling),
+        };
 
-            // Add system message
-            messages.push(ModelChatMessage::System(ChatMessageContent {
-                content: enhanced_system_prompt_with_summary,
-                name: None,
-                additional_kwargs: std::collections::HashMap::new(),
-            }));
+        // Call the language model in streaming mode
+        let stream = if native_tool_calling {
+            model.stream_with_tools(messages, self.tool_definitions()).await
+        } else {
+            model.stream(messages).await
+        };
 
-            // If there is a memory module, load memory variables and add them to the message list
-            if let Some(memory) = &memory_clone {
-                match memory.load_memory_variables(&std::collections::HashMap::new()).await {
-                    Ok(memories) => {
-                        info!("Loaded memory variables: {:?}", memories);
-                        if let Some(chat_history) = memories.get("chat_history") {
-                            if let serde_json::Value::Array(messages_array) = chat_history {
-                                for message in messages_array {
-                                    if let serde_json::Value::Object(msg_obj) = message {
-                                        let role = msg_obj.get("role").and_then(|v| v.as_str()).unwrap_or("unknown");
-                                        let content = msg_obj.get("content").and_then(|v| v.as_str()).unwrap_or("");
-                                        
-                                        // Skip empty content messages
-                                        if content.trim().is_empty() {
-                                            continue;
-                                        }
-                                        
-                                        // Skip assistant messages containing complete history messages
-                                        if role == "assistant" && content.contains("user:") && content.contains("assistant:") {
-                                            continue;
-                                        }
-                                        
-                                        // Add debug log
-                                        // info!("Loaded message: role={}, content={}", role, content);
-                                        
-                                        match role {
-                                            "human" | "user" => {
-                                                // Add debug log
-                                                log::info!("Loaded human message: content={}", content);
-                                                messages.push(ModelChatMessage::Human(ChatMessageContent {
-                                                    content: content.to_string(),
-                                                    name: None,
-                                                    additional_kwargs: std::collections::HashMap::new(),
-                                                }));
-                                            },
-                                            "ai" | "assistant" => {
-                                                // Add debug log
-                                                log::info!("Loaded AI message: content={}", content);
-                                                messages.push(ModelChatMessage::AIMessage(ChatMessageContent {
-                                                    content: content.to_string(),
-                                                    name: None,
-                                                    additional_kwargs: std::collections::HashMap::new(),
-                                                }));
-                                            },
-                                            "tool" => {
-                                                // Handle tool messages
-                                                let content_str = content.to_string();
-                                                // Add debug log
-                                                log::info!("Loaded tool message: content={}", content_str);
-                                                messages.push(ModelChatMessage::ToolMessage(ChatMessageContent {
-                                                    content: content_str,
-                                                    name: None,
-                                                    additional_kwargs: std::collections::HashMap::new(),
-                                                }));
-                                            },
-                                            _ => {
-                                                // Add debug log
-                                                log::info!("Loaded unknown role message: role={}, content={}", role, content);
-                                                // Ignore messages with unknown roles
-                                            }
+        let mut held_tokens = Vec::new();
+        let result = match stream {
+            Ok(mut stream) => {
+                let mut accumulator = ChatCompletionAccumulator::new();
+                let mut stream_error = None;
+                while let Some(chunk) = stream.next().await {
+                    match chunk {
+                        Ok(chunk) => {
+                            if !chunk.content.is_empty() {
+                                match sink {
+                                    TokenSink::Events(sender) => {
+                                        if sender.send(Ok(AgentStreamEvent::Token(chunk.content.clone()))).await.is_err() {
+                                            return Err(anyhow::anyhow!("Stream receiver has been dropped"));
                                         }
-                                    }
+                                    },
+                                    // Whether the response is the final answer is only known once it is complete
+                                    TokenSink::Answer(_) => held_tokens.push(chunk.content.clone()),
                                 }
                             }
+                            accumulator.push(&chunk);
+                        },
+                        Err(e) => {
+                            stream_error = Some(e);
+                            break;
                         }
-                    },
-                    Err(e) => {
-                        // If loading memory fails, log the error but continue execution
-                        log::warn!("Failed to load memory variables: {}", e);
                     }
                 }
-            }
-
-            // Add current user message
-            messages.push(ModelChatMessage::Human(ChatMessageContent {
-                content: input_text.clone(),
-                name: None,
-                additional_kwargs: std::collections::HashMap::new(),
-            }));
-            // info!("Added current user message: role=user, content={}", input_text);
-            
-            // Add debug log, showing all messages
-            log::info!("Messages to be sent to model:");
-            for (i, msg) in messages.iter().enumerate() {
-                match msg {
-                    ModelChatMessage::System(content) => {
-                        log::info!("  {}. role=system, content={}", i+1, content.content);
-                    },
-                    ModelChatMessage::Human(content) => {
-                        log::info!("  {}. role=user, content={}", i+1, content.content);
-                    },
-                    ModelChatMessage::AIMessage(content) => {
-                        log::info!("  {}. role=assistant, content={}", i+1, content.content);
-                    },
-                    ModelChatMessage::ToolMessage(content) => {
-                        log::info!("  {}. role=tool, content={}", i+1, content.content);
-                    },
+                match stream_error {
+                    Some(e) => Err(e),
+                    None => Ok(accumulator.finish()),
                 }
+            },
+            Err(e) => Err(e),
+        };
+
+        let completed = result.is_ok();
+        let output = self.handle_completion(&input_text, result).await;
+        if let (TokenSink::Answer(sender), AgentOutput::Finish(finish)) = (&sink, &output) {
+            // A failed model call finishes with the error message instead of the partial response
+            let tokens = if completed { held_tokens } else { vec![finish.answer().to_string()] };
+            Self::send_answer_tokens(sender, tokens).await?;
+        }
+        Ok(output)
+    }
+
+    // Send the tokens of a final answer to AgentExecutor::stream
+    async fn send_answer_tokens(sender: &mpsc::Sender<String>, tokens: Vec<String>) -> Result<(), anyhow::Error> {
+        for token in tokens.into_iter().filter(|token| !token.is_empty()) {
+            if sender.send(token).await.is_err() {
+                return Err(anyhow::anyhow!("Stream receiver has been dropped"));
             }
+        }
+        Ok(())
+    }
 
-            // Call the language model
-            let result = model.invoke(messages).await;
-
-            match result {
-                Ok(completion) => {
-                    // Parse model output
-                    let content = match completion.message {
-                        ModelChatMessage::AIMessage(content) => content.content,
-                        _ => { format!("{},{:?}", "Non-AI message received", completion.message) }
-                    };
-
-                    // Get model name from OpenAI model, use default value if not available
-                    let model_name = model.model_name().map(|s| s.to_string()).unwrap_or("unknown".to_string());
-
-                    // If there is a memory module, save the current conversation to memory
-                    if let Some(memory) = &memory_clone {
-                        let mut inputs = std::collections::HashMap::new();
-                        inputs.insert("input".to_string(), serde_json::Value::String(input_text.clone()));
-                        
-                        // Preprocess content, if it's JSON string format, extract the content field
-                        let processed_content = if content.starts_with('"') && content.ends_with('"') {
-                            // Try to parse as JSON string
-                            match serde_json::from_str::<serde_json::Value>(&content) {
-                                Ok(serde_json::Value::String(s)) => s,
-                                _ => content.clone(),
-                            }
-                        } else if content.starts_with('{') && content.ends_with('}') {
-                            // Try to parse as JSON object
-                            match serde_json::from_str::<serde_json::Value>(&content) {
-                                Ok(json_obj) => {
-                                    // If it's a JSON object, try to extract the content field
-                                    if let Some(content_value) = json_obj.get("content") {
-                                        if let Some(content_str) = content_value.as_str() {
-                                            content_str.to_string()
-                                        } else {
-                                            content.clone()
-                                        }
-                                    } else {
-                                        content.clone()
-                                    }
-                                },
-                                _ => content.clone(),
-                            }
-                        } else {
-                            content.clone()
-                        };
-                        
-                        let mut outputs = std::collections::HashMap::new();
-                        outputs.insert("output".to_string(), serde_json::Value::String(processed_content));
-                        
-                        if let Err(e) = memory.save_context(&inputs, &outputs).await {
-                            log::warn!("Failed to save context to memory: {}", e);
-                        }
-                    }
+    /// Stream one planning step, emitting content tokens followed by the resulting output
+    pub fn stream_plan(&self, intermediate_steps: Vec<AgentStep>, inputs: HashMap<String, String>) -> AgentEventStream {
+        let agent = self.clone();
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let output = agent.stream_plan_next(&intermediate_steps, &inputs, &agent.callback_manager, TokenSink::Events(&sender)).await;
+            let _ = sender.send(output.map(AgentStreamEvent::Output)).await;
+        });
+        Box::pin(ReceiverStream::new(receiver))
+    }
 
-                    // Parse model output, determine if tool call is needed
-                    // Here should correctly parse the JSON format of model output
-                    if let Ok(parsed_output) = parse_model_output(&content) {
-                        match parsed_output {
-                            AgentOutput::Action(action) => {
-                                // Directly return the Action parsed by the model
-                                return Ok(AgentOutput::Action(action));
-                            }
-                            AgentOutput::Finish(_) => {
-                                // Directly return the answer
-                                let mut return_values = std::collections::HashMap::new();
-                                return_values.insert("answer".to_string(), content.clone());
-                                return_values.insert("model".to_string(), model_name);
-                                return Ok(AgentOutput::Finish(AgentFinish { return_values }));
-                            }
-                        }
-                    } else {
-                        // If parsing fails, try to extract tool call information
-                        // Check if tool call keywords are included
-                        if content.contains("call_tool") {
-                            // Try to extract JSON format tool call from content
-                            // Here should more intelligently parse tool calls instead of using default tools
-                            if let Ok(agent_action) = parse_tool_call_from_content(&content) {
-                                Ok(AgentOutput::Action(agent_action))
-                            } else {
-                                // If unable to parse tool call, directly return the answer
-                                let mut return_values = std::collections::HashMap::new();
-                                return_values.insert("answer".to_string(), content.clone());
-                                return_values.insert("model".to_string(), model_name);
-                                Ok(AgentOutput::Finish(AgentFinish { return_values }))
-                            }
-                        } else {
-                            // Directly return the answer
-                            let mut return_values = std::collections::HashMap::new();
-                            return_values.insert("answer".to_string(), content.clone());
-                            return_values.insert("model".to_string(), model_name);
-                            Ok(AgentOutput::Finish(AgentFinish { return_values }))
-                        }
-                    }
-                }
+    /// Stream the tokens of the final answer for the input
+    /// Tools are run by an AgentExecutor with its default limits, see AgentExecutor::stream.
+    pub fn stream_tokens(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn Stream<Item = Result<String, anyhow::Error>> + Send>> {
+        AgentExecutor::new(self.clone()).stream(inputs)
+    }
+}
+
+// Event emitted while streaming an agent step
+#[derive(Clone, Debug)]
+pub enum AgentStreamEvent {
+    // Content token produced by the model
+    Token(String),
+    // Final output of the step, always the last event
+    Output(AgentOutput),
+}
+
+// Stream of agent events
+pub type AgentEventStream = Pin<Box<dyn Stream<Item = Result<AgentStreamEvent, anyhow::Error>> + Send>>;
+
+// Receiver of the content tokens of a streamed planning step
+#[derive(Clone, Copy)]
+enum TokenSink<'a> {
+    // Every token, as it arrives
+    Events(&'a mpsc::Sender<Result<AgentStreamEvent, anyhow::Error>>),
+    // The tokens of a final answer only
+    Answer(&'a mpsc::Sender<String>),
+}
+
+// Result of preparing a planning step
+enum PlanRequest {
+    Finish(AgentOutput),
+    Model {
+        model: Arc<dyn ChatModel>,
+        input_text: String,
+        messages: Vec<ModelChatMessage>,
+        native_tool_calling: bool,
+    },
+}
+
+// Tool of the agent handed out by Agent::tools, invoking the shared tool
+struct SharedTool(Arc<dyn Tool + Send + Sync>);
+
+impl Tool for SharedTool {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn description(&self) -> &str {
+        self.0.description()
+    }
+
+    fn input_schema(&self) -> Value {
+        self.0.input_schema()
+    }
+
+    fn invoke(&self, input: &str) -> Pin<Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>> {
+        self.0.invoke(input)
+    }
+
+    fn invoke_with_callbacks<'a>(&'a self, input: &'a str, callbacks: &'a CallbackManager) -> Pin<Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + 'a>> {
+        self.0.invoke_with_callbacks(input, callbacks)
+    }
+
+    // Downcasts see the shared tool, e.g. McpToolAdapter
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.0.as_any()
+    }
+}
+
+impl Agent for McpAgent {
+    fn tools(&self) -> Vec<Box<dyn Tool + Send + Sync>> {
+        // MCP and local tools are shared with the returned handles
+        self.tools.iter()
+            .map(|tool| Box::new(SharedTool(tool.clone())) as Box<dyn Tool + Send + Sync>)
+            .collect()
+    }
+
+    fn execute(
+        &self,
+        action: &AgentAction,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>,
+    > {
+        let tool_name = action.tool.clone();
+        let tool_input = action.tool_input.clone();
+        Box::pin(async move {
+            self.find_tool(&tool_name)?.invoke(&tool_input).await
+        })
+    }
+
+    // Tools report their own run, MCP tools add the handlers attached to the adapter
+    fn execute_with_callbacks<'a>(
+        &'a self,
+        action: &'a AgentAction,
+        callbacks: &'a CallbackManager,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + 'a>,
+    > {
+        Box::pin(async move {
+            match self.find_tool(&action.tool) {
+                Ok(tool) => tool.invoke_with_callbacks(&action.tool_input, callbacks).await,
                 Err(e) => {
-                    // Return error message when an error occurs
-                    // Get model name from OpenAI model, use default value if not available
-                    let model_name = if let Some(ref model) = openai_model_clone {
-                        model.model_name().map(|s| s.to_string()).unwrap_or("unknown".to_string())
-                    } else {
-                        "unknown".to_string()
-                    };
-                    
-                    // Even if model call fails, save user message to memory
-                    if let Some(memory) = &memory_clone {
-                        let mut inputs = std::collections::HashMap::new();
-                        inputs.insert("input".to_string(), serde_json::Value::String(input_text.clone()));
-                        
-                        let mut outputs = std::collections::HashMap::new();
-                        outputs.insert("output".to_string(), serde_json::Value::String(format!("Model invocation failed: {}", e)));
-                        
-                        if let Err(e) = memory.save_context(&inputs, &outputs).await {
-                            log::warn!("Failed to save context to memory: {}", e);
-                        }
-                    }
-                    
-                    let mut return_values = std::collections::HashMap::new();
-                    return_values.insert("answer".to_string(), format!("Model invocation failed: {}", e));
-                    return_values.insert("model".to_string(), model_name);
-                    Ok(AgentOutput::Finish(AgentFinish { return_values }))
-                }
+                    callbacks.on_tool_start(&action.tool, &action.tool_input).on_tool_error(&action.tool, &e.to_string());
+                    Err(e)
+                },
             }
         })
     }
 
+    fn plan<'a>(
+        &'a self,
+        intermediate_steps: &'a [AgentStep],
+        inputs: &'a HashMap<String, String>,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<AgentOutput, anyhow::Error>> + Send + 'a>,
+    > {
+        Box::pin(self.plan_next(intermediate_steps, inputs, &self.callback_manager))
+    }
+
+    fn plan_with_callbacks<'a>(
+        &'a self,
+        intermediate_steps: &'a [AgentStep],
+        inputs: &'a HashMap<String, String>,
+        callbacks: &'a CallbackManager,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<AgentOutput, anyhow::Error>> + Send + 'a>,
+    > {
+        Box::pin(self.plan_next(intermediate_steps, inputs, callbacks))
+    }
+
+    fn stream_plan_with_callbacks<'a>(
+        &'a self,
+        intermediate_steps: &'a [AgentStep],
+        inputs: &'a HashMap<String, String>,
+        callbacks: &'a CallbackManager,
+        tokens: &'a mpsc::Sender<String>,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<AgentOutput, anyhow::Error>> + Send + 'a>,
+    > {
+        Box::pin(self.stream_plan_next(intermediate_steps, inputs, callbacks, TokenSink::Answer(tokens)))
+    }
+
+    fn save_answer<'a>(
+        &'a self,
+        inputs: &'a HashMap<String, String>,
+        answer: &'a str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            let input_text = inputs.get("input").map(|input| input.trim()).unwrap_or_default();
+            self.save_to_memory(input_text, answer).await;
+        })
+    }
+
+    fn callback_manager(&self) -> CallbackManager {
+        self.callback_manager.clone()
+    }
+
+    fn clone_agent(&self) -> Box<dyn Agent> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for McpAgent {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            tools: self.tools.clone(), // Tools are shared through Arc
+            system_prompt: self.system_prompt.clone(),
+            openai_model: self.openai_model.clone(), // Clone OpenAI model instance
+            chat_model: self.chat_model.clone(),
+            memory: self.memory.clone(), // Clone memory module
+            callback_manager: self.callback_manager.clone(),
+            react_prompt: self.react_prompt.clone(),
+            react_json_prompt: self.react_json_prompt.clone(),
+            prompt_messages: self.prompt_messages.clone(),
+            model_registry: self.model_registry.clone(),
+            tokenizer: self.tokenizer.clone(),
+        }
+    }
+}
+
+impl Runnable<HashMap<String, String>, AgentOutput> for McpAgent {
+    fn invoke(
+        &self,
+        input: HashMap<String, String>,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<AgentOutput, anyhow::Error>> + Send>> {
+        // Clone the agent in advance to avoid using self in async move
+        let agent = self.clone();
+        Box::pin(async move {
+            agent.plan_next(&[], &input, &agent.callback_manager).await
+        })
+    }
+
     fn clone_to_owned(
         &self,
-    ) -> Box<dyn Runnable<std::collections::HashMap<String, String>, AgentOutput> + Send + Sync>
+    ) -> Box<dyn Runnable<HashMap<String, String>, AgentOutput> + Send + Sync>
     {
         Box::new(self.clone())
     }
@@ -537,7 +1033,7 @@ fn extract_json_object(content: &str) -> Option<String> {
             if end > start {
                 // Extract possible JSON object
                 let json_str = &content[start..=end];
-                
+
                 // Verify if it's a valid JSON object
                 if let Ok(value) = serde_json::from_str::<serde_json::Value>(json_str) {
                     if value.is_object() {
@@ -556,7 +1052,7 @@ fn parse_tool_call_from_content(content: &str) -> Result<AgentAction, anyhow::Er
     if let Some(json_str) = extract_json_object(content) {
         // Parse JSON
         let value: Value = serde_json::from_str(&json_str)?;
-        
+
         // Check if there's a call_tool field
         if let Some(call_tool) = value.get("call_tool").and_then(|v| v.as_object()) {
             // Extract tool name
@@ -565,26 +1061,240 @@ fn parse_tool_call_from_content(content: &str) -> Result<AgentAction, anyhow::Er
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| anyhow::anyhow!("Missing tool name"))?
                 .to_string();
-            
+
             // Extract parameters and convert to string
             let tool_input = call_tool
                 .get("parameters")
                 .cloned()
                 .unwrap_or(Value::Object(serde_json::Map::new()))
                 .to_string();
-            
+
             // Create AgentAction
             let action = AgentAction {
                 tool: tool_name,
                 tool_input,
                 log: content.to_string(),
                 thought: None,
+                tool_call_id: None,
             };
-            
+
             return Ok(action);
         }
     }
-    
+
     // If unable to parse, return error
     Err(anyhow::anyhow!("Failed to parse tool call from content"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use serde_json::json;
+    use crate::callbacks::{CallbackHandler, RunInfo};
+    use crate::{AgentExecutor, DeterministicFakeEmbedding, ExampleTool, InMemoryVectorStore, McpTool, SimpleMcpClient, VectorStoreRetrieverMemory};
+
+    // Model answering with scripted messages and recording the prompts
+    struct ScriptedModel {
+        responses: Mutex<Vec<ModelChatMessage>>,
+        prompts: Mutex<Vec<Vec<ModelChatMessage>>>,
+        tool_calling: bool,
+    }
+
+    impl ScriptedModel {
+        fn new(responses: Vec<ModelChatMessage>) -> Arc<Self> {
+            Arc::new(Self { responses: Mutex::new(responses), prompts: Mutex::new(Vec::new()), tool_calling: false })
+        }
+
+        fn with_tool_calling(responses: Vec<ModelChatMessage>) -> Arc<Self> {
+            Arc::new(Self { responses: Mutex::new(responses), prompts: Mutex::new(Vec::new()), tool_calling: true })
+        }
+    }
+
+    impl ChatModel for ScriptedModel {
+        fn supports_tool_calling(&self) -> bool {
+            self.tool_calling
+        }
+
+        fn invoke(&self, messages: Vec<ModelChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, anyhow::Error>> + Send + '_>> {
+            Box::pin(async move {
+                self.prompts.lock().unwrap().push(messages);
+                Ok(ChatCompletion {
+                    message: self.responses.lock().unwrap().remove(0),
+                    usage: None,
+                    model_name: "scripted".to_string(),
+                })
+            })
+        }
+    }
+
+    fn ai_message(content: &str) -> ModelChatMessage {
+        ModelChatMessage::AIMessage(ChatMessageContent {
+            content: content.to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })
+    }
+
+    fn tool_calls_message(calls: &[(&str, &str, &str)]) -> ModelChatMessage {
+        let calls = calls.iter().map(|(id, name, arguments)| ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }.to_openai_value()).collect();
+        ModelChatMessage::AIMessage(ChatMessageContent {
+            content: String::new(),
+            name: None,
+            additional_kwargs: HashMap::from([("tool_calls".to_string(), Value::Array(calls))]),
+        })
+    }
+
+    fn inputs(input: &str) -> HashMap<String, String> {
+        HashMap::from([("input".to_string(), input.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_context_sections_use_configured_memory_keys() {
+        let store = Arc::new(InMemoryVectorStore::new(Arc::new(DeterministicFakeEmbedding::new(64))));
+        let memory = VectorStoreRetrieverMemory::new(store).with_memory_key("past_messages".to_string());
+        let model = ScriptedModel::new(vec![ai_message("Noted, green it is"), ai_message("Green")]);
+        let agent = McpAgent::with_memory(Arc::new(SimpleMcpClient::new(String::new())), "You are helpful".to_string(), Box::new(memory))
+            .with_chat_model(model.clone());
+        let executor = AgentExecutor::new(agent);
+
+        executor.run(&inputs("my favourite colour is green")).await.unwrap();
+        executor.run(&inputs("what is my favourite colour?")).await.unwrap();
+
+        let prompts = model.prompts.lock().unwrap();
+        let ModelChatMessage::System(system) = &prompts[1][0] else { panic!("expected a system message") };
+        assert!(system.content.contains("Relevant messages from past conversations:\n"), "{}", system.content);
+        assert!(system.content.contains("my favourite colour is green"), "{}", system.content);
+    }
+
+    #[tokio::test]
+    async fn test_forced_stop_answer_is_saved_to_memory() {
+        let memory = crate::SimpleMemory::new();
+        let model = ScriptedModel::new(vec![ai_message(r#"{"call_tool": {"name": "get_weather", "parameters": {"city": "Paris"}}}"#)]);
+        let agent = McpAgent::with_memory(Arc::new(SimpleMcpClient::new(String::new())), "You are helpful".to_string(), Box::new(memory.clone()))
+            .with_chat_model(model);
+        let executor = AgentExecutor::new(agent).with_max_iterations(Some(1));
+
+        let output = executor.run(&inputs("weather in Paris?")).await.unwrap();
+        assert_eq!(output.stop_reason, crate::AgentStopReason::MaxIterations);
+        let variables = memory.load_memory_variables(&HashMap::new()).await.unwrap();
+        let history = variables["chat_history"].as_array().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["content"], "weather in Paris?");
+        assert_eq!(history[1]["content"], output.answer());
+    }
+
+    // Records the name of every tool callback
+    #[derive(Default)]
+    struct ToolEventRecorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl CallbackHandler for ToolEventRecorder {
+        fn on_tool_start(&self, _run: &RunInfo, tool_name: &str, _input: &str) {
+            self.events.lock().unwrap().push(format!("start {}", tool_name));
+        }
+
+        fn on_tool_end(&self, _run: &RunInfo, tool_name: &str, _output: &str) {
+            self.events.lock().unwrap().push(format!("end {}", tool_name));
+        }
+
+        fn on_tool_error(&self, _run: &RunInfo, tool_name: &str, _error: &str) {
+            self.events.lock().unwrap().push(format!("error {}", tool_name));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_and_mcp_tools_fire_callbacks_once() {
+        let mut client = SimpleMcpClient::new(String::new());
+        client.register_tool_handler("get_price".to_string(), |params| async move {
+            Ok(json!({"token": params["token"], "price": 2}))
+        });
+        let client: Arc<dyn McpClient> = Arc::new(client);
+        let recorder = Arc::new(ToolEventRecorder::default());
+        let tool_recorder = Arc::new(ToolEventRecorder::default());
+        let model = ScriptedModel::new(vec![
+            ai_message(r#"{"call_tool": {"name": "get_weather", "parameters": {"city": "Paris"}}}"#),
+            ai_message(r#"{"call_tool": {"name": "get_price", "parameters": {"token": "DOT"}}}"#),
+            ai_message(r#"{"content": "Sunny, DOT is at 2"}"#),
+        ]);
+        let mut agent = McpAgent::new(client.clone(), "You are helpful".to_string())
+            .with_chat_model(model)
+            .with_callback_manager(CallbackManager::new().with_handler(recorder.clone()));
+        agent.add_tool(Box::new(ExampleTool::new("get_weather".to_string(), "Get the weather".to_string())));
+        let adapter = McpToolAdapter::new(client, McpTool::new("get_price".to_string(), "Get a token price".to_string()))
+            .with_callback_manager(CallbackManager::new().with_handler(tool_recorder.clone()));
+        agent.add_tool(Box::new(adapter));
+        assert_eq!(agent.tools().len(), 2);
+
+        let output = AgentExecutor::new(agent.clone()).run(&inputs("weather in Paris and the DOT price?")).await.unwrap();
+        assert_eq!(output.intermediate_steps[0].observation, r#"Tool get_weather received input: {"city":"Paris"}"#);
+        assert!(output.intermediate_steps[1].observation.contains("\"price\": 2"), "{}", output.intermediate_steps[1].observation);
+        assert_eq!(*recorder.events.lock().unwrap(), vec!["start get_weather", "end get_weather", "start get_price", "end get_price"]);
+        assert_eq!(*tool_recorder.events.lock().unwrap(), vec!["start get_price", "end get_price"]);
+
+        // Tools called directly report to their own callback manager
+        agent.tools()[1].invoke(r#"{"token": "DOT"}"#).await.unwrap();
+        assert_eq!(recorder.events.lock().unwrap().len(), 4);
+        assert_eq!(tool_recorder.events.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_calls_each_get_an_observation() {
+        let model = ScriptedModel::with_tool_calling(vec![
+            tool_calls_message(&[("call_paris", "get_weather", r#"{"city":"Paris"}"#), ("call_rome", "get_weather", r#"{"city":"Rome"}"#)]),
+            ai_message("Paris and Rome are sunny"),
+        ]);
+        let mut agent = McpAgent::new(Arc::new(SimpleMcpClient::new(String::new())), "You are helpful".to_string())
+            .with_chat_model(model.clone());
+        agent.add_tool(Box::new(ExampleTool::new("get_weather".to_string(), "Get the weather".to_string())));
+
+        let output = AgentExecutor::new(agent).run(&inputs("weather in Paris and Rome?")).await.unwrap();
+        assert_eq!(output.answer(), "Paris and Rome are sunny");
+        assert_eq!(output.intermediate_steps.len(), 2);
+
+        // Every tool call id gets its own tool message
+        let prompts = model.prompts.lock().unwrap();
+        let tool_results: Vec<(String, String)> = prompts[1].iter().filter_map(|message| match message {
+            ModelChatMessage::ToolMessage(content) => Some((content.additional_kwargs["tool_call_id"].as_str().unwrap().to_string(), content.content.clone())),
+            _ => None,
+        }).collect();
+        assert_eq!(tool_results, vec![
+            ("call_paris".to_string(), r#"Tool get_weather received input: {"city":"Paris"}"#.to_string()),
+            ("call_rome".to_string(), r#"Tool get_weather received input: {"city":"Rome"}"#.to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_run_runs_every_tool_step() {
+        let mut first_step = tool_calls_message(&[("call_weather", "get_weather", r#"{"city":"Paris"}"#)]);
+        if let ModelChatMessage::AIMessage(content) = &mut first_step {
+            content.content = "Let me check the weather first".to_string();
+        }
+        let model = ScriptedModel::with_tool_calling(vec![
+            first_step,
+            tool_calls_message(&[("call_price", "get_price", r#"{"token":"DOT"}"#)]),
+            ai_message("Sunny in Paris, DOT is at 2"),
+        ]);
+        let mut agent = McpAgent::new(Arc::new(SimpleMcpClient::new(String::new())), "You are helpful".to_string())
+            .with_chat_model(model.clone());
+        agent.add_tool(Box::new(ExampleTool::new("get_weather".to_string(), "Get the weather".to_string())));
+        agent.add_tool(Box::new(ExampleTool::new("get_price".to_string(), "Get a token price".to_string())));
+
+        let tokens: Vec<String> = agent.stream_tokens(inputs("weather in Paris and the DOT price?"))
+            .map(|token| token.unwrap())
+            .collect()
+            .await;
+
+        // Only the final answer is streamed, after both tool results were sent to the model
+        assert_eq!(tokens, vec!["Sunny in Paris, DOT is at 2"]);
+        let prompts = model.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 3);
+        let tool_messages = prompts[2].iter().filter(|message| matches!(message, ModelChatMessage::ToolMessage(_))).count();
+        assert_eq!(tool_messages, 2);
+    }
+}
@@ -1,7 +1,9 @@
 // Agent module definition
 mod agent;
+mod executor;
 mod mcp_agent;
 
 // Re-export module content
-pub use agent::{Agent, AgentAction, AgentFinish, AgentOutput, AgentRunner, SimpleAgent, SimpleAgentRunner};
-pub use mcp_agent::McpAgent;
\ No newline at end of file
+pub use agent::{Agent, AgentAction, AgentFinish, AgentOutput, AgentRunner, AgentStep, SimpleAgent, SimpleAgentRunner};
+pub use executor::{AgentExecutor, AgentExecutorOutput, AgentStopReason, EarlyStoppingMethod};
+pub use mcp_agent::{AgentEventStream, AgentStreamEvent, McpAgent, DEFAULT_REACT_JSON_PROMPT, DEFAULT_REACT_PROMPT};
\ No newline at end of file
@@ -1,33 +1,66 @@
 // Callback handler interface definition
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 use crate::agents::{AgentAction, AgentFinish};
+use crate::models::TokenUsage;
+
+// Information about the run an event belongs to
+#[derive(Clone, Debug)]
+pub struct RunInfo {
+    pub run_id: Uuid,
+    // Run that started this run, None for top-level runs
+    pub parent_run_id: Option<Uuid>,
+    // Name of the model, tool or chain being run
+    pub name: String,
+    pub start_time: DateTime<Utc>,
+    started: Instant,
+}
+
+impl RunInfo {
+    pub fn new(name: String, parent_run_id: Option<Uuid>) -> Self {
+        Self {
+            run_id: Uuid::new_v4(),
+            parent_run_id,
+            name,
+            start_time: Utc::now(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Time elapsed since the run started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
 
 // Minimal callback system (aligned with langchain-core)
 pub trait CallbackHandler: Send + Sync {
     // LLM related callbacks (core)
-    fn on_llm_start(&self, _model_name: &str, _prompts: &[String]) {}
-    
-    fn on_llm_new_token(&self, _token: &str) {}
-    
-    fn on_llm_end(&self, _model_name: &str) {}
-    
-    fn on_llm_error(&self, _model_name: &str, _error: &str) {}
-    
+    fn on_llm_start(&self, _run: &RunInfo, _model_name: &str, _prompts: &[String]) {}
+
+    fn on_llm_new_token(&self, _run: &RunInfo, _token: &str) {}
+
+    fn on_llm_end(&self, _run: &RunInfo, _model_name: &str, _usage: Option<&TokenUsage>) {}
+
+    fn on_llm_error(&self, _run: &RunInfo, _model_name: &str, _error: &str) {}
+
     // Tool related callbacks (core)
-    fn on_tool_start(&self, _tool_name: &str, _input: &str) {}
-    
-    fn on_tool_end(&self, _tool_name: &str, _output: &str) {}
-    
-    fn on_tool_error(&self, _tool_name: &str, _error: &str) {}
-    
+    fn on_tool_start(&self, _run: &RunInfo, _tool_name: &str, _input: &str) {}
+
+    fn on_tool_end(&self, _run: &RunInfo, _tool_name: &str, _output: &str) {}
+
+    fn on_tool_error(&self, _run: &RunInfo, _tool_name: &str, _error: &str) {}
+
     // Chain related callbacks (core)
-    fn on_chain_start(&self, _chain_name: &str) {}
-    
-    fn on_chain_end(&self, _chain_name: &str) {}
-    
-    fn on_chain_error(&self, _chain_name: &str, _error: &str) {}
-    
+    fn on_chain_start(&self, _run: &RunInfo, _chain_name: &str) {}
+
+    fn on_chain_end(&self, _run: &RunInfo, _chain_name: &str) {}
+
+    fn on_chain_error(&self, _run: &RunInfo, _chain_name: &str, _error: &str) {}
+
     // Agent related callbacks
-    fn on_agent_action(&self, _action: &AgentAction) {}
-    
-    fn on_agent_finish(&self, _finish: &AgentFinish) {}
-}
\ No newline at end of file
+    fn on_agent_action(&self, _run: &RunInfo, _action: &AgentAction) {}
+
+    fn on_agent_finish(&self, _run: &RunInfo, _finish: &AgentFinish) {}
+}
@@ -0,0 +1,158 @@
+// Callback handler appending lifecycle events to a JSONL file
+use anyhow::Error;
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::agents::{AgentAction, AgentFinish};
+use crate::callbacks::{CallbackHandler, RunInfo};
+use crate::models::TokenUsage;
+
+// Writes one JSON object per event, suitable for tracing and offline analysis
+pub struct JsonlCallbackHandler {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl JsonlCallbackHandler {
+    /// Open (or create) the file in append mode
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Write an event line, end and error events also record the elapsed time
+    fn write_event(&self, event: &str, run: &RunInfo, finished: bool, data: Value) {
+        let mut record = json!({
+            "event": event,
+            "run_id": run.run_id.to_string(),
+            "parent_run_id": run.parent_run_id.map(|id| id.to_string()),
+            "name": run.name,
+            "timestamp": Utc::now().to_rfc3339(),
+        });
+        if finished {
+            record["elapsed_ms"] = json!(run.elapsed().as_millis() as u64);
+        }
+        if let (Value::Object(record), Value::Object(data)) = (&mut record, data) {
+            record.extend(data);
+        }
+
+        // Callbacks must not fail the run, write errors are only logged
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(e) = writeln!(file, "{}", record) {
+            log::warn!("Failed to write callback event to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl CallbackHandler for JsonlCallbackHandler {
+    fn on_llm_start(&self, run: &RunInfo, model_name: &str, prompts: &[String]) {
+        self.write_event("llm_start", run, false, json!({"model_name": model_name, "prompts": prompts}));
+    }
+
+    fn on_llm_new_token(&self, run: &RunInfo, token: &str) {
+        self.write_event("llm_new_token", run, false, json!({"token": token}));
+    }
+
+    fn on_llm_end(&self, run: &RunInfo, model_name: &str, usage: Option<&TokenUsage>) {
+        let usage = usage.map(|usage| json!({
+            "prompt_tokens": usage.prompt_tokens,
+            "completion_tokens": usage.completion_tokens,
+            "total_tokens": usage.total_tokens,
+        }));
+        self.write_event("llm_end", run, true, json!({"model_name": model_name, "usage": usage}));
+    }
+
+    fn on_llm_error(&self, run: &RunInfo, model_name: &str, error: &str) {
+        self.write_event("llm_error", run, true, json!({"model_name": model_name, "error": error}));
+    }
+
+    fn on_tool_start(&self, run: &RunInfo, tool_name: &str, input: &str) {
+        self.write_event("tool_start", run, false, json!({"tool_name": tool_name, "input": input}));
+    }
+
+    fn on_tool_end(&self, run: &RunInfo, tool_name: &str, output: &str) {
+        self.write_event("tool_end", run, true, json!({"tool_name": tool_name, "output": output}));
+    }
+
+    fn on_tool_error(&self, run: &RunInfo, tool_name: &str, error: &str) {
+        self.write_event("tool_error", run, true, json!({"tool_name": tool_name, "error": error}));
+    }
+
+    fn on_chain_start(&self, run: &RunInfo, chain_name: &str) {
+        self.write_event("chain_start", run, false, json!({"chain_name": chain_name}));
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, chain_name: &str) {
+        self.write_event("chain_end", run, true, json!({"chain_name": chain_name}));
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, chain_name: &str, error: &str) {
+        self.write_event("chain_error", run, true, json!({"chain_name": chain_name, "error": error}));
+    }
+
+    fn on_agent_action(&self, run: &RunInfo, action: &AgentAction) {
+        self.write_event("agent_action", run, false, json!({
+            "tool": action.tool,
+            "tool_input": action.tool_input,
+            "tool_call_id": action.tool_call_id,
+            "log": action.log,
+        }));
+    }
+
+    fn on_agent_finish(&self, run: &RunInfo, finish: &AgentFinish) {
+        self.write_event("agent_finish", run, false, json!({"return_values": finish.return_values}));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callbacks::CallbackManager;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_jsonl_handler_records_run_hierarchy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traces").join("events.jsonl");
+        let handler = Arc::new(JsonlCallbackHandler::new(&path).unwrap());
+        let manager = CallbackManager::new().with_handler(handler);
+
+        let chain_run = manager.on_chain_start("AgentExecutor");
+        let tool_run = chain_run.get_child().on_tool_start("get_weather", "{\"city\":\"Paris\"}");
+        tool_run.on_tool_error("get_weather", "timeout");
+        chain_run.on_chain_end("AgentExecutor");
+
+        let events: Vec<Value> = std::fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<&str> = events.iter().map(|e| e["event"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["chain_start", "tool_start", "tool_error", "chain_end"]);
+
+        let chain_id = chain_run.run_id().to_string();
+        assert_eq!(events[0]["parent_run_id"], Value::Null);
+        assert_eq!(events[1]["parent_run_id"], chain_id);
+        assert_eq!(events[2]["error"], "timeout");
+        assert!(events[2]["elapsed_ms"].is_u64());
+        assert_eq!(events[3]["run_id"], chain_id);
+    }
+}
@@ -0,0 +1,71 @@
+// Callback handler writing lifecycle events to the log
+use log::{debug, error, info};
+use crate::agents::{AgentAction, AgentFinish};
+use crate::callbacks::{CallbackHandler, RunInfo};
+use crate::models::TokenUsage;
+
+// Logs every event through the log crate, tokens are logged at debug level
+#[derive(Clone, Debug, Default)]
+pub struct LoggingCallbackHandler;
+
+impl LoggingCallbackHandler {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CallbackHandler for LoggingCallbackHandler {
+    fn on_llm_start(&self, run: &RunInfo, model_name: &str, prompts: &[String]) {
+        info!("[llm:{}] start model={} prompts={}", run.run_id, model_name, prompts.len());
+    }
+
+    fn on_llm_new_token(&self, run: &RunInfo, token: &str) {
+        debug!("[llm:{}] token={:?}", run.run_id, token);
+    }
+
+    fn on_llm_end(&self, run: &RunInfo, model_name: &str, usage: Option<&TokenUsage>) {
+        match usage {
+            Some(usage) => info!(
+                "[llm:{}] end model={} elapsed={:?} tokens={}",
+                run.run_id, model_name, run.elapsed(), usage.total_tokens
+            ),
+            None => info!("[llm:{}] end model={} elapsed={:?}", run.run_id, model_name, run.elapsed()),
+        }
+    }
+
+    fn on_llm_error(&self, run: &RunInfo, model_name: &str, err: &str) {
+        error!("[llm:{}] error model={} elapsed={:?}: {}", run.run_id, model_name, run.elapsed(), err);
+    }
+
+    fn on_tool_start(&self, run: &RunInfo, tool_name: &str, input: &str) {
+        info!("[tool:{}] start tool={} input={}", run.run_id, tool_name, input);
+    }
+
+    fn on_tool_end(&self, run: &RunInfo, tool_name: &str, output: &str) {
+        info!("[tool:{}] end tool={} elapsed={:?} output={}", run.run_id, tool_name, run.elapsed(), output);
+    }
+
+    fn on_tool_error(&self, run: &RunInfo, tool_name: &str, err: &str) {
+        error!("[tool:{}] error tool={} elapsed={:?}: {}", run.run_id, tool_name, run.elapsed(), err);
+    }
+
+    fn on_chain_start(&self, run: &RunInfo, chain_name: &str) {
+        info!("[chain:{}] start chain={}", run.run_id, chain_name);
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, chain_name: &str) {
+        info!("[chain:{}] end chain={} elapsed={:?}", run.run_id, chain_name, run.elapsed());
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, chain_name: &str, err: &str) {
+        error!("[chain:{}] error chain={} elapsed={:?}: {}", run.run_id, chain_name, run.elapsed(), err);
+    }
+
+    fn on_agent_action(&self, run: &RunInfo, action: &AgentAction) {
+        info!("[chain:{}] agent action tool={} input={}", run.run_id, action.tool, action.tool_input);
+    }
+
+    fn on_agent_finish(&self, run: &RunInfo, finish: &AgentFinish) {
+        info!("[chain:{}] agent finish return_values={:?}", run.run_id, finish.return_values);
+    }
+}
@@ -0,0 +1,168 @@
+// Callback manager - dispatches lifecycle events to multiple handlers and tracks run hierarchy
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::agents::{AgentAction, AgentFinish};
+use crate::callbacks::{CallbackHandler, RunInfo};
+use crate::models::TokenUsage;
+
+// Group of handlers that can be attached to agents, models and tools (aligned with langchain CallbackManager)
+#[derive(Clone, Default)]
+pub struct CallbackManager {
+    handlers: Vec<Arc<dyn CallbackHandler>>,
+    // Runs started through this manager become children of this run
+    parent_run_id: Option<Uuid>,
+}
+
+impl CallbackManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a handler
+    pub fn with_handler(mut self, handler: Arc<dyn CallbackHandler>) -> Self {
+        self.add_handler(handler);
+        self
+    }
+
+    /// Add a handler, the same handler instance is only registered once
+    pub fn add_handler(&mut self, handler: Arc<dyn CallbackHandler>) {
+        if !self.handlers.iter().any(|h| Arc::ptr_eq(h, &handler)) {
+            self.handlers.push(handler);
+        }
+    }
+
+    pub fn handlers(&self) -> &[Arc<dyn CallbackHandler>] {
+        &self.handlers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn parent_run_id(&self) -> Option<Uuid> {
+        self.parent_run_id
+    }
+
+    /// Combine with the handlers of another manager, keeping the parent run of this one
+    pub fn merge(&self, other: &CallbackManager) -> CallbackManager {
+        let mut merged = self.clone();
+        for handler in &other.handlers {
+            merged.add_handler(handler.clone());
+        }
+        merged
+    }
+
+    // Start a new run as child of the parent run
+    fn start_run(&self, name: &str) -> CallbackRunManager {
+        CallbackRunManager {
+            run: RunInfo::new(name.to_string(), self.parent_run_id),
+            handlers: self.handlers.clone(),
+        }
+    }
+
+    /// Start an LLM run
+    pub fn on_llm_start(&self, model_name: &str, prompts: &[String]) -> CallbackRunManager {
+        let run_manager = self.start_run(model_name);
+        for handler in &self.handlers {
+            handler.on_llm_start(&run_manager.run, model_name, prompts);
+        }
+        run_manager
+    }
+
+    /// Start a tool run
+    pub fn on_tool_start(&self, tool_name: &str, input: &str) -> CallbackRunManager {
+        let run_manager = self.start_run(tool_name);
+        for handler in &self.handlers {
+            handler.on_tool_start(&run_manager.run, tool_name, input);
+        }
+        run_manager
+    }
+
+    /// Start a chain (agent) run
+    pub fn on_chain_start(&self, chain_name: &str) -> CallbackRunManager {
+        let run_manager = self.start_run(chain_name);
+        for handler in &self.handlers {
+            handler.on_chain_start(&run_manager.run, chain_name);
+        }
+        run_manager
+    }
+}
+
+// Handle of a started run, used to report the remaining events of the run
+#[derive(Clone)]
+pub struct CallbackRunManager {
+    run: RunInfo,
+    handlers: Vec<Arc<dyn CallbackHandler>>,
+}
+
+impl CallbackRunManager {
+    pub fn run(&self) -> &RunInfo {
+        &self.run
+    }
+
+    pub fn run_id(&self) -> Uuid {
+        self.run.run_id
+    }
+
+    /// Get a manager whose runs are children of this run
+    pub fn get_child(&self) -> CallbackManager {
+        CallbackManager {
+            handlers: self.handlers.clone(),
+            parent_run_id: Some(self.run.run_id),
+        }
+    }
+
+    pub fn on_llm_new_token(&self, token: &str) {
+        for handler in &self.handlers {
+            handler.on_llm_new_token(&self.run, token);
+        }
+    }
+
+    pub fn on_llm_end(&self, model_name: &str, usage: Option<&TokenUsage>) {
+        for handler in &self.handlers {
+            handler.on_llm_end(&self.run, model_name, usage);
+        }
+    }
+
+    pub fn on_llm_error(&self, model_name: &str, error: &str) {
+        for handler in &self.handlers {
+            handler.on_llm_error(&self.run, model_name, error);
+        }
+    }
+
+    pub fn on_tool_end(&self, tool_name: &str, output: &str) {
+        for handler in &self.handlers {
+            handler.on_tool_end(&self.run, tool_name, output);
+        }
+    }
+
+    pub fn on_tool_error(&self, tool_name: &str, error: &str) {
+        for handler in &self.handlers {
+            handler.on_tool_error(&self.run, tool_name, error);
+        }
+    }
+
+    pub fn on_chain_end(&self, chain_name: &str) {
+        for handler in &self.handlers {
+            handler.on_chain_end(&self.run, chain_name);
+        }
+    }
+
+    pub fn on_chain_error(&self, chain_name: &str, error: &str) {
+        for handler in &self.handlers {
+            handler.on_chain_error(&self.run, chain_name, error);
+        }
+    }
+
+    pub fn on_agent_action(&self, action: &AgentAction) {
+        for handler in &self.handlers {
+            handler.on_agent_action(&self.run, action);
+        }
+    }
+
+    pub fn on_agent_finish(&self, finish: &AgentFinish) {
+        for handler in &self.handlers {
+            handler.on_agent_finish(&self.run, finish);
+        }
+    }
+}
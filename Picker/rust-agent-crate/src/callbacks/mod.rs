@@ -1,5 +1,11 @@
 // Callback module definition
 mod handler;
+mod jsonl;
+mod logging;
+mod manager;
 
 // Re-export module content
-pub use handler::CallbackHandler;
\ No newline at end of file
+pub use handler::{CallbackHandler, RunInfo};
+pub use jsonl::JsonlCallbackHandler;
+pub use logging::LoggingCallbackHandler;
+pub use manager::{CallbackManager, CallbackRunManager};
@@ -3,8 +3,6 @@ use std::collections::HashMap;
 use std::pin::Pin;
 use futures::stream::Stream;
 use serde_json::Value;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
 
 // Runnable interface definition
 pub trait Runnable<I: Send + 'static, O: Send + 'static>: Send + Sync {
@@ -62,18 +60,15 @@ pub trait Runnable<I: Send + 'static, O: Send + 'static>: Send + Sync {
     }
     
     // Async stream processing - optional implementation
+    // The default implementation yields the invoke result as a single item, streaming components override it
     fn astream(
         &self, 
-        _input: I
+        input: I
     ) -> Pin<Box<dyn std::future::Future<Output = Box<dyn Stream<Item = Result<O, anyhow::Error>> + Send>> + Send>> {
-        let _self_clone = self.clone_to_owned();
+        let self_clone = self.clone_to_owned();
         
         Box::pin(async move {
-            // Simple implementation: return an empty stream
-            let (_tx, rx) = mpsc::channel(10);
-            // Create and return an empty stream, add explicit type conversion
-            let stream: Box<dyn Stream<Item = Result<O, anyhow::Error>> + Send> = Box::new(ReceiverStream::new(rx));
-            stream
+            self_clone.stream(input)
         })
     }
     
@@ -183,10 +178,8 @@ impl<I: 'static + Send, O: 'static + Send> Runnable<I, O> for RunnableSequence<I
 // Example implementation of clone_to_owned method for Box<dyn Runnable>
 impl<I: Send + 'static, O: Send + 'static> Runnable<I, O> for Box<dyn Runnable<I, O> + Send + Sync> {
     fn invoke(&self, input: I) -> Pin<Box<dyn std::future::Future<Output = Result<O, anyhow::Error>> + Send>> {
-        let self_clone = self.clone_to_owned();
-        Box::pin(async move {
-            self_clone.invoke(input).await
-        })
+        // Dispatch to the boxed runnable, calling invoke on the Box itself would recurse into this impl
+        (**self).invoke(input)
     }
     
     fn clone_to_owned(&self) -> Box<dyn Runnable<I, O> + Send + Sync> {
@@ -0,0 +1,113 @@
+// Embeddings interface and a deterministic fake for tests
+use std::future::Future;
+use std::pin::Pin;
+use anyhow::{Error, Result};
+use sha2::{Digest, Sha256};
+
+/// Vector produced by an embedding model
+pub type Embedding = Vec<f32>;
+
+// Embedding model interface, aligned with LangChain's Embeddings
+pub trait Embeddings: Send + Sync {
+    // Model name
+    fn model_name(&self) -> Option<&str> {
+        None
+    }
+
+    // Dimension of the vectors, None until the model has been configured or called once
+    fn dimensions(&self) -> Option<usize>;
+
+    // Core method: embed search documents, one vector per text in the same order
+    fn embed_documents(&self, texts: Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Embedding>, Error>> + Send + '_>>;
+
+    // Embed a search query
+    fn embed_query(&self, text: String) -> Pin<Box<dyn Future<Output = Result<Embedding, Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.embed_documents(vec![text]).await?
+                .pop()
+                .ok_or_else(|| Error::msg("The embedding model returned no vector for the query"))
+        })
+    }
+}
+
+/// Check that a batch returned one vector per text
+pub(crate) fn check_batch(expected: usize, vectors: &[Embedding]) -> Result<()> {
+    if vectors.len() != expected {
+        return Err(Error::msg(format!("Expected {} embeddings, the server returned {}", expected, vectors.len())));
+    }
+    Ok(())
+}
+
+/// Embeddings derived from a SHA-256 of the text, aligned with LangChain's DeterministicFakeEmbedding
+/// The same text always yields the same unit vector, without any model or network access.
+#[derive(Debug, Clone)]
+pub struct DeterministicFakeEmbedding {
+    size: usize,
+}
+
+impl DeterministicFakeEmbedding {
+    /// Create fake embeddings with vectors of the given size
+    pub fn new(size: usize) -> Self {
+        Self { size }
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = Vec::with_capacity(self.size);
+        let mut block = 0u32;
+        while vector.len() < self.size {
+            let digest = Sha256::new()
+                .chain_update(block.to_le_bytes())
+                .chain_update(text.as_bytes())
+                .finalize();
+            for bytes in digest.chunks_exact(4) {
+                let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                // Map to [-1, 1]
+                vector.push((value as f64 / u32::MAX as f64 * 2.0 - 1.0) as f32);
+            }
+            block += 1;
+        }
+        vector.truncate(self.size);
+
+        let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|value| *value /= norm);
+        }
+        vector
+    }
+}
+
+impl Embeddings for DeterministicFakeEmbedding {
+    fn model_name(&self) -> Option<&str> {
+        Some("deterministic-fake")
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.size)
+    }
+
+    fn embed_documents(&self, texts: Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Embedding>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            Ok(texts.iter().map(|text| self.embed(text)).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake_embeddings_are_deterministic_unit_vectors() {
+        let embeddings = DeterministicFakeEmbedding::new(20);
+        let documents = embeddings.embed_documents(vec!["hello".to_string(), "world".to_string()]).await.unwrap();
+        let query = embeddings.embed_query("hello".to_string()).await.unwrap();
+
+        assert_eq!(documents.len(), 2);
+        assert_eq!(query.len(), 20);
+        assert_eq!(documents[0], query);
+        assert_ne!(documents[0], documents[1]);
+        let norm = query.iter().map(|value| value * value).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert_eq!(embeddings.dimensions(), Some(20));
+    }
+}
@@ -0,0 +1,9 @@
+// Embeddings module definition
+mod base;
+mod openai;
+mod ollama;
+
+// Re-export module content
+pub use base::{DeterministicFakeEmbedding, Embedding, Embeddings};
+pub use openai::OpenAIEmbeddings;
+pub use ollama::OllamaEmbeddings;
@@ -0,0 +1,153 @@
+// Ollama native embeddings (/api/embed)
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::base::{check_batch, Embedding, Embeddings};
+use crate::models::ModelApiError;
+
+const DEFAULT_CHUNK_SIZE: usize = 32;
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Embedding>,
+}
+
+/// Embeddings of a local Ollama server, e.g. nomic-embed-text or bge-m3
+#[derive(Clone)]
+pub struct OllamaEmbeddings {
+    client: Client,
+    base_url: String,
+    model_name: String,
+    keep_alive: Option<String>,
+    // Truncate inputs longer than the context length instead of failing
+    truncate: bool,
+    // Dimensions observed in the first response
+    detected_dimensions: Arc<OnceLock<usize>>,
+    chunk_size: usize,
+}
+
+impl OllamaEmbeddings {
+    /// Create Ollama embeddings, base_url defaults to http://localhost:11434 and the model to nomic-embed-text
+    pub fn new(base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()).trim_end_matches('/').to_string(),
+            model_name: "nomic-embed-text".to_string(),
+            keep_alive: None,
+            truncate: true,
+            detected_dimensions: Arc::new(OnceLock::new()),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Get base URL
+    pub fn base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    /// Set model name
+    pub fn with_model(mut self, model_name: String) -> Self {
+        self.model_name = model_name;
+        self
+    }
+
+    /// How long the model stays loaded after a request, e.g. "5m" or "-1"
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Fail on inputs longer than the context length instead of truncating them
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Set the maximum number of texts sent in one request
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+        let mut body = json!({
+            "model": self.model_name,
+            "input": texts,
+            "truncate": self.truncate,
+        });
+        if let Some(keep_alive) = &self.keep_alive {
+            body["keep_alive"] = Value::String(keep_alive.clone());
+        }
+
+        let response = self.client.post(format!("{}/api/embed", self.base_url)).json(&body).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::new(ModelApiError::from_response(response).await));
+        }
+
+        let response: OllamaEmbedResponse = response.json().await?;
+        check_batch(texts.len(), &response.embeddings)?;
+        if let Some(vector) = response.embeddings.first() {
+            let _ = self.detected_dimensions.set(vector.len());
+        }
+        Ok(response.embeddings)
+    }
+}
+
+impl Embeddings for OllamaEmbeddings {
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.detected_dimensions.get().copied()
+    }
+
+    fn embed_documents(&self, texts: Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Embedding>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(self.chunk_size) {
+                vectors.extend(self.embed_batch(batch).await?);
+            }
+            Ok(vectors)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+
+    #[tokio::test]
+    async fn test_embed_documents_in_batches() {
+        let app = Router::new().route("/api/embed", post(|Json(body): Json<Value>| async move {
+            assert_eq!(body["model"], "bge-m3");
+            assert_eq!(body["keep_alive"], "-1");
+            let embeddings: Vec<Value> = body["input"].as_array().unwrap().iter()
+                .map(|text| json!([text.as_str().unwrap().len() as f32, 1.0, 0.0]))
+                .collect();
+            Json(json!({"model": "bge-m3", "embeddings": embeddings}))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let embeddings = OllamaEmbeddings::new(Some(format!("http://{}/", address)))
+            .with_model("bge-m3".to_string())
+            .with_keep_alive("-1".to_string())
+            .with_chunk_size(2);
+        assert_eq!(embeddings.dimensions(), None);
+
+        let texts = vec!["a".to_string(), "bb".to_string(), "ccc".to_string()];
+        let vectors = embeddings.embed_documents(texts).await.unwrap();
+        assert_eq!(vectors.iter().map(|vector| vector[0]).collect::<Vec<_>>(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(embeddings.dimensions(), Some(3));
+    }
+}
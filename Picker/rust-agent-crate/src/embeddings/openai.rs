@@ -0,0 +1,228 @@
+// OpenAI-compatible embeddings (/embeddings)
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::base::{check_batch, Embedding, Embeddings};
+use crate::models::{ModelApiError, OpenAIChatModel};
+
+// OpenAI accepts at most 2048 inputs per request
+const DEFAULT_CHUNK_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingData {
+    embedding: Embedding,
+    #[serde(default)]
+    index: usize,
+}
+
+// Dimensions of well-known models, used before the first response
+fn known_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-3-small" | "text-embedding-ada-002" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        _ => None,
+    }
+}
+
+/// Embeddings of an OpenAI-compatible API (OpenAI, Azure proxies, vLLM, LM Studio, llama.cpp, ...)
+#[derive(Clone)]
+pub struct OpenAIEmbeddings {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    model_name: String,
+    // Requested output dimensions, supported by text-embedding-3 models
+    requested_dimensions: Option<usize>,
+    // Dimensions observed in the first response
+    detected_dimensions: Arc<OnceLock<usize>>,
+    chunk_size: usize,
+    additional_headers: HashMap<String, String>,
+}
+
+impl OpenAIEmbeddings {
+    /// Create OpenAI embeddings, base_url defaults to https://api.openai.com/v1 and the model to text-embedding-3-small
+    pub fn new(api_key: String, base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            model_name: "text-embedding-3-small".to_string(),
+            requested_dimensions: None,
+            detected_dimensions: Arc::new(OnceLock::new()),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            additional_headers: HashMap::new(),
+        }
+    }
+
+    /// Use the API key and base URL of a chat model
+    pub fn from_chat_model(model: &OpenAIChatModel) -> Self {
+        Self::new(model.api_key().to_string(), Some(model.base_url().clone()))
+    }
+
+    /// Get base URL
+    pub fn base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    /// Set model name
+    pub fn with_model(mut self, model_name: String) -> Self {
+        self.model_name = model_name;
+        self
+    }
+
+    /// Request vectors of this size (text-embedding-3 models shorten their output)
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.requested_dimensions = Some(dimensions);
+        self
+    }
+
+    /// Set the maximum number of texts sent in one request
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Add additional request headers
+    pub fn with_additional_header(mut self, key: String, value: String) -> Self {
+        self.additional_headers.insert(key, value);
+        self
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+        let mut body = json!({
+            "model": self.model_name,
+            "input": texts,
+            "encoding_format": "float",
+        });
+        if let Some(dimensions) = self.requested_dimensions {
+            body["dimensions"] = Value::from(dimensions);
+        }
+
+        let mut request = self.client
+            .post(format!("{}/embeddings", self.base_url.trim_end_matches('/')))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&body);
+        for (key, value) in &self.additional_headers {
+            request = request.header(key, value);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(Error::new(ModelApiError::from_response(response).await));
+        }
+
+        // Entries carry their input index and may come back in any order
+        let mut response: OpenAIEmbeddingResponse = response.json().await?;
+        response.data.sort_by_key(|data| data.index);
+        let vectors: Vec<Embedding> = response.data.into_iter().map(|data| data.embedding).collect();
+        check_batch(texts.len(), &vectors)?;
+        if let Some(vector) = vectors.first() {
+            let _ = self.detected_dimensions.set(vector.len());
+        }
+        Ok(vectors)
+    }
+}
+
+impl Embeddings for OpenAIEmbeddings {
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model_name)
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.requested_dimensions
+            .or_else(|| self.detected_dimensions.get().copied())
+            .or_else(|| known_dimensions(&self.model_name))
+    }
+
+    fn embed_documents(&self, texts: Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Embedding>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(self.chunk_size) {
+                vectors.extend(self.embed_batch(batch).await?);
+            }
+            Ok(vectors)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::{HeaderMap, StatusCode}, response::IntoResponse, routing::post, Json, Router};
+    use std::sync::Mutex;
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_batches_and_restores_input_order() {
+        let requests: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = Router::new().route("/v1/embeddings", post(move |headers: HeaderMap, Json(body): Json<Value>| {
+            let recorded = recorded.clone();
+            async move {
+                assert_eq!(headers["authorization"], "Bearer test-key");
+                recorded.lock().unwrap().push(body.clone());
+                // Vector [length, index], returned in reverse order
+                let data: Vec<Value> = body["input"].as_array().unwrap().iter().enumerate().rev()
+                    .map(|(index, text)| json!({"index": index, "embedding": [text.as_str().unwrap().len() as f32, index as f32]}))
+                    .collect();
+                Json(json!({"object": "list", "data": data}))
+            }
+        }));
+        let base_url = serve(app).await;
+
+        let chat_model = OpenAIChatModel::new("test-key".to_string(), Some(format!("{}/v1", base_url)));
+        let embeddings = OpenAIEmbeddings::from_chat_model(&chat_model)
+            .with_model("text-embedding-3-small".to_string())
+            .with_dimensions(2)
+            .with_chunk_size(2);
+        let texts = vec!["a".to_string(), "bb".to_string(), "ccc".to_string()];
+        let vectors = embeddings.embed_documents(texts).await.unwrap();
+
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![2.0, 1.0], vec![3.0, 0.0]]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["dimensions"], 2);
+        assert_eq!(requests[1]["input"], json!(["ccc"]));
+        assert_eq!(embeddings.dimensions(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_api_errors_and_detected_dimensions() {
+        let app = Router::new()
+            .route("/embeddings", post(|Json(body): Json<Value>| async move {
+                if body["model"] == "missing" {
+                    return (StatusCode::NOT_FOUND, "model not found").into_response();
+                }
+                Json(json!({"data": [{"index": 0, "embedding": [0.1, 0.2, 0.3]}]})).into_response()
+            }));
+        let base_url = serve(app).await;
+
+        let embeddings = OpenAIEmbeddings::new("key".to_string(), Some(base_url.clone())).with_model("local".to_string());
+        assert_eq!(embeddings.dimensions(), None);
+        assert_eq!(embeddings.embed_query("hi".to_string()).await.unwrap().len(), 3);
+        assert_eq!(embeddings.dimensions(), Some(3));
+
+        let missing = OpenAIEmbeddings::new("key".to_string(), Some(base_url)).with_model("missing".to_string());
+        let error = missing.embed_query("hi".to_string()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ModelApiError>().unwrap().status, 404);
+    }
+}
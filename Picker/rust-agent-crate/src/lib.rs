@@ -2,20 +2,41 @@
 
 mod core;
 mod models;
+mod embeddings;
+mod vectorstores;
 pub mod tools;
 pub mod memory;
 mod agents;
 mod callbacks;
 mod mcp;
+mod prompt;
 
 // Re-export main components for external use
 pub use core::{Runnable, RunnableExt, RunnableSequence};
-pub use models::{ChatModel, ChatMessage as ModelChatMessage, ChatMessageContent, ChatCompletion, TokenUsage, OpenAIChatModel};
-pub use tools::{Tool, Toolkit, ExampleTool, ExampleToolkit, find_matching_tool_index, parse_model_output};
-pub use memory::{BaseMemory, SimpleMemory, MessageHistoryMemory, SummaryMemory, CompositeMemory, CompositeMemoryConfig, ChatMessageRecord, ChatMessage};
-pub use agents::{Agent, McpAgent, AgentAction, AgentFinish, AgentOutput, AgentRunner, SimpleAgent, SimpleAgentRunner};
-pub use callbacks::CallbackHandler;
-pub use mcp::{McpClient, SimpleMcpClient, McpTool, McpToolAdapter, McpServer, SimpleMcpServer};
+pub use models::{ChatModel, ChatMessage as ModelChatMessage, ChatMessageContent, ChatCompletion, ChatCompletionAccumulator, ChatCompletionChunk, ChatCompletionStream, TokenUsage, OpenAIChatModel, AnthropicChatModel, ANTHROPIC_API_VERSION, OllamaChatModel, LocalModelProvider, LocalModelInfo, LocalServerKind, ModelCapabilities, RouterChatModel, RouteHealth, CircuitState, ModelApiError, CachedChatModel, CacheMode, CachedCompletion, DiskResponseCache, ResponseCache, ToolCall, ToolCallChunk, ToolDefinition, Tokenizer, trim_messages, BpeTokenizer, BpeEncoding, HeuristicTokenizer, ModelRegistry, ModelProfile};
+#[cfg(feature = "sqlite")]
+pub use models::SqliteResponseCache;
+pub use embeddings::{DeterministicFakeEmbedding, Embedding, Embeddings, OllamaEmbeddings, OpenAIEmbeddings};
+pub use vectorstores::{Document, InMemoryVectorStore, MetadataCondition, MetadataFilter, ScoredDocument, VectorStore};
+#[cfg(feature = "sqlite")]
+pub use vectorstores::SqliteVectorStore;
+pub use tools::{Tool, Toolkit, ExampleTool, ExampleToolkit, SchemaViolation, ToolValidationError, default_input_schema, find_matching_tool_index, parse_model_output, validate_json_schema};
+pub use memory::{BaseMemory, SimpleMemory, MessageHistoryMemory, SummaryMemory, CompositeMemory, CompositeMemoryConfig, EntityMemory, VectorStoreRetrieverMemory, ChatMessageHistory, ChatMessageRecord, ChatMessage, ChatSessionInfo, FileChatMessageHistory, SessionBundle, SessionManager};
+#[cfg(feature = "sqlite")]
+pub use memory::SqliteChatMessageHistory;
+pub use agents::{Agent, McpAgent, DEFAULT_REACT_JSON_PROMPT, DEFAULT_REACT_PROMPT, AgentAction, AgentEventStream, AgentExecutor, AgentExecutorOutput, AgentStopReason, EarlyStoppingMethod, AgentFinish, AgentOutput, AgentRunner, AgentStep, AgentStreamEvent, SimpleAgent, SimpleAgentRunner};
+pub use callbacks::{CallbackHandler, CallbackManager, CallbackRunManager, JsonlCallbackHandler, LoggingCallbackHandler, RunInfo};
+pub use prompt::{ChatPromptTemplate, FewShotPromptTemplate, MessagePromptTemplate, PromptTemplate};
+pub use mcp::{McpClient, SimpleMcpClient, StdioMcpClient, McpTool, McpToolAdapter, McpServer, SimpleMcpServer};
+pub use mcp::{CallToolResult, ToolResultContent};
+pub use mcp::{McpClientPool, McpServerHealth, ToolConflictPolicy, TOOL_NAMESPACE_SEPARATOR};
+pub use mcp::{LoggingLevel, McpNotificationHandler, McpRequestContext};
+pub use mcp::{McpResource, McpResourceTemplate, Resource, ResourceContents, match_uri_template};
+pub use mcp::{GetPromptResult, McpPrompt, McpPromptTemplate, Prompt, PromptArgument, PromptMessage};
+pub use mcp::{
+    ClientCapabilities, Implementation, InitializeParams, InitializeResult, ServerCapabilities,
+    LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS, is_supported_protocol_version, negotiate_protocol_version,
+};
 use anyhow::Error;
 use std::collections::HashMap;
 
@@ -25,47 +46,10 @@ pub use core::pipe;
 pub use anyhow;
 
 // Main function to run Agent
+// Runs the multi-step AgentExecutor with default limits and returns the final answer
 pub async fn run_agent(agent: &McpAgent, input: String) -> Result<String, Error> {
     let mut inputs = HashMap::new();
     inputs.insert("input".to_string(), input);
-    let output = agent.invoke(inputs).await?;
-    
-    match output {
-        AgentOutput::Action(action) => {
-            // Find the corresponding tool using fuzzy matching mechanism
-            let tools = agent.tools();
-            match find_matching_tool_index(&tools, &action.tool) {
-                Some(matched_name) => {
-                    // After finding a matching tool name, search for the specific tool again
-                    if let Some(tool) = tools.iter().find(|t| t.name() == matched_name) {
-                        // Invoke the tool
-                        let tool_result = tool.invoke(&action.tool_input).await?;
-                        
-                        // Feed the tool execution result back to Agent for further processing
-                        let mut new_inputs = HashMap::new();
-                        new_inputs.insert("input".to_string(), format!("[CUSTOMIZE_TOOL_RESULT] {{\"tool\": \"{}\", \"result\": {}}}", matched_name, tool_result));
-                        let new_output = agent.invoke(new_inputs).await?;
-                        
-                        match new_output {
-                            AgentOutput::Finish(finish) => {
-                                Ok(finish.return_values.get("answer").map(|s| s.clone()).unwrap_or_else(|| "".to_string()))
-                            },
-                            _ => {
-                                // If still Action, simply return the tool result for now
-                                Ok(format!("Tool {} executed successfully, result: {}", matched_name, tool_result))
-                            }
-                        }
-                    } else {
-                        Err(Error::msg(format!("Tool {} does not exist", matched_name)))
-                    }
-                },
-                None => {
-                    Err(Error::msg(format!("Tool {} does not exist", action.tool)))
-                }
-            }
-        },
-        AgentOutput::Finish(finish) => {
-            Ok(finish.return_values.get("answer").map(|s| s.clone()).unwrap_or_else(|| "".to_string()))
-        },
-    }
+    let output = AgentExecutor::new(agent.clone()).run(&inputs).await?;
+    Ok(output.answer().to_string())
 }
@@ -3,13 +3,15 @@ use anyhow::Error;
 use std::collections::HashMap;
 use std::sync::Arc;
 use serde_json::Value;
-use crate::tools::Tool;
+use crate::callbacks::CallbackManager;
+use crate::tools::{validate_json_schema, Tool, ToolValidationError};
 use super::client::{McpClient, McpTool};
 use log::info;
 // MCP tool adapter
 pub struct McpToolAdapter {
     mcp_client: Arc<dyn McpClient>,
     mcp_tool: McpTool,
+    callback_manager: CallbackManager,
 }
 
 impl McpToolAdapter {
@@ -17,6 +19,7 @@ impl McpToolAdapter {
         Self {
             mcp_client,
             mcp_tool,
+            callback_manager: CallbackManager::new(),
         }
     }
     
@@ -25,6 +28,7 @@ impl McpToolAdapter {
         Self {
             mcp_client: Arc::from(mcp_client),
             mcp_tool,
+            callback_manager: CallbackManager::new(),
         }
     }
     
@@ -37,6 +41,51 @@ impl McpToolAdapter {
     pub fn get_mcp_tool(&self) -> McpTool {
         self.mcp_tool.clone()
     }
+    
+    // Attach a callback manager, notified of every tool invocation
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+    
+    // Get clone of the callback manager
+    pub fn get_callback_manager(&self) -> CallbackManager {
+        self.callback_manager.clone()
+    }
+    
+    // Call the tool on the MCP server, the flag tells whether the server reported a tool error
+    async fn call(&self, input_str: &str) -> Result<(String, bool), Error> {
+        let tool_name = &self.mcp_tool.name;
+        // Try to parse input as JSON parameters, add fault tolerance
+        let parameters: HashMap<String, Value> = match serde_json::from_str(input_str) {
+            Ok(params) => params,
+            Err(_) => {
+                // Simple handling, use input as default parameter
+                let mut map = HashMap::new();
+                map.insert("query".to_string(), Value::String(input_str.to_string()));
+                map
+            },
+        };
+        
+        // Validate the arguments before calling the tool, the error tells the model what to fix
+        let violations = validate_json_schema(&self.mcp_tool.input_schema, &serde_json::to_value(&parameters)?);
+        if !violations.is_empty() {
+            return Err(ToolValidationError {
+                tool: tool_name.clone(),
+                violations,
+            }.into());
+        }
+        
+        // Call the tool on the MCP server
+        let result = self.mcp_client.call_tool_result(tool_name, parameters).await?;
+        
+        // Tool errors become observations, the model can fix the call or explain the failure
+        let output = result.to_observation();
+        if result.is_error {
+            return Ok((format!("Tool {} failed: {}", tool_name, output), true));
+        }
+        Ok((output, false))
+    }
 }
 
 impl Tool for McpToolAdapter {
@@ -48,29 +97,37 @@ impl Tool for McpToolAdapter {
         &self.mcp_tool.description
     }
     
+    fn input_schema(&self) -> Value {
+        self.mcp_tool.input_schema.clone()
+    }
+    
     fn invoke(&self, input: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + '_>> {
-        let client = self.mcp_client.clone();
-        let tool_name = self.mcp_tool.name.clone();
         let input_str = input.to_string();
-        info!("Invoking MCP tool {} with input: {}", tool_name, input_str);
         Box::pin(async move {
-            // Try to parse input as JSON parameters, add fault tolerance
-            let parameters: HashMap<String, Value> = match serde_json::from_str(&input_str) {
-                Ok(params) => params,
-                Err(_) => {
-                    // Simple handling, use input as default parameter
-                    let mut map = HashMap::new();
-                    map.insert("query".to_string(), Value::String(input_str.clone()));
-                    map
+            self.invoke_with_callbacks(&input_str, &CallbackManager::new()).await
+        })
+    }
+    
+    // The events go to the handlers of the caller and of the adapter, in one tool run
+    fn invoke_with_callbacks<'a>(&'a self, input: &'a str, callbacks: &'a CallbackManager) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + 'a>> {
+        let tool_name = self.mcp_tool.name.clone();
+        info!("Invoking MCP tool {} with input: {}", tool_name, input);
+        let run_manager = callbacks.merge(&self.callback_manager).on_tool_start(&tool_name, input);
+        Box::pin(async move {
+            match self.call(input).await {
+                Ok((output, false)) => {
+                    run_manager.on_tool_end(&tool_name, &output);
+                    Ok(output)
+                },
+                Ok((output, true)) => {
+                    run_manager.on_tool_error(&tool_name, &output);
+                    Ok(output)
                 },
-            };
-            
-            // Call the tool on the MCP server
-            let result_future = client.call_tool(&tool_name, parameters);
-            let result = result_future.await?;
-            
-            // Convert result to string
-            Ok(serde_json::to_string_pretty(&result)?)
+                Err(e) => {
+                    run_manager.on_tool_error(&tool_name, &e.to_string());
+                    Err(e)
+                },
+            }
         })
     }
     
@@ -78,4 +135,41 @@ impl Tool for McpToolAdapter {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::SimpleMcpClient;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_arguments_validated_against_input_schema() {
+        let mut client = SimpleMcpClient::new(String::new());
+        client.register_tool_handler("get_weather".to_string(), |params| async move {
+            Ok(json!({"city": params["city"]}))
+        });
+        let tool = McpTool::new("get_weather".to_string(), "Get the weather of a city".to_string())
+            .with_input_schema(json!({
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string", "minLength": 1},
+                    "days": {"type": "integer", "minimum": 1, "maximum": 7}
+                },
+                "required": ["city"],
+                "additionalProperties": false
+            }));
+        let adapter = McpToolAdapter::new(Arc::new(client), tool);
+
+        let output = adapter.invoke(r#"{"city": "Paris", "days": 3}"#).await.unwrap();
+        assert!(output.contains("Paris"));
+
+        let error = adapter.invoke(r#"{"days": "3", "unit": "C"}"#).await.unwrap_err();
+        let validation_error = error.downcast_ref::<ToolValidationError>().unwrap();
+        assert_eq!(validation_error.tool, "get_weather");
+        let paths: Vec<&str> = validation_error.violations.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&"/city") && paths.contains(&"/days") && paths.contains(&"/unit"));
+        assert!(error.to_string().starts_with("Invalid arguments for tool get_weather:"));
+    }
+}
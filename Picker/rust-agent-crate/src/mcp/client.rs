@@ -10,15 +10,56 @@ use uuid::Uuid;
 
 use crate::mcp::JSONRPCRequest;
 use crate::mcp::JSONRPCResponse;
+use crate::mcp::{
+    ClientCapabilities, Implementation, InitializeParams, InitializeResult, ServerCapabilities,
+    LATEST_PROTOCOL_VERSION,
+};
+use crate::mcp::{CallToolResult, GetPromptResult, McpPrompt, McpResource, McpResourceTemplate, ResourceContents};
+use crate::mcp::resource::{list_all_pages, parse_read_result};
+use crate::mcp::protocol::{parse_initialize_result, MCP_PROTOCOL_VERSION_HEADER, MCP_SESSION_ID_HEADER};
+use crate::models::sse::sse_events;
+use futures::stream::StreamExt;
+use crate::tools::default_input_schema;
 
 // MCP tool structure
 #[derive(Debug,Clone)]
 pub struct McpTool {
     pub name: String,
     pub description: String,
-    // Other tool-related fields
+    // JSON Schema of the tool arguments (inputSchema in tools/list)
+    pub input_schema: Value,
 }
 
+impl McpTool {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            name,
+            description,
+            input_schema: default_input_schema(),
+        }
+    }
+    
+    // Set the JSON Schema of the tool arguments
+    pub fn with_input_schema(mut self, input_schema: Value) -> Self {
+        self.input_schema = input_schema;
+        self
+    }
+    
+    // Parse a tool entry of a tools/list response
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let name = value.get("name")?.as_str()?.to_string();
+        let description = value.get("description").and_then(|d| d.as_str()).unwrap_or_default().to_string();
+        let input_schema = match value.get("inputSchema") {
+            Some(schema) if schema.is_object() => schema.clone(),
+            _ => default_input_schema(),
+        };
+        Some(Self { name, description, input_schema })
+    }
+}
+
+/// Handler of notifications sent by the server, called with the method and the params
+pub type McpNotificationHandler = Arc<dyn Fn(&str, &Value) + Send + Sync>;
+
 // Simple MCP client implementation, modify SimpleMcpClient structure, add tool handler field
 #[derive(Clone)]
 pub struct SimpleMcpClient {
@@ -28,6 +69,18 @@ pub struct SimpleMcpClient {
     pub tool_handlers: HashMap<String, Arc<dyn Fn(HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<Value, Error>> + Send>> + Send + Sync>>,
     // Connection status flag, indicates whether successfully connected to MCP server
     pub is_mcp_server_connected: Arc<Mutex<bool>>,
+    // clientInfo and capabilities sent in initialize
+    pub client_info: Implementation,
+    pub capabilities: ClientCapabilities,
+    // Result of the initialize handshake, None before connecting or for servers without initialize
+    pub initialize_result: Arc<Mutex<Option<InitializeResult>>>,
+    // Path of the Streamable HTTP endpoint (e.g. "/mcp"), None uses the plain {url}/rpc endpoint
+    pub streamable_http_path: Option<String>,
+    // Mcp-Session-Id assigned by the server in initialize
+    pub session_id: Arc<Mutex<Option<String>>>,
+    pub notification_handlers: Vec<McpNotificationHandler>,
+    // Task reading the GET stream of server notifications
+    notification_stream: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
 }
 
 // Implement methods for SimpleMcpClient structure
@@ -38,7 +91,255 @@ impl SimpleMcpClient {
             available_tools: Vec::new(),
             tool_handlers: HashMap::new(),
             is_mcp_server_connected: Arc::new(Mutex::new(false)), // Initial state is disconnected
+            client_info: Implementation::default(),
+            capabilities: ClientCapabilities::default(),
+            initialize_result: Arc::new(Mutex::new(None)),
+            streamable_http_path: None,
+            session_id: Arc::new(Mutex::new(None)),
+            notification_handlers: Vec::new(),
+            notification_stream: Arc::new(Mutex::new(None)),
+        }
+    }
+    
+    /// Use the Streamable HTTP transport at {url}{path}, e.g. "/mcp"
+    /// Requests then carry the session id, tool calls receive progress over SSE and
+    /// server notifications are read from a GET stream opened by connect.
+    pub fn with_streamable_http(mut self, path: impl Into<String>) -> Self {
+        self.streamable_http_path = Some(path.into());
+        self
+    }
+    
+    /// Register a handler called for every notification received from the server
+    /// (notifications/progress, notifications/message, notifications/tools/list_changed, ...)
+    pub fn register_notification_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, &Value) + Send + Sync + 'static,
+    {
+        self.notification_handlers.push(Arc::new(handler));
+    }
+    
+    /// Session id of the Streamable HTTP transport
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    fn endpoint(&self) -> String {
+        match &self.streamable_http_path {
+            Some(path) => format!("{}{}", self.url, path),
+            None => format!("{}/rpc", self.url),
+        }
+    }
+    
+    // Send a JSON-RPC message and return its response, None for notifications
+    // With Streamable HTTP, notifications streamed before the response are passed to the handlers.
+    async fn send_message(&self, message: &JSONRPCRequest) -> Result<Option<JSONRPCResponse>, Error> {
+        let client = reqwest::Client::new();
+        let mut request = client.post(self.endpoint()).json(message);
+        if self.streamable_http_path.is_some() {
+            request = request.header("Accept", "application/json, text/event-stream");
+            if let Some(session_id) = self.session_id() {
+                request = request.header(MCP_SESSION_ID_HEADER, session_id);
+            }
+            if let Some(version) = self.protocol_version() {
+                request = request.header(MCP_PROTOCOL_VERSION_HEADER, version);
+            }
+        }
+        let response = request.send().await
+            .map_err(|e| Error::msg(format!("Failed to send {} request: {}", message.method, e)))?;
+        
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND && self.session_id().is_some() {
+            return Err(Error::msg("MCP session expired, connect again"));
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::msg(format!("MCP server returned HTTP error {}: {}", status, body)));
+        }
+        if let Some(session_id) = response.headers().get(MCP_SESSION_ID_HEADER).and_then(|id| id.to_str().ok()) {
+            *self.session_id.lock().unwrap_or_else(|e| e.into_inner()) = Some(session_id.to_string());
+        }
+        if message.id.is_none() || status == reqwest::StatusCode::ACCEPTED {
+            return Ok(None);
+        }
+        
+        let is_event_stream = response.headers().get("content-type")
+            .and_then(|t| t.to_str().ok())
+            .is_some_and(|t| t.starts_with("text/event-stream"));
+        if !is_event_stream {
+            let text = response.text().await
+                .map_err(|e| Error::msg(format!("Failed to read response body: {}", e)))?;
+            if text.trim().is_empty() {
+                return Ok(None);
+            }
+            let rpc_response = serde_json::from_str(&text)
+                .map_err(|e| Error::msg(format!("Failed to parse response as JSON: {}. Response content: {}", e, text)))?;
+            return Ok(Some(rpc_response));
+        }
+        
+        // SSE stream: notifications first, then the response to our request
+        let mut events = Box::pin(sse_events(response.bytes_stream()));
+        while let Some(event) = events.next().await {
+            let value: Value = match serde_json::from_str(&event?.data) {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("Ignoring invalid SSE message from MCP server: {}", e);
+                    continue;
+                }
+            };
+            if value.get("method").is_some() {
+                dispatch_notification(&self.notification_handlers, &value);
+            } else if value.get("id") == message.id.as_ref() {
+                return Ok(Some(serde_json::from_value(value)?));
+            }
+        }
+        Err(Error::msg(format!("MCP server closed the stream without answering {}", message.method)))
+    }
+    
+    // Send a request and return its result, JSON-RPC errors become errors
+    async fn request(&self, method: &str, params: Option<Value>) -> Result<Value, Error> {
+        let request = JSONRPCRequest::new(method, params);
+        let response = self.send_message(&request).await?
+            .ok_or_else(|| Error::msg(format!("Empty {} response", method)))?;
+        if let Some(error) = response.error {
+            return Err(Error::new(error));
         }
+        Ok(response.result.unwrap_or(Value::Null))
+    }
+    
+    // Resources are only requested from servers that announced them (or did not negotiate at all)
+    fn supports_resources(&self) -> bool {
+        self.server_capabilities().is_none_or(|c| c.resources.is_some())
+    }
+    
+    // Prompts are only requested from servers that announced them (or did not negotiate at all)
+    fn supports_prompts(&self) -> bool {
+        self.server_capabilities().is_none_or(|c| c.prompts.is_some())
+    }
+    
+    // Read server notifications from the GET stream until it ends or the client disconnects
+    fn open_notification_stream(&self) {
+        let Some(session_id) = self.session_id() else {
+            return;
+        };
+        let url = self.endpoint();
+        let protocol_version = self.protocol_version().unwrap_or_default();
+        let handlers = self.notification_handlers.clone();
+        let handle = tokio::spawn(async move {
+            let response = reqwest::Client::new()
+                .get(&url)
+                .header("Accept", "text/event-stream")
+                .header(MCP_SESSION_ID_HEADER, session_id)
+                .header(MCP_PROTOCOL_VERSION_HEADER, protocol_version)
+                .send()
+                .await;
+            let response = match response {
+                Ok(response) if response.status().is_success() => response,
+                // Servers may not offer the stream (405), notifications then only come with responses
+                Ok(response) => {
+                    debug!("MCP server does not offer a notification stream: {}", response.status());
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to open MCP notification stream: {}", e);
+                    return;
+                }
+            };
+            let mut events = Box::pin(sse_events(response.bytes_stream()));
+            while let Some(Ok(event)) = events.next().await {
+                if let Ok(value) = serde_json::from_str::<Value>(&event.data) {
+                    dispatch_notification(&handlers, &value);
+                }
+            }
+            debug!("MCP notification stream closed");
+        });
+        if let Some(previous) = self.notification_stream.lock().unwrap_or_else(|e| e.into_inner()).replace(handle) {
+            previous.abort();
+        }
+    }
+    
+    /// Set the clientInfo sent in initialize
+    pub fn with_client_info(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.client_info = Implementation::new(name, version);
+        self
+    }
+    
+    /// Set the capabilities announced in initialize
+    pub fn with_capabilities(mut self, capabilities: ClientCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+    
+    /// Run the initialize handshake and send notifications/initialized
+    /// Fails if the server answers with a protocol version this client does not support.
+    /// Servers without initialize (method not found) are accepted in legacy mode and return None.
+    pub async fn initialize(&self) -> Result<Option<InitializeResult>, Error> {
+        if self.url.is_empty() {
+            return Err(Error::msg("No URL set for MCP client"));
+        }
+        let params = InitializeParams {
+            protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            capabilities: self.capabilities.clone(),
+            client_info: self.client_info.clone(),
+        };
+        let request = JSONRPCRequest::new("initialize", Some(serde_json::to_value(params)?));
+        
+        // A previous session must not be sent along with a new initialize
+        *self.session_id.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.set_initialize_result(None);
+        let rpc_response = self.send_message(&request).await?
+            .ok_or_else(|| Error::msg("Empty initialize response"))?;
+        
+        if let Some(error) = rpc_response.error {
+            if error.code == -32601 {
+                // Older servers only know tools/list, tools/call and ping
+                warn!("MCP server at {} does not support initialize, continuing without capability negotiation", self.url);
+                self.set_initialize_result(None);
+                return Ok(None);
+            }
+            return Err(Error::msg(format!("Initialize failed: {} (code: {})", error.message, error.code)));
+        }
+        // The client must not continue with a version it cannot speak
+        let result = parse_initialize_result(rpc_response.result).inspect_err(|_| {
+            *self.session_id.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        })?;
+        info!(
+            "Initialized MCP session with {} {} (protocol {})",
+            result.server_info.name, result.server_info.version, result.protocol_version
+        );
+        
+        // The negotiated version is sent in the MCP-Protocol-Version header from now on
+        self.set_initialize_result(Some(result.clone()));
+        let notification = JSONRPCRequest::notification("notifications/initialized", None);
+        self.send_message(&notification).await?;
+        Ok(Some(result))
+    }
+    
+    fn set_initialize_result(&self, result: Option<InitializeResult>) {
+        *self.initialize_result.lock().unwrap_or_else(|e| e.into_inner()) = result;
+    }
+    
+    /// Protocol version negotiated in initialize
+    pub fn protocol_version(&self) -> Option<String> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.protocol_version.clone())
+    }
+    
+    /// serverInfo returned by initialize
+    pub fn server_info(&self) -> Option<Implementation> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.server_info.clone())
+    }
+    
+    /// Capabilities returned by initialize
+    pub fn server_capabilities(&self) -> Option<ServerCapabilities> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.capabilities.clone())
+    }
+    
+    /// Instructions returned by initialize
+    pub fn server_instructions(&self) -> Option<String> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().and_then(|r| r.instructions.clone())
     }
     
     // Add custom tool method
@@ -86,138 +387,109 @@ impl SimpleMcpClient {
     }
 }
 
+// Pass a server notification to the registered handlers
+pub(crate) fn dispatch_notification(handlers: &[McpNotificationHandler], message: &Value) {
+    let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+        return;
+    };
+    debug!("Received MCP notification {}", method);
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    for handler in handlers {
+        handler(method, &params);
+    }
+}
+
 // Implement McpClient trait for SimpleMcpClient
 impl McpClient for SimpleMcpClient {
-    // Connect to MCP server
+    // Connect to MCP server and negotiate the protocol version and capabilities
     fn connect(&mut self, url: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
         let url = url.to_string();
         Box::pin(async move {
             self.url = url;
+            self.initialize().await?;
+            if self.streamable_http_path.is_some() {
+                self.open_notification_stream();
+            }
+            self.set_server_connected(true);
             Ok(())
         })
     }
     
     // Get available tool list
     fn get_tools(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpTool>, Error>> + Send + '_>> {
-        let url = self.url.clone();
         let local_tools = self.available_tools.clone();
-        let is_connected = self.is_mcp_server_connected.clone();
         Box::pin(async move {
             // First check connection status flag, return local tool list directly if not connected
-            let connected = if let Ok(conn) = is_connected.lock() {
-                *conn
-            } else {
-                false
-            };
-
-            if !connected {
+            if !self.is_server_connected() {
                 warn!("MCP server is not connected, returning local tools only");
                 return Ok(local_tools);
             }
             
-            if !url.is_empty() {
-                // Construct JSON-RPC request
-                let request = JSONRPCRequest {
-                    jsonrpc: "2.0".to_string(),
-                    id: Some(Value::String(Uuid::new_v4().to_string())),
-                    method: "tools/list".to_string(),
-                    params: None,
-                };
-
-                // Send HTTP POST request
-                let client = reqwest::Client::new();
-                let response = client
-                    .post(&format!("{}/rpc", url))
-                    .json(&request)
-                    .send()
-                    .await;
-
-                // Check if request was sent successfully
-                match response {
-                    Ok(response) => {
-                        // Check HTTP status code
-                        if !response.status().is_success() {
-                            let status = response.status();
-                            let body = response.text().await.unwrap_or_else(|_| "Unable to read response body".to_string());
-                            warn!("MCP server returned HTTP error {}: {}. Response body: {}", status.as_u16(), status.canonical_reason().unwrap_or("Unknown error"), body);
-                            // Return local tool list when server returns error
-                            return Ok(local_tools);
-                        }
-
-                        // Get response text for debugging
-                        let response_text = response.text().await
-                            .map_err(|e| Error::msg(format!("Failed to read response body: {}", e)))?;
-                        
-                        // Check if response is empty
-                        if response_text.trim().is_empty() {
-                            warn!("MCP server returned empty response");
-                            // Return local tool list when server returns empty response
-                            return Ok(local_tools);
-                        }
-
-                        // Try to parse JSON
-                        let rpc_response: JSONRPCResponse = serde_json::from_str(&response_text)
-                            .map_err(|e| {
-                                warn!("Failed to parse response as JSON: {}. Response content: {}", e, response_text);
-                                // Return local tool list when JSON parsing fails
-                                Error::msg(format!("Failed to parse response as JSON: {}. Response content: {}", e, response_text))
-                            })?;
-                        
-                        // Check for errors
-                        if let Some(error) = rpc_response.error {
-                            warn!("JSON-RPC error: {} (code: {})", error.message, error.code);
-                            // Return local tool list when JSON-RPC returns error
-                            return Ok(local_tools);
-                        }
-                        
-                        // Parse tool list
-                        if let Some(result) = rpc_response.result {
-                            debug!("Server response result: {:?}", result);
-                            if let Some(tools_value) = result.get("tools") {
-                                debug!("Tools value: {:?}", tools_value);
-                                if let Ok(tools_array) = serde_json::from_value::<Vec<serde_json::Value>>(tools_value.clone()) {
-                                    let mut tools = Vec::new();
-                                    // First add local tools to tools
-                                    tools.extend(local_tools);
-                                    for tool_value in tools_array {
-                                        debug!("Processing tool value: {:?}", tool_value);
-                                        if let (Ok(name), Ok(description)) = (
-                                            serde_json::from_value::<String>(tool_value["name"].clone()),
-                                            serde_json::from_value::<String>(tool_value["description"].clone())
-                                        ) {
-                                            tools.push(McpTool {
-                                                name,
-                                                description,
-                                            });
-                                        } else {
-                                            warn!("Failed to parse tool from server response: {:?}", tool_value);
-                                        }
-                                    }
-                                    return Ok(tools);
-                                } else {
-                                    warn!("Failed to parse tools array from server response: {:?}", tools_value);
-                                }
+            // Servers that negotiated capabilities without tools are not asked for them
+            if self.server_capabilities().is_some_and(|c| c.tools.is_none()) {
+                debug!("MCP server does not provide tools, returning local tools only");
+                return Ok(local_tools);
+            }
+            
+            if self.url.is_empty() {
+                // Return local tool list if no URL is set
+                return Ok(local_tools);
+            }
+            
+            // Construct JSON-RPC request
+            let request = JSONRPCRequest::new("tools/list", None);
+            let rpc_response = match self.send_message(&request).await {
+                Ok(Some(rpc_response)) => rpc_response,
+                Ok(None) => {
+                    warn!("MCP server returned empty response");
+                    // Return local tool list when server returns empty response
+                    return Ok(local_tools);
+                }
+                Err(e) => {
+                    // Return local tool list when the server is unreachable or returns an error
+                    warn!("Failed to get tools from MCP server: {}", e);
+                    return Ok(local_tools);
+                }
+            };
+            
+            // Check for errors
+            if let Some(error) = rpc_response.error {
+                warn!("JSON-RPC error: {} (code: {})", error.message, error.code);
+                // Return local tool list when JSON-RPC returns error
+                return Ok(local_tools);
+            }
+            
+            // Parse tool list
+            if let Some(result) = rpc_response.result {
+                debug!("Server response result: {:?}", result);
+                if let Some(tools_value) = result.get("tools") {
+                    debug!("Tools value: {:?}", tools_value);
+                    if let Ok(tools_array) = serde_json::from_value::<Vec<serde_json::Value>>(tools_value.clone()) {
+                        let mut tools = Vec::new();
+                        // First add local tools to tools
+                        tools.extend(local_tools);
+                        for tool_value in tools_array {
+                            debug!("Processing tool value: {:?}", tool_value);
+                            if let Some(tool) = McpTool::from_value(&tool_value) {
+                                tools.push(tool);
                             } else {
-                                warn!("No 'tools' field in server response result: {:?}", result);
+                                warn!("Failed to parse tool from server response: {:?}", tool_value);
                             }
-                        } else {
-                            warn!("No result in JSON-RPC response");
                         }
-                        
-                        // Return local tool list if parsing fails
-                        warn!("Failed to parse tools from server response");
-                        Ok(local_tools)
-                    }
-                    Err(e) => {
-                        // Return local tool list when unable to connect to server
-                        warn!("Failed to send request to MCP server: {}", e);
-                        Ok(local_tools)
+                        return Ok(tools);
+                    } else {
+                        warn!("Failed to parse tools array from server response: {:?}", tools_value);
                     }
+                } else {
+                    warn!("No 'tools' field in server response result: {:?}", result);
                 }
             } else {
-                // Return local tool list if no URL is set
-                Ok(local_tools)
+                warn!("No result in JSON-RPC response");
             }
+            
+            // Return local tool list if parsing fails
+            warn!("Failed to parse tools from server response");
+            Ok(local_tools)
         })
     }
     
@@ -237,30 +509,23 @@ impl McpClient for SimpleMcpClient {
                 // Otherwise send JSON-RPC request via HTTP
                 if !url.is_empty() {
                     // Construct JSON-RPC request
-                    let request = JSONRPCRequest {
-                        jsonrpc: "2.0".to_string(),
-                        id: Some(Value::String(Uuid::new_v4().to_string())),
-                        method: "tools/call".to_string(),
-                        params: Some(json!({
-                            "name": tool_name,
-                            "arguments": params
-                        })),
-                    };
-
-                    // Send HTTP POST request
-                    let client = reqwest::Client::new();
-                    let response = client
-                        .post(&format!("{}/rpc", url))
-                        .json(&request)
-                        .send()
-                        .await?;
+                    let mut call_params = json!({
+                        "name": tool_name,
+                        "arguments": params
+                    });
+                    // Ask for progress notifications, they are streamed back with the response
+                    if self.streamable_http_path.is_some() {
+                        call_params["_meta"] = json!({"progressToken": Uuid::new_v4().to_string()});
+                    }
+                    let request = JSONRPCRequest::new("tools/call", Some(call_params));
 
-                    // Parse response
-                    let rpc_response: JSONRPCResponse = response.json().await?;
+                    // Send request and parse response
+                    let rpc_response = self.send_message(&request).await?
+                        .ok_or_else(|| Error::msg("Empty tools/call response"))?;
                     
-                    // Check for errors
+                    // Check for errors, kept as JSONRPCError so callers can tell them from transport failures
                     if let Some(error) = rpc_response.error {
-                        return Err(Error::msg(format!("JSON-RPC error: {} (code: {})", error.message, error.code)));
+                        return Err(Error::new(error));
                     }
                     
                     // Return result
@@ -292,10 +557,25 @@ impl McpClient for SimpleMcpClient {
         let url = self.url.clone();
         let is_connected = self.is_mcp_server_connected.clone();
         Box::pin(async move {
-            // Simple implementation: simulate successful disconnection
+            if let Some(handle) = self.notification_stream.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                handle.abort();
+            }
+            // Streamable HTTP sessions are terminated explicitly, the server may refuse (405)
+            let session_id = self.session_id.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(session_id) = session_id {
+                let response = reqwest::Client::new()
+                    .delete(self.endpoint())
+                    .header(MCP_SESSION_ID_HEADER, session_id)
+                    .send()
+                    .await;
+                if let Err(e) = response {
+                    warn!("Failed to terminate MCP session: {}", e);
+                }
+            }
             if let Ok(mut conn) = is_connected.lock() {
                 *conn = false;
             }
+            self.set_initialize_result(None);
             info!("Disconnected from MCP server at {}", url);
             Ok(())
         })
@@ -318,11 +598,8 @@ impl McpClient for SimpleMcpClient {
     
     // Clone method
     fn clone(&self) -> Box<dyn McpClient> {
-        // Manually create deep copy of available_tools
-        let tools = self.available_tools.iter().map(|t| McpTool {
-            name: t.name.clone(),
-            description: t.description.clone()
-        }).collect();
+        // Create deep copy of available_tools
+        let tools = self.available_tools.clone();
         
         // Copy tool handlers
         let tool_handlers = self.tool_handlers.clone();
@@ -334,58 +611,107 @@ impl McpClient for SimpleMcpClient {
             Arc::new(Mutex::new(false))
         };
         
+        // Copy the negotiated session
+        let initialize_result = self.initialize_result.lock().map(|r| r.clone()).unwrap_or(None);
+        
         Box::new(SimpleMcpClient {
             url: self.url.clone(),
             available_tools: tools,
             tool_handlers,
             is_mcp_server_connected: is_connected,
+            client_info: self.client_info.clone(),
+            capabilities: self.capabilities.clone(),
+            initialize_result: Arc::new(Mutex::new(initialize_result)),
+            streamable_http_path: self.streamable_http_path.clone(),
+            session_id: Arc::new(Mutex::new(self.session_id())),
+            notification_handlers: self.notification_handlers.clone(),
+            // Shared so that disconnecting any clone closes the stream
+            notification_stream: self.notification_stream.clone(),
+        })
+    }
+    
+    // List resources of the server
+    fn list_resources(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResource>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if !self.supports_resources() {
+                return Ok(Vec::new());
+            }
+            list_all_pages("resources", |params| self.request("resources/list", params)).await
+        })
+    }
+    
+    // List resource templates of the server
+    fn list_resource_templates(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResourceTemplate>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if !self.supports_resources() {
+                return Ok(Vec::new());
+            }
+            list_all_pages("resourceTemplates", |params| self.request("resources/templates/list", params)).await
+        })
+    }
+    
+    // Read a resource
+    fn read_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            parse_read_result(self.request("resources/read", Some(params)).await?)
+        })
+    }
+    
+    // Subscribe to notifications/resources/updated of a resource
+    fn subscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            self.request("resources/subscribe", Some(params)).await?;
+            Ok(())
+        })
+    }
+    
+    // Stop receiving notifications/resources/updated of a resource
+    fn unsubscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            self.request("resources/unsubscribe", Some(params)).await?;
+            Ok(())
+        })
+    }
+    
+    // List prompts of the server
+    fn list_prompts(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpPrompt>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if !self.supports_prompts() {
+                return Ok(Vec::new());
+            }
+            list_all_pages("prompts", |params| self.request("prompts/list", params)).await
+        })
+    }
+    
+    // Get a prompt with its arguments filled in
+    fn get_prompt(&self, name: &str, arguments: HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<GetPromptResult, Error>> + Send + '_>> {
+        let params = json!({"name": name, "arguments": arguments});
+        Box::pin(async move {
+            let result = self.request("prompts/get", Some(params)).await?;
+            serde_json::from_value(result).map_err(|e| Error::msg(format!("Invalid prompts/get result: {}", e)))
         })
     }
     
     // Ping服务器
     fn ping(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
-        let url = self.url.clone();
         Box::pin(async move {
-            if !url.is_empty() {
-                // 创建 ping 请求
-                let request = JSONRPCRequest {
-                    jsonrpc: "2.0".to_string(),
-                    id: Some(Value::Number(serde_json::Number::from(1))),
-                    method: "ping".to_string(),
-                    params: None,
-                };
-            
-                // 发送请求到服务器 - 使用正确的路径 /rpc
-                let url = format!("{}/rpc", url);
-                let client = reqwest::Client::new();
-                let response = client
-                    .post(&url)
-                    .header("Content-Type", "application/json")
-                    .json(&request)
-                    .send()
-                    .await
-                    .map_err(|e| Error::msg(format!("Failed to send ping request: {}", e)))?;
-            
-                // 检查响应状态
-                if !response.status().is_success() {
-                    return Err(Error::msg(format!("Ping request failed with status: {}", response.status())));
-                }
-            
-                // 解析响应
-                let response_text = response.text().await
-                    .map_err(|e| Error::msg(format!("Failed to read response: {}", e)))?;
-                let response_value: Value = serde_json::from_str(&response_text)
-                    .map_err(|e| Error::msg(format!("Failed to parse response: {}", e)))?;
+            if !self.url.is_empty() {
+                // 创建 ping 请求并发送到服务器
+                let request = JSONRPCRequest::new("ping", None);
+                let response = self.send_message(&request).await
+                    .map_err(|e| Error::msg(format!("Ping request failed: {}", e)))?
+                    .ok_or_else(|| Error::msg("No result in ping response"))?;
             
                 // 检查响应中是否有错误
-                if let Some(error) = response_value.get("error") {
-                    if !error.is_null() {
-                        return Err(Error::msg(format!("Ping request returned error: {}", error)));
-                    }
+                if let Some(error) = response.error {
+                    return Err(Error::msg(format!("Ping request returned error: {} (code: {})", error.message, error.code)));
                 }
                 
                 // 检查是否有结果字段
-                if let Some(_result) = response_value.get("result") {
+                if response.result.is_some() {
                     // Ping 成功，返回空结果
                     Ok(())
                 } else {
@@ -412,10 +738,10 @@ pub trait McpClient: Send + Sync {
     fn get_tools(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpTool>, Error>> + Send + '_>> {
         Box::pin(async move {
             // Simple implementation: return simulated tool list
-            Ok(vec![McpTool {
-                name: "example_tool".to_string(),
-                description: "Example tool description".to_string()
-            }])
+            Ok(vec![McpTool::new(
+                "example_tool".to_string(),
+                "Example tool description".to_string(),
+            )])
         })
     }
     
@@ -429,6 +755,13 @@ pub trait McpClient: Send + Sync {
         })
     }
     
+    // Call specified tool and parse the result into content blocks
+    // Failures of the tool itself are Ok results with is_error set, Err means the call did not happen.
+    fn call_tool_result(&self, tool_name: &str, params: HashMap<String, Value>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<CallToolResult, Error>> + Send + '_>> {
+        let call = self.call_tool(tool_name, params);
+        Box::pin(async move { call.await.map(CallToolResult::from_value) })
+    }
+    
     // Disconnect
     fn disconnect(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
         Box::pin(async move {
@@ -462,4 +795,49 @@ pub trait McpClient: Send + Sync {
             Err(Error::msg("HTTP client not implemented in trait"))
         })
     }
+    
+    // List resources of the server, clients without resource support have none
+    fn list_resources(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResource>, Error>> + Send + '_>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+    
+    // List resource templates of the server
+    fn list_resource_templates(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResourceTemplate>, Error>> + Send + '_>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+    
+    // Read a resource
+    fn read_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, Error>> + Send + '_>> {
+        let uri = uri.to_string();
+        Box::pin(async move {
+            Err(Error::msg(format!("Resources not supported by this client, cannot read {}", uri)))
+        })
+    }
+    
+    // Subscribe to notifications/resources/updated of a resource
+    fn subscribe_resource(&self, _uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            Err(Error::msg("Resource subscriptions not supported by this client"))
+        })
+    }
+    
+    // Stop receiving notifications/resources/updated of a resource
+    fn unsubscribe_resource(&self, _uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            Err(Error::msg("Resource subscriptions not supported by this client"))
+        })
+    }
+
+    // List prompts of the server, clients without prompt support have none
+    fn list_prompts(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpPrompt>, Error>> + Send + '_>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+    
+    // Get a prompt with its arguments filled in
+    fn get_prompt(&self, name: &str, _arguments: HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<GetPromptResult, Error>> + Send + '_>> {
+        let name = name.to_string();
+        Box::pin(async move {
+            Err(Error::msg(format!("Prompts not supported by this client, cannot get {}", name)))
+        })
+    }
 }
@@ -0,0 +1,188 @@
+// MCP tool results: content blocks returned by tools/call
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mcp::ResourceContents;
+
+// Content block of a tool result
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum ToolResultContent {
+    Text {
+        text: String,
+    },
+    // Base64 encoded image
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    // Base64 encoded audio
+    Audio {
+        data: String,
+        mime_type: String,
+    },
+    // Resource embedded in the result
+    Resource {
+        resource: ResourceContents,
+    },
+    // Link to a resource the client can read with resources/read
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+impl ToolResultContent {
+    pub fn text(text: impl Into<String>) -> Self {
+        ToolResultContent::Text { text: text.into() }
+    }
+
+    /// Image content, data is base64 encoded here
+    pub fn image(mime_type: impl Into<String>, data: &[u8]) -> Self {
+        use base64::Engine;
+        ToolResultContent::Image {
+            data: base64::engine::general_purpose::STANDARD.encode(data),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    pub fn resource(resource: ResourceContents) -> Self {
+        ToolResultContent::Resource { resource }
+    }
+
+    // Text shown to the model, binary content is described instead of inlined
+    fn observation(&self) -> String {
+        match self {
+            ToolResultContent::Text { text } => text.clone(),
+            ToolResultContent::Image { data, mime_type } => format!("[image {}, {} base64 bytes]", mime_type, data.len()),
+            ToolResultContent::Audio { data, mime_type } => format!("[audio {}, {} base64 bytes]", mime_type, data.len()),
+            ToolResultContent::Resource { resource } => match resource.as_text() {
+                Some(text) => text.to_string(),
+                None => format!("[resource {}]", resource.uri()),
+            },
+            ToolResultContent::ResourceLink { uri, name, .. } => format!("[resource link {} ({})]", name, uri),
+        }
+    }
+}
+
+// Result of tools/call
+// Tool failures are results with is_error set, so the model sees them and can recover;
+// JSON-RPC errors are kept for protocol problems such as an unknown tool.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    #[serde(default)]
+    pub content: Vec<ToolResultContent>,
+    // JSON value matching the outputSchema of the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    /// Successful result with one text block
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: vec![ToolResultContent::text(text)],
+            ..Default::default()
+        }
+    }
+
+    /// Failed result, the message is shown to the model
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            content: vec![ToolResultContent::text(message)],
+            is_error: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_content(mut self, content: ToolResultContent) -> Self {
+        self.content.push(content);
+        self
+    }
+
+    /// Attach structured content, its JSON text is also added as a text block for older clients
+    pub fn with_structured_content(mut self, structured_content: Value) -> Self {
+        self.content.push(ToolResultContent::text(structured_content.to_string()));
+        self.structured_content = Some(structured_content);
+        self
+    }
+
+    /// Parse a tools/call result, values of servers predating content blocks become a text block
+    pub fn from_value(value: Value) -> Self {
+        if value.get("content").is_some_and(|c| c.is_array()) {
+            if let Ok(result) = serde_json::from_value::<CallToolResult>(value.clone()) {
+                return result;
+            }
+        }
+        match value {
+            Value::String(text) => Self::text(text),
+            other => Self::text(serde_json::to_string_pretty(&other).unwrap_or_else(|_| other.to_string())),
+        }
+    }
+
+    // Result of a local tool, output that is a serialized CallToolResult is passed through as is
+    pub(crate) fn from_tool_output(output: String) -> Self {
+        if output.trim_start().starts_with('{') {
+            if let Ok(value) = serde_json::from_str::<Value>(&output) {
+                if value.get("content").is_some_and(|c| c.is_array()) {
+                    if let Ok(result) = serde_json::from_value::<CallToolResult>(value) {
+                        return result;
+                    }
+                }
+            }
+        }
+        Self::text(output)
+    }
+
+    /// Text of the result for the model, content blocks are separated by new lines
+    pub fn to_observation(&self) -> String {
+        let mut observation = self.content.iter()
+            .map(|content| content.observation())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if observation.is_empty() {
+            if let Some(structured_content) = &self.structured_content {
+                observation = structured_content.to_string();
+            }
+        }
+        observation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_call_tool_result_wire_format() {
+        let result = CallToolResult::text("balance: 1.5 ETH")
+            .with_content(ToolResultContent::image("image/png", &[0, 159, 255]))
+            .with_content(ToolResultContent::resource(ResourceContents::text("logs://task/1", None, "mined")));
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(value, json!({
+            "content": [
+                {"type": "text", "text": "balance: 1.5 ETH"},
+                {"type": "image", "data": "AJ//", "mimeType": "image/png"},
+                {"type": "resource", "resource": {"uri": "logs://task/1", "text": "mined"}}
+            ],
+            "isError": false
+        }));
+        assert_eq!(CallToolResult::from_value(value), result);
+        assert_eq!(result.to_observation(), "balance: 1.5 ETH\n[image image/png, 4 base64 bytes]\nmined");
+
+        let link: ToolResultContent = serde_json::from_value(json!({"type": "resource_link", "uri": "picker://manifests", "name": "Pickers"})).unwrap();
+        assert_eq!(link.observation(), "[resource link Pickers (picker://manifests)]");
+
+        // Results of servers without content blocks
+        assert_eq!(CallToolResult::from_value(json!("done")).to_observation(), "done");
+        assert_eq!(CallToolResult::from_value(json!({"city": "Paris"})).to_observation(), "{\n  \"city\": \"Paris\"\n}");
+    }
+}
@@ -0,0 +1,114 @@
+// Per-request context of the MCP server, lets tools push notifications while they run
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::mcp::JSONRPCRequest;
+
+// Delivers a notification to the client over the transport of the current request
+pub(crate) type NotificationSink = Arc<dyn Fn(JSONRPCRequest) + Send + Sync>;
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: McpRequestContext;
+}
+
+// Severity of notifications/message, ordered from least to most severe (RFC 5424)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+// Context of the request being handled, available to tools through McpRequestContext::current
+#[derive(Clone)]
+pub struct McpRequestContext {
+    request_id: Value,
+    progress_token: Option<Value>,
+    // Minimum level set by the client with logging/setLevel
+    log_level: LoggingLevel,
+    sink: Option<NotificationSink>,
+}
+
+impl McpRequestContext {
+    pub(crate) fn new(request_id: Value, params: Option<&Value>, log_level: LoggingLevel, sink: Option<NotificationSink>) -> Self {
+        let progress_token = params
+            .and_then(|p| p.get("_meta"))
+            .and_then(|m| m.get("progressToken"))
+            .filter(|t| !t.is_null())
+            .cloned();
+        Self {
+            request_id,
+            progress_token,
+            log_level,
+            sink,
+        }
+    }
+
+    /// Context of the MCP request handled by the current task, None outside of a request
+    pub fn current() -> Option<Self> {
+        REQUEST_CONTEXT.try_with(|context| context.clone()).ok()
+    }
+
+    // Run a future with this context as the current one
+    pub(crate) async fn scope<F: Future>(self, future: F) -> F::Output {
+        REQUEST_CONTEXT.scope(self, future).await
+    }
+
+    /// JSON-RPC id of the request
+    pub fn request_id(&self) -> &Value {
+        &self.request_id
+    }
+
+    /// Progress token sent by the client in _meta, progress is only reported when present
+    pub fn progress_token(&self) -> Option<&Value> {
+        self.progress_token.as_ref()
+    }
+
+    /// Send notifications/progress, progress must increase with every call
+    pub fn report_progress(&self, progress: f64, total: Option<f64>, message: Option<&str>) {
+        let Some(token) = &self.progress_token else {
+            return;
+        };
+        let mut params = json!({
+            "progressToken": token,
+            "progress": progress,
+        });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if let Some(message) = message {
+            params["message"] = json!(message);
+        }
+        self.notify("notifications/progress", params);
+    }
+
+    /// Send notifications/message if the level is at least the one requested by the client
+    pub fn log(&self, level: LoggingLevel, logger: Option<&str>, data: Value) {
+        if level < self.log_level {
+            return;
+        }
+        let mut params = json!({
+            "level": level,
+            "data": data,
+        });
+        if let Some(logger) = logger {
+            params["logger"] = json!(logger);
+        }
+        self.notify("notifications/message", params);
+    }
+
+    /// Send any notification to the client
+    pub fn notify(&self, method: &str, params: Value) {
+        if let Some(sink) = &self.sink {
+            sink(JSONRPCRequest::notification(method, Some(params)));
+        }
+    }
+}
@@ -1,19 +1,38 @@
 // MCP adapter implementation module definition
 mod client;
+mod context;
 mod adapter;
 mod server;
+mod protocol;
+mod resource;
+mod prompt;
+mod pool;
+mod content;
+mod stdio;
 
 // Re-export module content
-pub use client::{McpClient, SimpleMcpClient, McpTool};
+pub use client::{McpClient, McpNotificationHandler, SimpleMcpClient, McpTool};
 pub use adapter::McpToolAdapter;
 pub use server::{McpServer, SimpleMcpServer};
+pub use stdio::StdioMcpClient;
+pub use content::{CallToolResult, ToolResultContent};
+pub use pool::{McpClientPool, McpServerHealth, ToolConflictPolicy, TOOL_NAMESPACE_SEPARATOR};
+pub use context::{LoggingLevel, McpRequestContext};
+pub use resource::{McpResource, McpResourceTemplate, Resource, ResourceContents, match_uri_template};
+pub use prompt::{GetPromptResult, McpPrompt, McpPromptTemplate, Prompt, PromptArgument, PromptMessage};
+pub use protocol::{
+    ClientCapabilities, Implementation, InitializeParams, InitializeResult, ServerCapabilities,
+    LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS, is_supported_protocol_version, negotiate_protocol_version,
+};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct JSONRPCRequest {
     pub jsonrpc: String,
+    // Absent for notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub id: Option<Value>,
     pub method: String,
     pub params: Option<Value>,
@@ -23,7 +42,10 @@ pub struct JSONRPCRequest {
 pub struct JSONRPCResponse {
     jsonrpc: String,
     id: Option<Value>,
+    // Exactly one of result and error is sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     error: Option<JSONRPCError>,
 }
 
@@ -33,10 +55,74 @@ pub struct JSONRPCError {
     message: String,
 }
 
+impl JSONRPCError {
+    // Error with a specific code, handlers return it through anyhow to control the response code
+    pub(crate) fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for JSONRPCError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code: {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for JSONRPCError {}
+
+impl JSONRPCRequest {
+    // Request expecting a response, identified by a fresh id
+    pub(crate) fn new(method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(Value::String(uuid::Uuid::new_v4().to_string())),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    // Notification, sent without id and never answered
+    pub(crate) fn notification(method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+impl JSONRPCResponse {
+    pub(crate) fn success(id: Option<Value>, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id.unwrap_or(Value::Null)),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub(crate) fn error(id: Option<Value>, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id.unwrap_or(Value::Null)),
+            result: None,
+            error: Some(JSONRPCError {
+                code,
+                message: message.into(),
+            }),
+        }
+    }
+}
+
 // MCP Ping interface test
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
     use tokio::time::{timeout, Duration};
     
     #[tokio::test]
@@ -79,4 +165,499 @@ mod tests {
             panic!("Failed to stop MCP server: {}", e);
         }
     }
+    
+    // Tool with a declared input schema
+    struct EchoTool;
+    
+    impl crate::tools::Tool for EchoTool {
+        fn name(&self) -> &str {
+            "echo"
+        }
+        
+        fn description(&self) -> &str {
+            "Echo the text"
+        }
+        
+        fn input_schema(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {"text": {"type": "string"}},
+                "required": ["text"]
+            })
+        }
+        
+        fn invoke(&self, input: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>> {
+            let input = input.to_string();
+            Box::pin(async move { Ok(input) })
+        }
+        
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_tools_list_input_schema() {
+        let server = SimpleMcpServer::new();
+        server.register_tool(std::sync::Arc::new(EchoTool)).unwrap();
+        let server_address = "127.0.0.1:6001";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let client = SimpleMcpClient::new(format!("http://{}", server_address));
+        client.set_server_connected(true);
+        let tools = timeout(Duration::from_secs(5), client.get_tools()).await.unwrap().unwrap();
+        let echo = tools.iter().find(|t| t.name == "echo").expect("echo tool listed");
+        assert_eq!(echo.input_schema["required"], serde_json::json!(["text"]));
+        assert_eq!(echo.input_schema["properties"]["text"]["type"], "string");
+        
+        server.stop().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_initialize_handshake() {
+        let server = SimpleMcpServer::new()
+            .with_server_info("test-server", "1.2.3")
+            .with_instructions("Use echo to repeat text");
+        let server_address = "127.0.0.1:6002";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let url = format!("http://{}", server_address);
+        let mut client = SimpleMcpClient::new(url.clone()).with_client_info("test-client", "0.1.0");
+        timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap();
+        
+        assert!(client.is_server_connected());
+        assert_eq!(client.protocol_version().as_deref(), Some(LATEST_PROTOCOL_VERSION));
+        assert_eq!(client.server_info(), Some(Implementation::new("test-server", "1.2.3")));
+        assert!(client.server_capabilities().unwrap().tools.is_some());
+        assert_eq!(client.server_instructions().as_deref(), Some("Use echo to repeat text"));
+        assert_eq!(server.client_info(), Some(Implementation::new("test-client", "0.1.0")));
+        assert!(server.is_initialized());
+        
+        // Older supported versions are echoed, unknown ones are answered with the latest
+        let http = reqwest::Client::new();
+        for (requested, expected) in [("2024-11-05", "2024-11-05"), ("1999-01-01", LATEST_PROTOCOL_VERSION)] {
+            let request = JSONRPCRequest::new("initialize", Some(serde_json::json!({
+                "protocolVersion": requested,
+                "capabilities": {},
+                "clientInfo": {"name": "raw", "version": "0"}
+            })));
+            let response: Value = http.post(format!("{}/rpc", url)).json(&request).send().await.unwrap().json().await.unwrap();
+            assert_eq!(response["result"]["protocolVersion"], expected);
+        }
+        
+        // Notifications get no JSON-RPC response
+        let notification = JSONRPCRequest::notification("notifications/initialized", None);
+        let response = http.post(format!("{}/rpc", url)).json(&notification).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+        
+        server.stop().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_client_refuses_unsupported_protocol_version() {
+        use axum::{routing::post, Json, Router};
+        
+        // Third-party server that only speaks a future protocol version
+        let app = Router::new().route("/rpc", post(|Json(request): Json<JSONRPCRequest>| async move {
+            Json(JSONRPCResponse::success(request.id, serde_json::json!({
+                "protocolVersion": "2099-01-01",
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": "future-server", "version": "9.0.0"}
+            })))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:6003").await.unwrap();
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        
+        let url = "http://127.0.0.1:6003".to_string();
+        let mut client = SimpleMcpClient::new(url.clone());
+        let error = timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("unsupported protocol version 2099-01-01"));
+        assert!(!client.is_server_connected());
+        assert_eq!(client.protocol_version(), None);
+        
+        handle.abort();
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_server_over_stdio() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        
+        let server = SimpleMcpServer::new();
+        server.register_tool(std::sync::Arc::new(EchoTool)).unwrap();
+        server.register_tool(std::sync::Arc::new(ProgressTool)).unwrap();
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_reader, server_writer) = tokio::io::split(server_side);
+        let serve = tokio::spawn(async move { server.serve(server_reader, server_writer).await });
+        
+        let (client_reader, mut client_writer) = tokio::io::split(client_side);
+        let mut lines = BufReader::new(client_reader).lines();
+        let send = |message: Value| {
+            let mut line = message.to_string();
+            line.push('\n');
+            line
+        };
+        
+        let initialize = send(serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": LATEST_PROTOCOL_VERSION, "capabilities": {}, "clientInfo": {"name": "stdio-test", "version": "0"}}
+        }));
+        client_writer.write_all(initialize.as_bytes()).await.unwrap();
+        let response: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], LATEST_PROTOCOL_VERSION);
+        assert!(response.get("error").is_none());
+        
+        // The notification gets no response, so the next line answers the tool call
+        let messages = [
+            send(serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"})),
+            "not json\n".to_string(),
+            send(serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {"name": "echo", "arguments": {"text": "hi"}}})),
+        ];
+        for message in messages {
+            client_writer.write_all(message.as_bytes()).await.unwrap();
+        }
+        let parse_error: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(parse_error["error"]["code"], -32700);
+        let response: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"]["content"][0]["text"], "{\"text\":\"hi\"}");
+        
+        // Notifications of a tool are written before its response
+        let call = send(serde_json::json!({
+            "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+            "params": {"name": "wait_for_confirmations", "arguments": {}, "_meta": {"progressToken": "p1"}}
+        }));
+        client_writer.write_all(call.as_bytes()).await.unwrap();
+        let mut methods = Vec::new();
+        loop {
+            let message: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            if message["id"] == 3 {
+                assert_eq!(message["result"]["content"][0]["text"], "confirmed");
+                break;
+            }
+            assert_eq!(message.get("id"), None);
+            methods.push(message["method"].as_str().unwrap().to_string());
+        }
+        assert_eq!(methods, vec!["notifications/progress", "notifications/message", "notifications/progress"]);
+        
+        // Closing the input stops the server
+        client_writer.shutdown().await.unwrap();
+        timeout(Duration::from_secs(5), serve).await.unwrap().unwrap().unwrap();
+    }
+    
+    // Tool reporting progress and a log message through the request context
+    struct ProgressTool;
+    
+    impl crate::tools::Tool for ProgressTool {
+        fn name(&self) -> &str {
+            "wait_for_confirmations"
+        }
+        
+        fn description(&self) -> &str {
+            "Wait for two block confirmations"
+        }
+        
+        fn invoke(&self, _input: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>> {
+            Box::pin(async move {
+                let context = McpRequestContext::current().expect("called inside an MCP request");
+                context.report_progress(1.0, Some(2.0), Some("1/2 confirmations"));
+                context.log(LoggingLevel::Debug, None, serde_json::json!("filtered by the default level"));
+                context.log(LoggingLevel::Info, Some("chain"), serde_json::json!("transaction mined"));
+                context.report_progress(2.0, Some(2.0), None);
+                Ok("confirmed".to_string())
+            })
+        }
+        
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_streamable_http_notifications() {
+        use std::sync::{Arc, Mutex};
+        
+        let server = SimpleMcpServer::new();
+        server.register_tool(Arc::new(ProgressTool)).unwrap();
+        let server_address = "127.0.0.1:6004";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let url = format!("http://{}", server_address);
+        let received: Arc<Mutex<Vec<(String, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut client = SimpleMcpClient::new(url.clone()).with_streamable_http("/mcp");
+        let sink = received.clone();
+        client.register_notification_handler(move |method, params| {
+            sink.lock().unwrap().push((method.to_string(), params.clone()));
+        });
+        timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap();
+        let session_id = client.session_id().expect("session id assigned in initialize");
+        assert_eq!(server.session_ids(), vec![session_id.clone()]);
+        
+        // Progress and log notifications arrive on the SSE response before the result
+        let result = timeout(Duration::from_secs(5), client.call_tool_result("wait_for_confirmations", HashMap::new())).await.unwrap().unwrap();
+        assert_eq!(result.to_observation(), "confirmed");
+        {
+            let received = received.lock().unwrap();
+            let methods: Vec<&str> = received.iter().map(|(method, _)| method.as_str()).collect();
+            assert_eq!(methods, vec!["notifications/progress", "notifications/message", "notifications/progress"]);
+            assert_eq!(received[0].1["progress"], 1.0);
+            assert_eq!(received[0].1["total"], 2.0);
+            assert_eq!(received[0].1["message"], "1/2 confirmations");
+            assert_eq!(received[1].1["level"], "info");
+            assert_eq!(received[1].1["data"], "transaction mined");
+        }
+        
+        // Server-initiated notifications use the GET stream, retried until the stream is open
+        let mut list_changed = false;
+        for _ in 0..30 {
+            server.notify_tools_list_changed();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            list_changed = received.lock().unwrap().iter().any(|(method, _)| method == "notifications/tools/list_changed");
+            if list_changed {
+                break;
+            }
+        }
+        assert!(list_changed);
+        
+        // Requests without or with an unknown session are rejected
+        let http = reqwest::Client::new();
+        let ping = JSONRPCRequest::new("ping", None);
+        let response = http.post(format!("{}/mcp", url)).json(&ping).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        client.disconnect().await.unwrap();
+        assert!(server.session_ids().is_empty());
+        let response = http.post(format!("{}/mcp", url)).header("mcp-session-id", session_id).json(&ping).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        
+        server.stop().await.unwrap();
+    }
+    
+    // Installed picker manifests, a plain text resource
+    struct ManifestsResource;
+    
+    impl Resource for ManifestsResource {
+        fn uri(&self) -> &str {
+            "picker://manifests"
+        }
+        
+        fn name(&self) -> &str {
+            "Installed pickers"
+        }
+        
+        fn mime_type(&self) -> Option<&str> {
+            Some("application/json")
+        }
+        
+        fn read(&self, uri: &str, _params: &HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, anyhow::Error>> + Send + '_>> {
+            let contents = ResourceContents::text(uri, self.mime_type(), r#"[{"name":"eth-miner"}]"#);
+            Box::pin(async move { Ok(vec![contents]) })
+        }
+    }
+    
+    // Wallet balance per chain and address, a binary template resource
+    struct BalanceResource;
+    
+    impl Resource for BalanceResource {
+        fn uri(&self) -> &str {
+            "wallet://{chain}/{address}/balance"
+        }
+        
+        fn name(&self) -> &str {
+            "Wallet balance"
+        }
+        
+        fn read(&self, uri: &str, params: &HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, anyhow::Error>> + Send + '_>> {
+            let balance = format!("{}:{}", params["chain"], params["address"]);
+            let contents = ResourceContents::blob(uri, Some("application/octet-stream"), balance.as_bytes());
+            Box::pin(async move { Ok(vec![contents]) })
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_resources() {
+        use std::sync::{Arc, Mutex};
+        
+        let server = SimpleMcpServer::new();
+        server.register_resource(Arc::new(ManifestsResource)).unwrap();
+        server.register_resource(Arc::new(BalanceResource)).unwrap();
+        let server_address = "127.0.0.1:6005";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let url = format!("http://{}", server_address);
+        let updated: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut client = SimpleMcpClient::new(url.clone()).with_streamable_http("/mcp");
+        let sink = updated.clone();
+        client.register_notification_handler(move |method, params| {
+            if method == "notifications/resources/updated" {
+                sink.lock().unwrap().push(params.clone());
+            }
+        });
+        timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap();
+        
+        let resources = client.list_resources().await.unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].uri, "picker://manifests");
+        assert_eq!(resources[0].mime_type.as_deref(), Some("application/json"));
+        let templates = client.list_resource_templates().await.unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].uri_template, "wallet://{chain}/{address}/balance");
+        
+        let contents = client.read_resource("picker://manifests").await.unwrap();
+        assert_eq!(contents[0].as_text(), Some(r#"[{"name":"eth-miner"}]"#));
+        let contents = client.read_resource("wallet://eth/0xabc/balance").await.unwrap();
+        assert_eq!(contents[0].uri(), "wallet://eth/0xabc/balance");
+        assert_eq!(contents[0].to_bytes().unwrap(), b"eth:0xabc".to_vec());
+        
+        let error = client.read_resource("wallet://eth/balance").await.unwrap_err();
+        let error = error.downcast_ref::<JSONRPCError>().expect("JSON-RPC error");
+        assert_eq!(error.code, -32002);
+        
+        // Only subscribed URIs are notified, over the GET stream
+        client.subscribe_resource("picker://manifests").await.unwrap();
+        let mut notified = false;
+        for _ in 0..30 {
+            server.notify_resource_updated("picker://logs");
+            server.notify_resource_updated("picker://manifests");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            notified = !updated.lock().unwrap().is_empty();
+            if notified {
+                break;
+            }
+        }
+        assert!(notified);
+        assert!(updated.lock().unwrap().iter().all(|params| params["uri"] == "picker://manifests"));
+        client.unsubscribe_resource("picker://manifests").await.unwrap();
+        
+        client.disconnect().await.unwrap();
+        server.stop().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_prompts() {
+        use crate::models::ChatMessage;
+        use crate::prompt::ChatPromptTemplate;
+        use std::sync::Arc;
+        
+        let template = ChatPromptTemplate::from_messages(&[
+            ("system", "You deploy contracts on {chain}."),
+            ("human", "Deploy an ERC20 named {name} with a supply of {supply}."),
+        ]).unwrap();
+        let prompt = McpPromptTemplate::new("deploy_erc20", template)
+            .with_description("Deploy an ERC20 with these defaults")
+            .with_argument("supply", "Initial supply", false);
+        let server = SimpleMcpServer::new();
+        server.register_prompt(Arc::new(prompt)).unwrap();
+        let server_address = "127.0.0.1:6006";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let url = format!("http://{}", server_address);
+        let mut client = SimpleMcpClient::new(url.clone());
+        timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap();
+        
+        let prompts = client.list_prompts().await.unwrap();
+        assert_eq!(prompts.len(), 1);
+        assert_eq!(prompts[0].name, "deploy_erc20");
+        assert_eq!(prompts[0].description, "Deploy an ERC20 with these defaults");
+        let required: Vec<(&str, bool)> = prompts[0].arguments.iter().map(|a| (a.name.as_str(), a.required)).collect();
+        assert_eq!(required, vec![("chain", true), ("name", true), ("supply", false)]);
+        
+        let mut arguments = HashMap::new();
+        arguments.insert("chain".to_string(), "Ethereum".to_string());
+        arguments.insert("name".to_string(), "PICK".to_string());
+        arguments.insert("supply".to_string(), "1000000".to_string());
+        let result = client.get_prompt("deploy_erc20", arguments.clone()).await.unwrap();
+        assert_eq!(result.description.as_deref(), Some("Deploy an ERC20 with these defaults"));
+        let messages = result.to_chat_messages();
+        assert_eq!(messages.len(), 2);
+        match &messages[1] {
+            ChatMessage::Human(content) => assert_eq!(content.content, "Deploy an ERC20 named PICK with a supply of 1000000."),
+            other => panic!("unexpected message {:?}", other),
+        }
+        
+        // Unknown prompts and missing required arguments are invalid params
+        arguments.remove("name");
+        for name in ["deploy_erc20", "mint_nft"] {
+            let error = client.get_prompt(name, arguments.clone()).await.unwrap_err();
+            assert_eq!(error.downcast_ref::<JSONRPCError>().expect("JSON-RPC error").code, -32602);
+        }
+        
+        client.disconnect().await.unwrap();
+        server.stop().await.unwrap();
+    }
+    
+    // Tool failing like a transfer with insufficient funds
+    struct TransferTool;
+    
+    impl crate::tools::Tool for TransferTool {
+        fn name(&self) -> &str {
+            "transfer"
+        }
+        
+        fn description(&self) -> &str {
+            "Transfer tokens, returns a receipt and a QR code of the transaction"
+        }
+        
+        fn invoke(&self, input: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, anyhow::Error>> + Send + '_>> {
+            let amount = serde_json::from_str::<Value>(input).ok().and_then(|args| args["amount"].as_f64()).unwrap_or_default();
+            Box::pin(async move {
+                if amount > 10.0 {
+                    return Err(anyhow::anyhow!("insufficient funds: balance is 10"));
+                }
+                let result = CallToolResult::text(format!("sent {}", amount))
+                    .with_content(ToolResultContent::image("image/png", &[1, 2, 3]));
+                Ok(serde_json::to_string(&result)?)
+            })
+        }
+        
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_mcp_tool_errors_are_results() {
+        use crate::tools::Tool;
+        use std::sync::Arc;
+        
+        let server = SimpleMcpServer::new();
+        server.register_tool(Arc::new(TransferTool)).unwrap();
+        let server_address = "127.0.0.1:6007";
+        server.start(server_address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let url = format!("http://{}", server_address);
+        let mut client = SimpleMcpClient::new(url.clone());
+        timeout(Duration::from_secs(5), client.connect(&url)).await.unwrap().unwrap();
+        
+        // Content blocks returned by the tool are passed through
+        let mut params = HashMap::new();
+        params.insert("amount".to_string(), serde_json::json!(2.5));
+        let result = client.call_tool_result("transfer", params.clone()).await.unwrap();
+        assert!(!result.is_error);
+        assert_eq!(result.content[1], ToolResultContent::image("image/png", &[1, 2, 3]));
+        
+        // Tool failures are isError results, the adapter turns them into observations
+        params.insert("amount".to_string(), serde_json::json!(50));
+        let result = client.call_tool_result("transfer", params).await.unwrap();
+        assert!(result.is_error);
+        assert_eq!(result.to_observation(), "insufficient funds: balance is 10");
+        let tools = client.get_tools().await.unwrap();
+        let adapter = McpToolAdapter::new(Arc::from(McpClient::clone(&client)), tools[0].clone());
+        assert_eq!(adapter.invoke(r#"{"amount": 50}"#).await.unwrap(), "Tool transfer failed: insufficient funds: balance is 10");
+        assert_eq!(adapter.invoke(r#"{"amount": 1}"#).await.unwrap(), "sent 1\n[image image/png, 4 base64 bytes]");
+        
+        // Unknown tools are still protocol errors
+        let error = client.call_tool("withdraw", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<JSONRPCError>().expect("JSON-RPC error").code, -32602);
+        
+        client.disconnect().await.unwrap();
+        server.stop().await.unwrap();
+    }
 }
@@ -0,0 +1,660 @@
+// Client over several MCP servers, tools and prompts are namespaced by server name
+use anyhow::Error;
+use futures::future::join_all;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+use crate::mcp::{
+    GetPromptResult, JSONRPCError, McpClient, McpPrompt, McpResource, McpResourceTemplate, McpTool, ResourceContents,
+};
+
+/// Separator between the server name and the tool or prompt name, e.g. "chain__get_balance"
+pub const TOOL_NAMESPACE_SEPARATOR: &str = "__";
+
+// How the tool and prompt names of different servers are merged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToolConflictPolicy {
+    // Every name is exposed as server__name
+    #[default]
+    AlwaysPrefix,
+    // Names are kept, names offered by several servers are exposed as server__name for all of them
+    PrefixOnConflict,
+    // Names are kept, the server added first wins and the same name of later servers is hidden
+    FirstWins,
+}
+
+// Health of an upstream server
+#[derive(Clone, Debug)]
+pub struct McpServerHealth {
+    pub name: String,
+    // False while the server is skipped, it is reconnected once the retry delay has passed
+    pub available: bool,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_success: Option<Instant>,
+}
+
+// Mutable health state of a server
+#[derive(Default)]
+struct ServerHealthState {
+    connected: bool,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    last_success: Option<Instant>,
+    // Set while the server is down
+    retry_at: Option<Instant>,
+}
+
+// Upstream server of the pool
+#[derive(Clone)]
+struct PoolServer {
+    name: String,
+    url: String,
+    // Calls share the read lock, (re)connecting takes the write lock
+    client: Arc<RwLock<Box<dyn McpClient>>>,
+    health: Arc<Mutex<ServerHealthState>>,
+}
+
+// Exposed name -> (server index, upstream name)
+type Routes = HashMap<String, (usize, String)>;
+
+/// McpClient over many upstream clients (HTTP and stdio)
+/// Tools are merged with server__tool namespacing and calls are routed to their server.
+/// A server failing `failure_threshold` times in a row, or failing to connect, is skipped
+/// for `retry_after` and then reconnected; the other servers keep working meanwhile.
+#[derive(Clone)]
+pub struct McpClientPool {
+    servers: Vec<PoolServer>,
+    conflict_policy: ToolConflictPolicy,
+    failure_threshold: u32,
+    retry_after: Duration,
+    tool_routes: Arc<Mutex<Routes>>,
+    prompt_routes: Arc<Mutex<Routes>>,
+}
+
+impl McpClientPool {
+    pub fn new() -> Self {
+        Self {
+            servers: Vec::new(),
+            conflict_policy: ToolConflictPolicy::default(),
+            failure_threshold: 3,
+            retry_after: Duration::from_secs(30),
+            tool_routes: Arc::new(Mutex::new(HashMap::new())),
+            prompt_routes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Add an upstream server, `url` is passed to its connect (ignored by stdio clients)
+    /// Names must be unique and must not contain TOOL_NAMESPACE_SEPARATOR.
+    pub fn add_server(&mut self, name: impl Into<String>, url: impl Into<String>, client: Box<dyn McpClient>) -> Result<(), Error> {
+        let name = name.into();
+        if name.is_empty() || name.contains(TOOL_NAMESPACE_SEPARATOR) {
+            return Err(Error::msg(format!(
+                "Invalid MCP server name '{}': must be non-empty and must not contain '{}'",
+                name, TOOL_NAMESPACE_SEPARATOR
+            )));
+        }
+        if self.servers.iter().any(|server| server.name == name) {
+            return Err(Error::msg(format!("MCP server '{}' is already in the pool", name)));
+        }
+        self.servers.push(PoolServer {
+            name,
+            url: url.into(),
+            client: Arc::new(RwLock::new(client)),
+            health: Arc::new(Mutex::new(ServerHealthState::default())),
+        });
+        Ok(())
+    }
+
+    /// Builder form of add_server
+    pub fn with_server(mut self, name: impl Into<String>, url: impl Into<String>, client: Box<dyn McpClient>) -> Result<Self, Error> {
+        self.add_server(name, url, client)?;
+        Ok(self)
+    }
+
+    pub fn with_conflict_policy(mut self, conflict_policy: ToolConflictPolicy) -> Self {
+        self.conflict_policy = conflict_policy;
+        self
+    }
+
+    /// Consecutive failures after which a server is skipped (default 3)
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Delay before a skipped server is reconnected (default 30s)
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Names of the servers, in the order they were added
+    pub fn server_names(&self) -> Vec<String> {
+        self.servers.iter().map(|server| server.name.clone()).collect()
+    }
+
+    /// Health of every server
+    pub fn health(&self) -> Vec<McpServerHealth> {
+        self.servers.iter().map(|server| {
+            let health = server.health.lock().unwrap_or_else(|e| e.into_inner());
+            McpServerHealth {
+                name: server.name.clone(),
+                available: health.retry_at.is_none(),
+                consecutive_failures: health.consecutive_failures,
+                last_error: health.last_error.clone(),
+                last_success: health.last_success,
+            }
+        }).collect()
+    }
+
+    /// Ping every server (reconnecting the ones due for a retry) and return the updated health
+    pub async fn check_health(&self) -> Vec<McpServerHealth> {
+        join_all((0..self.servers.len()).map(|index| async move {
+            if let Ok(client) = self.acquire(index).await {
+                let result = client.ping().await;
+                self.observe(index, result).ok();
+            }
+        })).await;
+        self.health()
+    }
+
+    // Client of a server ready for a call, (re)connecting it when needed
+    async fn acquire(&self, index: usize) -> Result<RwLockReadGuard<'_, Box<dyn McpClient>>, Error> {
+        let server = &self.servers[index];
+        let needs_connect = {
+            let health = server.health.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(retry_at) = health.retry_at {
+                if Instant::now() < retry_at {
+                    return Err(Error::msg(format!(
+                        "MCP server '{}' is unavailable: {}",
+                        server.name,
+                        health.last_error.as_deref().unwrap_or("unknown error")
+                    )));
+                }
+            }
+            !health.connected
+        };
+
+        if needs_connect {
+            let mut client = server.client.write().await;
+            // Another call may have reconnected while we waited for the lock
+            if !server.health.lock().unwrap_or_else(|e| e.into_inner()).connected {
+                info!("Connecting MCP server '{}'", server.name);
+                let _ = client.disconnect().await;
+                if let Err(e) = client.connect(&server.url).await {
+                    self.record_failure(index, &e, true);
+                    return Err(e.context(format!("Failed to connect MCP server '{}'", server.name)));
+                }
+                let mut health = server.health.lock().unwrap_or_else(|e| e.into_inner());
+                health.connected = true;
+                health.retry_at = None;
+            }
+        }
+        Ok(server.client.read().await)
+    }
+
+    // Update the health of a server with the result of a call
+    fn observe<T>(&self, index: usize, result: Result<T, Error>) -> Result<T, Error> {
+        match &result {
+            // JSON-RPC errors are answers of a working server
+            Err(e) if e.downcast_ref::<JSONRPCError>().is_none() => self.record_failure(index, e, false),
+            _ => {
+                let mut health = self.servers[index].health.lock().unwrap_or_else(|e| e.into_inner());
+                health.consecutive_failures = 0;
+                health.last_success = Some(Instant::now());
+            },
+        }
+        result
+    }
+
+    fn record_failure(&self, index: usize, error: &Error, connect_failed: bool) {
+        let server = &self.servers[index];
+        let mut health = server.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.consecutive_failures += 1;
+        health.last_error = Some(error.to_string());
+        if connect_failed || health.consecutive_failures >= self.failure_threshold {
+            warn!("MCP server '{}' is down for {:?}: {}", server.name, self.retry_after, error);
+            health.connected = false;
+            health.retry_at = Some(Instant::now() + self.retry_after);
+        }
+    }
+
+    // Merge the names listed by every server according to the conflict policy
+    fn merge_names(&self, listed: &[(usize, Vec<String>)]) -> Vec<(String, usize, String)> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (_, names) in listed {
+            for name in names.iter().collect::<HashSet<_>>() {
+                *counts.entry(name.as_str()).or_default() += 1;
+            }
+        }
+
+        let mut merged = Vec::new();
+        let mut exposed_names = HashSet::new();
+        for (index, names) in listed {
+            let server = &self.servers[*index].name;
+            for name in names {
+                let prefixed = match self.conflict_policy {
+                    ToolConflictPolicy::AlwaysPrefix => true,
+                    ToolConflictPolicy::PrefixOnConflict => counts[name.as_str()] > 1,
+                    ToolConflictPolicy::FirstWins => false,
+                };
+                let exposed = if prefixed {
+                    format!("{}{}{}", server, TOOL_NAMESPACE_SEPARATOR, name)
+                } else {
+                    name.clone()
+                };
+                if !exposed_names.insert(exposed.clone()) {
+                    warn!("'{}' of MCP server '{}' is hidden by another server", name, server);
+                    continue;
+                }
+                merged.push((exposed, *index, name.clone()));
+            }
+        }
+        merged
+    }
+
+    // Server and upstream name of an exposed name, server__name also works for hidden or unlisted names
+    fn resolve(&self, routes: &Mutex<Routes>, name: &str) -> Option<(usize, String)> {
+        if let Some(route) = routes.lock().unwrap_or_else(|e| e.into_inner()).get(name) {
+            return Some(route.clone());
+        }
+        let (server, upstream) = name.split_once(TOOL_NAMESPACE_SEPARATOR)?;
+        let index = self.servers.iter().position(|s| s.name == server)?;
+        Some((index, upstream.to_string()))
+    }
+
+    // Run a request on every available server, failing servers are logged and left out
+    async fn collect<'a, T, F>(&'a self, what: &str, request: F) -> Vec<(usize, T)>
+    where
+        F: Fn(RwLockReadGuard<'a, Box<dyn McpClient>>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, Error>> + Send + 'a>>,
+    {
+        let request = &request;
+        let results = join_all((0..self.servers.len()).map(|index| async move {
+            let result = match self.acquire(index).await {
+                Ok(client) => {
+                    let result = request(client).await;
+                    self.observe(index, result)
+                },
+                Err(e) => Err(e),
+            };
+            (index, result)
+        })).await;
+
+        results.into_iter().filter_map(|(index, result)| match result {
+            Ok(items) => Some((index, items)),
+            Err(e) => {
+                warn!("Skipping {} of MCP server '{}': {}", what, self.servers[index].name, e);
+                None
+            },
+        }).collect()
+    }
+
+    // Run a request on the servers in order until one succeeds, e.g. the one owning a resource URI
+    async fn first_success<'a, T, F>(&'a self, request: F) -> Result<T, Error>
+    where
+        F: Fn(RwLockReadGuard<'a, Box<dyn McpClient>>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, Error>> + Send + 'a>>,
+    {
+        let mut last_error = Error::msg("No MCP server available");
+        for index in 0..self.servers.len() {
+            let result = match self.acquire(index).await {
+                Ok(client) => {
+                    let result = request(client).await;
+                    self.observe(index, result)
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl Default for McpClientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl McpClient for McpClientPool {
+    // Connect every server, fails only when no server could be connected
+    fn connect(&mut self, _url: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            for server in &self.servers {
+                let mut health = server.health.lock().unwrap_or_else(|e| e.into_inner());
+                health.connected = false;
+                health.retry_at = None;
+            }
+            let results = join_all((0..self.servers.len()).map(|index| self.acquire(index))).await;
+            let errors: Vec<String> = results.into_iter()
+                .filter_map(|result| result.err())
+                .map(|e| format!("{:#}", e))
+                .collect();
+            if !self.servers.is_empty() && errors.len() == self.servers.len() {
+                return Err(Error::msg(format!("No MCP server could be connected: {}", errors.join("; "))));
+            }
+            for error in errors {
+                warn!("{}", error);
+            }
+            Ok(())
+        })
+    }
+
+    // Tools of every available server, named according to the conflict policy
+    fn get_tools(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpTool>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let listed = self.collect("tools", |client| Box::pin(async move { client.get_tools().await })).await;
+            let mut tools_by_name: HashMap<(usize, String), McpTool> = HashMap::new();
+            let names: Vec<(usize, Vec<String>)> = listed.into_iter().map(|(index, tools)| {
+                let names = tools.iter().map(|tool| tool.name.clone()).collect();
+                for tool in tools {
+                    tools_by_name.insert((index, tool.name.clone()), tool);
+                }
+                (index, names)
+            }).collect();
+
+            let mut routes = HashMap::new();
+            let mut tools = Vec::new();
+            for (exposed, index, name) in self.merge_names(&names) {
+                if let Some(tool) = tools_by_name.get(&(index, name.clone())) {
+                    tools.push(McpTool {
+                        name: exposed.clone(),
+                        ..tool.clone()
+                    });
+                }
+                routes.insert(exposed, (index, name));
+            }
+            *self.tool_routes.lock().unwrap_or_else(|e| e.into_inner()) = routes;
+            Ok(tools)
+        })
+    }
+
+    // Route the call to the server of the tool
+    fn call_tool(&self, tool_name: &str, params: HashMap<String, Value>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value, Error>> + Send + '_>> {
+        let tool_name = tool_name.to_string();
+        Box::pin(async move {
+            let route = match self.resolve(&self.tool_routes, &tool_name) {
+                Some(route) => Some(route),
+                None => {
+                    // Tools may have been added since the last listing
+                    self.get_tools().await?;
+                    self.resolve(&self.tool_routes, &tool_name)
+                },
+            };
+            let (index, name) = route.ok_or_else(|| Error::msg(format!("Tool '{}' not found on any MCP server", tool_name)))?;
+            let client = self.acquire(index).await?;
+            let result = client.call_tool(&name, params).await;
+            self.observe(index, result)
+        })
+    }
+
+    // Disconnect every server
+    fn disconnect(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            join_all(self.servers.iter().map(|server| async move {
+                let client = server.client.read().await;
+                if let Err(e) = client.disconnect().await {
+                    warn!("Failed to disconnect MCP server '{}': {}", server.name, e);
+                }
+                server.health.lock().unwrap_or_else(|e| e.into_inner()).connected = false;
+            })).await;
+            Ok(())
+        })
+    }
+
+    fn clone(&self) -> Box<dyn McpClient> {
+        Box::new(Clone::clone(self))
+    }
+
+    // Succeeds when at least one server answers
+    fn ping(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            let answered = self.collect("ping", |client| Box::pin(async move { client.ping().await })).await;
+            if answered.is_empty() && !self.servers.is_empty() {
+                return Err(Error::msg("No MCP server answered ping"));
+            }
+            Ok(())
+        })
+    }
+
+    // Resources of every available server, URIs are not namespaced
+    fn list_resources(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResource>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let listed = self.collect("resources", |client| Box::pin(async move { client.list_resources().await })).await;
+            Ok(listed.into_iter().flat_map(|(_, resources)| resources).collect())
+        })
+    }
+
+    fn list_resource_templates(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResourceTemplate>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let listed = self.collect("resource templates", |client| Box::pin(async move { client.list_resource_templates().await })).await;
+            Ok(listed.into_iter().flat_map(|(_, templates)| templates).collect())
+        })
+    }
+
+    // Read from the first server that knows the URI
+    fn read_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, Error>> + Send + '_>> {
+        let uri = uri.to_string();
+        Box::pin(async move {
+            let uri = &uri;
+            self.first_success(|client| Box::pin(async move { client.read_resource(uri).await })).await
+        })
+    }
+
+    fn subscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let uri = uri.to_string();
+        Box::pin(async move {
+            let uri = &uri;
+            self.first_success(|client| Box::pin(async move { client.subscribe_resource(uri).await })).await
+        })
+    }
+
+    fn unsubscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let uri = uri.to_string();
+        Box::pin(async move {
+            let uri = &uri;
+            self.first_success(|client| Box::pin(async move { client.unsubscribe_resource(uri).await })).await
+        })
+    }
+
+    // Prompts of every available server, named like the tools
+    fn list_prompts(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpPrompt>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let listed = self.collect("prompts", |client| Box::pin(async move { client.list_prompts().await })).await;
+            let mut prompts_by_name: HashMap<(usize, String), McpPrompt> = HashMap::new();
+            let names: Vec<(usize, Vec<String>)> = listed.into_iter().map(|(index, prompts)| {
+                let names = prompts.iter().map(|prompt| prompt.name.clone()).collect();
+                for prompt in prompts {
+                    prompts_by_name.insert((index, prompt.name.clone()), prompt);
+                }
+                (index, names)
+            }).collect();
+
+            let mut routes = HashMap::new();
+            let mut prompts = Vec::new();
+            for (exposed, index, name) in self.merge_names(&names) {
+                if let Some(prompt) = prompts_by_name.get(&(index, name.clone())) {
+                    prompts.push(McpPrompt {
+                        name: exposed.clone(),
+                        ..prompt.clone()
+                    });
+                }
+                routes.insert(exposed, (index, name));
+            }
+            *self.prompt_routes.lock().unwrap_or_else(|e| e.into_inner()) = routes;
+            Ok(prompts)
+        })
+    }
+
+    fn get_prompt(&self, name: &str, arguments: HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<GetPromptResult, Error>> + Send + '_>> {
+        let name = name.to_string();
+        Box::pin(async move {
+            let route = match self.resolve(&self.prompt_routes, &name) {
+                Some(route) => Some(route),
+                None => {
+                    self.list_prompts().await?;
+                    self.resolve(&self.prompt_routes, &name)
+                },
+            };
+            let (index, upstream) = route.ok_or_else(|| Error::msg(format!("Prompt '{}' not found on any MCP server", name)))?;
+            let client = self.acquire(index).await?;
+            let result = client.get_prompt(&upstream, arguments).await;
+            self.observe(index, result)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    // Upstream client answering "<server>:<tool>", its transport can be broken on demand
+    #[derive(Clone)]
+    struct MockClient {
+        server: &'static str,
+        tools: Vec<&'static str>,
+        reachable: Arc<AtomicBool>,
+        connects: Arc<AtomicUsize>,
+    }
+
+    impl MockClient {
+        fn new(server: &'static str, tools: &[&'static str]) -> Self {
+            Self {
+                server,
+                tools: tools.to_vec(),
+                reachable: Arc::new(AtomicBool::new(true)),
+                connects: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn check_reachable(&self) -> Result<(), Error> {
+            if self.reachable.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(Error::msg(format!("connection refused by {}", self.server)))
+            }
+        }
+    }
+
+    impl McpClient for MockClient {
+        fn connect(&mut self, _url: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+            Box::pin(async move {
+                self.connects.fetch_add(1, Ordering::SeqCst);
+                self.check_reachable()
+            })
+        }
+
+        fn get_tools(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpTool>, Error>> + Send + '_>> {
+            Box::pin(async move {
+                self.check_reachable()?;
+                Ok(self.tools.iter().map(|name| McpTool::new(name.to_string(), format!("{} of {}", name, self.server))).collect())
+            })
+        }
+
+        fn call_tool(&self, tool_name: &str, _params: HashMap<String, Value>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value, Error>> + Send + '_>> {
+            let tool_name = tool_name.to_string();
+            Box::pin(async move {
+                self.check_reachable()?;
+                if !self.tools.contains(&tool_name.as_str()) {
+                    return Err(Error::new(JSONRPCError::new(-32602, format!("Unknown tool {}", tool_name))));
+                }
+                Ok(json!(format!("{}:{}", self.server, tool_name)))
+            })
+        }
+
+        fn clone(&self) -> Box<dyn McpClient> {
+            Box::new(Clone::clone(self))
+        }
+    }
+
+    fn pool(policy: ToolConflictPolicy, servers: &[&MockClient]) -> McpClientPool {
+        let mut pool = McpClientPool::new().with_conflict_policy(policy);
+        for server in servers {
+            pool.add_server(server.server, "", Box::new(Clone::clone(*server))).unwrap();
+        }
+        pool
+    }
+
+    async fn tool_names(pool: &McpClientPool) -> Vec<String> {
+        let mut names: Vec<String> = pool.get_tools().await.unwrap().into_iter().map(|tool| tool.name).collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_tool_namespacing_and_routing() {
+        let chain = MockClient::new("chain", &["get_balance", "search"]);
+        let files = MockClient::new("files", &["read_file", "search"]);
+
+        let mut prefixed = pool(ToolConflictPolicy::AlwaysPrefix, &[&chain, &files]);
+        prefixed.connect("").await.unwrap();
+        assert_eq!(tool_names(&prefixed).await, vec!["chain__get_balance", "chain__search", "files__read_file", "files__search"]);
+        assert_eq!(prefixed.call_tool("files__search", HashMap::new()).await.unwrap(), json!("files:search"));
+
+        let mut on_conflict = pool(ToolConflictPolicy::PrefixOnConflict, &[&chain, &files]);
+        on_conflict.connect("").await.unwrap();
+        assert_eq!(tool_names(&on_conflict).await, vec!["chain__search", "files__search", "get_balance", "read_file"]);
+        assert_eq!(on_conflict.call_tool("get_balance", HashMap::new()).await.unwrap(), json!("chain:get_balance"));
+        assert!(on_conflict.call_tool("search", HashMap::new()).await.is_err());
+
+        let mut first_wins = pool(ToolConflictPolicy::FirstWins, &[&chain, &files]);
+        first_wins.connect("").await.unwrap();
+        assert_eq!(tool_names(&first_wins).await, vec!["get_balance", "read_file", "search"]);
+        assert_eq!(first_wins.call_tool("search", HashMap::new()).await.unwrap(), json!("chain:search"));
+        // Hidden tools stay reachable with their namespaced name
+        assert_eq!(first_wins.call_tool("files__search", HashMap::new()).await.unwrap(), json!("files:search"));
+
+        assert!(McpClientPool::new().with_server("bad__name", "", Box::new(MockClient::new("x", &[]))).is_err());
+        assert!(first_wins.add_server("chain", "", Box::new(MockClient::new("chain", &[]))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_health() {
+        let chain = MockClient::new("chain", &["get_balance"]);
+        let files = MockClient::new("files", &["read_file"]);
+        files.reachable.store(false, Ordering::SeqCst);
+
+        // A server that cannot connect is left out, the others keep working
+        let mut pool = pool(ToolConflictPolicy::AlwaysPrefix, &[&chain, &files])
+            .with_failure_threshold(2)
+            .with_retry_after(Duration::from_millis(50));
+        pool.connect("").await.unwrap();
+        assert_eq!(tool_names(&pool).await, vec!["chain__get_balance"]);
+        let error = pool.call_tool("files__read_file", HashMap::new()).await.unwrap_err();
+        assert!(error.to_string().contains("unavailable"), "{}", error);
+        assert!(!pool.health()[1].available);
+
+        // It is reconnected once the retry delay has passed
+        files.reachable.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(pool.call_tool("files__read_file", HashMap::new()).await.unwrap(), json!("files:read_file"));
+        assert_eq!(files.connects.load(Ordering::SeqCst), 2);
+        assert!(pool.health()[1].available);
+
+        // JSON-RPC errors are answers, transport errors count towards the threshold
+        assert!(pool.call_tool("chain__missing", HashMap::new()).await.is_err());
+        assert_eq!(pool.health()[0].consecutive_failures, 0);
+        chain.reachable.store(false, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert!(pool.call_tool("chain__get_balance", HashMap::new()).await.is_err());
+        }
+        let health = &pool.health()[0];
+        assert!(!health.available);
+        assert_eq!(health.consecutive_failures, 2);
+        assert!(health.last_error.as_deref().unwrap().contains("connection refused"));
+        assert_eq!(tool_names(&pool).await, vec!["files__read_file"]);
+
+        // Every server down is an error
+        files.reachable.store(false, Ordering::SeqCst);
+        assert!(pool.connect("").await.is_err());
+    }
+}
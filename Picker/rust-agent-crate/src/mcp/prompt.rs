@@ -0,0 +1,240 @@
+// MCP prompts: reusable prompt recipes exposed by the server
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::models::{ChatMessage, ChatMessageContent};
+use crate::prompt::ChatPromptTemplate;
+
+// Prompt served by an MCP server
+pub trait Prompt: Send + Sync {
+    // Unique name, used by prompts/get
+    fn name(&self) -> &str;
+
+    // Description shown to the user when picking a prompt
+    fn description(&self) -> &str {
+        ""
+    }
+
+    // Arguments accepted by the prompt
+    fn arguments(&self) -> Vec<PromptArgument> {
+        Vec::new()
+    }
+
+    // Build the messages, required arguments are checked by the server before calling it
+    fn get(&self, arguments: &HashMap<String, String>) -> Pin<Box<dyn Future<Output = Result<Vec<PromptMessage>, Error>> + Send + '_>>;
+}
+
+// Argument of a prompt, values are always strings on the wire
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl PromptArgument {
+    pub fn new(name: impl Into<String>, description: impl Into<String>, required: bool) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            required,
+        }
+    }
+}
+
+// Message of a prompts/get result, MCP only knows the "user" and "assistant" roles
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PromptMessage {
+    pub role: String,
+    // Content block such as {"type": "text", "text": "..."}
+    pub content: Value,
+}
+
+impl PromptMessage {
+    /// Text message, role is "user" or "assistant"
+    pub fn text(role: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: json!({"type": "text", "text": text.into()}),
+        }
+    }
+
+    /// Convert a chat message, system and tool messages are sent with the user role
+    pub fn from_chat_message(message: &ChatMessage) -> Self {
+        match message {
+            ChatMessage::AIMessage(content) => Self::text("assistant", content.content.clone()),
+            ChatMessage::System(content)
+            | ChatMessage::Human(content)
+            | ChatMessage::ToolMessage(content) => Self::text("user", content.content.clone()),
+        }
+    }
+
+    /// Text of the content, embedded text resources included
+    pub fn content_text(&self) -> Option<&str> {
+        match self.content.get("type").and_then(|t| t.as_str()) {
+            Some("text") => self.content.get("text").and_then(|t| t.as_str()),
+            Some("resource") => self.content.get("resource").and_then(|r| r.get("text")).and_then(|t| t.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Convert to a chat message, content without text (images, audio) is described by its type
+    pub fn to_chat_message(&self) -> ChatMessage {
+        let text = match self.content_text() {
+            Some(text) => text.to_string(),
+            None => {
+                let kind = self.content.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
+                match self.content.get("mimeType").and_then(|m| m.as_str()) {
+                    Some(mime_type) => format!("[{} content {}]", kind, mime_type),
+                    None => format!("[{} content]", kind),
+                }
+            },
+        };
+        let content = ChatMessageContent {
+            content: text,
+            name: None,
+            additional_kwargs: HashMap::new(),
+        };
+        match self.role.as_str() {
+            "assistant" => ChatMessage::AIMessage(content),
+            _ => ChatMessage::Human(content),
+        }
+    }
+}
+
+// Prompt entry of a prompts/list response
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+}
+
+// Result of prompts/get
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GetPromptResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+impl GetPromptResult {
+    /// Messages as ChatMessage, ready to be passed to a model or McpAgent::with_prompt_messages
+    pub fn to_chat_messages(&self) -> Vec<ChatMessage> {
+        self.messages.iter().map(|message| message.to_chat_message()).collect()
+    }
+}
+
+// Prompt built from a ChatPromptTemplate, every template variable is an argument
+#[derive(Clone, Debug)]
+pub struct McpPromptTemplate {
+    name: String,
+    description: String,
+    template: ChatPromptTemplate,
+    arguments: Vec<PromptArgument>,
+}
+
+impl McpPromptTemplate {
+    /// Create a prompt, the template variables become required arguments
+    pub fn new(name: impl Into<String>, template: ChatPromptTemplate) -> Self {
+        let arguments = template.input_variables().into_iter()
+            .map(|variable| PromptArgument::new(variable, "", true))
+            .collect();
+        Self {
+            name: name.into(),
+            description: String::new(),
+            template,
+            arguments,
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Describe an argument, optional arguments are formatted as an empty string when missing
+    pub fn with_argument(mut self, name: &str, description: impl Into<String>, required: bool) -> Self {
+        let argument = PromptArgument::new(name, description, required);
+        match self.arguments.iter_mut().find(|a| a.name == name) {
+            Some(existing) => *existing = argument,
+            None => self.arguments.push(argument),
+        }
+        self
+    }
+}
+
+impl Prompt for McpPromptTemplate {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn arguments(&self) -> Vec<PromptArgument> {
+        self.arguments.clone()
+    }
+
+    fn get(&self, arguments: &HashMap<String, String>) -> Pin<Box<dyn Future<Output = Result<Vec<PromptMessage>, Error>> + Send + '_>> {
+        let mut inputs = arguments.clone();
+        for argument in &self.arguments {
+            inputs.entry(argument.name.clone()).or_default();
+        }
+        let messages = self.template.format_messages(&inputs)
+            .map(|messages| messages.iter().map(PromptMessage::from_chat_message).collect());
+        Box::pin(async move { messages })
+    }
+}
+
+// Listing entry of a registered prompt, in prompts/list format
+pub(crate) fn prompt_listing(prompt: &dyn Prompt) -> Value {
+    json!({
+        "name": prompt.name(),
+        "description": prompt.description(),
+        "arguments": prompt.arguments(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prompt_template_arguments_and_messages() {
+        let template = ChatPromptTemplate::from_messages(&[
+            ("system", "You deploy contracts on {chain}."),
+            ("human", "Deploy an ERC20 named {name}{notes}"),
+        ]).unwrap();
+        let prompt = McpPromptTemplate::new("deploy_erc20", template)
+            .with_argument("notes", "Extra instructions", false);
+        let arguments = prompt.arguments();
+        assert_eq!(arguments.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), vec!["chain", "name", "notes"]);
+        assert!(arguments[0].required && !arguments[2].required);
+
+        let mut inputs = HashMap::new();
+        inputs.insert("chain".to_string(), "Ethereum".to_string());
+        inputs.insert("name".to_string(), "PICK".to_string());
+        let messages = prompt.get(&inputs).await.unwrap();
+        assert_eq!(messages[0], PromptMessage::text("user", "You deploy contracts on Ethereum."));
+        assert_eq!(messages[1].content_text(), Some("Deploy an ERC20 named PICK"));
+
+        let image = PromptMessage {
+            role: "assistant".to_string(),
+            content: json!({"type": "image", "data": "AA==", "mimeType": "image/png"}),
+        };
+        match image.to_chat_message() {
+            ChatMessage::AIMessage(content) => assert_eq!(content.content, "[image content image/png]"),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
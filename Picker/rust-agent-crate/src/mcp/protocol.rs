@@ -0,0 +1,114 @@
+// MCP lifecycle types (initialize / notifications/initialized)
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Protocol version proposed by the client and preferred by the server
+pub const LATEST_PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Protocol versions this crate can speak, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+// Headers of the Streamable HTTP transport
+pub(crate) const MCP_SESSION_ID_HEADER: &str = "mcp-session-id";
+pub(crate) const MCP_PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// Check whether a protocol version is one of SUPPORTED_PROTOCOL_VERSIONS
+pub fn is_supported_protocol_version(version: &str) -> bool {
+    SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+}
+
+/// Pick the version answered to an initialize request: the requested one if supported, otherwise the latest
+pub fn negotiate_protocol_version(requested: &str) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS.iter()
+        .find(|version| **version == requested)
+        .copied()
+        .unwrap_or(LATEST_PROTOCOL_VERSION)
+}
+
+// Name and version of an MCP client or server (clientInfo / serverInfo)
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Implementation {
+    pub name: String,
+    pub version: String,
+}
+
+impl Implementation {
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+}
+
+impl Default for Implementation {
+    fn default() -> Self {
+        Self::new("rust-agent", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+// Capabilities announced by the client, a present field means the feature is supported
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ClientCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roots: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<Value>,
+}
+
+// Capabilities announced by the server, a present field means the feature is supported
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ServerCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<Value>,
+}
+
+// Params of the initialize request
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeParams {
+    pub protocol_version: String,
+    #[serde(default)]
+    pub capabilities: ClientCapabilities,
+    pub client_info: Implementation,
+}
+
+// Result of the initialize request
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: String,
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    pub server_info: Implementation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+// Parse an initialize result and refuse protocol versions this crate cannot speak
+pub(crate) fn parse_initialize_result(result: Option<Value>) -> Result<InitializeResult, Error> {
+    let result: InitializeResult = serde_json::from_value(result.unwrap_or(Value::Null))
+        .map_err(|e| Error::msg(format!("Invalid initialize result: {}", e)))?;
+    if !is_supported_protocol_version(&result.protocol_version) {
+        return Err(Error::msg(format!(
+            "MCP server {} uses unsupported protocol version {}, supported versions: {:?}",
+            result.server_info.name, result.protocol_version, SUPPORTED_PROTOCOL_VERSIONS
+        )));
+    }
+    Ok(result)
+}
@@ -0,0 +1,275 @@
+// MCP resources: readable context exposed by the server
+use anyhow::Error;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+// Resource served by an MCP server
+// The URI may be a template such as "wallet://{chain}/{address}/balance", it is then listed in
+// resources/templates/list and read for every URI matching the template.
+pub trait Resource: Send + Sync {
+    // URI or URI template of the resource
+    fn uri(&self) -> &str;
+
+    // Human readable name
+    fn name(&self) -> &str;
+
+    // Description of the contents, shown to the model
+    fn description(&self) -> &str {
+        ""
+    }
+
+    // MIME type of the contents
+    fn mime_type(&self) -> Option<&str> {
+        None
+    }
+
+    // Read the contents, `params` holds the values of the template variables
+    fn read(&self, uri: &str, params: &HashMap<String, String>) -> Pin<Box<dyn Future<Output = Result<Vec<ResourceContents>, Error>> + Send + '_>>;
+
+    // Whether the URI is a template
+    fn is_template(&self) -> bool {
+        self.uri().contains('{')
+    }
+}
+
+// Contents of a resource, binary data is base64 encoded on the wire
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ResourceContents {
+    #[serde(rename_all = "camelCase")]
+    Text {
+        uri: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Blob {
+        uri: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        blob: String,
+    },
+}
+
+impl ResourceContents {
+    /// Text contents
+    pub fn text(uri: impl Into<String>, mime_type: Option<&str>, text: impl Into<String>) -> Self {
+        ResourceContents::Text {
+            uri: uri.into(),
+            mime_type: mime_type.map(|m| m.to_string()),
+            text: text.into(),
+        }
+    }
+
+    /// Binary contents
+    pub fn blob(uri: impl Into<String>, mime_type: Option<&str>, data: &[u8]) -> Self {
+        ResourceContents::Blob {
+            uri: uri.into(),
+            mime_type: mime_type.map(|m| m.to_string()),
+            blob: base64::engine::general_purpose::STANDARD.encode(data),
+        }
+    }
+
+    pub fn uri(&self) -> &str {
+        match self {
+            ResourceContents::Text { uri, .. } | ResourceContents::Blob { uri, .. } => uri,
+        }
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text { mime_type, .. } | ResourceContents::Blob { mime_type, .. } => mime_type.as_deref(),
+        }
+    }
+
+    /// Text of text contents, None for binary contents
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text { text, .. } => Some(text),
+            ResourceContents::Blob { .. } => None,
+        }
+    }
+
+    /// Raw bytes, text contents are returned as UTF-8
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            ResourceContents::Text { text, .. } => Ok(text.as_bytes().to_vec()),
+            ResourceContents::Blob { blob, .. } => base64::engine::general_purpose::STANDARD.decode(blob)
+                .map_err(|e| Error::msg(format!("Invalid base64 resource contents: {}", e))),
+        }
+    }
+}
+
+// Resource entry of a resources/list response
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+// Template entry of a resources/templates/list response
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceTemplate {
+    pub uri_template: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+// Listing entry of a registered resource, in resources/list or resources/templates/list format
+pub(crate) fn resource_listing(resource: &dyn Resource) -> Value {
+    let uri_field = if resource.is_template() { "uriTemplate" } else { "uri" };
+    let mut entry = json!({
+        uri_field: resource.uri(),
+        "name": resource.name(),
+        "description": resource.description(),
+    });
+    if let Some(mime_type) = resource.mime_type() {
+        entry["mimeType"] = json!(mime_type);
+    }
+    entry
+}
+
+// Collect every page of a paginated list result, `request` is called with the cursor params
+pub(crate) async fn list_all_pages<T, F, Fut>(field: &str, request: F) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+    F: Fn(Option<Value>) -> Fut,
+    Fut: Future<Output = Result<Value, Error>>,
+{
+    let mut items = Vec::new();
+    let mut cursor: Option<Value> = None;
+    loop {
+        let params = cursor.take().map(|cursor| json!({"cursor": cursor}));
+        let result = request(params).await?;
+        if let Some(entries) = result.get(field).and_then(|e| e.as_array()) {
+            for entry in entries {
+                match serde_json::from_value(entry.clone()) {
+                    Ok(item) => items.push(item),
+                    Err(e) => log::warn!("Failed to parse {} entry {:?}: {}", field, entry, e),
+                }
+            }
+        }
+        match result.get("nextCursor") {
+            Some(next) if !next.is_null() => cursor = Some(next.clone()),
+            _ => return Ok(items),
+        }
+    }
+}
+
+// Parse the contents of a resources/read result
+pub(crate) fn parse_read_result(result: Value) -> Result<Vec<ResourceContents>, Error> {
+    let contents = result.get("contents").cloned().unwrap_or(Value::Array(Vec::new()));
+    serde_json::from_value(contents).map_err(|e| Error::msg(format!("Invalid resources/read result: {}", e)))
+}
+
+/// Match a URI against a template with {var} placeholders (RFC 6570 simple expansion)
+/// Variables match at least one character and never a '/'.
+pub fn match_uri_template(template: &str, uri: &str) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let mut rest = uri;
+    let mut pattern = template;
+
+    while !pattern.is_empty() {
+        match pattern.find('{') {
+            // Literal prefix must match exactly
+            Some(start) if start > 0 => {
+                rest = rest.strip_prefix(&pattern[..start])?;
+                pattern = &pattern[start..];
+            },
+            Some(_) => {
+                let end = pattern.find('}')?;
+                let name = &pattern[1..end];
+                pattern = &pattern[end + 1..];
+                // The variable runs until the next literal, or to the end of the segment
+                let literal_end = pattern.find('{').unwrap_or(pattern.len());
+                let literal = &pattern[..literal_end];
+                let segment_end = rest.find('/').unwrap_or(rest.len());
+                let value_end = if literal.is_empty() {
+                    segment_end
+                } else {
+                    rest.find(literal).filter(|i| *i <= segment_end)?
+                };
+                if value_end == 0 {
+                    return None;
+                }
+                params.insert(name.to_string(), rest[..value_end].to_string());
+                rest = &rest[value_end..];
+            },
+            None => {
+                rest = rest.strip_prefix(pattern)?;
+                pattern = "";
+            },
+        }
+    }
+
+    if rest.is_empty() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+// Template matching the URI among several, the most specific one wins: the longest literal prefix
+// before the first variable, ties go to the template that sorts first
+pub(crate) fn best_template_match<'a>(templates: impl Iterator<Item = &'a str>, uri: &str) -> Option<(&'a str, HashMap<String, String>)> {
+    let literal_prefix_len = |template: &str| template.find('{').unwrap_or(template.len());
+    templates
+        .filter_map(|template| match_uri_template(template, uri).map(|params| (template, params)))
+        .min_by(|(a, _), (b, _)| literal_prefix_len(b).cmp(&literal_prefix_len(a)).then_with(|| a.cmp(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_uri_template() {
+        let params = match_uri_template("wallet://{chain}/{address}/balance", "wallet://eth/0xabc/balance").unwrap();
+        assert_eq!(params["chain"], "eth");
+        assert_eq!(params["address"], "0xabc");
+        assert_eq!(match_uri_template("logs://task/{id}.log", "logs://task/42.log").unwrap()["id"], "42");
+        assert!(match_uri_template("wallet://{chain}/balance", "wallet://eth/0xabc/balance").is_none());
+        assert!(match_uri_template("wallet://{chain}", "wallet://").is_none());
+        assert!(match_uri_template("picker://manifests", "picker://manifests").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_best_template_match_is_deterministic() {
+        let templates = ["wallet://{chain}/{address}", "wallet://eth/{address}", "wallet://{network}/{id}"];
+        let (template, params) = best_template_match(templates.iter().copied(), "wallet://eth/0xabc").unwrap();
+        assert_eq!(template, "wallet://eth/{address}");
+        assert_eq!(params["address"], "0xabc");
+        // Equal prefixes are ordered by the template, whatever the registration order
+        for order in [[0, 2], [2, 0]] {
+            let (template, _) = best_template_match(order.iter().map(|i| templates[*i]), "wallet://sol/0xdef").unwrap();
+            assert_eq!(template, "wallet://{chain}/{address}");
+        }
+    }
+
+    #[test]
+    fn test_resource_contents_serialization() {
+        let blob = ResourceContents::blob("file:///icon.png", Some("image/png"), &[0, 159, 255]);
+        let value = serde_json::to_value(&blob).unwrap();
+        assert_eq!(value, json!({"uri": "file:///icon.png", "mimeType": "image/png", "blob": "AJ//"}));
+        let parsed: ResourceContents = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.to_bytes().unwrap(), vec![0, 159, 255]);
+
+        let text: ResourceContents = serde_json::from_value(json!({"uri": "logs://1", "text": "ok"})).unwrap();
+        assert_eq!(text.as_text(), Some("ok"));
+        assert_eq!(text.mime_type(), None);
+    }
+}
@@ -1,23 +1,41 @@
 // MCP server abstract definition
 use anyhow::Error;
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use crate::tools::Tool;
 use serde::{Deserialize, Serialize};
 use axum::{
     extract::State,
-    response::Json,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::{BroadcastStream, UnboundedReceiverStream};
+use futures::stream::StreamExt;
+use uuid::Uuid;
 use tower_http::cors::CorsLayer;
-use serde_json::Value;
-use log::{info, error};
+use serde_json::{json, Value};
+use log::{debug, info, error, warn};
 
 use crate::mcp::JSONRPCRequest;
 use crate::mcp::JSONRPCResponse;
 use crate::mcp::JSONRPCError;
+use crate::mcp::Resource;
+use crate::mcp::resource::{best_template_match, resource_listing};
+use crate::mcp::{CallToolResult, Prompt};
+use crate::mcp::prompt::prompt_listing;
+use crate::mcp::{ClientCapabilities, Implementation, InitializeParams, InitializeResult, ServerCapabilities, negotiate_protocol_version};
+use crate::mcp::{LoggingLevel, McpRequestContext, is_supported_protocol_version};
+use crate::mcp::context::NotificationSink;
+use crate::mcp::protocol::{MCP_PROTOCOL_VERSION_HEADER, MCP_SESSION_ID_HEADER};
+
+// Notifications buffered per session for slow GET streams
+const NOTIFICATION_BUFFER: usize = 256;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct CallToolParams {
@@ -29,17 +47,63 @@ struct CallToolParams {
 pub struct SimpleMcpServer {
     address: String,
     tools: Arc<Mutex<HashMap<String, Arc<dyn Tool>>>>,
+    // Resources by URI or URI template
+    resources: Arc<Mutex<HashMap<String, Arc<dyn Resource>>>>,
+    // Prompts by name
+    prompts: Arc<Mutex<HashMap<String, Arc<dyn Prompt>>>>,
     is_running: Arc<Mutex<bool>>,
     server_handle: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    server_info: Implementation,
+    instructions: Option<String>,
+    // Session of the transports without session ids (/rpc and stdio)
+    session: SharedSession,
+    // Streamable HTTP sessions by Mcp-Session-Id
+    sessions: Arc<Mutex<HashMap<String, SharedSession>>>,
+}
+
+// Lifecycle state of a connected client
+#[derive(Clone, Debug)]
+struct ServerSession {
+    // Params of the last initialize request
+    client: Option<InitializeParams>,
+    // Set by notifications/initialized
+    initialized: bool,
+    // Minimum level of notifications/message, set with logging/setLevel
+    log_level: LoggingLevel,
+    // Resource URIs subscribed with resources/subscribe
+    subscriptions: HashSet<String>,
+    // Server-initiated notifications, delivered on the GET stream (HTTP) or stdout (stdio)
+    notifications: broadcast::Sender<JSONRPCRequest>,
 }
 
+impl ServerSession {
+    fn new() -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_BUFFER);
+        Self {
+            client: None,
+            initialized: false,
+            log_level: LoggingLevel::Info,
+            subscriptions: HashSet::new(),
+            notifications,
+        }
+    }
+}
+
+type SharedSession = Arc<Mutex<ServerSession>>;
+
 impl SimpleMcpServer {
     pub fn new() -> Self {
         Self {
             address: "127.0.0.1:6000".to_string(),
             tools: Arc::new(Mutex::new(HashMap::new())),
+            resources: Arc::new(Mutex::new(HashMap::new())),
+            prompts: Arc::new(Mutex::new(HashMap::new())),
             is_running: Arc::new(Mutex::new(false)),
             server_handle: Arc::new(Mutex::new(None)),
+            server_info: Implementation::default(),
+            instructions: None,
+            session: Arc::new(Mutex::new(ServerSession::new())),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
         }
     }
     
@@ -47,6 +111,231 @@ impl SimpleMcpServer {
         self.address = address;
         self
     }
+    
+    /// Set the serverInfo returned by initialize
+    pub fn with_server_info(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.server_info = Implementation::new(name, version);
+        self
+    }
+    
+    /// Set the instructions returned by initialize, clients may add them to the system prompt
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+    
+    /// Capabilities announced in the initialize result
+    pub fn capabilities(&self) -> ServerCapabilities {
+        ServerCapabilities {
+            tools: Some(json!({"listChanged": true})),
+            resources: Some(json!({"subscribe": true, "listChanged": true})),
+            prompts: Some(json!({"listChanged": true})),
+            logging: Some(json!({})),
+            ..Default::default()
+        }
+    }
+    
+    /// clientInfo sent by the client in initialize, None before the handshake
+    pub fn client_info(&self) -> Option<Implementation> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        session.client.as_ref().map(|params| params.client_info.clone())
+    }
+    
+    /// Capabilities announced by the client in initialize
+    pub fn client_capabilities(&self) -> Option<ClientCapabilities> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        session.client.as_ref().map(|params| params.capabilities.clone())
+    }
+    
+    /// Whether the client has sent notifications/initialized
+    pub fn is_initialized(&self) -> bool {
+        self.session.lock().unwrap_or_else(|e| e.into_inner()).initialized
+    }
+    
+    /// Ids of the open Streamable HTTP sessions
+    pub fn session_ids(&self) -> Vec<String> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect()
+    }
+    
+    // Sessions of all transports
+    fn all_sessions(&self) -> Vec<SharedSession> {
+        let mut sessions: Vec<SharedSession> = self.sessions.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect();
+        sessions.push(self.session.clone());
+        sessions
+    }
+    
+    /// Send a notification to every connected client
+    /// Clients receive it on their GET stream (Streamable HTTP) or stdout (stdio), the /rpc endpoint cannot receive notifications.
+    pub fn notify(&self, method: &str, params: Option<Value>) {
+        for session in self.all_sessions() {
+            let session = session.lock().unwrap_or_else(|e| e.into_inner());
+            // Sending only fails when nobody listens
+            let _ = session.notifications.send(JSONRPCRequest::notification(method, params.clone()));
+        }
+    }
+    
+    /// Tell clients to fetch tools/list again
+    pub fn notify_tools_list_changed(&self) {
+        self.notify("notifications/tools/list_changed", None);
+    }
+    
+    /// Register a resource, clients are told to fetch resources/list again
+    /// When several templates match a read URI, the one with the longest literal prefix is used,
+    /// ties go to the template that sorts first.
+    pub fn register_resource(&self, resource: Arc<dyn Resource>) -> Result<(), Error> {
+        {
+            let mut resources = self.resources.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
+            resources.insert(resource.uri().to_string(), resource);
+        }
+        self.notify("notifications/resources/list_changed", None);
+        Ok(())
+    }
+    
+    /// Tell the clients subscribed to a resource URI that its contents changed
+    pub fn notify_resource_updated(&self, uri: &str) {
+        for session in self.all_sessions() {
+            let session = session.lock().unwrap_or_else(|e| e.into_inner());
+            if session.subscriptions.contains(uri) {
+                let params = json!({"uri": uri});
+                let _ = session.notifications.send(JSONRPCRequest::notification("notifications/resources/updated", Some(params)));
+            }
+        }
+    }
+    
+    /// Register a prompt, clients are told to fetch prompts/list again
+    pub fn register_prompt(&self, prompt: Arc<dyn Prompt>) -> Result<(), Error> {
+        {
+            let mut prompts = self.prompts.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
+            prompts.insert(prompt.name().to_string(), prompt);
+        }
+        self.notify("notifications/prompts/list_changed", None);
+        Ok(())
+    }
+    
+    /// Send notifications/message to the clients whose log level allows it
+    pub fn send_log_message(&self, level: LoggingLevel, logger: Option<&str>, data: Value) {
+        let mut params = json!({
+            "level": level,
+            "data": data,
+        });
+        if let Some(logger) = logger {
+            params["logger"] = json!(logger);
+        }
+        for session in self.all_sessions() {
+            let session = session.lock().unwrap_or_else(|e| e.into_inner());
+            if level >= session.log_level {
+                let _ = session.notifications.send(JSONRPCRequest::notification("notifications/message", Some(params.clone())));
+            }
+        }
+    }
+    
+    // Shared state of the HTTP and stdio transports
+    fn state(&self) -> Arc<SimpleMcpServerState> {
+        Arc::new(SimpleMcpServerState {
+            tools: self.tools.clone(),
+            resources: self.resources.clone(),
+            prompts: self.prompts.clone(),
+            server_info: self.server_info.clone(),
+            instructions: self.instructions.clone(),
+            capabilities: self.capabilities(),
+            session: self.session.clone(),
+            sessions: self.sessions.clone(),
+        })
+    }
+    
+    /// Serve newline-delimited JSON-RPC over stdin/stdout until stdin is closed
+    /// Stdout is reserved for protocol messages, logs must go to stderr (the env_logger default).
+    pub async fn serve_stdio(&self) -> Result<(), Error> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+    
+    /// Serve newline-delimited JSON-RPC over any reader/writer pair until the reader is closed
+    /// Requests are handled concurrently, so responses may be written out of order.
+    pub async fn serve<R, W>(&self, reader: R, writer: W) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        info!("Serving MCP over stdio");
+        let state = self.state();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        
+        // Single writer task, one JSON message per line
+        let writer_task = tokio::spawn(async move {
+            let mut writer = writer;
+            while let Some(mut line) = receiver.recv().await {
+                line.push('\n');
+                writer.write_all(line.as_bytes()).await?;
+                writer.flush().await?;
+            }
+            Ok::<(), Error>(())
+        });
+        
+        // Notifications of tools and of the server itself are written like responses
+        let notification_sender = sender.clone();
+        let sink: NotificationSink = Arc::new(move |notification| {
+            if let Ok(line) = serde_json::to_string(&notification) {
+                let _ = notification_sender.send(line);
+            }
+        });
+        let mut server_notifications = state.session.lock().unwrap_or_else(|e| e.into_inner()).notifications.subscribe();
+        let server_sink = sink.clone();
+        let forward_task = tokio::spawn(async move {
+            loop {
+                match server_notifications.recv().await {
+                    Ok(notification) => server_sink(notification),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => warn!("Dropped {} notifications", skipped),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: JSONRPCRequest = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    warn!("Failed to parse JSON-RPC message: {}", e);
+                    let response = JSONRPCResponse::error(None, -32700, format!("Parse error: {}", e));
+                    let _ = sender.send(serde_json::to_string(&response)?);
+                    continue;
+                }
+            };
+            if request.id.is_none() {
+                // Notifications change the session state and must be applied in order
+                handle_notification(&state.session, &request.method);
+                continue;
+            }
+            let state = state.clone();
+            let sender = sender.clone();
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                let session = state.session.clone();
+                if let Some(response) = handle_jsonrpc_message(state, session, request, Some(sink)).await {
+                    if let Ok(line) = serde_json::to_string(&response) {
+                        let _ = sender.send(line);
+                    }
+                }
+            });
+        }
+        
+        // Let pending requests finish writing their responses
+        forward_task.abort();
+        drop(sink);
+        drop(sender);
+        writer_task.await.map_err(|e| Error::msg(format!("Stdio writer task failed: {}", e)))??;
+        info!("MCP stdio input closed");
+        Ok(())
+    }
+}
+
+impl Default for SimpleMcpServer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Simple test handler
@@ -55,85 +344,378 @@ async fn test_handler() -> &'static str {
     "Hello, Rust-Agent!"
 }
 
-// Handle JSON-RPC request
+// Handle JSON-RPC request over HTTP, notifications are acknowledged with 202 and no body
 #[axum::debug_handler]
 async fn handle_jsonrpc_request(
     State(state): State<Arc<SimpleMcpServerState>>,
     Json(payload): Json<JSONRPCRequest>,
-) -> Json<JSONRPCResponse> {
-    let response = match payload.method.as_str() {
-        "tools/call" => {
-            // Handle tool call request
-            match handle_tool_call(state, payload.params).await {
-                Ok(result) => {
-                    JSONRPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: Some(payload.id.unwrap_or(Value::Null)),
-                        result: Some(result),
-                        error: None,
-                    }
-                }
-                Err(e) => {
-                    JSONRPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: Some(payload.id.unwrap_or(Value::Null)),
-                        result: None,
-                        error: Some(JSONRPCError {
-                            code: -32603,
-                            message: e.to_string(),
-                        }),
-                    }
-                }
-            }
+) -> Response {
+    // Plain request/response endpoint, notifications sent by tools are dropped
+    let session = state.session.clone();
+    match handle_jsonrpc_message(state, session, payload, None).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+// Handle a message POSTed to the Streamable HTTP endpoint
+// Tool calls are answered with an SSE stream carrying their notifications when the client accepts it,
+// everything else with a single JSON response.
+#[axum::debug_handler]
+async fn handle_streamable_post(
+    State(state): State<Arc<SimpleMcpServerState>>,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    if let Err((status, message)) = check_protocol_version_header(&headers) {
+        return rpc_error_response(status, -32600, message);
+    }
+    // JSON-RPC responses from the client, this server sends no requests that need them
+    if message.get("method").is_none() {
+        return StatusCode::ACCEPTED.into_response();
+    }
+    let request: JSONRPCRequest = match serde_json::from_value(message) {
+        Ok(request) => request,
+        Err(e) => return rpc_error_response(StatusCode::BAD_REQUEST, -32600, format!("Invalid request: {}", e)),
+    };
+    
+    // initialize opens a new session, its id is returned in the Mcp-Session-Id header
+    if request.method == "initialize" && request.id.is_some() {
+        let session_id = Uuid::new_v4().to_string();
+        let session = Arc::new(Mutex::new(ServerSession::new()));
+        let response = match handle_jsonrpc_message(state.clone(), session.clone(), request, None).await {
+            Some(response) => response,
+            None => return StatusCode::ACCEPTED.into_response(),
+        };
+        if response.error.is_some() {
+            return Json(response).into_response();
         }
-        "ping" => {
-            // Handle ping request
-            JSONRPCResponse {
-                jsonrpc: "2.0".to_string(),
-                id: Some(payload.id.unwrap_or(Value::Null)),
-                result: Some(Value::Object(serde_json::Map::new())),
-                error: None,
-            }
+        state.sessions.lock().unwrap_or_else(|e| e.into_inner()).insert(session_id.clone(), session);
+        info!("Opened MCP session {}", session_id);
+        let mut http_response = Json(response).into_response();
+        if let Ok(value) = HeaderValue::from_str(&session_id) {
+            http_response.headers_mut().insert(MCP_SESSION_ID_HEADER, value);
         }
-        "tools/list" => {
-            // Handle tool list request
-            match handle_list_tools(state).await {
-                Ok(result) => {
-                    JSONRPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: Some(payload.id.unwrap_or(Value::Null)),
-                        result: Some(result),
-                        error: None,
-                    }
-                }
-                Err(e) => {
-                    JSONRPCResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: Some(payload.id.unwrap_or(Value::Null)),
-                        result: None,
-                        error: Some(JSONRPCError {
-                            code: -32603,
-                            message: e.to_string(),
-                        }),
-                    }
+        return http_response;
+    }
+    
+    let session = match find_session(&state, &headers) {
+        Ok(session) => session,
+        Err((status, message)) => return rpc_error_response(status, -32600, message),
+    };
+    if request.id.is_none() {
+        handle_notification(&session, &request.method);
+        return StatusCode::ACCEPTED.into_response();
+    }
+    
+    if request.method == "tools/call" && accepts_event_stream(&headers) {
+        // Progress and log notifications of the call are streamed before the response, then the stream ends
+        let (sender, receiver) = mpsc::unbounded_channel::<String>();
+        let notification_sender = sender.clone();
+        let sink: NotificationSink = Arc::new(move |notification| {
+            if let Ok(data) = serde_json::to_string(&notification) {
+                let _ = notification_sender.send(data);
+            }
+        });
+        tokio::spawn(async move {
+            if let Some(response) = handle_jsonrpc_message(state, session, request, Some(sink)).await {
+                if let Ok(data) = serde_json::to_string(&response) {
+                    let _ = sender.send(data);
                 }
             }
+        });
+        let events = UnboundedReceiverStream::new(receiver)
+            .map(|data| Ok::<_, Infallible>(Event::default().event("message").data(data)));
+        return Sse::new(events).into_response();
+    }
+    
+    // JSON response, notifications sent while handling the request go to the GET stream
+    let notifications = session.lock().unwrap_or_else(|e| e.into_inner()).notifications.clone();
+    let sink: NotificationSink = Arc::new(move |notification| {
+        let _ = notifications.send(notification);
+    });
+    match handle_jsonrpc_message(state, session, request, Some(sink)).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+// Open the SSE stream of server-initiated notifications of a session
+#[axum::debug_handler]
+async fn handle_streamable_get(
+    State(state): State<Arc<SimpleMcpServerState>>,
+    headers: HeaderMap,
+) -> Response {
+    if !accepts_event_stream(&headers) {
+        return StatusCode::NOT_ACCEPTABLE.into_response();
+    }
+    let session = match find_session(&state, &headers) {
+        Ok(session) => session,
+        Err((status, message)) => return rpc_error_response(status, -32600, message),
+    };
+    let receiver = session.lock().unwrap_or_else(|e| e.into_inner()).notifications.subscribe();
+    // Lagged receivers skip the notifications they missed
+    let events = BroadcastStream::new(receiver).filter_map(|notification| async move {
+        let data = serde_json::to_string(&notification.ok()?).ok()?;
+        Some(Ok::<_, Infallible>(Event::default().event("message").data(data)))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+// Terminate a session, its GET streams end once pending requests are done
+#[axum::debug_handler]
+async fn handle_streamable_delete(
+    State(state): State<Arc<SimpleMcpServerState>>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(session_id) = session_id_header(&headers) else {
+        return rpc_error_response(StatusCode::BAD_REQUEST, -32600, "Missing Mcp-Session-Id header");
+    };
+    match state.sessions.lock().unwrap_or_else(|e| e.into_inner()).remove(&session_id) {
+        Some(_) => {
+            info!("Closed MCP session {}", session_id);
+            StatusCode::OK.into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+fn rpc_error_response(status: StatusCode, code: i32, message: impl Into<String>) -> Response {
+    (status, Json(JSONRPCResponse::error(None, code, message))).into_response()
+}
+
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers.get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+fn session_id_header(headers: &HeaderMap) -> Option<String> {
+    headers.get(MCP_SESSION_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .map(|id| id.to_string())
+}
+
+// Requests after initialize must carry a known session id, 404 tells the client to initialize again
+fn find_session(state: &SimpleMcpServerState, headers: &HeaderMap) -> Result<SharedSession, (StatusCode, String)> {
+    let Some(session_id) = session_id_header(headers) else {
+        return Err((StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header".to_string()));
+    };
+    state.sessions.lock().unwrap_or_else(|e| e.into_inner())
+        .get(&session_id)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown session {}", session_id)))
+}
+
+// The MCP-Protocol-Version header is optional, but must name a supported version when sent
+fn check_protocol_version_header(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    match headers.get(MCP_PROTOCOL_VERSION_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(version) if !is_supported_protocol_version(version) => {
+            Err((StatusCode::BAD_REQUEST, format!("Unsupported protocol version {}", version)))
         }
-        _ => {
-            // Unsupported method
-            JSONRPCResponse {
-                jsonrpc: "2.0".to_string(),
-                id: Some(payload.id.unwrap_or(Value::Null)),
-                result: None,
-                error: Some(JSONRPCError {
-                    code: -32601,
-                    message: "Method not found".to_string(),
-                }),
+        _ => Ok(()),
+    }
+}
+
+// Dispatch a JSON-RPC message independently of the transport, returns None for notifications
+async fn handle_jsonrpc_message(
+    state: Arc<SimpleMcpServerState>,
+    session: SharedSession,
+    payload: JSONRPCRequest,
+    sink: Option<NotificationSink>,
+) -> Option<JSONRPCResponse> {
+    let Some(id) = payload.id.clone() else {
+        handle_notification(&session, &payload.method);
+        return None;
+    };
+    
+    let result = match payload.method.as_str() {
+        "initialize" => handle_initialize(&state, &session, payload.params),
+        // Handle tool call request, the tool can reach the client through McpRequestContext
+        "tools/call" => {
+            let log_level = session.lock().unwrap_or_else(|e| e.into_inner()).log_level;
+            let context = McpRequestContext::new(id, payload.params.as_ref(), log_level, sink);
+            context.scope(handle_tool_call(state, payload.params)).await
+        }
+        // Handle ping request
+        "ping" => Ok(Value::Object(serde_json::Map::new())),
+        // Handle tool list request
+        "tools/list" => handle_list_tools(state).await,
+        "logging/setLevel" => handle_set_level(&session, payload.params),
+        "resources/list" => handle_list_resources(&state, false),
+        "resources/templates/list" => handle_list_resources(&state, true),
+        "resources/read" => handle_read_resource(state, payload.params).await,
+        "resources/subscribe" => handle_subscribe(&session, payload.params, true),
+        "resources/unsubscribe" => handle_subscribe(&session, payload.params, false),
+        "prompts/list" => handle_list_prompts(&state),
+        "prompts/get" => handle_get_prompt(state, payload.params).await,
+        // Unsupported method
+        _ => return Some(JSONRPCResponse::error(payload.id, -32601, "Method not found")),
+    };
+    
+    Some(match result {
+        Ok(result) => JSONRPCResponse::success(payload.id, result),
+        Err(e) => match e.downcast_ref::<JSONRPCError>() {
+            // Handlers choose the code of protocol errors, everything else is an internal error
+            Some(error) => JSONRPCResponse::error(payload.id, error.code, error.message.clone()),
+            None => JSONRPCResponse::error(payload.id, -32603, e.to_string()),
+        },
+    })
+}
+
+fn handle_notification(session: &SharedSession, method: &str) {
+    match method {
+        "notifications/initialized" => {
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            if session.client.is_none() {
+                warn!("Received notifications/initialized before initialize");
             }
+            session.initialized = true;
+            info!("MCP client initialized");
         }
+        // Unknown notifications must be ignored
+        _ => debug!("Ignoring notification {}", method),
+    }
+}
+
+fn handle_initialize(
+    state: &SimpleMcpServerState,
+    session: &SharedSession,
+    params: Option<Value>,
+) -> Result<Value, Error> {
+    let params: InitializeParams = serde_json::from_value(params.unwrap_or(Value::Null))
+        .map_err(|e| Error::new(JSONRPCError::new(-32602, format!("Invalid initialize parameters: {}", e))))?;
+    
+    // Answer with the requested version if supported, the client decides whether it can use another one
+    let protocol_version = negotiate_protocol_version(&params.protocol_version);
+    if protocol_version != params.protocol_version {
+        warn!(
+            "Client {} requested unsupported protocol version {}, offering {}",
+            params.client_info.name, params.protocol_version, protocol_version
+        );
+    }
+    info!("Initializing MCP session with {} {}", params.client_info.name, params.client_info.version);
+    
+    {
+        let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+        session.client = Some(params);
+        // A new initialize starts a new session
+        session.initialized = false;
+    }
+    
+    let result = InitializeResult {
+        protocol_version: protocol_version.to_string(),
+        capabilities: state.capabilities.clone(),
+        server_info: state.server_info.clone(),
+        instructions: state.instructions.clone(),
     };
+    Ok(serde_json::to_value(result)?)
+}
+
+fn handle_set_level(session: &SharedSession, params: Option<Value>) -> Result<Value, Error> {
+    let level = params.as_ref().and_then(|p| p.get("level")).cloned().unwrap_or(Value::Null);
+    let level: LoggingLevel = serde_json::from_value(level)
+        .map_err(|e| Error::msg(format!("Invalid log level: {}", e)))?;
+    session.lock().unwrap_or_else(|e| e.into_inner()).log_level = level;
+    Ok(json!({}))
+}
+
+fn handle_list_resources(state: &SimpleMcpServerState, templates: bool) -> Result<Value, Error> {
+    let resources = state.resources.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
+    let mut listing: Vec<(&String, Value)> = resources.iter()
+        .filter(|(_, resource)| resource.is_template() == templates)
+        .map(|(uri, resource)| (uri, resource_listing(resource.as_ref())))
+        .collect();
+    // Stable order for clients that page or diff the list
+    listing.sort_by(|a, b| a.0.cmp(b.0));
+    let entries: Vec<Value> = listing.into_iter().map(|(_, entry)| entry).collect();
+    if templates {
+        Ok(json!({"resourceTemplates": entries}))
+    } else {
+        Ok(json!({"resources": entries}))
+    }
+}
+
+// Resource registered for a URI, exact URIs take precedence over templates
+// Among matching templates the one with the longest literal prefix wins, ties go to the template that sorts first
+fn find_resource(state: &SimpleMcpServerState, uri: &str) -> Option<(Arc<dyn Resource>, HashMap<String, String>)> {
+    let resources = state.resources.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(resource) = resources.get(uri) {
+        return Some((resource.clone(), HashMap::new()));
+    }
+    let templates = resources.values()
+        .filter(|resource| resource.is_template())
+        .map(|resource| resource.uri());
+    let (template, params) = best_template_match(templates, uri)?;
+    Some((resources.get(template)?.clone(), params))
+}
+
+fn resource_uri_param(params: &Option<Value>) -> Result<String, Error> {
+    params.as_ref()
+        .and_then(|p| p.get("uri"))
+        .and_then(|uri| uri.as_str())
+        .map(|uri| uri.to_string())
+        .ok_or_else(|| Error::new(JSONRPCError::new(-32602, "Missing resource uri")))
+}
+
+async fn handle_read_resource(
+    state: Arc<SimpleMcpServerState>,
+    params: Option<Value>,
+) -> Result<Value, Error> {
+    let uri = resource_uri_param(&params)?;
+    let (resource, template_params) = find_resource(&state, &uri)
+        .ok_or_else(|| Error::new(JSONRPCError::new(-32002, format!("Resource not found: {}", uri))))?;
+    let contents = resource.read(&uri, &template_params).await?;
+    Ok(json!({"contents": contents}))
+}
+
+fn handle_subscribe(session: &SharedSession, params: Option<Value>, subscribe: bool) -> Result<Value, Error> {
+    let uri = resource_uri_param(&params)?;
+    let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+    if subscribe {
+        session.subscriptions.insert(uri);
+    } else {
+        session.subscriptions.remove(&uri);
+    }
+    Ok(json!({}))
+}
+
+fn handle_list_prompts(state: &SimpleMcpServerState) -> Result<Value, Error> {
+    let prompts = state.prompts.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
+    let mut names: Vec<&String> = prompts.keys().collect();
+    names.sort();
+    let entries: Vec<Value> = names.into_iter().map(|name| prompt_listing(prompts[name].as_ref())).collect();
+    Ok(json!({"prompts": entries}))
+}
+
+async fn handle_get_prompt(
+    state: Arc<SimpleMcpServerState>,
+    params: Option<Value>,
+) -> Result<Value, Error> {
+    let params = params.unwrap_or(Value::Null);
+    let name = params.get("name")
+        .and_then(|name| name.as_str())
+        .ok_or_else(|| Error::new(JSONRPCError::new(-32602, "Missing prompt name")))?;
+    let prompt = state.prompts.lock().unwrap_or_else(|e| e.into_inner()).get(name).cloned()
+        .ok_or_else(|| Error::new(JSONRPCError::new(-32602, format!("Unknown prompt: {}", name))))?;
+    
+    // Argument values are strings, other JSON values are passed as their JSON text
+    let arguments: HashMap<String, String> = params.get("arguments")
+        .and_then(|arguments| arguments.as_object())
+        .map(|arguments| arguments.iter()
+            .map(|(key, value)| (key.clone(), value.as_str().map(|s| s.to_string()).unwrap_or_else(|| value.to_string())))
+            .collect())
+        .unwrap_or_default();
+    if let Some(missing) = prompt.arguments().iter().find(|a| a.required && !arguments.contains_key(&a.name)) {
+        return Err(Error::new(JSONRPCError::new(-32602, format!("Missing required argument: {}", missing.name))));
+    }
     
-    Json(response)
+    let messages = prompt.get(&arguments).await?;
+    let mut result = json!({"messages": messages});
+    if !prompt.description().is_empty() {
+        result["description"] = json!(prompt.description());
+    }
+    Ok(result)
 }
 
 async fn handle_list_tools(
@@ -148,11 +730,7 @@ async fn handle_list_tools(
         let mcp_tool = serde_json::json!({
             "name": tool.name(),
             "description": tool.description(),
-            "inputSchema": {
-                "type": "object",
-                "properties": {},
-                "required": []
-            }
+            "inputSchema": tool.input_schema()
         });
         tools_list.push(mcp_tool);
     }
@@ -171,13 +749,13 @@ async fn handle_tool_call(
 ) -> Result<serde_json::Value, Error> {
     // Parse parameters
     let call_params: CallToolParams = serde_json::from_value(params.unwrap_or(serde_json::Value::Null))
-        .map_err(|e| Error::msg(format!("Invalid parameters: {}", e)))?;
+        .map_err(|e| Error::new(JSONRPCError::new(-32602, format!("Invalid parameters: {}", e))))?;
     
     // Find tool and get its Arc reference
     let tool = {
         let tools = state.tools.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
         tools.get(&call_params.name)
-            .ok_or_else(|| Error::msg(format!("Tool '{}' not found", call_params.name)))?
+            .ok_or_else(|| Error::new(JSONRPCError::new(-32602, format!("Tool '{}' not found", call_params.name))))?
             .clone()
     };
     
@@ -189,14 +767,28 @@ async fn handle_tool_call(
     };
     
     // Call tool (now can be called without holding the lock)
-    let result = tool.invoke(&input_str).await?;
-    Ok(serde_json::Value::String(result))
+    // Tool failures are returned as isError results so the model can see them and recover
+    let result = match tool.invoke(&input_str).await {
+        Ok(output) => CallToolResult::from_tool_output(output),
+        Err(e) => {
+            warn!("Tool '{}' failed: {}", call_params.name, e);
+            CallToolResult::error(e.to_string())
+        }
+    };
+    Ok(serde_json::to_value(result)?)
 }
 
 // Server state structure
 #[derive(Clone)]
 struct SimpleMcpServerState {
     tools: Arc<Mutex<HashMap<String, Arc<dyn Tool>>>>,
+    resources: Arc<Mutex<HashMap<String, Arc<dyn Resource>>>>,
+    prompts: Arc<Mutex<HashMap<String, Arc<dyn Prompt>>>>,
+    server_info: Implementation,
+    instructions: Option<String>,
+    capabilities: ServerCapabilities,
+    session: SharedSession,
+    sessions: Arc<Mutex<HashMap<String, SharedSession>>>,
 }
 
 // MCP server abstraction
@@ -219,13 +811,12 @@ impl McpServer for SimpleMcpServer {
         info!("Starting MCP server on {}", address);
         
         // Create server state
-        let state = Arc::new(SimpleMcpServerState {
-            tools: self.tools.clone(),
-        });
+        let state = self.state();
         
         // Create routes
         let app = Router::new()
             .route("/rpc", post(handle_jsonrpc_request))
+            .route("/mcp", post(handle_streamable_post).get(handle_streamable_get).delete(handle_streamable_delete))
             .route("/test", get(test_handler))
             .with_state(state)
             .layer(CorsLayer::permissive()); // Allow all CORS requests
@@ -261,8 +852,11 @@ impl McpServer for SimpleMcpServer {
     // Register tool to MCP server
     fn register_tool(&self, tool: Arc<dyn Tool>) -> Result<(), Error> {
         let name = tool.name().to_string();
-        let mut tools = self.tools.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
-        tools.insert(name, tool);
+        {
+            let mut tools = self.tools.lock().map_err(|e| Error::msg(format!("Failed to acquire lock: {}", e)))?;
+            tools.insert(name, tool);
+        }
+        self.notify_tools_list_changed();
         Ok(())
     }
     
@@ -0,0 +1,577 @@
+// MCP client over the stdio transport (newline-delimited JSON-RPC with a child process)
+use anyhow::Error;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+
+use crate::mcp::client::dispatch_notification;
+use crate::mcp::protocol::parse_initialize_result;
+use crate::mcp::resource::{list_all_pages, parse_read_result};
+use crate::mcp::{
+    ClientCapabilities, Implementation, InitializeParams, InitializeResult, JSONRPCRequest, JSONRPCResponse,
+    GetPromptResult, McpClient, McpNotificationHandler, McpPrompt, McpResource, McpResourceTemplate, McpTool, ResourceContents,
+    ServerCapabilities, LATEST_PROTOCOL_VERSION,
+};
+
+// Number of stderr lines kept for diagnostics
+const STDERR_LINES: usize = 200;
+
+type PendingRequests = Arc<Mutex<HashMap<String, oneshot::Sender<JSONRPCResponse>>>>;
+type StderrLines = Arc<Mutex<VecDeque<String>>>;
+
+// Running server process, every process has its own requests and stderr so a restart does not mix them up
+struct StdioProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // Requests waiting for their response, keyed by the serialized id
+    pending: PendingRequests,
+    stderr: StderrLines,
+}
+
+// MCP client launching the server as a child process and talking to it over stdin/stdout
+#[derive(Clone)]
+pub struct StdioMcpClient {
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    current_dir: Option<PathBuf>,
+    client_info: Implementation,
+    capabilities: ClientCapabilities,
+    request_timeout: Duration,
+    shutdown_timeout: Duration,
+    process: Arc<tokio::sync::Mutex<Option<StdioProcess>>>,
+    // Stderr of the last started process, kept after it exits
+    stderr: Arc<Mutex<StderrLines>>,
+    initialize_result: Arc<Mutex<Option<InitializeResult>>>,
+    notification_handlers: Vec<McpNotificationHandler>,
+}
+
+impl StdioMcpClient {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            current_dir: None,
+            client_info: Implementation::default(),
+            capabilities: ClientCapabilities::default(),
+            request_timeout: Duration::from_secs(60),
+            shutdown_timeout: Duration::from_secs(5),
+            process: Arc::new(tokio::sync::Mutex::new(None)),
+            stderr: Arc::new(Mutex::new(Arc::new(Mutex::new(VecDeque::new())))),
+            initialize_result: Arc::new(Mutex::new(None)),
+            notification_handlers: Vec::new(),
+        }
+    }
+
+    /// Append a command line argument
+    pub fn with_arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append command line arguments
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable of the child process, the parent environment is inherited
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the working directory of the child process
+    pub fn with_current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the clientInfo sent in initialize
+    pub fn with_client_info(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.client_info = Implementation::new(name, version);
+        self
+    }
+
+    /// Set the capabilities announced in initialize
+    pub fn with_capabilities(mut self, capabilities: ClientCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set how long to wait for a response
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Set how long to wait for the process to exit after closing stdin before killing it
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Register a handler called for every notification written by the server, must be set before start
+    pub fn register_notification_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, &Value) + Send + Sync + 'static,
+    {
+        self.notification_handlers.push(Arc::new(handler));
+    }
+
+    /// Spawn the server process and run the initialize handshake
+    pub async fn start(&self) -> Result<InitializeResult, Error> {
+        {
+            let mut process = self.process.lock().await;
+            if process.is_some() {
+                return Err(Error::msg(format!("MCP server process {} is already running", self.command)));
+            }
+            *process = Some(self.spawn()?);
+        }
+
+        match self.initialize().await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                // Do not leave a process we refuse to talk to
+                let _ = self.shutdown().await;
+                Err(e)
+            }
+        }
+    }
+
+    fn spawn(&self) -> Result<StdioProcess, Error> {
+        info!("Starting MCP server process: {} {:?}", self.command, self.args);
+        let mut command = Command::new(&self.command);
+        command
+            .args(&self.args)
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        let mut child = command.spawn()
+            .map_err(|e| Error::msg(format!("Failed to start MCP server process {}: {}", self.command, e)))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| Error::msg("Failed to open stdin of MCP server process"))?;
+        let stdout = child.stdout.take().ok_or_else(|| Error::msg("Failed to open stdout of MCP server process"))?;
+        let stderr = child.stderr.take().ok_or_else(|| Error::msg("Failed to open stderr of MCP server process"))?;
+
+        // Keep the tail of stderr for error messages
+        let stderr_lines: StderrLines = Arc::new(Mutex::new(VecDeque::new()));
+        *self.stderr.lock().unwrap_or_else(|e| e.into_inner()) = stderr_lines.clone();
+        let stderr_buffer = stderr_lines.clone();
+        let stderr_task = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("MCP server stderr: {}", line);
+                let mut stderr_lines = stderr_lines.lock().unwrap_or_else(|e| e.into_inner());
+                if stderr_lines.len() == STDERR_LINES {
+                    stderr_lines.pop_front();
+                }
+                stderr_lines.push_back(line);
+            }
+        });
+
+        // Route responses to the waiting requests
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let process_pending = pending.clone();
+        let process = self.process.clone();
+        let handlers = self.notification_handlers.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                handle_stdout_line(&line, &pending, &process, &handlers).await;
+            }
+            // Stdout closed, the process is gone and nothing will answer anymore
+            debug!("MCP server stdout closed");
+            // Collect the last stderr lines first, they explain why the process exited
+            let _ = tokio::time::timeout(Duration::from_secs(1), stderr_task).await;
+            pending.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        });
+
+        Ok(StdioProcess { child, stdin, pending: process_pending, stderr: stderr_buffer })
+    }
+
+    async fn initialize(&self) -> Result<InitializeResult, Error> {
+        let params = InitializeParams {
+            protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            capabilities: self.capabilities.clone(),
+            client_info: self.client_info.clone(),
+        };
+        let result = self.request("initialize", Some(serde_json::to_value(params)?)).await?;
+        let result = parse_initialize_result(Some(result))?;
+        info!(
+            "Initialized MCP session with {} {} (protocol {})",
+            result.server_info.name, result.server_info.version, result.protocol_version
+        );
+        self.notify("notifications/initialized", None).await?;
+        *self.initialize_result.lock().unwrap_or_else(|e| e.into_inner()) = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Send a request and wait for its result
+    pub async fn request(&self, method: &str, params: Option<Value>) -> Result<Value, Error> {
+        let request = JSONRPCRequest::new(method, params);
+        let key = request.id.as_ref().map(|id| id.to_string()).unwrap_or_default();
+        let (sender, receiver) = oneshot::channel();
+        let (pending, stderr) = self.write_message(&request, Some((key.clone(), sender))).await?;
+
+        let response = match tokio::time::timeout(self.request_timeout, receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err(self.exited_error(&stderr)),
+            Err(_) => {
+                pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+                return Err(Error::msg(format!("MCP request {} timed out after {:?}", method, self.request_timeout)));
+            }
+        };
+        if let Some(error) = response.error {
+            return Err(Error::new(error));
+        }
+        Ok(response.result.unwrap_or(Value::Null))
+    }
+
+    /// Send a notification, no response is expected
+    pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<(), Error> {
+        self.write_message(&JSONRPCRequest::notification(method, params), None).await?;
+        Ok(())
+    }
+
+    // Write a message to the running process, registering the waiting request with that same process
+    async fn write_message(
+        &self,
+        message: &JSONRPCRequest,
+        waiting: Option<(String, oneshot::Sender<JSONRPCResponse>)>,
+    ) -> Result<(PendingRequests, StderrLines), Error> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        let mut process = self.process.lock().await;
+        let process = process.as_mut().ok_or_else(|| Error::msg("MCP server process is not running"))?;
+        let key = waiting.map(|(key, sender)| {
+            process.pending.lock().unwrap_or_else(|e| e.into_inner()).insert(key.clone(), sender);
+            key
+        });
+        if process.stdin.write_all(line.as_bytes()).await.is_err() || process.stdin.flush().await.is_err() {
+            if let Some(key) = key {
+                process.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+            }
+            return Err(self.exited_error(&process.stderr));
+        }
+        Ok((process.pending.clone(), process.stderr.clone()))
+    }
+
+    // Error for requests that cannot be answered anymore, with the end of the process stderr as context
+    fn exited_error(&self, stderr: &StderrLines) -> Error {
+        let stderr: Vec<String> = stderr.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect();
+        let tail = stderr[stderr.len().saturating_sub(10)..].join("\n");
+        if tail.is_empty() {
+            Error::msg(format!("MCP server process {} exited", self.command))
+        } else {
+            Error::msg(format!("MCP server process {} exited, stderr:\n{}", self.command, tail))
+        }
+    }
+
+    /// Close stdin, wait for the process to exit and kill it after the shutdown timeout
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        let process = self.process.lock().await.take();
+        *self.initialize_result.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        let Some(StdioProcess { mut child, stdin, .. }) = process else {
+            return Ok(());
+        };
+        drop(stdin);
+        match tokio::time::timeout(self.shutdown_timeout, child.wait()).await {
+            Ok(status) => info!("MCP server process {} exited with {}", self.command, status?),
+            Err(_) => {
+                warn!("MCP server process {} did not exit after closing stdin, killing it", self.command);
+                child.kill().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Last lines written by the server process to stderr
+    pub fn stderr_output(&self) -> Vec<String> {
+        let stderr = self.stderr.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let lines = stderr.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect();
+        lines
+    }
+
+    /// Protocol version negotiated in initialize
+    pub fn protocol_version(&self) -> Option<String> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.protocol_version.clone())
+    }
+
+    /// serverInfo returned by initialize
+    pub fn server_info(&self) -> Option<Implementation> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.server_info.clone())
+    }
+
+    /// Capabilities returned by initialize
+    pub fn server_capabilities(&self) -> Option<ServerCapabilities> {
+        let result = self.initialize_result.lock().unwrap_or_else(|e| e.into_inner());
+        result.as_ref().map(|r| r.capabilities.clone())
+    }
+}
+
+// Handle one line written by the server: a response, or a request/notification from the server
+async fn handle_stdout_line(
+    line: &str,
+    pending: &PendingRequests,
+    process: &tokio::sync::Mutex<Option<StdioProcess>>,
+    handlers: &[McpNotificationHandler],
+) {
+    if line.trim().is_empty() {
+        return;
+    }
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Ignoring non JSON-RPC output of MCP server: {} ({})", line, e);
+            return;
+        }
+    };
+
+    if let Some(method) = message.get("method").and_then(|m| m.as_str()) {
+        let id = match message.get("id") {
+            Some(id) if !id.is_null() => id.clone(),
+            _ => {
+                dispatch_notification(handlers, &message);
+                return;
+            }
+        };
+        // Only ping is answered, the client announces no other capabilities to the server
+        let response = match method {
+            "ping" => JSONRPCResponse::success(Some(id), json!({})),
+            _ => JSONRPCResponse::error(Some(id), -32601, "Method not found"),
+        };
+        if let Ok(mut line) = serde_json::to_string(&response) {
+            line.push('\n');
+            if let Some(process) = process.lock().await.as_mut() {
+                let _ = process.stdin.write_all(line.as_bytes()).await;
+                let _ = process.stdin.flush().await;
+            }
+        }
+        return;
+    }
+
+    let response: JSONRPCResponse = match serde_json::from_value(message) {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Ignoring invalid JSON-RPC response from MCP server: {}", e);
+            return;
+        }
+    };
+    let key = response.id.as_ref().map(|id| id.to_string()).unwrap_or_default();
+    match pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&key) {
+        Some(sender) => {
+            let _ = sender.send(response);
+        }
+        None => warn!("Received response for unknown request id {}", key),
+    }
+}
+
+impl McpClient for StdioMcpClient {
+    // Start the server process, the url is not used by the stdio transport
+    fn connect(&mut self, _url: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.start().await?;
+            Ok(())
+        })
+    }
+
+    // Get available tool list
+    fn get_tools(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpTool>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if self.server_capabilities().is_some_and(|c| c.tools.is_none()) {
+                return Ok(Vec::new());
+            }
+            let result = self.request("tools/list", None).await?;
+            let tools = result.get("tools").and_then(|t| t.as_array()).cloned().unwrap_or_default();
+            Ok(tools.iter().filter_map(McpTool::from_value).collect())
+        })
+    }
+
+    // Call specified tool
+    fn call_tool(&self, tool_name: &str, params: HashMap<String, Value>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value, Error>> + Send + '_>> {
+        // Ask for progress notifications, they are passed to the notification handlers
+        let params = json!({
+            "name": tool_name,
+            "arguments": params,
+            "_meta": {"progressToken": uuid::Uuid::new_v4().to_string()}
+        });
+        Box::pin(async move {
+            self.request("tools/call", Some(params)).await
+        })
+    }
+
+    // Stop the server process
+    fn disconnect(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.shutdown().await
+        })
+    }
+
+    // Clone method, clones share the server process
+    fn clone(&self) -> Box<dyn McpClient> {
+        Box::new(Clone::clone(self))
+    }
+
+    // List resources of the server
+    fn list_resources(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResource>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if self.server_capabilities().is_some_and(|c| c.resources.is_none()) {
+                return Ok(Vec::new());
+            }
+            list_all_pages("resources", |params| self.request("resources/list", params)).await
+        })
+    }
+
+    // List resource templates of the server
+    fn list_resource_templates(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpResourceTemplate>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if self.server_capabilities().is_some_and(|c| c.resources.is_none()) {
+                return Ok(Vec::new());
+            }
+            list_all_pages("resourceTemplates", |params| self.request("resources/templates/list", params)).await
+        })
+    }
+
+    // Read a resource
+    fn read_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<ResourceContents>, Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            parse_read_result(self.request("resources/read", Some(params)).await?)
+        })
+    }
+
+    // Subscribe to notifications/resources/updated of a resource
+    fn subscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            self.request("resources/subscribe", Some(params)).await?;
+            Ok(())
+        })
+    }
+
+    // Stop receiving notifications/resources/updated of a resource
+    fn unsubscribe_resource(&self, uri: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        let params = json!({"uri": uri});
+        Box::pin(async move {
+            self.request("resources/unsubscribe", Some(params)).await?;
+            Ok(())
+        })
+    }
+
+    // List prompts of the server
+    fn list_prompts(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<McpPrompt>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if self.server_capabilities().is_some_and(|c| c.prompts.is_none()) {
+                return Ok(Vec::new());
+            }
+            list_all_pages("prompts", |params| self.request("prompts/list", params)).await
+        })
+    }
+
+    // Get a prompt with its arguments filled in
+    fn get_prompt(&self, name: &str, arguments: HashMap<String, String>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<GetPromptResult, Error>> + Send + '_>> {
+        let params = json!({"name": name, "arguments": arguments});
+        Box::pin(async move {
+            let result = self.request("prompts/get", Some(params)).await?;
+            serde_json::from_value(result).map_err(|e| Error::msg(format!("Invalid prompts/get result: {}", e)))
+        })
+    }
+
+    // Ping server
+    fn ping(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.request("ping", None).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    // Minimal MCP server written in sh, answers by matching on the serialized request
+    const SH_SERVER: &str = r#"
+echo "sh server starting" >&2
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed -n 's/^{"jsonrpc":"2.0","id":\("[^"]*"\).*/\1/p')
+  [ -z "$id" ] && continue
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '%s\n' "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2025-03-26\",\"capabilities\":{\"tools\":{}},\"serverInfo\":{\"name\":\"sh-server\",\"version\":\"1.0.0\"}}}" ;;
+    *'"method":"tools/list"'*)
+      printf '%s\n' "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"echo\",\"description\":\"Echo\",\"inputSchema\":{\"type\":\"object\"}}]}}" ;;
+    *'"method":"tools/call"'*)
+      printf '%s\n' "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":\"$MCP_GREETING\"}" ;;
+    *'"method":"crash"'*)
+      echo "fatal: crashed on purpose" >&2; exit 1 ;;
+    *)
+      printf '%s\n' "{\"jsonrpc\":\"2.0\",\"id\":$id,\"error\":{\"code\":-32601,\"message\":\"Method not found\"}}" ;;
+  esac
+done
+"#;
+
+    #[tokio::test]
+    async fn test_stdio_client_with_child_process() {
+        let mut client = StdioMcpClient::new("sh")
+            .with_args(["-c", SH_SERVER])
+            .with_env("MCP_GREETING", "hello")
+            .with_request_timeout(Duration::from_secs(5));
+        client.connect("").await.unwrap();
+        assert_eq!(client.protocol_version().as_deref(), Some("2025-03-26"));
+        assert_eq!(client.server_info().unwrap().name, "sh-server");
+
+        let tools = client.get_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        assert_eq!(client.call_tool("echo", HashMap::new()).await.unwrap(), json!("hello"));
+        assert!(client.request("unknown", None).await.unwrap_err().to_string().contains("Method not found"));
+
+        // A crash fails the pending request with the captured stderr
+        let error = client.request("crash", None).await.unwrap_err().to_string();
+        assert!(error.contains("fatal: crashed on purpose"), "{}", error);
+        assert!(client.stderr_output().contains(&"sh server starting".to_string()));
+
+        client.disconnect().await.unwrap();
+        assert!(client.ping().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stdio_client_restart() {
+        let mut client = StdioMcpClient::new("sh")
+            .with_args(["-c", SH_SERVER])
+            .with_env("MCP_GREETING", "hello")
+            .with_request_timeout(Duration::from_secs(5));
+        client.connect("").await.unwrap();
+        assert!(client.request("crash", None).await.is_err());
+
+        // Reconnecting like the client pool does, the old process neither fails the new requests nor shows its stderr
+        for _ in 0..3 {
+            client.disconnect().await.unwrap();
+            client.connect("").await.unwrap();
+            assert_eq!(client.call_tool("echo", HashMap::new()).await.unwrap(), json!("hello"));
+            assert!(!client.stderr_output().iter().any(|line| line.contains("fatal")));
+        }
+        client.disconnect().await.unwrap();
+    }
+}
@@ -12,8 +12,13 @@ use log::{info, warn, error};
 use std::future::Future;
 
 use crate::memory::base::{BaseMemory, MemoryVariables};
-use crate::memory::message_history::{MessageHistoryMemory, ChatMessage};
+use crate::memory::message_history::{ChatMessageHistory, MessageHistoryMemory, ChatMessage};
 use crate::memory::summary::SummaryMemory;
+use crate::memory::encryption::MemoryCipher;
+use crate::memory::vector_store_memory::VectorStoreRetrieverMemory;
+use crate::memory::entity_memory::EntityMemory;
+use crate::models::{ChatModel, Tokenizer};
+use crate::prompt::PromptTemplate;
 use crate::memory::utils::{
     ensure_data_dir_exists, get_data_dir_from_env, get_summary_threshold_from_env,
     get_recent_messages_count_from_env, generate_session_id
@@ -26,7 +31,7 @@ pub struct CompositeMemoryConfig {
     pub data_dir: PathBuf,
     /// Session ID (automatically generated internally)
     pub session_id: Option<String>,
-    /// Summary threshold (in token count, 1 token ≈ 4 English characters, 1 token ≈ 1 Chinese character)
+    /// Summary threshold (in token count, see CompositeMemory::with_tokenizer)
     pub summary_threshold: usize,
     /// Number of recent messages to keep (in message count)
     pub recent_messages_count: usize,
@@ -50,6 +55,7 @@ impl Default for CompositeMemoryConfig {
 /// 
 /// This struct combines multiple memory types, providing a unified interface to manage different types of memory.
 /// It can simultaneously manage message history and summary memory, and provide intelligent summary generation functionality.
+/// An optional long-term memory retrieves relevant messages of past sessions, see `with_long_term_memory`.
 #[derive(Debug, Clone)]
 pub struct CompositeMemory {
     /// Configuration
@@ -58,6 +64,10 @@ pub struct CompositeMemory {
     message_history: Option<Arc<MessageHistoryMemory>>,
     /// Summary memory
     summary_memory: Option<Arc<SummaryMemory>>,
+    /// Long-term memory over a vector store
+    long_term_memory: Option<Arc<VectorStoreRetrieverMemory>>,
+    /// Entities and user profile shared by the sessions of the user
+    entity_memory: Option<Arc<EntityMemory>>,
     /// In-memory memory variables
     memory_variables: Arc<RwLock<MemoryVariables>>,
 }
@@ -88,6 +98,17 @@ impl CompositeMemory {
 
     /// Create a composite memory instance with configuration
     pub async fn with_config(config: CompositeMemoryConfig) -> Result<Self> {
+        Self::open(config, None).await
+    }
+
+    /// Create a composite memory instance whose history and summary files are encrypted at rest
+    /// Existing plaintext sessions stay readable and are encrypted on their next write, or all
+    /// at once with memory::reencrypt_data_dir.
+    pub async fn with_cipher(config: CompositeMemoryConfig, cipher: MemoryCipher) -> Result<Self> {
+        Self::open(config, Some(cipher)).await
+    }
+
+    async fn open(config: CompositeMemoryConfig, cipher: Option<MemoryCipher>) -> Result<Self> {
         // Ensure data directory exists
         ensure_data_dir_exists(&config.data_dir).await?;
 
@@ -96,26 +117,48 @@ impl CompositeMemory {
             .unwrap_or_else(|| generate_session_id());
 
         // Always create message history memory
-        let history = MessageHistoryMemory::new_with_recent_count(
-            session_id.clone(),
+        let history = MessageHistoryMemory::new_with_cipher(
+            session_id,
             config.data_dir.clone(),
-            config.recent_messages_count
+            config.recent_messages_count,
+            cipher.clone()
         ).await?;
+
+        Self::with_message_history(config, history, cipher).await
+    }
+
+    /// Create a composite memory instance keeping messages in the given store, e.g. a SqliteChatMessageHistory
+    /// The session ID of the store replaces the one of the configuration, summaries are kept in data_dir.
+    pub async fn with_chat_history(mut config: CompositeMemoryConfig, chat_history: Arc<dyn ChatMessageHistory>) -> Result<Self> {
+        ensure_data_dir_exists(&config.data_dir).await?;
+        config.session_id = Some(chat_history.session_id().to_string());
+        let history = MessageHistoryMemory::from_chat_history(chat_history, config.recent_messages_count);
+        Self::with_message_history(config, history, None).await
+    }
+
+    async fn with_message_history(config: CompositeMemoryConfig, history: MessageHistoryMemory, cipher: Option<MemoryCipher>) -> Result<Self> {
+        let session_id = history.get_session_id().to_string();
         let message_history = Some(Arc::new(history));
 
         // Always create summary memory with shared message history
         let summary = SummaryMemory::new_with_shared_history(
-            session_id.clone(),
+            session_id,
             config.data_dir.clone(),
             config.summary_threshold,
             message_history.clone().unwrap() // We just created it, so it's safe to unwrap
         ).await?;
+        let summary = match cipher {
+            Some(cipher) => summary.with_cipher(cipher),
+            None => summary,
+        };
         let summary_memory = Some(Arc::new(summary));
 
         Ok(Self {
             config,
             message_history,
             summary_memory,
+            long_term_memory: None,
+            entity_memory: None,
             memory_variables: Arc::new(RwLock::new(HashMap::new())),
         })
     }
@@ -127,6 +170,74 @@ impl CompositeMemory {
         Self::with_config(config).await
     }
 
+    /// Count tokens for the summary threshold with this tokenizer instead of the character estimate
+    /// Use the tokenizer of the chat model, e.g. ModelRegistry::tokenizer_for(model_name).
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.summary_memory = self.summary_memory
+            .map(|summary| Arc::new((*summary).clone().with_tokenizer(tokenizer)));
+        self
+    }
+
+    /// Generate summaries with this model, usually the chat model of the agent
+    /// Required when auto_generate_summary is enabled, summaries fail with a configuration error otherwise.
+    pub fn with_chat_model(mut self, model: Arc<dyn ChatModel>) -> Self {
+        self.summary_memory = self.summary_memory
+            .map(|summary| Arc::new((*summary).clone().with_chat_model(model)));
+        self
+    }
+
+    /// Set the summary prompt template, see SummaryMemory::with_summary_prompt_template
+    pub fn with_summary_prompt_template(mut self, template: PromptTemplate) -> Self {
+        self.summary_memory = self.summary_memory
+            .map(|summary| Arc::new((*summary).clone().with_summary_prompt_template(template)));
+        self
+    }
+
+    /// Set the token budget of one summary request, longer runs of new messages are summarized in chunks first
+    pub fn with_summary_chunk_tokens(mut self, tokens: usize) -> Self {
+        self.summary_memory = self.summary_memory
+            .map(|summary| Arc::new((*summary).clone().with_summary_chunk_tokens(tokens)));
+        self
+    }
+
+    /// Also store messages in a vector store and retrieve the ones relevant to the input
+    /// The retrieved messages are returned in the memory variable of the long-term memory
+    /// ("relevant_history" by default). Messages are tagged with the session id of this memory.
+    pub fn with_long_term_memory(mut self, memory: VectorStoreRetrieverMemory) -> Self {
+        let memory = match self.config.session_id.clone()
+            .or_else(|| self.message_history.as_ref().map(|history| history.get_session_id().to_string()))
+        {
+            Some(session_id) => memory.with_session_id(session_id),
+            None => memory,
+        };
+        self.long_term_memory = Some(Arc::new(memory));
+        self
+    }
+
+    /// Also extract entities and user facts after each turn and return the relevant ones
+    /// The entities are returned in the memory variable of the entity memory ("entities" by default).
+    /// Facts learned in this memory are tagged with its session id.
+    pub fn with_entity_memory(mut self, memory: EntityMemory) -> Self {
+        let memory = match self.config.session_id.clone()
+            .or_else(|| self.message_history.as_ref().map(|history| history.get_session_id().to_string()))
+        {
+            Some(session_id) => memory.with_session_id(session_id),
+            None => memory,
+        };
+        self.entity_memory = Some(Arc::new(memory));
+        self
+    }
+
+    /// Get the long-term memory
+    pub fn long_term_memory(&self) -> Option<&VectorStoreRetrieverMemory> {
+        self.long_term_memory.as_deref()
+    }
+
+    /// Get the entity memory, to view and edit the stored facts
+    pub fn entity_memory(&self) -> Option<&EntityMemory> {
+        self.entity_memory.as_deref()
+    }
+
     /// Add message to memory
     pub async fn add_message(&self, message: ChatMessage) -> Result<()> {
         // Add to message history (always enabled)
@@ -134,6 +245,8 @@ impl CompositeMemory {
             history.add_message(&message).await?;
         }
 
+        self.add_to_long_term_memory(&message).await;
+
         // Check if summary generation is needed (always enabled)
         if self.config.auto_generate_summary {
             info!("Checking if summary generation is needed...");
@@ -153,6 +266,15 @@ impl CompositeMemory {
         Ok(())
     }
 
+    // Index a message in the long-term memory, best effort: the message is already in the chat history
+    async fn add_to_long_term_memory(&self, message: &ChatMessage) {
+        if let Some(ref long_term) = self.long_term_memory {
+            if let Err(e) = long_term.add_message(message).await {
+                warn!("Failed to add message to long-term memory: {}", e);
+            }
+        }
+    }
+
     /// Get message count
     pub async fn get_message_count(&self) -> Result<usize> {
         if let Some(ref history) = self.message_history {
@@ -242,6 +364,14 @@ impl BaseMemory for CompositeMemory {
         
         // Add configuration related variables
         vars.push("config".to_string());
+
+        if let Some(ref long_term) = self.long_term_memory {
+            vars.push(long_term.memory_key().to_string());
+        }
+
+        if let Some(ref entities) = self.entity_memory {
+            vars.push(entities.memory_key().to_string());
+        }
         
         vars
     }
@@ -269,6 +399,19 @@ impl BaseMemory for CompositeMemory {
                 }
             }
 
+            // Load past messages relevant to the input
+            if let Some(ref long_term) = self.long_term_memory {
+                match long_term.load_memory_variables(inputs).await {
+                    Ok(variables) => result.extend(variables),
+                    Err(e) => warn!("Failed to load relevant messages from long-term memory: {}", e),
+                }
+            }
+
+            // Load the user profile and the entities mentioned in the input
+            if let Some(ref entities) = self.entity_memory {
+                result.extend(entities.load_memory_variables(inputs).await?);
+            }
+
             // Add input
             if let Some(input) = inputs.get("input") {
                 result.insert("input".to_string(), input.clone());
@@ -300,35 +443,42 @@ impl BaseMemory for CompositeMemory {
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
 
-            // Create user message
+            // Create the user and assistant messages
+            let mut messages = Vec::new();
             if !input.is_empty() {
-                let user_message = ChatMessage {
+                messages.push(ChatMessage {
                     id: uuid::Uuid::new_v4().to_string(),
                     role: "user".to_string(),
                     content: input.to_string(),
                     timestamp: chrono::Utc::now().to_rfc3339(),
                     metadata: None,
-                };
-                
-                // Add directly to message history without triggering summary generation
-                if let Some(ref history) = self.message_history {
-                    history.add_message(&user_message).await?;
-                }
+                });
             }
-
-            // Create assistant message
             if !output.is_empty() {
-                let assistant_message = ChatMessage {
+                messages.push(ChatMessage {
                     id: uuid::Uuid::new_v4().to_string(),
                     role: "assistant".to_string(),
                     content: output.to_string(),
                     timestamp: chrono::Utc::now().to_rfc3339(),
                     metadata: None,
-                };
-                
-                // Add directly to message history without triggering summary generation
-                if let Some(ref history) = self.message_history {
-                    history.add_message(&assistant_message).await?;
+                });
+            }
+
+            // Add directly to message history without triggering summary generation
+            if let Some(ref history) = self.message_history {
+                for message in &messages {
+                    history.add_message(message).await?;
+                }
+            }
+            // The turn is saved before indexing it in the long-term memory
+            for message in &messages {
+                self.add_to_long_term_memory(message).await;
+            }
+
+            // Update the entities with the facts of this turn, the turn is already saved when the extraction fails
+            if let Some(ref entities) = self.entity_memory {
+                if let Err(e) = entities.save_context(inputs, outputs).await {
+                    warn!("Failed to update entities: {}", e);
                 }
             }
 
@@ -373,6 +523,13 @@ impl BaseMemory for CompositeMemory {
                 summary.clear().await?;
             }
 
+            // Clear the long-term messages of this session
+            if let Some(ref long_term) = self.long_term_memory {
+                long_term.clear().await?;
+            }
+
+            // Entities belong to the user and are kept, see EntityMemory::clear
+
             // Clear internal memory variables
             self.memory_variables.write().await.clear();
 
@@ -514,4 +671,100 @@ mod tests {
         let count = memory.get_message_count().await.unwrap();
         assert_eq!(count, 0);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_encrypted_session_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CompositeMemoryConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            session_id: Some("secret".to_string()),
+            summary_threshold: 1000,
+            recent_messages_count: 10,
+            auto_generate_summary: false,
+        };
+        let cipher = MemoryCipher::from_key([3u8; 32]);
+        let memory = CompositeMemory::with_cipher(config.clone(), cipher.clone()).await.unwrap();
+        let inputs = HashMap::from([("input".to_string(), json!("my seed is apple banana"))]);
+        let outputs = HashMap::from([("output".to_string(), json!("Please never share it"))]);
+        memory.save_context(&inputs, &outputs).await.unwrap();
+
+        let raw = tokio::fs::read(temp_dir.path().join("secret_history.jsonl")).await.unwrap();
+        assert!(crate::memory::encryption::is_encrypted(&raw));
+        assert!(!String::from_utf8_lossy(&raw).contains("apple"));
+
+        let reopened = CompositeMemory::with_cipher(config.clone(), cipher).await.unwrap();
+        assert_eq!(reopened.get_recent_messages(10).await.unwrap()[0].content, "my seed is apple banana");
+        assert!(CompositeMemory::with_config(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_long_term_memory_across_sessions() {
+        use crate::embeddings::DeterministicFakeEmbedding;
+        use crate::vectorstores::{InMemoryVectorStore, VectorStore};
+
+        let temp_dir = TempDir::new().unwrap();
+        let store: Arc<dyn VectorStore> = Arc::new(InMemoryVectorStore::new(Arc::new(DeterministicFakeEmbedding::new(32))));
+        let open_session = |session_id: &str| {
+            let config = CompositeMemoryConfig {
+                data_dir: temp_dir.path().to_path_buf(),
+                session_id: Some(session_id.to_string()),
+                summary_threshold: 1000,
+                recent_messages_count: 10,
+                auto_generate_summary: false,
+            };
+            let store = store.clone();
+            async move {
+                CompositeMemory::with_config(config).await.unwrap()
+                    .with_long_term_memory(VectorStoreRetrieverMemory::new(store).with_k(1))
+            }
+        };
+
+        let first = open_session("first").await;
+        let inputs = HashMap::from([("input".to_string(), json!("I live in Lisbon"))]);
+        let outputs = HashMap::from([("output".to_string(), json!("Nice city!"))]);
+        first.save_context(&inputs, &outputs).await.unwrap();
+
+        let second = open_session("second").await;
+        assert!(second.memory_variables().contains(&"relevant_history".to_string()));
+        let variables = second.load_memory_variables(&inputs).await.unwrap();
+        assert!(variables["relevant_history"].as_str().unwrap().ends_with("user: I live in Lisbon"));
+        assert_eq!(variables["chat_history"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_long_term_memory_failures_keep_the_turn() {
+        use crate::embeddings::{Embedding, Embeddings};
+        use crate::vectorstores::InMemoryVectorStore;
+
+        // Embeddings of an unreachable provider
+        struct FailingEmbeddings;
+
+        impl Embeddings for FailingEmbeddings {
+            fn dimensions(&self) -> Option<usize> {
+                None
+            }
+
+            fn embed_documents(&self, _texts: Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Embedding>>> + Send + '_>> {
+                Box::pin(async { Err(anyhow::anyhow!("embedding service unavailable")) })
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = CompositeMemoryConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            auto_generate_summary: false,
+            ..CompositeMemoryConfig::default()
+        };
+        let memory = CompositeMemory::with_config(config).await.unwrap()
+            .with_long_term_memory(VectorStoreRetrieverMemory::new(Arc::new(InMemoryVectorStore::new(Arc::new(FailingEmbeddings)))));
+
+        let inputs = HashMap::from([("input".to_string(), json!("I live in Lisbon"))]);
+        let outputs = HashMap::from([("output".to_string(), json!("Nice city!"))]);
+        memory.save_context(&inputs, &outputs).await.unwrap();
+        assert_eq!(memory.get_message_count().await.unwrap(), 2);
+
+        let variables = memory.load_memory_variables(&inputs).await.unwrap();
+        assert!(!variables.contains_key("relevant_history"));
+        assert_eq!(variables["chat_history"].as_array().unwrap().len(), 2);
+    }
+}
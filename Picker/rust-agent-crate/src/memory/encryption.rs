@@ -0,0 +1,323 @@
+// Encryption at rest for memory files
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Error, Result};
+use argon2::Argon2;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Start of every encrypted memory file, followed by the key id, the nonce and the ciphertext
+pub const ENCRYPTED_FILE_MAGIC: &[u8; 6] = b"RAENC\x01";
+
+/// File in the data directory holding the salt of passphrase derived keys
+pub const SALT_FILE_NAME: &str = ".encryption_salt";
+
+const KEY_ID_LEN: usize = 8;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+
+/// 32-byte key with the id written in the header of the files it encrypts
+#[derive(Clone)]
+struct KeyEntry {
+    id: [u8; KEY_ID_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl KeyEntry {
+    fn new(key: &[u8; 32]) -> Self {
+        let digest = Sha256::new()
+            .chain_update(b"rust-agent memory key")
+            .chain_update(key)
+            .finalize();
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&digest[..KEY_ID_LEN]);
+        Self {
+            id,
+            cipher: XChaCha20Poly1305::new(key.into()),
+        }
+    }
+
+    fn hex_id(&self) -> String {
+        self.id.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// AEAD cipher for memory files (XChaCha20-Poly1305 with a random nonce per write)
+///
+/// Files are encrypted with the current key. Previous keys added with `with_previous_key` can
+/// still decrypt older files, so keys can be rotated and the files re-encrypted later with
+/// `reencrypt_data_dir`. Plaintext files are read as they are and encrypted on the next write.
+#[derive(Clone)]
+pub struct MemoryCipher {
+    current: KeyEntry,
+    previous: Vec<KeyEntry>,
+}
+
+impl MemoryCipher {
+    /// Use a supplied 32-byte key
+    pub fn from_key(key: [u8; 32]) -> Self {
+        Self {
+            current: KeyEntry::new(&key),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Use a base64 encoded 32-byte key
+    pub fn from_base64_key(key: &str) -> Result<Self> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| Error::msg(format!("Encryption key must be 32 bytes, got {}", bytes.len())))?;
+        Ok(Self::from_key(key))
+    }
+
+    /// Derive the key from a passphrase with Argon2id
+    pub fn from_passphrase_and_salt(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| Error::msg(format!("Failed to derive encryption key: {}", e)))?;
+        Ok(Self::from_key(key))
+    }
+
+    /// Derive the key from a passphrase, with the salt stored in the data directory
+    /// The salt file is created with a random salt on first use.
+    pub async fn from_passphrase(passphrase: &str, data_dir: &Path) -> Result<Self> {
+        let salt = load_or_create_salt(data_dir).await?;
+        Self::from_passphrase_and_salt(passphrase, &salt)
+    }
+
+    /// Cipher configured by the environment, if any
+    /// `MEMORY_ENCRYPTION_KEY` holds a base64 encoded 32-byte key, otherwise the key is derived
+    /// from `MEMORY_ENCRYPTION_PASSPHRASE`.
+    pub async fn from_env(data_dir: &Path) -> Result<Option<Self>> {
+        if let Ok(key) = std::env::var("MEMORY_ENCRYPTION_KEY") {
+            return Self::from_base64_key(&key).map(Some);
+        }
+        match std::env::var("MEMORY_ENCRYPTION_PASSPHRASE") {
+            Ok(passphrase) => Self::from_passphrase(&passphrase, data_dir).await.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Keep the current key of the other cipher (and its previous keys) for decryption only
+    pub fn with_previous_key(mut self, previous: MemoryCipher) -> Self {
+        self.previous.push(previous.current);
+        self.previous.extend(previous.previous);
+        self
+    }
+
+    /// Id of the current key, written in the header of encrypted files
+    pub fn key_id(&self) -> String {
+        self.current.hex_id()
+    }
+
+    /// Encrypt with the current key and a random nonce
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut header = Vec::with_capacity(ENCRYPTED_FILE_MAGIC.len() + KEY_ID_LEN + NONCE_LEN);
+        header.extend_from_slice(ENCRYPTED_FILE_MAGIC);
+        header.extend_from_slice(&self.current.id);
+        // The header is authenticated with the content
+        let ciphertext = self.current.cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &header })
+            .map_err(|_| Error::msg("Failed to encrypt memory data"))?;
+        header.extend_from_slice(&nonce);
+        header.extend_from_slice(&ciphertext);
+        Ok(header)
+    }
+
+    /// Decrypt data written by `encrypt` with the current or a previous key
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let header_len = ENCRYPTED_FILE_MAGIC.len() + KEY_ID_LEN;
+        if !is_encrypted(data) || data.len() < header_len + NONCE_LEN {
+            return Err(Error::msg("Data is not an encrypted memory file"));
+        }
+        let key_id = &data[ENCRYPTED_FILE_MAGIC.len()..header_len];
+        let entry = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|entry| entry.id == key_id)
+            .ok_or_else(|| Error::msg(format!(
+                "Memory file was encrypted with an unknown key ({}), check the passphrase",
+                key_id.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
+            )))?;
+        let nonce = XNonce::from_slice(&data[header_len..header_len + NONCE_LEN]);
+        entry.cipher
+            .decrypt(nonce, Payload { msg: &data[header_len + NONCE_LEN..], aad: &data[..header_len] })
+            .map_err(|_| Error::msg("Failed to decrypt memory data, the file is corrupted or was modified"))
+    }
+
+    // Whether the data is encrypted with the current key
+    fn is_current(&self, data: &[u8]) -> bool {
+        is_encrypted(data) && data.get(ENCRYPTED_FILE_MAGIC.len()..ENCRYPTED_FILE_MAGIC.len() + KEY_ID_LEN) == Some(&self.current.id[..])
+    }
+}
+
+impl fmt::Debug for MemoryCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryCipher")
+            .field("key_id", &self.current.hex_id())
+            .field("previous_keys", &self.previous.len())
+            .finish()
+    }
+}
+
+/// Whether the data starts with the header of an encrypted memory file
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_FILE_MAGIC)
+}
+
+/// Decode the content of a memory file, decrypting it when it is encrypted
+pub fn decode_file_content(data: Vec<u8>, cipher: Option<&MemoryCipher>) -> Result<String> {
+    if !is_encrypted(&data) {
+        return Ok(String::from_utf8(data)?);
+    }
+    match cipher {
+        Some(cipher) => Ok(String::from_utf8(cipher.decrypt(&data)?)?),
+        None => Err(Error::msg("Memory file is encrypted but no encryption key is configured")),
+    }
+}
+
+/// Encode the content of a memory file, encrypting it when a cipher is given
+pub fn encode_file_content(content: &str, cipher: Option<&MemoryCipher>) -> Result<Vec<u8>> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(content.as_bytes()),
+        None => Ok(content.as_bytes().to_vec()),
+    }
+}
+
+async fn load_or_create_salt(data_dir: &Path) -> Result<Vec<u8>> {
+    let path = data_dir.join(SALT_FILE_NAME);
+    match tokio::fs::read(&path).await {
+        Ok(salt) => Ok(salt),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tokio::fs::create_dir_all(data_dir).await?;
+            let mut salt = vec![0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            // Never replace a salt created concurrently, files encrypted with it would be lost
+            let mut file = match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(tokio::fs::read(&path).await?),
+                Err(e) => return Err(e.into()),
+            };
+            tokio::io::AsyncWriteExt::write_all(&mut file, &salt).await?;
+            Ok(salt)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Files handled by `reencrypt_data_dir`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionReport {
+    /// Plaintext files that were encrypted
+    pub encrypted: Vec<PathBuf>,
+    /// Files encrypted with a previous key that were re-encrypted with the current key
+    pub rotated: Vec<PathBuf>,
+    /// Files already encrypted with the current key
+    pub unchanged: Vec<PathBuf>,
+}
+
+// Session files written by the memory module
+fn is_memory_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with("_history.jsonl") || name.ends_with("_summary.json") || name.ends_with("_entities.json"))
+}
+
+/// Encrypt the plaintext session files of the data directory and re-encrypt the files of
+/// previous keys with the current key
+///
+/// This migrates existing plaintext sessions and completes a key rotation: build the cipher
+/// with the new key and add the old one with `with_previous_key`. Each file is replaced
+/// atomically, a failure leaves the remaining files readable with the same cipher.
+pub async fn reencrypt_data_dir(data_dir: &Path, cipher: &MemoryCipher) -> Result<EncryptionReport> {
+    let mut report = EncryptionReport::default();
+    let mut entries = tokio::fs::read_dir(data_dir).await?;
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() && is_memory_file(&entry.path()) {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+
+    for path in paths {
+        let data = tokio::fs::read(&path).await?;
+        if cipher.is_current(&data) {
+            report.unchanged.push(path);
+            continue;
+        }
+        let was_encrypted = is_encrypted(&data);
+        let content = decode_file_content(data, Some(cipher))
+            .map_err(|e| Error::msg(format!("{}: {}", path.display(), e)))?;
+        crate::memory::utils::atomic_write_file_with_cipher(&path, &content, Some(cipher)).await?;
+        if was_encrypted {
+            report.rotated.push(path);
+        } else {
+            report.encrypted.push(path);
+        }
+    }
+    info!(
+        "[MemoryCipher] Encrypted {} files, rotated {} files to key {}",
+        report.encrypted.len(), report.rotated.len(), cipher.key_id()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_and_rotation() {
+        let old = MemoryCipher::from_passphrase_and_salt("correct horse", b"0123456789abcdef").unwrap();
+        let data = old.encrypt(b"0x1234 private key").unwrap();
+        assert!(is_encrypted(&data));
+        assert_ne!(old.encrypt(b"0x1234 private key").unwrap(), data, "nonces must differ");
+        assert_eq!(old.decrypt(&data).unwrap(), b"0x1234 private key");
+
+        let wrong = MemoryCipher::from_passphrase_and_salt("wrong horse", b"0123456789abcdef").unwrap();
+        assert!(wrong.decrypt(&data).unwrap_err().to_string().contains("unknown key"));
+
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(old.decrypt(&tampered).is_err());
+
+        let new = MemoryCipher::from_key([7u8; 32]).with_previous_key(old.clone());
+        assert_eq!(new.decrypt(&data).unwrap(), b"0x1234 private key");
+        assert!(!new.is_current(&data));
+        assert!(new.is_current(&new.encrypt(b"x").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_reencrypt_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = dir.path().join("s1_history.jsonl");
+        let summary = dir.path().join("s1_summary.json");
+        let other = dir.path().join("notes.txt");
+        tokio::fs::write(&history, "{\"messages\": []}").await.unwrap();
+        tokio::fs::write(&other, "keep me").await.unwrap();
+
+        let old = MemoryCipher::from_passphrase("secret", dir.path()).await.unwrap();
+        // The salt is reused for the same data directory
+        assert_eq!(MemoryCipher::from_passphrase("secret", dir.path()).await.unwrap().key_id(), old.key_id());
+        tokio::fs::write(&summary, old.encrypt(b"{\"summary\": null}").unwrap()).await.unwrap();
+
+        let report = reencrypt_data_dir(dir.path(), &old).await.unwrap();
+        assert_eq!(report.encrypted, vec![history.clone()]);
+        assert_eq!(report.unchanged, vec![summary.clone()]);
+        assert_eq!(tokio::fs::read_to_string(&other).await.unwrap(), "keep me");
+
+        let new = MemoryCipher::from_key([1u8; 32]).with_previous_key(old);
+        let report = reencrypt_data_dir(dir.path(), &new).await.unwrap();
+        assert_eq!(report.rotated.len(), 2);
+        let content = decode_file_content(tokio::fs::read(&history).await.unwrap(), Some(&MemoryCipher::from_key([1u8; 32]))).unwrap();
+        assert_eq!(content, "{\"messages\": []}");
+    }
+}
@@ -0,0 +1,612 @@
+// Entity memory, facts about the user and the entities of the conversation extracted by a chat model
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, Weak};
+use anyhow::{Error, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::memory::base::BaseMemory;
+use crate::memory::encryption::MemoryCipher;
+use crate::memory::utils::{atomic_write_file_with_cipher, delete_file, file_exists, read_file_content_with_cipher};
+use crate::prompt::PromptTemplate;
+use crate::{ChatMessageContent, ChatModel, ModelChatMessage};
+
+/// Default memory variable holding the relevant entities
+pub const DEFAULT_ENTITY_MEMORY_KEY: &str = "entities";
+
+/// Entity holding the facts about the user, always injected
+pub const USER_PROFILE_ENTITY: &str = "User";
+
+/// Default extraction prompt, {entities} is replaced by the known entities and {input}/{output} by the turn
+pub const DEFAULT_ENTITY_EXTRACTION_PROMPT: &str = "You maintain a memory of facts about the user and the named entities of the conversation, such as wallet addresses, blockchains, token contracts, pickers, people and projects.\n\nKnown entities:\n{entities}\n\nLast conversation turn:\nUser: {input}\nAssistant: {output}\n\nExtract the facts stated or confirmed in this turn. Facts about the user themselves (preferences, accounts, addresses) belong to the entity \"User\". Return only a JSON object {{\"entities\": [{{\"name\": \"...\", \"type\": \"...\", \"facts\": [\"...\"]}}]}} listing every entity whose facts changed with its complete updated list of facts: keep the known facts that are still valid and drop the contradicted ones. Copy addresses and identifiers exactly. Return {{\"entities\": []}} when nothing changed.";
+
+/// Fact stored about an entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityFact {
+    /// Fact ID, used to edit or delete the fact
+    pub id: String,
+    pub content: String,
+    /// Session in which the fact was learned, None for facts added through the API
+    pub session_id: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Named entity with its facts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    /// Entity name, unique per user (case-insensitive)
+    pub name: String,
+    /// Entity type, e.g. "wallet", "chain", "token" or "picker"
+    pub entity_type: Option<String>,
+    pub facts: Vec<EntityFact>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Entity {
+    fn new(name: &str, entity_type: Option<String>) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Self {
+            name: name.to_string(),
+            entity_type,
+            facts: Vec::new(),
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    // "- USDC (token): fact; fact"
+    fn format_line(&self) -> String {
+        let facts = self.facts.iter().map(|fact| fact.content.as_str()).collect::<Vec<_>>().join("; ");
+        match &self.entity_type {
+            Some(entity_type) => format!("- {} ({}): {}", self.name, entity_type, facts),
+            None => format!("- {}: {}", self.name, facts),
+        }
+    }
+}
+
+// Entities of a user as stored in {user_id}_entities.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct EntityStoreData {
+    user_id: String,
+    entities: Vec<Entity>,
+}
+
+type EntityStore = Arc<RwLock<EntityStoreData>>;
+
+// Stores of the open entity files, so that all the instances of a user (one per session) update the same entities
+fn entity_stores() -> &'static Mutex<HashMap<PathBuf, Weak<RwLock<EntityStoreData>>>> {
+    static STORES: OnceLock<Mutex<HashMap<PathBuf, Weak<RwLock<EntityStoreData>>>>> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Entity as returned by the model
+#[derive(Debug, Deserialize)]
+struct ExtractedEntity {
+    name: String,
+    #[serde(rename = "type", default)]
+    entity_type: Option<String>,
+    #[serde(default)]
+    facts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtractionResult {
+    #[serde(default)]
+    entities: Vec<ExtractedEntity>,
+}
+
+/// Entity and user-profile memory, aligned with LangChain's ConversationEntityMemory
+///
+/// After each turn the model extracts the entities of the conversation with their facts and
+/// updates the ones already known. Entities are stored per user in `{user_id}_entities.json`,
+/// so they are shared by all sessions of the user: instances opened on the same file share their
+/// entities, an update made in one session is never overwritten by another. `load_memory_variables` returns the user
+/// profile and the entities mentioned in the input. Facts can be viewed and edited with
+/// `entities`, `add_fact`, `update_fact`, `delete_fact` and `delete_entity`.
+#[derive(Clone)]
+pub struct EntityMemory {
+    user_id: String,
+    data_dir: PathBuf,
+    chat_model: Arc<dyn ChatModel>,
+    session_id: Option<String>,
+    memory_key: String,
+    input_key: String,
+    extraction_prompt_template: PromptTemplate,
+    // Maximum number of entities injected besides the user profile
+    max_entities: usize,
+    cipher: Option<MemoryCipher>,
+    data: EntityStore,
+}
+
+impl EntityMemory {
+    /// Load the entities of the user from data_dir, extracting new facts with the model
+    pub async fn new(user_id: String, data_dir: PathBuf, chat_model: Arc<dyn ChatModel>) -> Result<Self> {
+        Self::new_with_cipher(user_id, data_dir, chat_model, None).await
+    }
+
+    /// Load the entities of the user, the entity file is encrypted at rest with the cipher when given
+    pub async fn new_with_cipher(user_id: String, data_dir: PathBuf, chat_model: Arc<dyn ChatModel>, cipher: Option<MemoryCipher>) -> Result<Self> {
+        if user_id.is_empty() || user_id.contains(['/', '\\']) || user_id == ".." {
+            return Err(Error::msg(format!("Invalid user id {:?}", user_id)));
+        }
+        tokio::fs::create_dir_all(&data_dir).await?;
+
+        // The registry is locked while loading, concurrent instances of the user get the same store
+        let file_path = tokio::fs::canonicalize(&data_dir).await?.join(format!("{}_entities.json", user_id));
+        let mut stores = entity_stores().lock().await;
+        let data = match stores.get(&file_path).and_then(Weak::upgrade) {
+            Some(data) => data,
+            None => {
+                let data = if file_exists(&file_path).await {
+                    serde_json::from_str(&read_file_content_with_cipher(&file_path, cipher.as_ref()).await?)?
+                } else {
+                    EntityStoreData { user_id: user_id.clone(), entities: Vec::new() }
+                };
+                let data = Arc::new(RwLock::new(data));
+                stores.retain(|_, store| store.strong_count() > 0);
+                stores.insert(file_path, Arc::downgrade(&data));
+                data
+            },
+        };
+        drop(stores);
+
+        Ok(Self {
+            user_id,
+            data_dir,
+            chat_model,
+            session_id: None,
+            memory_key: DEFAULT_ENTITY_MEMORY_KEY.to_string(),
+            input_key: "input".to_string(),
+            extraction_prompt_template: PromptTemplate::new(DEFAULT_ENTITY_EXTRACTION_PROMPT).expect("default entity prompt is valid"),
+            max_entities: 10,
+            cipher,
+            data,
+        })
+    }
+
+    /// Set the session id recorded with extracted facts
+    pub fn with_session_id(mut self, session_id: String) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    /// Set the memory variable holding the relevant entities
+    pub fn with_memory_key(mut self, memory_key: String) -> Self {
+        self.memory_key = memory_key;
+        self
+    }
+
+    /// Set the input variable holding the user message
+    pub fn with_input_key(mut self, input_key: String) -> Self {
+        self.input_key = input_key;
+        self
+    }
+
+    /// Set the extraction prompt, it receives {entities}, {input} and {output} and must ask for the JSON of DEFAULT_ENTITY_EXTRACTION_PROMPT
+    pub fn with_extraction_prompt_template(mut self, template: PromptTemplate) -> Self {
+        self.extraction_prompt_template = template;
+        self
+    }
+
+    /// Set the maximum number of mentioned entities injected besides the user profile
+    pub fn with_max_entities(mut self, max_entities: usize) -> Self {
+        self.max_entities = max_entities;
+        self
+    }
+
+    /// Get the user id
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Get the memory variable name
+    pub fn memory_key(&self) -> &str {
+        &self.memory_key
+    }
+
+    /// All entities of the user
+    pub async fn entities(&self) -> Vec<Entity> {
+        self.data.read().await.entities.clone()
+    }
+
+    /// Get an entity by name (case-insensitive)
+    pub async fn get_entity(&self, name: &str) -> Option<Entity> {
+        let data = self.data.read().await;
+        find_entity(&data.entities, name).map(|index| data.entities[index].clone())
+    }
+
+    /// The user profile and the entities mentioned in the text, most recently updated first
+    pub async fn relevant_entities(&self, text: &str) -> Vec<Entity> {
+        let text = text.to_lowercase();
+        let data = self.data.read().await;
+        let mut mentioned: Vec<&Entity> = data.entities.iter()
+            .filter(|entity| !entity.name.eq_ignore_ascii_case(USER_PROFILE_ENTITY))
+            .filter(|entity| text.contains(&entity.name.to_lowercase()))
+            .collect();
+        mentioned.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        mentioned.truncate(self.max_entities);
+
+        let profile = find_entity(&data.entities, USER_PROFILE_ENTITY).map(|index| &data.entities[index]);
+        profile.into_iter().chain(mentioned).cloned().collect()
+    }
+
+    /// Add a fact to an entity, the entity is created when it does not exist
+    pub async fn add_fact(&self, entity_name: &str, entity_type: Option<String>, content: &str) -> Result<EntityFact> {
+        let fact = new_fact(content, None);
+        let mut data = self.data.write().await;
+        let index = match find_entity(&data.entities, entity_name) {
+            Some(index) => index,
+            None => {
+                data.entities.push(Entity::new(entity_name, entity_type.clone()));
+                data.entities.len() - 1
+            },
+        };
+        let entity = &mut data.entities[index];
+        if entity_type.is_some() {
+            entity.entity_type = entity_type;
+        }
+        entity.facts.push(fact.clone());
+        entity.updated_at = fact.updated_at.clone();
+        self.save(&data).await?;
+        Ok(fact)
+    }
+
+    /// Replace the content of a fact, return false when the fact does not exist
+    pub async fn update_fact(&self, entity_name: &str, fact_id: &str, content: &str) -> Result<bool> {
+        let mut data = self.data.write().await;
+        let Some(index) = find_entity(&data.entities, entity_name) else {
+            return Ok(false);
+        };
+        let entity = &mut data.entities[index];
+        let Some(fact) = entity.facts.iter_mut().find(|fact| fact.id == fact_id) else {
+            return Ok(false);
+        };
+        fact.content = content.trim().to_string();
+        fact.updated_at = chrono::Utc::now().to_rfc3339();
+        entity.updated_at = fact.updated_at.clone();
+        self.save(&data).await?;
+        Ok(true)
+    }
+
+    /// Delete a fact, return false when the fact does not exist
+    pub async fn delete_fact(&self, entity_name: &str, fact_id: &str) -> Result<bool> {
+        let mut data = self.data.write().await;
+        let Some(index) = find_entity(&data.entities, entity_name) else {
+            return Ok(false);
+        };
+        let entity = &mut data.entities[index];
+        let count = entity.facts.len();
+        entity.facts.retain(|fact| fact.id != fact_id);
+        if entity.facts.len() == count {
+            return Ok(false);
+        }
+        entity.updated_at = chrono::Utc::now().to_rfc3339();
+        self.save(&data).await?;
+        Ok(true)
+    }
+
+    /// Delete an entity with its facts, return false when it does not exist
+    pub async fn delete_entity(&self, entity_name: &str) -> Result<bool> {
+        let mut data = self.data.write().await;
+        let Some(index) = find_entity(&data.entities, entity_name) else {
+            return Ok(false);
+        };
+        data.entities.remove(index);
+        self.save(&data).await?;
+        Ok(true)
+    }
+
+    /// Extract the entities of a turn with the model and update the stored facts
+    /// Return the names of the entities that changed.
+    pub async fn extract_entities(&self, input: &str, output: &str) -> Result<Vec<String>> {
+        if input.trim().is_empty() && output.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let known = self.relevant_entities(&format!("{}\n{}", input, output)).await;
+        let mut prompt_inputs = HashMap::new();
+        prompt_inputs.insert("entities".to_string(), if known.is_empty() { "(none)".to_string() } else { format_entities(&known) });
+        prompt_inputs.insert("input".to_string(), input.to_string());
+        prompt_inputs.insert("output".to_string(), output.to_string());
+        let prompt = self.extraction_prompt_template.format(&prompt_inputs)?;
+
+        let response = self.chat_model.invoke(vec![ModelChatMessage::Human(ChatMessageContent {
+            content: prompt,
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })]).await?;
+        let content = match response.message {
+            ModelChatMessage::AIMessage(content) => content.content,
+            _ => return Err(Error::msg("Expected AI message response")),
+        };
+        let extracted = parse_extraction(&content)?;
+
+        let mut data = self.data.write().await;
+        let mut changed = Vec::new();
+        for entity in extracted.entities {
+            changed.extend(self.merge_entity(&mut data, entity));
+        }
+        if !changed.is_empty() {
+            info!("[EntityMemory] Updated entities: {:?}", changed);
+            self.save(&data).await?;
+        }
+        Ok(changed)
+    }
+
+    // Replace the facts of an entity with the extracted ones, facts with unchanged content keep their id
+    // Return the name of the entity when its facts changed.
+    fn merge_entity(&self, data: &mut EntityStoreData, extracted: ExtractedEntity) -> Option<String> {
+        let name = extracted.name.trim();
+        let facts: Vec<String> = extracted.facts.iter()
+            .map(|fact| fact.trim().to_string())
+            .filter(|fact| !fact.is_empty())
+            .collect();
+        // Entities are only deleted through the API
+        if name.is_empty() || facts.is_empty() {
+            return None;
+        }
+        let entity_type = extracted.entity_type.filter(|entity_type| !entity_type.trim().is_empty());
+
+        let index = match find_entity(&data.entities, name) {
+            Some(index) => index,
+            None => {
+                data.entities.push(Entity::new(name, entity_type.clone()));
+                data.entities.len() - 1
+            },
+        };
+        let entity = &mut data.entities[index];
+        let previous: Vec<String> = entity.facts.iter().map(|fact| fact.content.to_lowercase()).collect();
+        let new_facts: Vec<EntityFact> = facts.iter()
+            .map(|content| {
+                entity.facts.iter()
+                    .find(|fact| fact.content.eq_ignore_ascii_case(content))
+                    .cloned()
+                    .unwrap_or_else(|| new_fact(content, self.session_id.clone()))
+            })
+            .collect();
+        let current: Vec<String> = new_facts.iter().map(|fact| fact.content.to_lowercase()).collect();
+        if entity.entity_type.is_none() {
+            entity.entity_type = entity_type;
+        }
+        if current == previous {
+            return None;
+        }
+        entity.facts = new_facts;
+        entity.updated_at = chrono::Utc::now().to_rfc3339();
+        Some(entity.name.clone())
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.data_dir.join(format!("{}_entities.json", self.user_id))
+    }
+
+    // Write the entities while the write lock is held
+    async fn save(&self, data: &EntityStoreData) -> Result<()> {
+        let json = serde_json::to_string_pretty(data)?;
+        atomic_write_file_with_cipher(&self.file_path(), &json, self.cipher.as_ref()).await
+    }
+}
+
+impl fmt::Debug for EntityMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EntityMemory")
+            .field("user_id", &self.user_id)
+            .field("data_dir", &self.data_dir)
+            .field("session_id", &self.session_id)
+            .field("memory_key", &self.memory_key)
+            .field("max_entities", &self.max_entities)
+            .field("cipher", &self.cipher)
+            .finish_non_exhaustive()
+    }
+}
+
+fn find_entity(entities: &[Entity], name: &str) -> Option<usize> {
+    let name = name.trim();
+    entities.iter().position(|entity| entity.name.eq_ignore_ascii_case(name))
+}
+
+fn new_fact(content: &str, session_id: Option<String>) -> EntityFact {
+    let now = chrono::Utc::now().to_rfc3339();
+    EntityFact {
+        id: uuid::Uuid::new_v4().to_string(),
+        content: content.trim().to_string(),
+        session_id,
+        created_at: now.clone(),
+        updated_at: now,
+    }
+}
+
+fn format_entities(entities: &[Entity]) -> String {
+    entities.iter().map(Entity::format_line).collect::<Vec<_>>().join("\n")
+}
+
+// The model may wrap the JSON object in text or a code block
+fn parse_extraction(content: &str) -> Result<ExtractionResult> {
+    let start = content.find('{');
+    let end = content.rfind('}');
+    match (start, end) {
+        (Some(start), Some(end)) if end > start => Ok(serde_json::from_str(&content[start..=end])?),
+        _ => Err(Error::msg(format!("No JSON object in entity extraction response: {}", content))),
+    }
+}
+
+impl BaseMemory for EntityMemory {
+    fn memory_variables(&self) -> Vec<String> {
+        vec![self.memory_key.clone()]
+    }
+
+    fn load_memory_variables<'a>(&'a self, inputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let input = inputs.get(&self.input_key).and_then(Value::as_str).unwrap_or("");
+            let entities = self.relevant_entities(input).await;
+            let mut variables = HashMap::new();
+            variables.insert(self.memory_key.clone(), Value::String(format_entities(&entities)));
+            Ok(variables)
+        })
+    }
+
+    fn save_context<'a>(&'a self, inputs: &'a HashMap<String, Value>, outputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let input = inputs.get(&self.input_key).and_then(Value::as_str).unwrap_or("");
+            let output = outputs.get("output").and_then(Value::as_str).unwrap_or("");
+            self.extract_entities(input, output).await?;
+            Ok(())
+        })
+    }
+
+    fn clear<'a>(&'a self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            // Remove all entities of the user
+            let mut data = self.data.write().await;
+            data.entities.clear();
+            delete_file(&self.file_path()).await?;
+            Ok(())
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn BaseMemory> {
+        Box::new(self.clone())
+    }
+
+    fn get_session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    fn set_session_id(&mut self, session_id: String) {
+        self.session_id = Some(session_id);
+    }
+
+    fn get_token_count(&self) -> Result<usize, Error> {
+        // Injected entities depend on the input
+        Ok(0)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use serde_json::json;
+    use crate::ChatCompletion;
+
+    // Model answering with scripted extraction results and recording the prompts
+    struct ScriptedModel {
+        responses: Mutex<Vec<String>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl ChatModel for ScriptedModel {
+        fn invoke(&self, messages: Vec<ModelChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+            Box::pin(async move {
+                if let Some(ModelChatMessage::Human(content)) = messages.last() {
+                    self.prompts.lock().unwrap().push(content.content.clone());
+                }
+                Ok(ChatCompletion {
+                    message: ModelChatMessage::AIMessage(ChatMessageContent {
+                        content: self.responses.lock().unwrap().remove(0),
+                        name: None,
+                        additional_kwargs: HashMap::new(),
+                    }),
+                    usage: None,
+                    model_name: "scripted".to_string(),
+                })
+            })
+        }
+    }
+
+    fn turn(input: &str, output: &str) -> (HashMap<String, Value>, HashMap<String, Value>) {
+        (HashMap::from([("input".to_string(), json!(input))]), HashMap::from([("output".to_string(), json!(output))]))
+    }
+
+    #[tokio::test]
+    async fn test_extract_update_and_edit_entities() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = Arc::new(ScriptedModel {
+            responses: Mutex::new(vec![
+                r#"```json
+{"entities": [{"name": "User", "type": "person", "facts": ["Prefers the Polygon chain", "Wallet address 0xAbC123"]},
+              {"name": "PepeToken", "type": "token", "facts": ["Deployed by the user at 0xDeF456"]}]}
+```"#.to_string(),
+                r#"{"entities": [{"name": "user", "facts": ["Prefers the Base chain", "Wallet address 0xAbC123"]}]}"#.to_string(),
+            ]),
+            prompts: Mutex::new(Vec::new()),
+        });
+        let memory = EntityMemory::new("alice".to_string(), dir.path().to_path_buf(), model.clone()).await.unwrap()
+            .with_session_id("s1".to_string());
+
+        let (inputs, outputs) = turn("My wallet is 0xAbC123, I use Polygon and deployed PepeToken at 0xDeF456", "Noted");
+        memory.save_context(&inputs, &outputs).await.unwrap();
+        let wallet_fact = memory.get_entity("User").await.unwrap().facts[1].clone();
+        assert_eq!(wallet_fact.session_id.as_deref(), Some("s1"));
+        let (inputs, outputs) = turn("I moved to Base", "Got it");
+        memory.save_context(&inputs, &outputs).await.unwrap();
+
+        // The second extraction sees the profile and keeps the id of the unchanged fact
+        assert!(model.prompts.lock().unwrap()[1].contains("- User (person): Prefers the Polygon chain; Wallet address 0xAbC123"));
+        let profile = memory.get_entity("User").await.unwrap();
+        assert_eq!(profile.facts.len(), 2);
+        assert_eq!(profile.facts[0].content, "Prefers the Base chain");
+        assert_eq!(profile.facts[1].id, wallet_fact.id);
+
+        // Entities persist for the user across sessions, mentioned entities are injected
+        let reopened = EntityMemory::new("alice".to_string(), dir.path().to_path_buf(), model.clone()).await.unwrap();
+        let variables = reopened.load_memory_variables(&HashMap::from([("input".to_string(), json!("How is pepetoken doing?"))])).await.unwrap();
+        let text = variables[DEFAULT_ENTITY_MEMORY_KEY].as_str().unwrap();
+        assert!(text.starts_with("- User (person): Prefers the Base chain"));
+        assert!(text.contains("- PepeToken (token): Deployed by the user at 0xDeF456"));
+        let variables = reopened.load_memory_variables(&HashMap::from([("input".to_string(), json!("Hello"))])).await.unwrap();
+        assert!(!variables[DEFAULT_ENTITY_MEMORY_KEY].as_str().unwrap().contains("PepeToken"));
+
+        // View, edit and delete facts
+        let fact_id = profile.facts[1].id.clone();
+        assert!(reopened.update_fact("user", &fact_id, "Wallet address 0xAbC999").await.unwrap());
+        let fact = reopened.add_fact("Picker #42", Some("picker".to_string()), "Favorite picker of the user").await.unwrap();
+        assert!(reopened.delete_fact("Picker #42", &fact.id).await.unwrap());
+        assert!(!reopened.delete_fact("Picker #42", &fact.id).await.unwrap());
+        assert!(reopened.delete_entity("PepeToken").await.unwrap());
+        let reopened = EntityMemory::new("alice".to_string(), dir.path().to_path_buf(), model).await.unwrap();
+        assert_eq!(reopened.get_entity("User").await.unwrap().facts[1].content, "Wallet address 0xAbC999");
+        assert!(reopened.get_entity("PepeToken").await.is_none());
+        assert_eq!(reopened.entities().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_of_a_user_share_entities() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = Arc::new(ScriptedModel {
+            responses: Mutex::new(vec![
+                r#"{"entities": [{"name": "User", "facts": ["Prefers the Polygon chain"]}]}"#.to_string(),
+                r#"{"entities": [{"name": "PepeToken", "type": "token", "facts": ["Deployed at 0xDeF456"]}]}"#.to_string(),
+            ]),
+            prompts: Mutex::new(Vec::new()),
+        });
+        let first = EntityMemory::new("bob".to_string(), dir.path().to_path_buf(), model.clone()).await.unwrap()
+            .with_session_id("s1".to_string());
+        let second = EntityMemory::new("bob".to_string(), dir.path().to_path_buf(), model.clone()).await.unwrap()
+            .with_session_id("s2".to_string());
+
+        // Each session saves its own turn, neither overwrites the facts of the other
+        let (inputs, outputs) = turn("I use Polygon", "Noted");
+        first.save_context(&inputs, &outputs).await.unwrap();
+        let (inputs, outputs) = turn("I deployed PepeToken at 0xDeF456", "Nice");
+        second.save_context(&inputs, &outputs).await.unwrap();
+        second.add_fact("User", None, "Wallet address 0xAbC123").await.unwrap();
+        assert_eq!(first.get_entity("user").await.unwrap().facts.len(), 2);
+
+        drop((first, second));
+        let reopened = EntityMemory::new("bob".to_string(), dir.path().to_path_buf(), model).await.unwrap();
+        assert_eq!(reopened.get_entity("User").await.unwrap().facts.len(), 2);
+        assert!(reopened.get_entity("PepeToken").await.is_some());
+    }
+}
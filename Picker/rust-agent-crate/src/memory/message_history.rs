@@ -1,9 +1,8 @@
 // Long-term memory implementation, persisting conversation history to file
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::fs::File;
-use tokio::io::{AsyncWriteExt, AsyncReadExt};
 use anyhow::{Error, Result};
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
@@ -11,7 +10,10 @@ use serde_json::Value;
 use std::pin::Pin;
 use std::future::Future;
 use log::{info, warn};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+
+use crate::memory::encryption::MemoryCipher;
+use crate::memory::utils::{atomic_write_file_with_cipher, read_file_content_with_cipher};
 
 // Chat message structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +55,134 @@ pub struct ChatSessionHistory {
     pub messages: Vec<ChatMessageRecord>,
     /// Session-level metadata
     pub metadata: Option<HashMap<String, serde_json::Value>>,
+    /// Sequence number of the last added message, kept when older messages are removed
+    #[serde(default)]
+    pub last_sequence_number: u64,
+}
+
+/// Overview of a stored session, without its messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSessionInfo {
+    pub session_id: String,
+    /// Creation time (RFC 3339)
+    pub created_at: String,
+    /// Time of the last change (RFC 3339)
+    pub updated_at: String,
+    pub message_count: usize,
+    /// Conversation summary, when the store keeps one
+    pub summary: Option<String>,
+    pub metadata: Option<HashMap<String, Value>>,
+}
+
+/// Message storage of one session, aligned with LangChain's BaseChatMessageHistory
+///
+/// `FileChatMessageHistory` keeps a JSON file per session, `SqliteChatMessageHistory`
+/// (`sqlite` feature) keeps all sessions in one database. Stores number the messages of a
+/// session with increasing sequence numbers, which summaries use to find new messages.
+pub trait ChatMessageHistory: Send + Sync + fmt::Debug {
+    // Session whose messages are stored
+    fn session_id(&self) -> &str;
+
+    // Append messages in one write, the store assigns their sequence numbers
+    fn add_messages(&self, messages: Vec<ChatMessageRecord>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+
+    // All messages ordered by sequence number
+    fn get_messages(&self) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>>;
+
+    // Delete all messages except the most recent ones, sequence numbers keep increasing
+    fn keep_recent_messages(&self, count: usize) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+
+    // Delete all messages and restart the sequence numbers
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+
+    // Append a single message
+    fn add_message(&self, message: ChatMessageRecord) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        self.add_messages(vec![message])
+    }
+
+    // The most recent messages, oldest first
+    fn get_recent_messages(&self, count: usize) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut messages = self.get_messages().await?;
+            let skip = messages.len().saturating_sub(count);
+            Ok(messages.split_off(skip))
+        })
+    }
+
+    // Messages with a timestamp at or after start and before end, oldest first
+    fn get_messages_in_range(&self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let messages = self.get_messages().await?;
+            Ok(messages
+                .into_iter()
+                .filter(|message| {
+                    let Ok(timestamp) = DateTime::parse_from_rfc3339(&message.timestamp) else {
+                        return false;
+                    };
+                    start.is_none_or(|start| timestamp >= start) && end.is_none_or(|end| timestamp < end)
+                })
+                .collect())
+        })
+    }
+
+    // Messages containing every word of the query (case-insensitive), at most limit, most recent first
+    fn search_messages(&self, query: String, limit: usize) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let terms: Vec<String> = query.split_whitespace().map(|term| term.to_lowercase()).collect();
+            if terms.is_empty() {
+                return Ok(Vec::new());
+            }
+            let messages = self.get_messages().await?;
+            Ok(messages
+                .into_iter()
+                .rev()
+                .filter(|message| {
+                    let content = message.content.to_lowercase();
+                    terms.iter().all(|term| content.contains(term.as_str()))
+                })
+                .take(limit)
+                .collect())
+        })
+    }
+
+    // Number of stored messages
+    fn message_count(&self) -> Pin<Box<dyn Future<Output = Result<usize, Error>> + Send + '_>> {
+        Box::pin(async move { Ok(self.get_messages().await?.len()) })
+    }
+}
+
+/// If an AI answer is a JSON string or an object with a "content" field, keep only the text
+fn extract_ai_content(content: &str) -> String {
+    if content.starts_with('"') && content.ends_with('"') {
+        // Try to parse as JSON string
+        match serde_json::from_str::<serde_json::Value>(content) {
+            Ok(serde_json::Value::String(s)) => s,
+            _ => content.to_string(),
+        }
+    } else if content.starts_with('{') && content.ends_with('}') {
+        // Try to parse as JSON object and extract the content field
+        match serde_json::from_str::<serde_json::Value>(content) {
+            Ok(json_obj) => json_obj.get("content")
+                .and_then(|content_value| content_value.as_str())
+                .map(|content_str| content_str.to_string())
+                .unwrap_or_else(|| content.to_string()),
+            _ => content.to_string(),
+        }
+    } else {
+        content.to_string()
+    }
+}
+
+/// Create a record to append, the store assigns the sequence number
+fn new_record(role: &str, content: String) -> ChatMessageRecord {
+    ChatMessageRecord {
+        role: role.to_string(),
+        content,
+        name: None,
+        additional_kwargs: None,
+        timestamp: Utc::now().to_rfc3339(),
+        sequence_number: 0,
+    }
 }
 
 /// File message history implementation, aligned with LangChain's FileChatMessageHistory
@@ -65,8 +195,8 @@ pub struct FileChatMessageHistory {
     file_path: PathBuf,
     /// In-memory session history
     session_history: Arc<RwLock<ChatSessionHistory>>,
-    /// Next message sequence number
-    next_sequence_number: Arc<RwLock<u64>>,
+    /// Cipher encrypting the file at rest (optional)
+    cipher: Option<MemoryCipher>,
 }
 
 impl Clone for FileChatMessageHistory {
@@ -75,7 +205,7 @@ impl Clone for FileChatMessageHistory {
             session_id: self.session_id.clone(),
             file_path: self.file_path.clone(),
             session_history: Arc::clone(&self.session_history),
-            next_sequence_number: Arc::clone(&self.next_sequence_number),
+            cipher: self.cipher.clone(),
         }
     }
 }
@@ -83,6 +213,12 @@ impl Clone for FileChatMessageHistory {
 impl FileChatMessageHistory {
     /// Create a new file message history instance
     pub async fn new(session_id: String, file_path: PathBuf) -> Result<Self> {
+        Self::new_with_cipher(session_id, file_path, None).await
+    }
+
+    /// Create a new file message history instance, encrypting the file when a cipher is given
+    /// Plaintext files are still read and get encrypted on the next write.
+    pub async fn new_with_cipher(session_id: String, file_path: PathBuf, cipher: Option<MemoryCipher>) -> Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = file_path.parent() {
             tokio::fs::create_dir_all(parent).await?;
@@ -96,13 +232,14 @@ impl FileChatMessageHistory {
             updated_at: now,
             messages: Vec::new(),
             metadata: None,
+            last_sequence_number: 0,
         };
         
         let instance = Self {
             session_id: session_id.clone(),
             file_path: file_path.clone(),
             session_history: Arc::new(RwLock::new(session_history)),
-            next_sequence_number: Arc::new(RwLock::new(1)),
+            cipher,
         };
         
         // Try to load existing session history
@@ -118,9 +255,7 @@ impl FileChatMessageHistory {
             return Ok(());
         }
         
-        let mut file = File::open(&self.file_path).await?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).await?;
+        let contents = read_file_content_with_cipher(&self.file_path, self.cipher.as_ref()).await?;
         
         if contents.trim().is_empty() {
             return Ok(());
@@ -128,21 +263,17 @@ impl FileChatMessageHistory {
         
         // Try to parse as JSON format session history (entire file is a JSON object)
         match serde_json::from_str::<ChatSessionHistory>(&contents) {
-            Ok(session_history) => {
+            Ok(mut session_history) => {
+                // Files written before last_sequence_number was stored continue after the highest number
+                let max_sequence_number = session_history.messages.iter().map(|m| m.sequence_number).max().unwrap_or(0);
+                session_history.last_sequence_number = session_history.last_sequence_number.max(max_sequence_number);
+
                 // Update in-memory session history
                 {
                     let mut history = self.session_history.write().await;
                     *history = session_history;
                 }
                 
-                // Update next message sequence number
-                {
-                    let history = self.session_history.read().await;
-                    let next_seq = history.messages.len() as u64 + 1;
-                    let mut next_sequence = self.next_sequence_number.write().await;
-                    *next_sequence = next_seq;
-                }
-                
                 info!("[FileChatMessageHistory] Loaded session history with {} messages from JSONL format", {
                     let history = self.session_history.read().await;
                     history.messages.len()
@@ -220,12 +351,7 @@ impl FileChatMessageHistory {
                         let mut history = self.session_history.write().await;
                         history.messages = messages;
                         history.updated_at = Utc::now().to_rfc3339();
-                    }
-                    
-                    // Update next message sequence number
-                    {
-                        let mut next_sequence = self.next_sequence_number.write().await;
-                        *next_sequence = max_sequence_number + 1;
+                        history.last_sequence_number = max_sequence_number;
                     }
                     
                     info!("[FileChatMessageHistory] Loaded session history with {} messages from old JSONL format", {
@@ -246,174 +372,99 @@ impl FileChatMessageHistory {
     
     /// Save session history to file (entire session as a JSON object)
     pub async fn save_session_history(&self) -> Result<()> {
-        // Get current session history
-        let history = {
-            let history_guard = self.session_history.read().await;
-            history_guard.clone()
-        };
-        
-        // Create temporary file
-        let temp_path = self.file_path.with_extension("tmp");
-        {
-            let mut file = File::create(&temp_path).await?;
-            
-            // Write entire session history as a JSON object to file
-            let json_content = serde_json::to_string_pretty(&history)?;
-            file.write_all(json_content.as_bytes()).await?;
-            
-            file.flush().await?;
-        }
-        
-        // Atomically replace original file
-        tokio::fs::rename(&temp_path, &self.file_path).await?;
-        
-        Ok(())
+        // The write lock keeps concurrent saves from sharing the temporary file
+        let history = self.session_history.write().await;
+        self.write_session_history(&history).await
+    }
+
+    async fn write_session_history(&self, history: &ChatSessionHistory) -> Result<()> {
+        // Write entire session history as a JSON object, atomically replacing the file
+        let json_content = serde_json::to_string_pretty(history)?;
+        atomic_write_file_with_cipher(&self.file_path, &json_content, self.cipher.as_ref()).await
     }
     
+    /// Get the whole session, with its creation time and metadata
+    pub async fn get_session_history(&self) -> ChatSessionHistory {
+        self.session_history.read().await.clone()
+    }
+
     /// Add user message, aligned with LangChain's add_user_message
     pub async fn add_user_message(&self, content: String) -> Result<()> {
         // Check if message content is empty
         if content.trim().is_empty() {
             return Ok(());
         }
-        
-        let sequence_number = {
-            let mut seq = self.next_sequence_number.write().await;
-            let current = *seq;
-            *seq += 1;
-            current
-        };
-        
-        let message = ChatMessageRecord {
-            role: "user".to_string(),
-            content,
-            name: None,
-            additional_kwargs: None,
-            timestamp: Utc::now().to_rfc3339(),
-            sequence_number,
-        };
-        
-        self.add_message(message).await?;
-        Ok(())
+        self.add_messages(vec![new_record("user", content)]).await
     }
     
-    /// Add AI message to history
+    /// Add AI message to history, JSON answers are reduced to their content
     pub async fn add_ai_message(&self, content: &str) -> Result<()> {
-        // Preprocess content, if it's JSON string format, extract the content field
-        let processed_content = if content.starts_with('"') && content.ends_with('"') {
-            // Try to parse as JSON string
-            match serde_json::from_str::<serde_json::Value>(content) {
-                Ok(serde_json::Value::String(s)) => s,
-                _ => content.to_string(),
+        self.add_messages(vec![new_record("assistant", extract_ai_content(content))]).await
+    }
+}
+
+impl ChatMessageHistory for FileChatMessageHistory {
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    fn add_messages(&self, messages: Vec<ChatMessageRecord>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            if messages.is_empty() {
+                return Ok(());
             }
-        } else if content.starts_with('{') && content.ends_with('}') {
-            // Try to parse as JSON object
-            match serde_json::from_str::<serde_json::Value>(content) {
-                Ok(json_obj) => {
-                    // If it's a JSON object, try to extract the content field
-                    if let Some(content_value) = json_obj.get("content") {
-                        if let Some(content_str) = content_value.as_str() {
-                            content_str.to_string()
-                        } else {
-                            content.to_string()
-                        }
-                    } else {
-                        content.to_string()
-                    }
-                },
-                _ => content.to_string(),
+            let mut history = self.session_history.write().await;
+            for mut message in messages {
+                history.last_sequence_number += 1;
+                message.sequence_number = history.last_sequence_number;
+                history.messages.push(message);
             }
-        } else {
-            content.to_string()
-        };
-        
-        let sequence_number = {
-            let mut seq = self.next_sequence_number.write().await;
-            let current = *seq;
-            *seq += 1;
-            current
-        };
-        
-        let message = ChatMessageRecord {
-            role: "assistant".to_string(),
-            content: processed_content,
-            name: None,
-            additional_kwargs: None,
-            timestamp: Utc::now().to_rfc3339(),
-            sequence_number,
-        };
-        
-        self.add_message(message).await?;
-        Ok(())
+            history.updated_at = Utc::now().to_rfc3339();
+            self.write_session_history(&history).await
+        })
     }
-    
-    /// Add message to memory and save to file
-    async fn add_message(&self, message: ChatMessageRecord) -> Result<()> {
-        // Add to memory
-        {
+
+    fn get_messages(&self) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move { Ok(self.session_history.read().await.messages.clone()) })
+    }
+
+    fn keep_recent_messages(&self, count: usize) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
             let mut history = self.session_history.write().await;
-            history.messages.push(message.clone());
+            if history.messages.len() <= count {
+                return Ok(());
+            }
+            let skip = history.messages.len() - count;
+            history.messages.drain(..skip);
             history.updated_at = Utc::now().to_rfc3339();
-        }
-        
-        // Save to file
-        self.save_session_history().await?;
-        
-        Ok(())
-    }
-    
-    /// Get all messages, aligned with LangChain's get_messages
-    pub async fn get_messages(&self) -> Result<Vec<ChatMessageRecord>> {
-        let history = self.session_history.read().await;
-        Ok(history.messages.clone())
+            self.write_session_history(&history).await
+        })
     }
-    
-    /// Clear all messages
-    pub async fn clear(&self) -> Result<()> {
-        // Reset session history
-        {
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
             let mut history = self.session_history.write().await;
             history.messages.clear();
             history.updated_at = Utc::now().to_rfc3339();
-        }
-        
-        // Reset message sequence number
-        {
-            let mut next_sequence = self.next_sequence_number.write().await;
-            *next_sequence = 1;
-        }
-        
-        // Save to file
-        self.save_session_history().await?;
-        
-        Ok(())
+            history.last_sequence_number = 0;
+            self.write_session_history(&history).await
+        })
     }
 }
 
 /// MessageHistoryMemory implementation, implementing BaseMemory trait
-#[derive(Debug)]
+///
+/// Messages are kept in a `ChatMessageHistory` store, a JSON file under the data directory by default.
+#[derive(Debug, Clone)]
 pub struct MessageHistoryMemory {
     /// Session ID
     session_id: String,
-    /// Data directory
-    data_dir: PathBuf,
-    /// File message history
-    chat_history: FileChatMessageHistory,
+    /// Message store
+    chat_history: Arc<dyn ChatMessageHistory>,
     /// Default number of recent messages to get
     default_recent_count: usize,
 }
 
-impl Clone for MessageHistoryMemory {
-    fn clone(&self) -> Self {
-        Self {
-            session_id: self.session_id.clone(),
-            data_dir: self.data_dir.clone(),
-            chat_history: self.chat_history.clone(),
-            default_recent_count: self.default_recent_count,
-        }
-    }
-}
-
 impl MessageHistoryMemory {
     /// Create a new MessageHistoryMemory instance
     pub async fn new(session_id: String, data_dir: PathBuf) -> Result<Self> {
@@ -424,79 +475,68 @@ impl MessageHistoryMemory {
     
     /// Create a new MessageHistoryMemory instance with specified recent message count
     pub async fn new_with_recent_count(session_id: String, data_dir: PathBuf, recent_count: usize) -> Result<Self> {
+        Self::new_with_cipher(session_id, data_dir, recent_count, None).await
+    }
+
+    /// Create a new MessageHistoryMemory instance whose history file is encrypted with the cipher
+    pub async fn new_with_cipher(session_id: String, data_dir: PathBuf, recent_count: usize, cipher: Option<MemoryCipher>) -> Result<Self> {
         // Ensure data directory exists
         tokio::fs::create_dir_all(&data_dir).await?;
         
         // Create file message history, using JSONL format
         let file_path = data_dir.join(format!("{}_history.jsonl", session_id));
-        let chat_history = FileChatMessageHistory::new(session_id.clone(), file_path).await?;
+        let chat_history = FileChatMessageHistory::new_with_cipher(session_id, file_path, cipher).await?;
         
-        Ok(Self {
-            session_id,
-            data_dir,
+        Ok(Self::from_chat_history(Arc::new(chat_history), recent_count))
+    }
+
+    /// Create a MessageHistoryMemory over any message store, e.g. a SqliteChatMessageHistory
+    pub fn from_chat_history(chat_history: Arc<dyn ChatMessageHistory>, recent_count: usize) -> Self {
+        Self {
+            session_id: chat_history.session_id().to_string(),
             chat_history,
             default_recent_count: recent_count,
-        })
+        }
     }
     
     /// Get session ID
     pub fn get_session_id(&self) -> &str {
         &self.session_id
     }
+
+    /// Get the message store
+    pub fn chat_history(&self) -> Arc<dyn ChatMessageHistory> {
+        self.chat_history.clone()
+    }
     
     /// Get recent messages
     pub async fn get_recent_messages(&self, count: usize) -> Result<Vec<ChatMessageRecord>> {
-        let messages = self.chat_history.get_messages().await?;
-        
-        // Get recent messages
-        let messages_len = messages.len();
-        let recent_messages: Vec<ChatMessageRecord> = if messages_len > count {
-            messages.into_iter().skip(messages_len - count).collect()
-        } else {
-            messages
-        };
-        
-        Ok(recent_messages)
+        self.chat_history.get_recent_messages(count).await
     }
     
     /// Get recent messages using default count
     pub async fn get_default_recent_messages(&self) -> Result<Vec<ChatMessageRecord>> {
         self.get_recent_messages(self.default_recent_count).await
     }
+
+    /// Get the messages sent at or after start and before end
+    pub async fn get_messages_in_range(&self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Result<Vec<ChatMessageRecord>> {
+        self.chat_history.get_messages_in_range(start, end).await
+    }
+
+    /// Search messages containing the query words, most recent first
+    pub async fn search_messages(&self, query: &str, limit: usize) -> Result<Vec<ChatMessageRecord>> {
+        self.chat_history.search_messages(query.to_string(), limit).await
+    }
     
     /// Get total message count
     pub async fn get_message_count(&self) -> Result<usize> {
-        let messages = self.chat_history.get_messages().await?;
-        Ok(messages.len())
+        self.chat_history.message_count().await
     }
     
     /// Keep only the most recent N messages
     pub async fn keep_recent_messages(&self, count: usize) -> Result<()> {
-        let messages = self.chat_history.get_messages().await?;
-        
-        if messages.len() <= count {
-            return Ok(());
-        }
-        
-        // Get recent messages
-        let messages_len = messages.len();
-        let recent_messages: Vec<ChatMessageRecord> = if messages_len > count {
-            messages.into_iter().skip(messages_len - count).collect()
-        } else {
-            messages
-        };
-        
-        // Update session history
-        {
-            let mut history = self.chat_history.session_history.write().await;
-            history.messages = recent_messages;
-            history.updated_at = Utc::now().to_rfc3339();
-        }
-        
-        // Save to file
-        self.chat_history.save_session_history().await?;
-        
-        Ok(())
+        self.chat_history.keep_recent_messages(count).await
     }
     
     /// Add ChatMessage to history
@@ -506,13 +546,6 @@ impl MessageHistoryMemory {
             return Ok(());
         }
         
-        let sequence_number = {
-            let mut seq = self.chat_history.next_sequence_number.write().await;
-            let current = *seq;
-            *seq += 1;
-            current
-        };
-        
         let record = ChatMessageRecord {
             role: message.role.clone(),
             content: message.content.clone(),
@@ -529,11 +562,11 @@ impl MessageHistoryMemory {
                 None
             },
             timestamp: message.timestamp.clone(),
-            sequence_number,
+            // Assigned by the store
+            sequence_number: 0,
         };
         
-        self.chat_history.add_message(record).await?;
-        Ok(())
+        self.chat_history.add_message(record).await
     }
     
     /// Get the most recent N messages, return ChatMessage type
@@ -601,49 +634,22 @@ impl BaseMemory for MessageHistoryMemory {
     
     fn save_context<'a>(&'a self, inputs: &'a HashMap<String, Value>, outputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
         Box::pin(async move {
+            let mut records = Vec::new();
+
             // Save user message
-            if let Some(input_value) = inputs.get("input") {
-                if let Some(content) = input_value.as_str() {
-                    self.chat_history.add_user_message(content.to_string()).await?;
+            if let Some(content) = inputs.get("input").and_then(|v| v.as_str()) {
+                if !content.trim().is_empty() {
+                    records.push(new_record("user", content.to_string()));
                 }
             }
             
-            // Save AI response
-            if let Some(output_value) = outputs.get("output") {
-                if let Some(content) = output_value.as_str() {
-                    // Preprocess content, if it's JSON string format, extract the content field
-                    let processed_content = if content.starts_with('"') && content.ends_with('"') {
-                        // Try to parse as JSON string
-                        match serde_json::from_str::<serde_json::Value>(content) {
-                            Ok(serde_json::Value::String(s)) => s,
-                            _ => content.to_string(),
-                        }
-                    } else if content.starts_with('{') && content.ends_with('}') {
-                        // Try to parse as JSON object
-                        match serde_json::from_str::<serde_json::Value>(content) {
-                            Ok(json_obj) => {
-                                // If it's a JSON object, try to extract the content field
-                                if let Some(content_value) = json_obj.get("content") {
-                                    if let Some(content_str) = content_value.as_str() {
-                                        content_str.to_string()
-                                    } else {
-                                        content.to_string()
-                                    }
-                                } else {
-                                    content.to_string()
-                                }
-                            },
-                            _ => content.to_string(),
-                        }
-                    } else {
-                        content.to_string()
-                    };
-                    
-                    self.chat_history.add_ai_message(&processed_content).await?;
-                }
+            // Save AI response, JSON answers are reduced to their content
+            if let Some(content) = outputs.get("output").and_then(|v| v.as_str()) {
+                records.push(new_record("assistant", extract_ai_content(content)));
             }
             
-            Ok(())
+            // Both messages of the turn are written together
+            self.chat_history.add_messages(records).await
         })
     }
     
@@ -669,11 +675,54 @@ impl BaseMemory for MessageHistoryMemory {
     fn get_token_count(&self) -> Result<usize, Error> {
         // Simplified implementation: estimate token count based on character count
         // In actual applications, a more precise token calculator can be used
-        let count = self.session_id.len() + self.data_dir.to_string_lossy().len();
-        Ok(count)
+        Ok(self.session_id.len())
     }
     
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_history_sequence_range_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = MessageHistoryMemory::new_with_recent_count("s1".to_string(), dir.path().to_path_buf(), 10).await.unwrap();
+        let inputs = HashMap::from([("input".to_string(), Value::from("What is my wallet balance?"))]);
+        let outputs = HashMap::from([("output".to_string(), Value::from("{\"content\": \"12 DOT\"}"))]);
+        memory.save_context(&inputs, &outputs).await.unwrap();
+        memory.save_context(&inputs, &HashMap::new()).await.unwrap();
+        memory.keep_recent_messages(1).await.unwrap();
+
+        // Reloading continues after the highest sequence number
+        let memory = MessageHistoryMemory::new_with_recent_count("s1".to_string(), dir.path().to_path_buf(), 10).await.unwrap();
+        memory.save_context(&HashMap::new(), &outputs).await.unwrap();
+        let messages = memory.get_recent_messages(10).await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.sequence_number).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(messages[1].content, "12 DOT");
+
+        let found = memory.search_messages("WALLET balance", 5).await.unwrap();
+        assert_eq!(found.len(), 1);
+        let future = Utc::now() + chrono::Duration::hours(1);
+        assert_eq!(memory.get_messages_in_range(None, Some(future)).await.unwrap().len(), 2);
+        assert!(memory.get_messages_in_range(Some(future), None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_history_numbering_survives_pruning_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = MessageHistoryMemory::new_with_recent_count("s1".to_string(), dir.path().to_path_buf(), 10).await.unwrap();
+        let inputs = HashMap::from([("input".to_string(), Value::from("hello"))]);
+        let outputs = HashMap::from([("output".to_string(), Value::from("hi"))]);
+        memory.save_context(&inputs, &outputs).await.unwrap();
+        memory.keep_recent_messages(0).await.unwrap();
+
+        // The pruned numbers are not reused after reopening the file
+        let memory = MessageHistoryMemory::new_with_recent_count("s1".to_string(), dir.path().to_path_buf(), 10).await.unwrap();
+        memory.save_context(&inputs, &HashMap::new()).await.unwrap();
+        let messages = memory.get_recent_messages(10).await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.sequence_number).collect::<Vec<_>>(), vec![3]);
+    }
+}
@@ -1,13 +1,25 @@
 // Memory system module
 pub mod base;
+pub mod encryption;
+pub mod entity_memory;
 pub mod message_history;
+pub mod session_manager;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_history;
 pub mod summary;
 pub mod utils;
 pub mod composite_memory;
+pub mod vector_store_memory;
 
 // Export main types and traits
 pub use base::{BaseMemory, SimpleMemory, MemoryVariables};
-pub use message_history::{MessageHistoryMemory, ChatMessage, ChatMessageRecord};
-pub use summary::{SummaryMemory, SummaryData};
+pub use entity_memory::{Entity, EntityFact, EntityMemory, DEFAULT_ENTITY_EXTRACTION_PROMPT, DEFAULT_ENTITY_MEMORY_KEY, USER_PROFILE_ENTITY};
+pub use encryption::{reencrypt_data_dir, EncryptionReport, MemoryCipher};
+pub use message_history::{MessageHistoryMemory, ChatMessage, ChatMessageHistory, ChatMessageRecord, ChatSessionHistory, ChatSessionInfo, FileChatMessageHistory};
+pub use session_manager::{SessionBundle, SessionManager, SESSION_BUNDLE_VERSION};
+#[cfg(feature = "sqlite")]
+pub use sqlite_history::SqliteChatMessageHistory;
+pub use summary::{SummaryMemory, SummaryData, DEFAULT_SUMMARY_CHUNK_TOKENS, DEFAULT_SUMMARY_PROMPT};
 pub use utils::*;
-pub use composite_memory::{CompositeMemory, CompositeMemoryConfig};
\ No newline at end of file
+pub use composite_memory::{CompositeMemory, CompositeMemoryConfig};
+pub use vector_store_memory::{VectorStoreRetrieverMemory, DEFAULT_RETRIEVER_MEMORY_KEY};
\ No newline at end of file
@@ -0,0 +1,337 @@
+// Session management over the session files of a data directory
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use anyhow::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::memory::encryption::MemoryCipher;
+use crate::memory::message_history::{ChatSessionHistory, ChatSessionInfo, FileChatMessageHistory};
+use crate::memory::summary::SummaryData;
+use crate::memory::utils::{atomic_write_file_with_cipher, delete_file, file_exists, generate_session_id, read_file_content_with_cipher};
+
+const HISTORY_SUFFIX: &str = "_history.jsonl";
+const SUMMARY_SUFFIX: &str = "_summary.json";
+
+/// Current version of the session bundle format
+pub const SESSION_BUNDLE_VERSION: u32 = 1;
+
+/// Portable export of a session with its messages and summary, always stored in plaintext
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionBundle {
+    pub format_version: u32,
+    /// Export time (RFC 3339)
+    pub exported_at: String,
+    pub session: ChatSessionHistory,
+    pub summary: Option<SummaryData>,
+}
+
+/// Lists, searches, exports, imports, forks and deletes the sessions written by
+/// `MessageHistoryMemory` and `SummaryMemory` in a data directory
+///
+/// A session consists of `{session_id}_history.jsonl` and `{session_id}_summary.json`.
+/// Configure the cipher of the memories with `with_cipher` when the files are encrypted,
+/// imported and forked sessions are then encrypted too.
+#[derive(Debug, Clone)]
+pub struct SessionManager {
+    data_dir: PathBuf,
+    cipher: Option<MemoryCipher>,
+}
+
+impl SessionManager {
+    /// Manage the sessions of the data directory
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self { data_dir, cipher: None }
+    }
+
+    /// Read and write encrypted session files
+    pub fn with_cipher(mut self, cipher: MemoryCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Get the data directory
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Ids of the sessions with a history or summary file, sorted
+    pub async fn session_ids(&self) -> Result<Vec<String>> {
+        let mut ids = BTreeSet::new();
+        let mut entries = match tokio::fs::read_dir(&self.data_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = name.strip_suffix(HISTORY_SUFFIX).or_else(|| name.strip_suffix(SUMMARY_SUFFIX)) {
+                ids.insert(id.to_string());
+            }
+        }
+        Ok(ids.into_iter().collect())
+    }
+
+    /// Metadata of all sessions, most recently updated first
+    pub async fn list_sessions(&self) -> Result<Vec<ChatSessionInfo>> {
+        let mut sessions = Vec::new();
+        for session_id in self.session_ids().await? {
+            if let Some(info) = self.get_session(&session_id).await? {
+                sessions.push(info);
+            }
+        }
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        Ok(sessions)
+    }
+
+    /// Metadata of a session, None if it does not exist
+    pub async fn get_session(&self, session_id: &str) -> Result<Option<ChatSessionInfo>> {
+        if !self.exists(session_id).await {
+            return Ok(None);
+        }
+        let session = self.load_history(session_id).await?;
+        let summary = self.load_summary(session_id).await?;
+        let updated_at = match &summary {
+            Some(summary) if summary.last_updated > session.updated_at => summary.last_updated.clone(),
+            _ => session.updated_at.clone(),
+        };
+        Ok(Some(ChatSessionInfo {
+            session_id: session_id.to_string(),
+            created_at: session.created_at,
+            updated_at,
+            message_count: session.messages.len(),
+            summary: summary.and_then(|summary| summary.summary),
+            metadata: session.metadata,
+        }))
+    }
+
+    /// Sessions whose id, summary or messages contain every word of the query (case-insensitive),
+    /// most recently updated first
+    pub async fn search_sessions(&self, query: &str) -> Result<Vec<ChatSessionInfo>> {
+        let terms: Vec<String> = query.split_whitespace().map(|term| term.to_lowercase()).collect();
+        let mut found = Vec::new();
+        for info in self.list_sessions().await? {
+            let session = self.load_history(&info.session_id).await?;
+            let mut text = format!("{}\n{}", info.session_id, info.summary.clone().unwrap_or_default());
+            for message in &session.messages {
+                text.push('\n');
+                text.push_str(&message.content);
+            }
+            let text = text.to_lowercase();
+            if terms.iter().all(|term| text.contains(term.as_str())) {
+                found.push(info);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Export a session with its summary
+    pub async fn export_session(&self, session_id: &str) -> Result<SessionBundle> {
+        if !self.exists(session_id).await {
+            return Err(Error::msg(format!("Session {} does not exist", session_id)));
+        }
+        Ok(SessionBundle {
+            format_version: SESSION_BUNDLE_VERSION,
+            exported_at: Utc::now().to_rfc3339(),
+            session: self.load_history(session_id).await?,
+            summary: self.load_summary(session_id).await?,
+        })
+    }
+
+    /// Export a session to a JSON file
+    pub async fn export_session_to_file(&self, session_id: &str, path: &Path) -> Result<()> {
+        let bundle = self.export_session(session_id).await?;
+        crate::memory::utils::atomic_write_file(path, &serde_json::to_string_pretty(&bundle)?).await
+    }
+
+    /// Import a bundle under its own session id or a new one, return the session id
+    /// Existing sessions are never overwritten.
+    pub async fn import_session(&self, bundle: SessionBundle, session_id: Option<String>) -> Result<String> {
+        if bundle.format_version > SESSION_BUNDLE_VERSION {
+            return Err(Error::msg(format!("Unsupported session bundle version {}", bundle.format_version)));
+        }
+        let session_id = session_id.unwrap_or_else(|| bundle.session.session_id.clone());
+        self.write_session(&session_id, bundle.session, bundle.summary).await?;
+        Ok(session_id)
+    }
+
+    /// Import a session from a JSON file written by `export_session_to_file`
+    pub async fn import_session_from_file(&self, path: &Path, session_id: Option<String>) -> Result<String> {
+        let bundle: SessionBundle = serde_json::from_str(&tokio::fs::read_to_string(path).await?)?;
+        self.import_session(bundle, session_id).await
+    }
+
+    /// Branch a conversation: copy the messages up to and including the sequence number into a
+    /// new session and return its id (generated when not given)
+    ///
+    /// The summary is copied when it only covers the copied messages.
+    pub async fn fork_session(&self, session_id: &str, sequence_number: u64, new_session_id: Option<String>) -> Result<String> {
+        let mut bundle = self.export_session(session_id).await?;
+        let new_session_id = new_session_id.unwrap_or_else(generate_session_id);
+        bundle.session.messages.retain(|message| message.sequence_number <= sequence_number);
+        // The branch numbers its new messages after the fork point
+        bundle.session.last_sequence_number = bundle.session.last_sequence_number.min(sequence_number);
+        bundle.summary = bundle.summary.filter(|summary| summary.sequence_number <= sequence_number);
+
+        let now = Utc::now().to_rfc3339();
+        let mut metadata = bundle.session.metadata.take().unwrap_or_default();
+        metadata.insert("forked_from".to_string(), serde_json::json!({
+            "session_id": session_id,
+            "sequence_number": sequence_number,
+        }));
+        bundle.session.metadata = Some(metadata);
+        bundle.session.created_at = now.clone();
+        bundle.session.updated_at = now;
+        self.write_session(&new_session_id, bundle.session, bundle.summary).await?;
+        Ok(new_session_id)
+    }
+
+    /// Delete the files of a session, return whether it existed
+    pub async fn delete_session(&self, session_id: &str) -> Result<bool> {
+        validate_session_id(session_id)?;
+        let existed = self.exists(session_id).await;
+        for path in [self.history_path(session_id), self.summary_path(session_id)] {
+            delete_file(&path.with_extension("tmp")).await?;
+            delete_file(&path).await?;
+        }
+        Ok(existed)
+    }
+
+    /// Delete all sessions, return how many were deleted
+    pub async fn delete_all_sessions(&self) -> Result<usize> {
+        let session_ids = self.session_ids().await?;
+        for session_id in &session_ids {
+            self.delete_session(session_id).await?;
+        }
+        Ok(session_ids.len())
+    }
+
+    fn history_path(&self, session_id: &str) -> PathBuf {
+        self.data_dir.join(format!("{}{}", session_id, HISTORY_SUFFIX))
+    }
+
+    fn summary_path(&self, session_id: &str) -> PathBuf {
+        self.data_dir.join(format!("{}{}", session_id, SUMMARY_SUFFIX))
+    }
+
+    async fn exists(&self, session_id: &str) -> bool {
+        validate_session_id(session_id).is_ok()
+            && (file_exists(&self.history_path(session_id)).await || file_exists(&self.summary_path(session_id)).await)
+    }
+
+    // Read through FileChatMessageHistory, which also upgrades the legacy line format
+    async fn load_history(&self, session_id: &str) -> Result<ChatSessionHistory> {
+        let history = FileChatMessageHistory::new_with_cipher(session_id.to_string(), self.history_path(session_id), self.cipher.clone()).await?;
+        let mut session = history.get_session_history().await;
+        if !file_exists(&self.history_path(session_id)).await {
+            // Summary only, the creation time of the empty history is unknown
+            session.created_at = String::new();
+            session.updated_at = String::new();
+        }
+        Ok(session)
+    }
+
+    async fn load_summary(&self, session_id: &str) -> Result<Option<SummaryData>> {
+        let path = self.summary_path(session_id);
+        if !file_exists(&path).await {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&read_file_content_with_cipher(&path, self.cipher.as_ref()).await?)?))
+    }
+
+    async fn write_session(&self, session_id: &str, mut session: ChatSessionHistory, summary: Option<SummaryData>) -> Result<()> {
+        validate_session_id(session_id)?;
+        if self.exists(session_id).await {
+            return Err(Error::msg(format!("Session {} already exists", session_id)));
+        }
+        session.session_id = session_id.to_string();
+        session.messages.sort_by_key(|message| message.sequence_number);
+        let cipher = self.cipher.as_ref();
+        atomic_write_file_with_cipher(&self.history_path(session_id), &serde_json::to_string_pretty(&session)?, cipher).await?;
+        if let Some(mut summary) = summary {
+            summary.session_id = session_id.to_string();
+            atomic_write_file_with_cipher(&self.summary_path(session_id), &serde_json::to_string(&summary)?, cipher).await?;
+        }
+        Ok(())
+    }
+}
+
+// Session ids become file names
+fn validate_session_id(session_id: &str) -> Result<()> {
+    let valid = !session_id.is_empty()
+        && session_id != "."
+        && session_id != ".."
+        && !session_id.contains(['/', '\\', '\0']);
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("Invalid session id {:?}", session_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use serde_json::json;
+    use crate::memory::base::BaseMemory;
+    use crate::memory::message_history::MessageHistoryMemory;
+
+    #[tokio::test]
+    async fn test_list_export_import_fork_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let memory = MessageHistoryMemory::new_with_recent_count("trip".to_string(), data_dir.clone(), 10).await.unwrap();
+        for (input, output) in [("Book a flight to Lisbon", "Booked"), ("And a hotel", "Done")] {
+            let inputs = HashMap::from([("input".to_string(), json!(input))]);
+            let outputs = HashMap::from([("output".to_string(), json!(output))]);
+            memory.save_context(&inputs, &outputs).await.unwrap();
+        }
+        let summary = SummaryData {
+            session_id: "trip".to_string(),
+            sequence_number: 2,
+            summary: Some("The user booked a flight".to_string()),
+            token_count: 6,
+            last_updated: Utc::now().to_rfc3339(),
+        };
+        tokio::fs::write(data_dir.join("trip_summary.json"), serde_json::to_string(&summary).unwrap()).await.unwrap();
+
+        let manager = SessionManager::new(data_dir.clone());
+        let sessions = manager.list_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].message_count, 4);
+        assert_eq!(sessions[0].summary.as_deref(), Some("The user booked a flight"));
+        assert_eq!(manager.search_sessions("LISBON flight").await.unwrap().len(), 1);
+        assert!(manager.search_sessions("paris").await.unwrap().is_empty());
+
+        // Round trip through a bundle file into another data directory
+        let bundle_path = dir.path().join("export").join("trip.json");
+        manager.export_session_to_file("trip", &bundle_path).await.unwrap();
+        let other = SessionManager::new(dir.path().join("other")).with_cipher(MemoryCipher::from_key([9u8; 32]));
+        let imported = other.import_session_from_file(&bundle_path, None).await.unwrap();
+        assert_eq!(imported, "trip");
+        assert_eq!(other.get_session("trip").await.unwrap().unwrap().message_count, 4);
+        assert!(other.import_session_from_file(&bundle_path, None).await.is_err());
+
+        // Forking at the end of the summary keeps it
+        let fork = manager.fork_session("trip", 2, Some("trip-branch".to_string())).await.unwrap();
+        let forked = manager.get_session(&fork).await.unwrap().unwrap();
+        assert_eq!(forked.message_count, 2);
+        assert_eq!(forked.summary.as_deref(), Some("The user booked a flight"));
+        assert_eq!(forked.metadata.unwrap()["forked_from"]["session_id"], json!("trip"));
+        // Forking before the end of the summary drops it
+        let fork = manager.fork_session("trip", 1, None).await.unwrap();
+        assert!(manager.get_session(&fork).await.unwrap().unwrap().summary.is_none());
+
+        // The forked history continues its own numbering
+        let branch = MessageHistoryMemory::new_with_recent_count("trip-branch".to_string(), data_dir.clone(), 10).await.unwrap();
+        branch.save_context(&HashMap::from([("input".to_string(), json!("Actually, Porto"))]), &HashMap::new()).await.unwrap();
+        assert_eq!(branch.get_recent_messages(1).await.unwrap()[0].sequence_number, 3);
+
+        assert!(manager.delete_session("trip").await.unwrap());
+        assert!(!manager.delete_session("trip").await.unwrap());
+        assert!(manager.delete_session("../trip").await.is_err());
+        assert_eq!(manager.delete_all_sessions().await.unwrap(), 2);
+        assert!(manager.list_sessions().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+// Chat message history stored in a SQLite database
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row, Transaction, TransactionBehavior};
+use serde_json::Value;
+
+use crate::memory::message_history::{ChatMessageHistory, ChatMessageRecord, ChatSessionInfo};
+
+const MESSAGE_COLUMNS: &str = "role, content, name, additional_kwargs, timestamp, sequence_number";
+
+/// Message history of one session in a SQLite database (requires the `sqlite` feature)
+///
+/// All sessions share one database with a sessions table and a messages table ordered by
+/// sequence number. Appends run in a transaction, so several handles and processes can write
+/// to the same file. Message contents are indexed with FTS5 for `search_messages`.
+#[derive(Clone)]
+pub struct SqliteChatMessageHistory {
+    connection: Arc<Mutex<Connection>>,
+    session_id: String,
+}
+
+impl SqliteChatMessageHistory {
+    /// Open or create the database file and the session
+    pub fn open(path: impl AsRef<Path>, session_id: impl Into<String>) -> Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        // Readers do not block the writer, writers wait for each other
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        Self::from_connection(connection, session_id.into())
+    }
+
+    /// Database living in memory, for tests
+    pub fn in_memory(session_id: impl Into<String>) -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?, session_id.into())
+    }
+
+    fn from_connection(connection: Connection, session_id: String) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS chat_sessions (
+                session_id TEXT PRIMARY KEY,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                last_sequence_number INTEGER NOT NULL DEFAULT 0,
+                metadata TEXT
+            );
+            CREATE TABLE IF NOT EXISTS chat_messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                sequence_number INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                name TEXT,
+                additional_kwargs TEXT,
+                timestamp TEXT NOT NULL,
+                timestamp_ms INTEGER,
+                UNIQUE (session_id, sequence_number)
+            );
+            CREATE INDEX IF NOT EXISTS chat_messages_time ON chat_messages (session_id, timestamp_ms);
+            CREATE VIRTUAL TABLE IF NOT EXISTS chat_messages_fts USING fts5(
+                content, content='chat_messages', content_rowid='id'
+            );
+            CREATE TRIGGER IF NOT EXISTS chat_messages_fts_insert AFTER INSERT ON chat_messages BEGIN
+                INSERT INTO chat_messages_fts (rowid, content) VALUES (new.id, new.content);
+            END;
+            CREATE TRIGGER IF NOT EXISTS chat_messages_fts_delete AFTER DELETE ON chat_messages BEGIN
+                INSERT INTO chat_messages_fts (chat_messages_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            END;",
+        )?;
+        Self {
+            connection: Arc::new(Mutex::new(connection)),
+            session_id,
+        }.registered()
+    }
+
+    /// History of another session in the same database
+    pub fn for_session(&self, session_id: impl Into<String>) -> Result<Self> {
+        Self {
+            connection: self.connection.clone(),
+            session_id: session_id.into(),
+        }.registered()
+    }
+
+    /// All sessions of the database, most recently updated first
+    pub fn list_sessions(&self) -> Result<Vec<ChatSessionInfo>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT s.session_id, s.created_at, s.updated_at, s.metadata,
+                (SELECT COUNT(*) FROM chat_messages m WHERE m.session_id = s.session_id)
+             FROM chat_sessions s ORDER BY s.updated_at DESC",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?, row.get::<_, i64>(4)?))
+        })?;
+        let mut sessions = Vec::new();
+        for row in rows {
+            let (session_id, created_at, updated_at, metadata, message_count) = row?;
+            sessions.push(ChatSessionInfo {
+                session_id,
+                created_at,
+                updated_at,
+                message_count: message_count as usize,
+                summary: None,
+                metadata: metadata.map(|metadata| serde_json::from_str(&metadata)).transpose()?,
+            });
+        }
+        Ok(sessions)
+    }
+
+    /// Delete a session and its messages from the database
+    pub fn delete_session(&self, session_id: &str) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        transaction.execute("DELETE FROM chat_messages WHERE session_id = ?1", [session_id])?;
+        transaction.execute("DELETE FROM chat_sessions WHERE session_id = ?1", [session_id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Replace the metadata of this session
+    pub fn set_metadata(&self, metadata: HashMap<String, Value>) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.ensure_session(&transaction)?;
+        transaction.execute(
+            "UPDATE chat_sessions SET metadata = ?1, updated_at = ?2 WHERE session_id = ?3",
+            params![serde_json::to_string(&metadata)?, Utc::now().to_rfc3339(), self.session_id],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Create the session row so that the session is listed before its first message
+    fn registered(self) -> Result<Self> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.ensure_session(&transaction)?;
+        transaction.commit()?;
+        drop(connection);
+        Ok(self)
+    }
+
+    // The session row may have been deleted through another handle
+    fn ensure_session(&self, transaction: &Transaction<'_>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        transaction.execute(
+            "INSERT OR IGNORE INTO chat_sessions (session_id, created_at, updated_at) VALUES (?1, ?2, ?2)",
+            params![self.session_id, now],
+        )?;
+        Ok(())
+    }
+
+    fn query_messages(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<ChatMessageRecord>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(sql)?;
+        let rows = statement.query_map(params, record_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+impl fmt::Debug for SqliteChatMessageHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteChatMessageHistory")
+            .field("session_id", &self.session_id)
+            .finish_non_exhaustive()
+    }
+}
+
+fn record_from_row(row: &Row<'_>) -> rusqlite::Result<ChatMessageRecord> {
+    let additional_kwargs = row.get::<_, Option<String>>(3)?
+        .map(|kwargs| serde_json::from_str(&kwargs))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+    Ok(ChatMessageRecord {
+        role: row.get(0)?,
+        content: row.get(1)?,
+        name: row.get(2)?,
+        additional_kwargs,
+        timestamp: row.get(4)?,
+        sequence_number: row.get::<_, i64>(5)? as u64,
+    })
+}
+
+fn timestamp_millis(timestamp: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|time| time.timestamp_millis())
+}
+
+// Every word becomes a quoted prefix query, so user input cannot break the FTS5 syntax
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn sql_limit(count: usize) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+// Queries are short local reads and writes, they run on the calling task
+impl ChatMessageHistory for SqliteChatMessageHistory {
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    fn add_messages(&self, messages: Vec<ChatMessageRecord>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            if messages.is_empty() {
+                return Ok(());
+            }
+            let mut connection = self.connection();
+            let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            self.ensure_session(&transaction)?;
+            let mut sequence_number: i64 = transaction.query_row(
+                "SELECT last_sequence_number FROM chat_sessions WHERE session_id = ?1",
+                [&self.session_id],
+                |row| row.get(0),
+            )?;
+            for message in messages {
+                sequence_number += 1;
+                let additional_kwargs = message.additional_kwargs.as_ref().map(serde_json::to_string).transpose()?;
+                transaction.execute(
+                    "INSERT INTO chat_messages (session_id, sequence_number, role, content, name, additional_kwargs, timestamp, timestamp_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        self.session_id,
+                        sequence_number,
+                        message.role,
+                        message.content,
+                        message.name,
+                        additional_kwargs,
+                        message.timestamp,
+                        timestamp_millis(&message.timestamp),
+                    ],
+                )?;
+            }
+            transaction.execute(
+                "UPDATE chat_sessions SET last_sequence_number = ?1, updated_at = ?2 WHERE session_id = ?3",
+                params![sequence_number, Utc::now().to_rfc3339(), self.session_id],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    fn get_messages(&self) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.query_messages(
+                &format!("SELECT {} FROM chat_messages WHERE session_id = ?1 ORDER BY sequence_number", MESSAGE_COLUMNS),
+                [&self.session_id],
+            )
+        })
+    }
+
+    fn keep_recent_messages(&self, count: usize) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            let connection = self.connection();
+            connection.execute(
+                "DELETE FROM chat_messages WHERE session_id = ?1 AND id NOT IN (
+                    SELECT id FROM chat_messages WHERE session_id = ?1 ORDER BY sequence_number DESC LIMIT ?2
+                )",
+                params![self.session_id, sql_limit(count)],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut connection = self.connection();
+            let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            transaction.execute("DELETE FROM chat_messages WHERE session_id = ?1", [&self.session_id])?;
+            transaction.execute(
+                "UPDATE chat_sessions SET last_sequence_number = 0, updated_at = ?1 WHERE session_id = ?2",
+                params![Utc::now().to_rfc3339(), self.session_id],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    fn get_recent_messages(&self, count: usize) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.query_messages(
+                &format!(
+                    "SELECT {columns} FROM (
+                        SELECT {columns} FROM chat_messages WHERE session_id = ?1 ORDER BY sequence_number DESC LIMIT ?2
+                    ) ORDER BY sequence_number",
+                    columns = MESSAGE_COLUMNS
+                ),
+                params![self.session_id, sql_limit(count)],
+            )
+        })
+    }
+
+    fn get_messages_in_range(&self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.query_messages(
+                &format!(
+                    "SELECT {} FROM chat_messages
+                     WHERE session_id = ?1 AND timestamp_ms IS NOT NULL
+                        AND (?2 IS NULL OR timestamp_ms >= ?2) AND (?3 IS NULL OR timestamp_ms < ?3)
+                     ORDER BY sequence_number",
+                    MESSAGE_COLUMNS
+                ),
+                params![self.session_id, start.map(|t| t.timestamp_millis()), end.map(|t| t.timestamp_millis())],
+            )
+        })
+    }
+
+    fn search_messages(&self, query: String, limit: usize) -> Pin<Box<dyn Future<Output = Result<Vec<ChatMessageRecord>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let query = fts_query(&query);
+            if query.is_empty() {
+                return Ok(Vec::new());
+            }
+            self.query_messages(
+                "SELECT m.role, m.content, m.name, m.additional_kwargs, m.timestamp, m.sequence_number
+                 FROM chat_messages_fts f JOIN chat_messages m ON m.id = f.rowid
+                 WHERE chat_messages_fts MATCH ?1 AND m.session_id = ?2
+                 ORDER BY m.sequence_number DESC LIMIT ?3",
+                params![query, self.session_id, sql_limit(limit)],
+            )
+        })
+    }
+
+    fn message_count(&self) -> Pin<Box<dyn Future<Output = Result<usize, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let count: i64 = self.connection()
+                .query_row("SELECT COUNT(*) FROM chat_messages WHERE session_id = ?1", [&self.session_id], |row| row.get(0))?;
+            Ok(count as usize)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(role: &str, content: &str, timestamp: &str) -> ChatMessageRecord {
+        ChatMessageRecord {
+            role: role.to_string(),
+            content: content.to_string(),
+            name: None,
+            additional_kwargs: None,
+            timestamp: timestamp.to_string(),
+            sequence_number: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_history_queries_and_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let history = SqliteChatMessageHistory::open(&path, "s1").unwrap();
+        history.add_messages(vec![
+            record("user", "Send 5 ETH to my cold wallet", "2025-03-01T10:00:00Z"),
+            record("assistant", "Which chain should I use?", "2025-03-01T10:00:05Z"),
+            record("user", "Use the Polkadot chain", "2025-03-02T09:00:00+02:00"),
+        ]).await.unwrap();
+        let other = history.for_session("s2").unwrap();
+        other.add_message(record("user", "wallet balance please", "2025-03-03T00:00:00Z")).await.unwrap();
+
+        // Reopening the file keeps the messages and their order
+        let history = SqliteChatMessageHistory::open(&path, "s1").unwrap();
+        let messages = history.get_messages().await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.sequence_number).collect::<Vec<_>>(), vec![1, 2, 3]);
+        let recent = history.get_recent_messages(2).await.unwrap();
+        assert_eq!(recent[0].content, "Which chain should I use?");
+
+        let start = DateTime::parse_from_rfc3339("2025-03-01T10:00:01Z").unwrap().with_timezone(&Utc);
+        let in_range = history.get_messages_in_range(Some(start), None).await.unwrap();
+        assert_eq!(in_range.len(), 2);
+
+        let found = history.search_messages("WALLET".to_string(), 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content, "Send 5 ETH to my cold wallet");
+        assert!(history.search_messages("\"unbalanced".to_string(), 10).await.unwrap().is_empty());
+
+        // Trimming keeps the numbering going
+        history.keep_recent_messages(1).await.unwrap();
+        history.add_message(record("assistant", "Done", "2025-03-02T08:00:00Z")).await.unwrap();
+        let messages = history.get_messages().await.unwrap();
+        assert_eq!(messages.iter().map(|m| m.sequence_number).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(history.search_messages("wallet".to_string(), 10).await.unwrap().len(), 0);
+
+        let sessions = history.list_sessions().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions.iter().find(|s| s.session_id == "s2").unwrap().message_count, 1);
+        history.delete_session("s2").unwrap();
+        assert_eq!(history.list_sessions().unwrap().len(), 1);
+    }
+}
@@ -7,17 +7,27 @@ use serde_json::{json, Value};
 use std::pin::Pin;
 use std::future::Future;
 use log::info;
-use uuid;
 use chrono;
-use crate::ChatMessage;
+use std::fmt;
 use std::sync::Arc;
 
-// Import FileChatMessageHistory
-use crate::memory::message_history::{FileChatMessageHistory, ChatMessageRecord, MessageHistoryMemory};
-// Import utility functions
-use crate::memory::utils::estimate_text_tokens;
+// Import message stores
+use crate::memory::message_history::{ChatMessageHistory, FileChatMessageHistory, ChatMessageRecord, MessageHistoryMemory};
 // Import common models
-use crate::{ChatModel, OpenAIChatModel, ModelChatMessage, ChatMessageContent};
+use crate::{ChatModel, ModelChatMessage, ChatMessageContent};
+use crate::models::{HeuristicTokenizer, Tokenizer};
+use crate::prompt::PromptTemplate;
+use crate::memory::encryption::MemoryCipher;
+use crate::memory::utils::{atomic_write_file_with_cipher, read_file_content_with_cipher};
+
+/// Default summary prompt, {summary} is replaced by the previous summary and {chat_history} by the new conversation text
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Progressively summarize the lines of conversation provided, adding onto the current summary and returning a new concise summary. Focus on the main topics discussed, key decisions made, and any important outcomes.\n\nCurrent summary:\n{summary}\n\nNew lines of conversation:\n{chat_history}\n\nNew summary:";
+
+/// Default token budget of the conversation text sent in one summary request
+pub const DEFAULT_SUMMARY_CHUNK_TOKENS: usize = 4000;
+
+// Levels of chunk summaries before the remaining text is summarized at once
+const MAX_SUMMARY_LEVELS: usize = 3;
 
 /// Summary data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,7 +38,7 @@ pub struct SummaryData {
     pub sequence_number: u64,
     /// Summary content
     pub summary: Option<String>,
-    /// Token count (approximately equal to message count * 4)
+    /// Token count of the summary, counted with the tokenizer of the summary memory
     pub token_count: usize,
     /// Last update time
     pub last_updated: String,
@@ -51,20 +61,31 @@ impl Default for SummaryData {
 /// This struct is responsible for generating and managing conversation summaries.
 /// It can automatically generate summaries when the conversation reaches a certain length,
 /// and load previous summaries when needed.
-#[derive(Debug)]
+/// Summaries are built incrementally: the previous summary is updated with the messages
+/// added since its sequence number. When these messages exceed the chunk token budget,
+/// every chunk is summarized on its own first and the chunk summaries are folded into
+/// the previous summary (hierarchical summary).
 pub struct SummaryMemory {
     /// Session ID
     session_id: String,
     /// Data directory
     data_dir: PathBuf,
-    /// Summary threshold (in token count, 1 token ≈ 4 English characters, 1 token ≈ 1 Chinese character)
+    /// Summary threshold (in token count, see tokenizer)
     summary_threshold: usize,
+    /// Tokenizer counting messages against the threshold, a character estimate by default
+    tokenizer: Arc<dyn Tokenizer>,
     /// Summary prompt template
-    summary_prompt_template: String,
+    summary_prompt_template: PromptTemplate,
+    /// Model generating summaries, summaries fail with a configuration error when not set
+    chat_model: Option<Arc<dyn ChatModel>>,
+    /// Token budget of the conversation text of one summary request
+    summary_chunk_tokens: usize,
     /// Number of recent messages to keep (in message count)
     recent_messages_count: usize,
-    /// Shared message history memory (optional)
-    message_history: Option<Arc<MessageHistoryMemory>>,
+    /// Message store, shared with the message history memory when created with one
+    chat_history: Arc<dyn ChatMessageHistory>,
+    /// Cipher encrypting the summary file at rest (optional)
+    cipher: Option<MemoryCipher>,
 }
 
 impl Clone for SummaryMemory {
@@ -73,27 +94,43 @@ impl Clone for SummaryMemory {
             session_id: self.session_id.clone(),
             data_dir: self.data_dir.clone(),
             summary_threshold: self.summary_threshold,
+            tokenizer: self.tokenizer.clone(),
             summary_prompt_template: self.summary_prompt_template.clone(),
+            chat_model: self.chat_model.clone(),
+            summary_chunk_tokens: self.summary_chunk_tokens,
             recent_messages_count: self.recent_messages_count,
-            message_history: self.message_history.clone(),
+            chat_history: self.chat_history.clone(),
+            cipher: self.cipher.clone(),
         }
     }
 }
 
+impl fmt::Debug for SummaryMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SummaryMemory")
+            .field("session_id", &self.session_id)
+            .field("data_dir", &self.data_dir)
+            .field("summary_threshold", &self.summary_threshold)
+            .field("tokenizer", &self.tokenizer)
+            .field("summary_prompt_template", &self.summary_prompt_template)
+            .field("chat_model", &self.chat_model.as_ref().map(|model| model.model_name().unwrap_or("unknown").to_string()))
+            .field("summary_chunk_tokens", &self.summary_chunk_tokens)
+            .field("recent_messages_count", &self.recent_messages_count)
+            .field("chat_history", &self.chat_history)
+            .field("cipher", &self.cipher)
+            .finish()
+    }
+}
+
 impl SummaryMemory {
-    /// Create a new summary memory instance
+    /// Create a new summary memory instance, messages are read from the session file in data_dir
     pub async fn new(session_id: String, data_dir: PathBuf, summary_threshold: usize) -> Result<Self> {
         // Ensure data directory exists
         tokio::fs::create_dir_all(&data_dir).await?;
         
-        Ok(Self {
-            session_id,
-            data_dir,
-            summary_threshold,
-            summary_prompt_template: "Please provide a concise summary of the following conversation. Focus on the main topics discussed, key decisions made, and any important outcomes.\n\nConversation:\n{chat_history}\n\nSummary:".to_string(),
-            recent_messages_count: crate::memory::utils::get_recent_messages_count_from_env(),
-            message_history: None,
-        })
+        let file_path = data_dir.join(format!("{}_history.jsonl", session_id));
+        let chat_history = FileChatMessageHistory::new(session_id, file_path).await?;
+        Self::new_with_chat_history(data_dir, summary_threshold, Arc::new(chat_history)).await
     }
     
     /// Create a new summary memory instance with shared message history
@@ -102,26 +139,67 @@ impl SummaryMemory {
         data_dir: PathBuf, 
         summary_threshold: usize,
         message_history: Arc<MessageHistoryMemory>
+    ) -> Result<Self> {
+        let mut summary = Self::new_with_chat_history(data_dir, summary_threshold, message_history.chat_history()).await?;
+        summary.session_id = session_id;
+        Ok(summary)
+    }
+
+    /// Create a summary memory over any message store, the summary file is kept in data_dir
+    pub async fn new_with_chat_history(
+        data_dir: PathBuf,
+        summary_threshold: usize,
+        chat_history: Arc<dyn ChatMessageHistory>
     ) -> Result<Self> {
         // Ensure data directory exists
         tokio::fs::create_dir_all(&data_dir).await?;
         
         Ok(Self {
-            session_id,
+            session_id: chat_history.session_id().to_string(),
             data_dir,
             summary_threshold,
-            summary_prompt_template: "Please provide a concise summary of the following conversation. Focus on the main topics discussed, key decisions made, and any important outcomes.\n\nConversation:\n{chat_history}\n\nSummary:".to_string(),
+            tokenizer: Arc::new(HeuristicTokenizer),
+            summary_prompt_template: PromptTemplate::new(DEFAULT_SUMMARY_PROMPT).expect("default summary prompt is valid"),
+            chat_model: None,
+            summary_chunk_tokens: DEFAULT_SUMMARY_CHUNK_TOKENS,
             recent_messages_count: crate::memory::utils::get_recent_messages_count_from_env(),
-            message_history: Some(message_history),
+            chat_history,
+            cipher: None,
         })
     }
     
-    /// Set summary prompt template
-    pub fn with_summary_prompt_template(mut self, template: String) -> Self {
+    /// Set summary prompt template, the new conversation text is passed as {chat_history} and the previous summary as {summary}
+    /// Templates without {summary} get the previous summary in front of the conversation text.
+    pub fn with_summary_prompt_template(mut self, template: PromptTemplate) -> Self {
         self.summary_prompt_template = template;
         self
     }
     
+    /// Generate summaries with this model, usually the chat model of the agent
+    pub fn with_chat_model(mut self, model: Arc<dyn ChatModel>) -> Self {
+        self.chat_model = Some(model);
+        self
+    }
+    
+    /// Set the token budget of the conversation text sent in one summary request
+    pub fn with_summary_chunk_tokens(mut self, tokens: usize) -> Self {
+        self.summary_chunk_tokens = tokens.max(1);
+        self
+    }
+    
+    /// Set the tokenizer used for the summary threshold, e.g. ModelRegistry::tokenizer_for the chat model
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+    
+    /// Encrypt the summary file with the cipher, plaintext summaries are still read
+    /// The message store is encrypted separately, e.g. with FileChatMessageHistory::new_with_cipher.
+    pub fn with_cipher(mut self, cipher: MemoryCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+    
     /// Set the number of recent messages to keep
     pub fn with_recent_messages_count(mut self, count: usize) -> Self {
         self.recent_messages_count = count;
@@ -138,16 +216,8 @@ impl SummaryMemory {
         // Load summary
         let summary_data = self.load_summary().await?;
         
-        // Load message history
-        let messages = if let Some(ref history) = self.message_history {
-            // Use shared message history
-            history.get_recent_messages(self.recent_messages_count).await?
-        } else {
-            // Create new FileChatMessageHistory instance
-            let file_path = self.data_dir.join(format!("{}_history.jsonl", self.session_id));
-            let chat_history = FileChatMessageHistory::new(self.session_id.clone(), file_path).await?;
-            chat_history.get_messages().await?
-        };
+        // Load recent messages
+        let messages = self.chat_history.get_recent_messages(self.recent_messages_count).await?;
         
         // Build context vector
         let mut context = Vec::new();
@@ -179,7 +249,7 @@ impl SummaryMemory {
             });
         }
         
-        let contents = tokio::fs::read_to_string(&file_path).await?;
+        let contents = read_file_content_with_cipher(&file_path, self.cipher.as_ref()).await?;
         let summary_data: SummaryData = serde_json::from_str(&contents)?;
         
         Ok(summary_data)
@@ -190,7 +260,7 @@ impl SummaryMemory {
         let file_path = self.get_summary_file_path();
         
         // Calculate token count for the summary
-        let token_count = estimate_text_tokens(summary);
+        let token_count = self.tokenizer.count_tokens(summary);
         
         let summary_data = SummaryData {
             session_id: self.session_id.clone(),
@@ -201,72 +271,99 @@ impl SummaryMemory {
         };
         
         let json = serde_json::to_string(&summary_data)?;
-        tokio::fs::write(&file_path, json).await?;
+        atomic_write_file_with_cipher(&file_path, &json, self.cipher.as_ref()).await?;
         
         Ok(())
     }
     
-    /// Generate summary
-    async fn generate_summary(&self, messages: &[ChatMessageRecord]) -> Result<(String, u64)> {
+    // Model of the summary memory, summaries are never generated with an implicit model
+    fn summary_model(&self) -> Result<Arc<dyn ChatModel>> {
+        self.chat_model.clone().ok_or_else(|| Error::msg(
+            "No chat model configured for summaries, set one with SummaryMemory::with_chat_model or CompositeMemory::with_chat_model"
+        ))
+    }
+    
+    /// Update the previous summary with new messages and return the new summary, nothing is saved
+    pub async fn generate_summary(&self, previous_summary: Option<&str>, messages: &[ChatMessageRecord]) -> Result<String> {
+        let model = self.summary_model()?;
         info!("Generating summary for {} messages", messages.len());
-
-        // Convert messages to text format
-        let mut chat_text = String::new();
-        for msg in messages {
-            let role = if msg.role == "user" { "User" } else { "Assistant" };
-            chat_text.push_str(&format!("{}: {}\n", role, msg.content));
+        let lines = messages.iter()
+            .map(|msg| {
+                let role = if msg.role == "user" { "User" } else { "Assistant" };
+                format!("{}: {}", role, msg.content)
+            })
+            .collect();
+        self.summarize_lines(model.as_ref(), previous_summary, lines, 0).await
+    }
+    
+    // Fold the lines into the previous summary, summarizing chunks first when they exceed the budget
+    fn summarize_lines<'a>(&'a self, model: &'a dyn ChatModel, previous_summary: Option<&'a str>, lines: Vec<String>, level: usize) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let chunks = self.split_into_chunks(lines);
+            if chunks.len() <= 1 || level >= MAX_SUMMARY_LEVELS {
+                let text = chunks.iter().map(|chunk| chunk.join("\n")).collect::<Vec<_>>().join("\n");
+                return self.invoke_summary(model, previous_summary, &text).await;
+            }
+            
+            info!("[SummaryMemory] Summarizing {} chunks at level {}", chunks.len(), level);
+            let mut chunk_summaries = Vec::new();
+            for chunk in chunks {
+                chunk_summaries.push(self.invoke_summary(model, None, &chunk.join("\n")).await?);
+            }
+            self.summarize_lines(model, previous_summary, chunk_summaries, level + 1).await
+        })
+    }
+    
+    // Group consecutive lines into chunks within the token budget, a longer line forms its own chunk
+    fn split_into_chunks(&self, lines: Vec<String>) -> Vec<Vec<String>> {
+        let mut chunks: Vec<Vec<String>> = Vec::new();
+        let mut chunk_tokens = 0;
+        for line in lines {
+            let tokens = self.tokenizer.count_tokens(&line);
+            match chunks.last_mut() {
+                Some(chunk) if chunk_tokens + tokens <= self.summary_chunk_tokens => {
+                    chunk.push(line);
+                    chunk_tokens += tokens;
+                },
+                _ => {
+                    chunks.push(vec![line]);
+                    chunk_tokens = tokens;
+                },
+            }
         }
+        chunks
+    }
+    
+    // One summary request to the model
+    async fn invoke_summary(&self, model: &dyn ChatModel, previous_summary: Option<&str>, chat_text: &str) -> Result<String> {
+        let mut prompt_inputs = HashMap::new();
+        let declares_summary = self.summary_prompt_template.input_variables().iter().any(|name| name == "summary");
+        let chat_text = match previous_summary {
+            Some(summary) if !declares_summary => format!("Previous conversation summary: {}\n\n{}", summary, chat_text),
+            _ => chat_text.to_string(),
+        };
+        prompt_inputs.insert("summary".to_string(), previous_summary.unwrap_or("").to_string());
+        prompt_inputs.insert("chat_history".to_string(), chat_text);
+        let summary_prompt = self.summary_prompt_template.format(&prompt_inputs)?;
         
-        // Use summary prompt template
-        let summary_prompt = self.summary_prompt_template.replace("{chat_history}", &chat_text);
-        
-        // Get API key and base URL from environment variables
-        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "OPENAI_API_KEY".to_string());
-        let base_url = std::env::var("OPENAI_API_URL").ok();
-        
-        // // Check if API key is valid, return error if invalid
-        // if api_key == "OPENAI_API_KEY" || api_key.is_empty() || api_key.starts_with("mock_api") {
-        //     return Err(anyhow::anyhow!("OpenAI API key is not configured or is invalid. Please set the OPENAI_API_KEY environment variable."));
-        // }
-        
-        // Create OpenAI model instance
-        let model = crate::OpenAIChatModel::new(api_key.clone(), base_url)
-            .with_model(std::env::var("OPENAI_API_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo".to_string()))
-            .with_temperature(0.3)
-            .with_max_tokens(1024);
-        
-        // Build message list
         let model_messages = vec![
-            crate::ModelChatMessage::System(crate::ChatMessageContent {
+            ModelChatMessage::System(ChatMessageContent {
                 content: "You are a helpful assistant that creates concise summaries of conversations.".to_string(),
                 name: None,
-                additional_kwargs: std::collections::HashMap::new(),
+                additional_kwargs: HashMap::new(),
             }),
-            crate::ModelChatMessage::Human(crate::ChatMessageContent {
+            ModelChatMessage::Human(ChatMessageContent {
                 content: summary_prompt,
                 name: None,
-                additional_kwargs: std::collections::HashMap::new(),
+                additional_kwargs: HashMap::new(),
             }),
         ];
         
-        // Call model to generate summary
         let response = model.invoke(model_messages).await?;
-        
-        // Extract response content
-        let summary = match response.message {
-            crate::ModelChatMessage::AIMessage(content) => content.content,
-            _ => return Err(anyhow::anyhow!("Expected AI message response")),
-        };
-
-        // Get the sequence number of the last message as the summary update sequence number
-        let last_sequence_number = messages.last()
-            .map(|msg| msg.sequence_number)
-            .unwrap_or(0);
-
-        // Save summary to file
-        self.save_summary(&summary, last_sequence_number).await?;
-        
-        Ok((summary, last_sequence_number))
+        match response.message {
+            ModelChatMessage::AIMessage(content) => Ok(content.content.trim().to_string()),
+            _ => Err(anyhow::anyhow!("Expected AI message response")),
+        }
     }
     
     /// Check if summary needs to be generated and generate if needed
@@ -275,13 +372,8 @@ impl SummaryMemory {
         let summary_data = self.load_summary().await?;
         let last_summary_sequence = summary_data.sequence_number;
         
-        // Load message history
-        let messages = if let Some(ref message_history) = self.message_history {
-            // Get all messages
-            message_history.get_recent_messages(usize::MAX).await?
-        } else {
-            return Ok(false);
-        };
+        // Load all messages
+        let messages = self.chat_history.get_messages().await?;
         
         // If no messages, no need to generate summary
         if messages.is_empty() {
@@ -305,14 +397,14 @@ impl SummaryMemory {
             chat_text.push_str(&format!("{}: {}\n", msg.role, msg.content));
         }
 
-        let total_tokens = estimate_text_tokens(&chat_text);
+        let total_tokens = self.tokenizer.count_tokens(&chat_text);
         
         // If token count exceeds threshold, generate summary
         if total_tokens > self.summary_threshold {
             info!("[SummaryMemory] Generating summary... ({} new messages, {} tokens)", messages_to_summarize.len(), total_tokens);
             
-            // Generate summary
-            let (summary, _) = self.generate_summary(&messages_to_summarize).await?;
+            // Update the previous summary with the new messages
+            let summary = self.generate_summary(summary_data.summary.as_deref(), &messages_to_summarize).await?;
             
             // Get the sequence number of the last message
             let last_sequence = messages_to_summarize.last().map(|m| m.sequence_number).unwrap_or(0);
@@ -321,9 +413,7 @@ impl SummaryMemory {
             self.save_summary(&summary, last_sequence).await?;
             
             // Keep only recent messages
-            if let Some(ref message_history) = self.message_history {
-                message_history.keep_recent_messages(self.recent_messages_count).await?;
-            }
+            self.chat_history.keep_recent_messages(self.recent_messages_count).await?;
             
             Ok(true)
         } else {
@@ -342,20 +432,19 @@ impl SummaryMemory {
         let summary_data = self.load_summary().await?;
         
         // Load message history
-        let file_path = self.data_dir.join(format!("{}_history.jsonl", self.session_id.clone()));
-        let chat_history = FileChatMessageHistory::new(self.session_id.clone(), file_path).await?;
-        let messages = chat_history.get_messages().await?;
+        let messages = self.chat_history.get_messages().await?;
         
         // Calculate total tokens in messages
         let mut chat_text = String::new();
         for msg in &messages {
             chat_text.push_str(&format!("{}: {}\n", msg.role, msg.content));
         }
-        let token_count = estimate_text_tokens(&chat_text);
+        let token_count = self.tokenizer.count_tokens(&chat_text);
         
         let stats = json!({
             "session_id": self.session_id,
             "summary_threshold": self.summary_threshold,
+            "tokenizer": self.tokenizer.name(),
             "recent_messages_count": self.recent_messages_count,
             "message_count": messages.len(),
             "token_count": token_count,
@@ -377,37 +466,14 @@ impl BaseMemory for SummaryMemory {
     }
     
     fn load_memory_variables<'a>(&'a self, _inputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, Error>> + Send + 'a>> {
-        let session_id = self.session_id.clone();
-        let data_dir = self.data_dir.clone();
-        let summary_threshold = self.summary_threshold;
         let recent_messages_count = self.recent_messages_count;
-        let use_shared_history = self.message_history.is_some();
         
         Box::pin(async move {
             // Load summary
-            let summary_memory = SummaryMemory {
-                session_id: session_id.clone(),
-                data_dir: data_dir.clone(),
-                summary_threshold,
-                summary_prompt_template: String::new(),
-                recent_messages_count,
-                message_history: None, // We'll handle this separately
-            };
-            
-            let summary_data = summary_memory.load_summary().await?;
+            let summary_data = self.load_summary().await?;
             
             // Load message history
-            let messages = if use_shared_history {
-                // This is a simplified approach - in a real implementation, we would need to pass the shared instance
-                // For now, we'll create a new instance but this should be improved
-                let file_path = data_dir.join(format!("{}_history.jsonl", session_id.clone()));
-                let chat_history = FileChatMessageHistory::new(session_id.clone(), file_path).await?;
-                chat_history.get_messages().await?
-            } else {
-                let file_path = data_dir.join(format!("{}_history.jsonl", session_id.clone()));
-                let chat_history = FileChatMessageHistory::new(session_id.clone(), file_path).await?;
-                chat_history.get_messages().await?
-            };
+            let messages = self.chat_history.get_messages().await?;
             
             // Convert to new format: system_prompt + chat_message
             let mut history_array = Vec::new();
@@ -490,33 +556,24 @@ impl BaseMemory for SummaryMemory {
                 }
             }
             
-            // Add messages to shared message history if available
-            if let Some(ref message_history) = self.message_history {
-                if let Some(user_msg) = user_message {
-                    let chat_msg = ChatMessage {
-                        id: uuid::Uuid::new_v4().to_string(),
-                        role: "user".to_string(),
-                        content: user_msg,
+            // Add messages of the turn to the message store together
+            let mut records = Vec::new();
+            for (role, content) in [("user", user_message), ("assistant", assistant_message)] {
+                if let Some(content) = content.filter(|content| !content.trim().is_empty()) {
+                    records.push(ChatMessageRecord {
+                        role: role.to_string(),
+                        content,
+                        name: None,
+                        additional_kwargs: None,
                         timestamp: chrono::Utc::now().to_rfc3339(),
-                        metadata: None,
-                    };
-                    message_history.add_message(&chat_msg).await?;
+                        sequence_number: 0,
+                    });
                 }
-                
-                if let Some(assistant_msg) = assistant_message {
-                    let chat_msg = ChatMessage {
-                        id: uuid::Uuid::new_v4().to_string(),
-                        role: "assistant".to_string(),
-                        content: assistant_msg,
-                        timestamp: chrono::Utc::now().to_rfc3339(),
-                        metadata: None,
-                    };
-                    message_history.add_message(&chat_msg).await?;
-                }
-                info!("save_context");
-                // Note: Removed check_and_generate_summary() call to avoid duplicate summary generation
-                // Summary generation is now handled by CompositeMemory::add_message
             }
+            self.chat_history.add_messages(records).await?;
+            info!("save_context");
+            // Note: Removed check_and_generate_summary() call to avoid duplicate summary generation
+            // Summary generation is now handled by CompositeMemory::add_message
             
             Ok(())
         })
@@ -528,9 +585,7 @@ impl BaseMemory for SummaryMemory {
         
         Box::pin(async move {
             // Clear message history
-            let file_path = data_dir.join(format!("{}_history.jsonl", session_id.clone()));
-            let chat_history = FileChatMessageHistory::new(session_id.clone(), file_path).await?;
-            chat_history.clear().await?;
+            self.chat_history.clear().await?;
             
             // Clear summary file
             let summary_path = data_dir.join(format!("{}_summary.json", session_id.clone()));
@@ -555,12 +610,91 @@ impl BaseMemory for SummaryMemory {
     }
     
     fn get_token_count(&self) -> Result<usize, Error> {
-        // Use common function to estimate token count
         let text = format!("{}:{}", self.session_id, self.data_dir.to_string_lossy());
-        Ok(estimate_text_tokens(&text))
+        Ok(self.tokenizer.count_tokens(&text))
     }
     
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::ChatCompletion;
+
+    // Model answering "summary N" and recording its prompts
+    #[derive(Default)]
+    struct RecordingModel {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl ChatModel for RecordingModel {
+        fn invoke(&self, messages: Vec<ModelChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+            Box::pin(async move {
+                let prompt = match messages.last() {
+                    Some(ModelChatMessage::Human(content)) => content.content.clone(),
+                    _ => String::new(),
+                };
+                let mut prompts = self.prompts.lock().unwrap();
+                prompts.push(prompt);
+                Ok(ChatCompletion {
+                    message: ModelChatMessage::AIMessage(ChatMessageContent {
+                        content: format!("summary {}", prompts.len()),
+                        name: None,
+                        additional_kwargs: HashMap::new(),
+                    }),
+                    usage: None,
+                    model_name: "recording".to_string(),
+                })
+            })
+        }
+    }
+
+    async fn save_turn(memory: &SummaryMemory, input: &str, output: &str) {
+        let inputs = HashMap::from([("input".to_string(), json!(input))]);
+        let outputs = HashMap::from([("output".to_string(), json!(output))]);
+        memory.save_context(&inputs, &outputs).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incremental_and_hierarchical_summaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = Arc::new(RecordingModel::default());
+        let memory = SummaryMemory::new("s1".to_string(), dir.path().to_path_buf(), 1).await.unwrap()
+            .with_recent_messages_count(2);
+
+        // Summaries need an injected model
+        save_turn(&memory, "first question", "first answer").await;
+        let error = memory.check_and_generate_summary().await.unwrap_err();
+        assert!(error.to_string().contains("with_chat_model"));
+        let memory = memory.with_chat_model(model.clone());
+        assert!(memory.check_and_generate_summary().await.unwrap());
+        save_turn(&memory, "second question", "second answer").await;
+        assert!(memory.check_and_generate_summary().await.unwrap());
+        assert!(!memory.check_and_generate_summary().await.unwrap());
+
+        // The second request only carries the new messages on top of the previous summary
+        let prompts = model.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains("Current summary:\nsummary 1"));
+        assert!(prompts[1].contains("User: second question\nAssistant: second answer"));
+        assert!(!prompts[1].contains("first question"));
+        let summary = memory.load_summary().await.unwrap();
+        assert_eq!(summary.summary.as_deref(), Some("summary 2"));
+        assert_eq!(summary.sequence_number, 4);
+
+        // New messages above the chunk budget are summarized per chunk, then folded into the summary
+        let memory = memory
+            .with_summary_chunk_tokens(8)
+            .with_summary_prompt_template(PromptTemplate::new("Summarize:\n{chat_history}").unwrap());
+        save_turn(&memory, "third question about the weather in Lisbon", "third answer about the sunny weather").await;
+        assert!(memory.check_and_generate_summary().await.unwrap());
+        let prompts = model.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 5);
+        assert!(prompts[2].contains("third question") && !prompts[2].contains("third answer"));
+        assert_eq!(prompts[4], "Summarize:\nPrevious conversation summary: summary 2\n\nsummary 3\nsummary 4");
+        assert_eq!(memory.load_summary().await.unwrap().summary.as_deref(), Some("summary 5"));
+    }
+}
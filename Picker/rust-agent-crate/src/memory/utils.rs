@@ -5,6 +5,8 @@ use serde::{Serialize, Deserialize};
 use serde_json::Value;
 use log::warn;
 
+use crate::memory::encryption::{decode_file_content, encode_file_content, MemoryCipher};
+
 /// 确保数据目录存在
 pub async fn ensure_data_dir_exists(data_dir: &Path) -> Result<()> {
     if !data_dir.exists() {
@@ -129,6 +131,15 @@ pub async fn read_file_content(file_path: &Path) -> Result<String> {
     })
 }
 
+/// 异步读取文件内容，加密文件使用 cipher 解密（明文文件直接读取）
+pub async fn read_file_content_with_cipher(file_path: &Path, cipher: Option<&MemoryCipher>) -> Result<String> {
+    let data = tokio::fs::read(file_path).await.map_err(|e| {
+        warn!("Failed to read file '{}': {}", file_path.display(), e);
+        Error::from(e)
+    })?;
+    decode_file_content(data, cipher)
+}
+
 /// 异步写入文件内容，带错误处理
 pub async fn write_file_content(file_path: &Path, content: &str) -> Result<()> {
     tokio::fs::write(file_path, content).await.map_err(|e| {
@@ -139,6 +150,13 @@ pub async fn write_file_content(file_path: &Path, content: &str) -> Result<()> {
 
 /// 原子写入文件内容（先写入临时文件，然后重命名）
 pub async fn atomic_write_file(file_path: &Path, content: &str) -> Result<()> {
+    atomic_write_file_with_cipher(file_path, content, None).await
+}
+
+/// 原子写入文件内容，提供 cipher 时加密后写入（每次写入使用新的随机 nonce）
+pub async fn atomic_write_file_with_cipher(file_path: &Path, content: &str, cipher: Option<&MemoryCipher>) -> Result<()> {
+    let data = encode_file_content(content, cipher)?;
+
     // 创建临时文件路径
     let temp_path = file_path.with_extension("tmp");
     
@@ -148,7 +166,10 @@ pub async fn atomic_write_file(file_path: &Path, content: &str) -> Result<()> {
     }
     
     // 写入临时文件
-    write_file_content(&temp_path, content).await?;
+    tokio::fs::write(&temp_path, data).await.map_err(|e| {
+        warn!("Failed to write file '{}': {}", temp_path.display(), e);
+        Error::from(e)
+    })?;
     
     // 原子重命名
     tokio::fs::rename(&temp_path, file_path).await.map_err(|e| {
@@ -0,0 +1,265 @@
+// Long-term memory retrieving relevant past messages from a vector store
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use anyhow::{Error, Result};
+use log::info;
+use serde_json::{json, Value};
+
+use crate::memory::base::BaseMemory;
+use crate::memory::message_history::ChatMessage;
+use crate::vectorstores::{Document, MetadataFilter, ScoredDocument, VectorStore};
+
+/// Default memory variable holding the relevant past messages
+pub const DEFAULT_RETRIEVER_MEMORY_KEY: &str = "relevant_history";
+
+/// Vector store backed memory, aligned with LangChain's VectorStoreRetrieverMemory
+///
+/// Every saved message is embedded with its session id, role and timestamp as metadata.
+/// `load_memory_variables` embeds the current input and returns the top-k most similar past
+/// messages of all sessions, optionally restricted by a metadata filter.
+#[derive(Clone)]
+pub struct VectorStoreRetrieverMemory {
+    vector_store: Arc<dyn VectorStore>,
+    session_id: Option<String>,
+    memory_key: String,
+    input_key: String,
+    k: usize,
+    // Results below this similarity are dropped
+    score_threshold: Option<f32>,
+    filter: Option<MetadataFilter>,
+    // Skip messages of the current session, which are usually already in the chat history
+    exclude_current_session: bool,
+}
+
+impl VectorStoreRetrieverMemory {
+    /// Create a memory over the vector store, returning the 4 most relevant messages
+    pub fn new(vector_store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            vector_store,
+            session_id: None,
+            memory_key: DEFAULT_RETRIEVER_MEMORY_KEY.to_string(),
+            input_key: "input".to_string(),
+            k: 4,
+            score_threshold: None,
+            filter: None,
+            exclude_current_session: false,
+        }
+    }
+
+    /// Set the session id stored with new messages
+    pub fn with_session_id(mut self, session_id: String) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    /// Set the memory variable holding the retrieved messages
+    pub fn with_memory_key(mut self, memory_key: String) -> Self {
+        self.memory_key = memory_key;
+        self
+    }
+
+    /// Set the input variable used as search query
+    pub fn with_input_key(mut self, input_key: String) -> Self {
+        self.input_key = input_key;
+        self
+    }
+
+    /// Set the number of messages to retrieve
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Drop results whose cosine similarity to the input is below the threshold
+    pub fn with_score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = Some(score_threshold);
+        self
+    }
+
+    /// Only retrieve messages whose metadata (session_id, role, timestamp) matches the filter
+    pub fn with_filter(mut self, filter: MetadataFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Only retrieve messages of other sessions
+    pub fn with_exclude_current_session(mut self, exclude: bool) -> Self {
+        self.exclude_current_session = exclude;
+        self
+    }
+
+    /// Get the memory variable name
+    pub fn memory_key(&self) -> &str {
+        &self.memory_key
+    }
+
+    /// Embed and store a message
+    pub async fn add_message(&self, message: &ChatMessage) -> Result<()> {
+        if message.content.trim().is_empty() {
+            return Ok(());
+        }
+        let mut document = Document::new(message.content.clone())
+            .with_id(message.id.clone())
+            .with_metadata("role", json!(message.role))
+            .with_metadata("timestamp", json!(message.timestamp));
+        if let Some(session_id) = &self.session_id {
+            document = document.with_metadata("session_id", json!(session_id));
+        }
+        self.vector_store.add_documents(vec![document]).await?;
+        Ok(())
+    }
+
+    /// The past messages most relevant to the query, most relevant first
+    pub async fn retrieve(&self, query: &str) -> Result<Vec<ScoredDocument>> {
+        if query.trim().is_empty() || self.k == 0 {
+            return Ok(Vec::new());
+        }
+        let mut filter = self.filter.clone().unwrap_or_default();
+        if let (true, Some(session_id)) = (self.exclude_current_session, &self.session_id) {
+            filter = filter.with_ne("session_id", json!(session_id));
+        }
+        let mut results = self.vector_store.similarity_search_with_score(query.to_string(), self.k, Some(filter)).await?;
+        if let Some(threshold) = self.score_threshold {
+            results.retain(|(_, score)| *score >= threshold);
+        }
+        Ok(results)
+    }
+
+    // "[2025-01-01T10:00:00Z] user: content" per line
+    fn format_documents(results: &[ScoredDocument]) -> String {
+        results
+            .iter()
+            .map(|(document, _)| {
+                let role = document.metadata.get("role").and_then(Value::as_str).unwrap_or("unknown");
+                match document.metadata.get("timestamp").and_then(Value::as_str) {
+                    Some(timestamp) => format!("[{}] {}: {}", timestamp, role, document.page_content),
+                    None => format!("{}: {}", role, document.page_content),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            metadata: None,
+        }
+    }
+}
+
+impl fmt::Debug for VectorStoreRetrieverMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectorStoreRetrieverMemory")
+            .field("session_id", &self.session_id)
+            .field("memory_key", &self.memory_key)
+            .field("k", &self.k)
+            .field("score_threshold", &self.score_threshold)
+            .field("filter", &self.filter)
+            .field("exclude_current_session", &self.exclude_current_session)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BaseMemory for VectorStoreRetrieverMemory {
+    fn memory_variables(&self) -> Vec<String> {
+        vec![self.memory_key.clone()]
+    }
+
+    fn load_memory_variables<'a>(&'a self, inputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<HashMap<String, Value>, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let query = inputs.get(&self.input_key).and_then(Value::as_str).unwrap_or("");
+            let results = self.retrieve(query).await?;
+            if !results.is_empty() {
+                info!("[VectorStoreRetrieverMemory] Retrieved {} relevant messages", results.len());
+            }
+            let mut variables = HashMap::new();
+            variables.insert(self.memory_key.clone(), Value::String(Self::format_documents(&results)));
+            Ok(variables)
+        })
+    }
+
+    fn save_context<'a>(&'a self, inputs: &'a HashMap<String, Value>, outputs: &'a HashMap<String, Value>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            if let Some(input) = inputs.get(&self.input_key).and_then(Value::as_str) {
+                self.add_message(&Self::message("user", input)).await?;
+            }
+            if let Some(output) = outputs.get("output").and_then(Value::as_str) {
+                self.add_message(&Self::message("assistant", output)).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn clear<'a>(&'a self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        Box::pin(async move {
+            // Only the messages of the current session are removed, the other sessions are kept
+            if let Some(session_id) = &self.session_id {
+                let filter = MetadataFilter::new().with_eq("session_id", json!(session_id));
+                self.vector_store.delete_by_filter(filter).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn BaseMemory> {
+        Box::new(self.clone())
+    }
+
+    fn get_session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    fn set_session_id(&mut self, session_id: String) {
+        self.session_id = Some(session_id);
+    }
+
+    fn get_token_count(&self) -> Result<usize, Error> {
+        // Retrieved messages depend on the input, nothing is held in memory
+        Ok(0)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DeterministicFakeEmbedding;
+    use crate::vectorstores::InMemoryVectorStore;
+
+    #[tokio::test]
+    async fn test_retrieves_messages_across_sessions() {
+        let store: Arc<dyn VectorStore> = Arc::new(InMemoryVectorStore::new(Arc::new(DeterministicFakeEmbedding::new(64))));
+        let old_session = VectorStoreRetrieverMemory::new(store.clone()).with_session_id("old".to_string());
+        let inputs = HashMap::from([("input".to_string(), json!("my favourite colour is green"))]);
+        let outputs = HashMap::from([("output".to_string(), json!("Noted!"))]);
+        old_session.save_context(&inputs, &outputs).await.unwrap();
+
+        let new_session = VectorStoreRetrieverMemory::new(store.clone())
+            .with_session_id("new".to_string())
+            .with_k(1)
+            .with_exclude_current_session(true);
+        new_session.add_message(&VectorStoreRetrieverMemory::message("user", "my favourite colour is green")).await.unwrap();
+
+        let variables = new_session.load_memory_variables(&inputs).await.unwrap();
+        let relevant = variables[DEFAULT_RETRIEVER_MEMORY_KEY].as_str().unwrap();
+        assert!(relevant.contains("user: my favourite colour is green"), "{}", relevant);
+        let results = new_session.retrieve("my favourite colour is green").await.unwrap();
+        assert_eq!(results[0].0.metadata["session_id"], json!("old"));
+
+        // Clearing the new session keeps the old one
+        new_session.clear().await.unwrap();
+        let results = old_session.retrieve("Noted!").await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|(document, _)| document.metadata["session_id"] == json!("old")));
+    }
+}
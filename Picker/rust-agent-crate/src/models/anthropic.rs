@@ -0,0 +1,694 @@
+// Anthropic Messages API model implementation
+use super::chat::{messages_to_prompts, stream_with_run_manager, ChatCompletion, ChatCompletionChunk, ChatCompletionStream, ChatModel, ModelApiError, ToolCallChunk, ToolDefinition};
+use super::message::{ChatMessage, ChatMessageContent, TokenUsage, ToolCall};
+use super::sse::{sse_events, SseEvent};
+use crate::callbacks::{CallbackHandler, CallbackManager};
+use crate::core::Runnable;
+use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Version sent in the anthropic-version header
+pub const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+
+// Usage statistics of the Messages API, cached input tokens are reported separately
+#[derive(Deserialize, Default, Clone)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: usize,
+    #[serde(default)]
+    output_tokens: usize,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<usize>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<usize>,
+}
+
+impl AnthropicUsage {
+    fn prompt_tokens(&self) -> usize {
+        self.input_tokens + self.cache_creation_input_tokens.unwrap_or(0) + self.cache_read_input_tokens.unwrap_or(0)
+    }
+
+    fn to_token_usage(&self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens(),
+            completion_tokens: self.output_tokens,
+            total_tokens: self.prompt_tokens() + self.output_tokens,
+        }
+    }
+}
+
+// Content block of a response
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: Value,
+    },
+    // Thinking and other blocks are not part of the answer
+    #[serde(other)]
+    Other,
+}
+
+// Response of POST /messages
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    model: Option<String>,
+    #[serde(default)]
+    content: Vec<AnthropicContentBlock>,
+    stop_reason: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+// Anthropic Messages API chat model
+#[derive(Clone)]
+pub struct AnthropicChatModel {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    model_name: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: u32,
+    additional_headers: HashMap<String, String>,
+    additional_params: HashMap<String, Value>,
+    tool_calling: bool,
+    callback_manager: CallbackManager,
+}
+
+impl AnthropicChatModel {
+    /// Create a new Anthropic chat model instance, base_url defaults to https://api.anthropic.com/v1
+    pub fn new(api_key: String, base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: base_url.unwrap_or_else(|| "https://api.anthropic.com/v1".to_string()),
+            model_name: None,
+            temperature: None,
+            // max_tokens is required by the Messages API
+            max_tokens: 4096,
+            additional_headers: HashMap::new(),
+            additional_params: HashMap::new(),
+            tool_calling: true,
+            callback_manager: CallbackManager::new(),
+        }
+    }
+
+    /// Get model name
+    pub fn model_name(&self) -> Option<&String> {
+        self.model_name.as_ref()
+    }
+
+    /// Get base URL
+    pub fn base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    /// Get temperature parameter
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// Get maximum number of tokens
+    pub fn max_tokens(&self) -> u32 {
+        self.max_tokens
+    }
+
+    /// Set model name
+    pub fn with_model(mut self, model_name: String) -> Self {
+        self.model_name = Some(model_name);
+        self
+    }
+
+    /// Set temperature parameter
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set maximum number of tokens (default 4096)
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Add additional request headers, e.g. anthropic-beta
+    pub fn with_additional_header(mut self, key: String, value: String) -> Self {
+        self.additional_headers.insert(key, value);
+        self
+    }
+
+    /// Add additional request parameters, e.g. top_k or metadata
+    pub fn with_additional_param(mut self, key: String, value: Value) -> Self {
+        self.additional_params.insert(key, value);
+        self
+    }
+
+    /// Enable or disable native tool calling
+    pub fn with_tool_calling(mut self, enabled: bool) -> Self {
+        self.tool_calling = enabled;
+        self
+    }
+
+    /// Add a callback handler, notified of LLM start, new tokens, end and errors
+    pub fn with_callback_handler(mut self, handler: Arc<dyn CallbackHandler>) -> Self {
+        self.callback_manager.add_handler(handler);
+        self
+    }
+
+    /// Set the callback manager, runs of the model become children of its parent run
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+
+    /// Get the callback manager
+    pub fn callback_manager(&self) -> &CallbackManager {
+        &self.callback_manager
+    }
+
+    /// Convert chat messages to the system prompt and the Messages API message list
+    /// System messages are joined into the top-level system prompt, tool calls become tool_use blocks
+    /// and tool messages become tool_result blocks of a user message.
+    fn convert_messages(messages: Vec<ChatMessage>) -> (Option<String>, Vec<Value>) {
+        let mut system_prompts = Vec::new();
+        let mut converted: Vec<(&'static str, Vec<Value>)> = Vec::new();
+
+        for message in messages {
+            let (role, blocks) = match message {
+                ChatMessage::System(content) => {
+                    system_prompts.push(content.content);
+                    continue;
+                },
+                ChatMessage::Human(content) => ("user", vec![json!({"type": "text", "text": content.content})]),
+                ChatMessage::AIMessage(content) => {
+                    let mut blocks = Vec::new();
+                    if !content.content.is_empty() {
+                        blocks.push(json!({"type": "text", "text": content.content}));
+                    }
+                    // Replay tool calls so that the following tool results can refer to them
+                    for tool_call in content.tool_calls() {
+                        let input: Value = serde_json::from_str(&tool_call.arguments).unwrap_or_else(|_| json!({}));
+                        blocks.push(json!({"type": "tool_use", "id": tool_call.id, "name": tool_call.name, "input": input}));
+                    }
+                    ("assistant", blocks)
+                },
+                ChatMessage::ToolMessage(content) => {
+                    let tool_use_id = content.additional_kwargs.get("tool_call_id")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    ("user", vec![json!({"type": "tool_result", "tool_use_id": tool_use_id, "content": content.content})])
+                },
+            };
+            if blocks.is_empty() {
+                continue;
+            }
+            // Consecutive messages of the same role are merged, results of parallel tool calls must share one message
+            match converted.last_mut() {
+                Some((last_role, last_blocks)) if *last_role == role => last_blocks.extend(blocks),
+                _ => converted.push((role, blocks)),
+            }
+        }
+
+        let system = if system_prompts.is_empty() { None } else { Some(system_prompts.join("\n\n")) };
+        let messages = converted.into_iter()
+            .map(|(role, blocks)| json!({"role": role, "content": blocks}))
+            .collect();
+        (system, messages)
+    }
+
+    /// Build the messages request body
+    fn build_request_body(&self, messages: Vec<ChatMessage>, tools: &[ToolDefinition]) -> Value {
+        let (system, messages) = Self::convert_messages(messages);
+
+        let mut request_body = json!({
+            "model": self.model_name.clone().unwrap_or_default(),
+            "max_tokens": self.max_tokens,
+            "messages": messages,
+        });
+        if let Some(system) = system {
+            request_body["system"] = json!(system);
+        }
+        if let Some(temp) = self.temperature {
+            request_body["temperature"] = json!(temp);
+        }
+
+        // Add tool definitions in the Messages API format
+        if self.tool_calling && !tools.is_empty() {
+            let tools_json: Vec<Value> = tools.iter().map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters,
+                })
+            }).collect();
+            request_body["tools"] = Value::Array(tools_json);
+        }
+
+        // Add additional parameters
+        for (key, value) in &self.additional_params {
+            request_body[key] = value.clone();
+        }
+
+        request_body
+    }
+
+    /// Send a messages request and check the response status
+    async fn send_request(&self, request_body: &Value) -> Result<reqwest::Response, Error> {
+        let api_url = format!("{}/messages", self.base_url);
+
+        let mut request = self.client.post(&api_url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_API_VERSION)
+            .header("Content-Type", "application/json");
+
+        // Add additional request headers
+        for (key, value) in &self.additional_headers {
+            request = request.header(key, value);
+        }
+
+        let response = request.json(request_body).send().await?;
+
+        // Check response status
+        if !response.status().is_success() {
+            return Err(Error::new(ModelApiError::from_response(response).await));
+        }
+
+        Ok(response)
+    }
+
+    /// Send a messages request, reporting the LLM run to the callback manager
+    async fn complete(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_completion(messages, tools).await {
+            Ok(completion) => {
+                run_manager.on_llm_end(&completion.model_name, completion.usage.as_ref());
+                Ok(completion)
+            },
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a messages request and convert the response
+    async fn request_completion(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let request_body = self.build_request_body(messages, &tools);
+        let response: AnthropicResponse = self.send_request(&request_body).await?.json().await?;
+
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for block in response.content {
+            match block {
+                AnthropicContentBlock::Text { text } => content.push_str(&text),
+                AnthropicContentBlock::ToolUse { id, name, input } => {
+                    let arguments = if input.is_null() { "{}".to_string() } else { input.to_string() };
+                    tool_calls.push(ToolCall { id, name, arguments }.to_openai_value());
+                },
+                AnthropicContentBlock::Other => {},
+            }
+        }
+
+        // Tool calls are stored in the OpenAI format shared by all models
+        let mut additional_kwargs = HashMap::new();
+        if !tool_calls.is_empty() {
+            additional_kwargs.insert("tool_calls".to_string(), Value::Array(tool_calls));
+        }
+        if let Some(stop_reason) = response.stop_reason {
+            additional_kwargs.insert("stop_reason".to_string(), Value::String(stop_reason));
+        }
+
+        Ok(ChatCompletion {
+            message: ChatMessage::AIMessage(ChatMessageContent {
+                content,
+                name: None,
+                additional_kwargs,
+            }),
+            usage: response.usage.map(|usage| usage.to_token_usage()),
+            model_name: response.model.unwrap_or_else(|| "unknown".to_string()),
+        })
+    }
+
+    /// Send a streaming messages request, reporting the LLM run to the callback manager
+    async fn complete_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_stream(messages, tools).await {
+            Ok(stream) => Ok(stream_with_run_manager(stream, run_manager, model_name)),
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a streaming messages request and convert SSE events into chunks
+    async fn request_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let mut request_body = self.build_request_body(messages, &tools);
+        request_body["stream"] = json!(true);
+        let response = self.send_request(&request_body).await?;
+
+        let stream = sse_events(response.bytes_stream())
+            .scan(AnthropicStreamState::default(), |state, event| futures::future::ready(Some(state.chunk(event))))
+            .filter_map(futures::future::ready);
+        Ok(Box::pin(stream))
+    }
+}
+
+// State carried between the events of a streamed message
+#[derive(Default)]
+struct AnthropicStreamState {
+    model_name: Option<String>,
+    usage: AnthropicUsage,
+    // Indexes of tool_use blocks that have not received input yet
+    tool_blocks_without_input: HashSet<usize>,
+}
+
+impl AnthropicStreamState {
+    // Convert one event into a chunk, events without content (ping, message_stop) give None
+    fn chunk(&mut self, event: Result<SseEvent, Error>) -> Option<Result<ChatCompletionChunk, Error>> {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return Some(Err(e)),
+        };
+        if event.data.trim().is_empty() {
+            return None;
+        }
+        let data: Value = match serde_json::from_str(&event.data) {
+            Ok(data) => data,
+            Err(e) => return Some(Err(Error::msg(format!("Failed to parse stream event: {} - {}", e, event.data)))),
+        };
+
+        let mut chunk = ChatCompletionChunk::default();
+        match data["type"].as_str().unwrap_or_default() {
+            "message_start" => {
+                let message = &data["message"];
+                self.model_name = message["model"].as_str().map(|s| s.to_string());
+                if let Ok(usage) = serde_json::from_value::<AnthropicUsage>(message["usage"].clone()) {
+                    self.usage = usage;
+                }
+                chunk.model_name = self.model_name.clone();
+            },
+            "content_block_start" => {
+                let index = data["index"].as_u64().unwrap_or_default() as usize;
+                let block = &data["content_block"];
+                match block["type"].as_str() {
+                    Some("tool_use") => {
+                        self.tool_blocks_without_input.insert(index);
+                        chunk.tool_call_chunks.push(ToolCallChunk {
+                            index,
+                            id: block["id"].as_str().map(|s| s.to_string()),
+                            name: block["name"].as_str().map(|s| s.to_string()),
+                            arguments: String::new(),
+                        });
+                    },
+                    Some("text") => chunk.content = block["text"].as_str().unwrap_or_default().to_string(),
+                    _ => return None,
+                }
+            },
+            "content_block_delta" => {
+                let index = data["index"].as_u64().unwrap_or_default() as usize;
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => chunk.content = delta["text"].as_str().unwrap_or_default().to_string(),
+                    Some("input_json_delta") => {
+                        let partial_json = delta["partial_json"].as_str().unwrap_or_default().to_string();
+                        if !partial_json.is_empty() {
+                            self.tool_blocks_without_input.remove(&index);
+                        }
+                        chunk.tool_call_chunks.push(ToolCallChunk {
+                            index,
+                            arguments: partial_json,
+                            ..Default::default()
+                        });
+                    },
+                    _ => return None,
+                }
+            },
+            "content_block_stop" => {
+                // Tools called without arguments still need a JSON object
+                let index = data["index"].as_u64().unwrap_or_default() as usize;
+                if !self.tool_blocks_without_input.remove(&index) {
+                    return None;
+                }
+                chunk.tool_call_chunks.push(ToolCallChunk {
+                    index,
+                    arguments: "{}".to_string(),
+                    ..Default::default()
+                });
+            },
+            "message_delta" => {
+                chunk.finish_reason = data["delta"]["stop_reason"].as_str().map(|s| s.to_string());
+                if let Some(output_tokens) = data["usage"]["output_tokens"].as_u64() {
+                    self.usage.output_tokens = output_tokens as usize;
+                }
+                chunk.usage = Some(self.usage.to_token_usage());
+                chunk.model_name = self.model_name.clone();
+            },
+            "error" => {
+                let message = data["error"]["message"].as_str().unwrap_or("unknown error");
+                let kind = data["error"]["type"].as_str().unwrap_or("error");
+                return Some(Err(Error::msg(format!("Stream error: {} - {}", kind, message))));
+            },
+            _ => return None,
+        }
+        Some(Ok(chunk))
+    }
+}
+
+impl ChatModel for AnthropicChatModel {
+    fn model_name(&self) -> Option<&str> {
+        self.model_name.as_deref()
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url.to_string()
+    }
+
+    fn supports_tool_calling(&self) -> bool {
+        self.tool_calling
+    }
+
+    fn identifying_params(&self) -> Value {
+        json!({
+            "model_name": self.model_name,
+            "base_url": self.base_url,
+            "temperature": self.temperature,
+            "max_tokens": self.max_tokens,
+            "additional_params": self.additional_params,
+        })
+    }
+
+    // Runs are reported to the callbacks of the caller, the handlers attached to the model are kept
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        Some(Arc::new(self.clone().with_callback_manager(callbacks.merge(&self.callback_manager))))
+    }
+
+    fn invoke(&self, messages: Vec<ChatMessage>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, Vec::new()))
+    }
+
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, tools))
+    }
+
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(self.complete_stream(messages, tools))
+    }
+}
+
+// Implement Runnable so that the model can be composed with prompts, astream yields incremental deltas
+impl Runnable<Vec<ChatMessage>, ChatCompletion> for AnthropicChatModel {
+    fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            model.complete(input, Vec::new()).await
+        })
+    }
+
+    fn astream(
+        &self,
+        input: Vec<ChatMessage>,
+    ) -> Pin<Box<dyn std::future::Future<Output = Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            let stream: Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send> = match model.complete_stream(input, Vec::new()).await {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(ChatCompletionChunk::into_completion))),
+                Err(e) => Box::new(futures::stream::once(async move { Err(e) })),
+            };
+            stream
+        })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, ChatCompletion> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use std::sync::Mutex;
+
+    fn message(content: &str, kwargs: HashMap<String, Value>) -> ChatMessageContent {
+        ChatMessageContent {
+            content: content.to_string(),
+            name: None,
+            additional_kwargs: kwargs,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_api_tool_use() {
+        let requests: Arc<Mutex<Vec<(HeaderMap, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = Router::new().route("/messages", post(move |headers: HeaderMap, Json(body): Json<Value>| {
+            let recorded = recorded.clone();
+            async move {
+                recorded.lock().unwrap().push((headers, body));
+                Json(json!({
+                    "id": "msg_1",
+                    "type": "message",
+                    "role": "assistant",
+                    "model": "claude-mock",
+                    "content": [
+                        {"type": "text", "text": "Checking the balance."},
+                        {"type": "tool_use", "id": "toolu_1", "name": "get_balance", "input": {"address": "0xabc"}}
+                    ],
+                    "stop_reason": "tool_use",
+                    "usage": {"input_tokens": 20, "cache_read_input_tokens": 5, "output_tokens": 7}
+                }))
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let model = AnthropicChatModel::new("test-key".to_string(), Some(format!("http://{}", address)))
+            .with_model("claude-mock".to_string())
+            .with_max_tokens(512);
+        let tools = vec![ToolDefinition::new(
+            "get_balance".to_string(),
+            "Get the balance of an address".to_string(),
+            json!({"type": "object", "properties": {"address": {"type": "string"}}}),
+        )];
+        let messages = vec![
+            ChatMessage::System(message("You manage wallets.", HashMap::new())),
+            ChatMessage::Human(message("Balance of 0xabc?", HashMap::new())),
+        ];
+        let completion = model.invoke_with_tools(messages, tools).await.unwrap();
+        assert_eq!(completion.model_name, "claude-mock");
+        let usage = completion.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (25, 7, 32));
+        let content = match completion.message {
+            ChatMessage::AIMessage(content) => content,
+            _ => panic!("Expected AI message"),
+        };
+        assert_eq!(content.content, "Checking the balance.");
+        let tool_calls = content.tool_calls();
+        assert_eq!(tool_calls[0].id, "toolu_1");
+        assert_eq!(tool_calls[0].arguments, "{\"address\":\"0xabc\"}");
+
+        // The tool result refers to the tool_use block
+        let mut tool_kwargs = HashMap::new();
+        tool_kwargs.insert("tool_call_id".to_string(), json!("toolu_1"));
+        let follow_up = vec![
+            ChatMessage::Human(message("Balance of 0xabc?", HashMap::new())),
+            ChatMessage::AIMessage(content),
+            ChatMessage::ToolMessage(message("1.5 ETH", tool_kwargs)),
+        ];
+        ChatModel::invoke(&model, follow_up).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let (headers, first) = &requests[0];
+        assert_eq!(headers["x-api-key"], "test-key");
+        assert_eq!(headers["anthropic-version"], ANTHROPIC_API_VERSION);
+        assert_eq!(first["system"], "You manage wallets.");
+        assert_eq!(first["max_tokens"], 512);
+        assert_eq!(first["messages"], json!([{"role": "user", "content": [{"type": "text", "text": "Balance of 0xabc?"}]}]));
+        assert_eq!(first["tools"][0]["input_schema"]["properties"]["address"]["type"], "string");
+
+        let (_, second) = &requests[1];
+        assert!(second.get("system").is_none());
+        assert_eq!(second["messages"][1]["content"][1], json!({"type": "tool_use", "id": "toolu_1", "name": "get_balance", "input": {"address": "0xabc"}}));
+        assert_eq!(second["messages"][2], json!({
+            "role": "user",
+            "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "1.5 ETH"}]
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_messages_api_streaming() {
+        let events = [
+            ("message_start", json!({"type": "message_start", "message": {"model": "claude-mock", "usage": {"input_tokens": 10, "output_tokens": 1}}})),
+            ("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+            ("ping", json!({"type": "ping"})),
+            ("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}})),
+            ("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}})),
+            ("content_block_stop", json!({"type": "content_block_stop", "index": 0})),
+            ("content_block_start", json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}}})),
+            ("content_block_delta", json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\":"}})),
+            ("content_block_delta", json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}})),
+            ("content_block_stop", json!({"type": "content_block_stop", "index": 1})),
+            ("content_block_start", json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_2", "name": "get_time", "input": {}}})),
+            ("content_block_stop", json!({"type": "content_block_stop", "index": 2})),
+            ("message_delta", json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 15}})),
+            ("message_stop", json!({"type": "message_stop"})),
+        ];
+        let body = events.iter()
+            .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+            .collect::<String>();
+        let app = Router::new().route("/messages", post(move |Json(request): Json<Value>| {
+            let body = body.clone();
+            async move {
+                assert_eq!(request["stream"], true);
+                ([("content-type", "text/event-stream")], body)
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let model = AnthropicChatModel::new("test-key".to_string(), Some(format!("http://{}", address)));
+        let messages = vec![ChatMessage::Human(message("Hi", HashMap::new()))];
+        let mut stream = ChatModel::stream(&model, messages).await.unwrap();
+        let mut accumulator = crate::models::ChatCompletionAccumulator::new();
+        let mut finish_reason = None;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            finish_reason = finish_reason.or(chunk.finish_reason.clone());
+            accumulator.push(&chunk);
+        }
+        assert_eq!(accumulator.content(), "Hello");
+        assert_eq!(finish_reason.as_deref(), Some("tool_use"));
+
+        let completion = accumulator.finish();
+        assert_eq!(completion.model_name, "claude-mock");
+        assert_eq!(completion.usage.unwrap().total_tokens, 25);
+        let tool_calls = match completion.message {
+            ChatMessage::AIMessage(content) => content.tool_calls(),
+            _ => panic!("Expected AI message"),
+        };
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!((tool_calls[0].id.as_str(), tool_calls[0].arguments.as_str()), ("toolu_1", "{\"city\":\"Paris\"}"));
+        assert_eq!((tool_calls[1].name.as_str(), tool_calls[1].arguments.as_str()), ("get_time", "{}"));
+    }
+}
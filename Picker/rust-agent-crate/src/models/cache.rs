@@ -0,0 +1,643 @@
+// Response cache for chat models, backed by a directory of JSON files or SQLite
+use super::chat::{ChatCompletion, ChatCompletionAccumulator, ChatCompletionChunk, ChatCompletionStream, ChatModel, ToolDefinition};
+use super::message::{ChatMessage, ChatMessageContent, TokenUsage};
+use crate::callbacks::CallbackManager;
+use crate::core::Runnable;
+use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How CachedChatModel uses the cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    // Answer from the cache, call the model and store the response on a miss
+    #[default]
+    ReadThrough,
+    // Always call the model and store the response, used to refresh recorded fixtures
+    RecordOnly,
+    // Only answer from the cache, a miss is an error and the model is never called
+    ReplayOnly,
+}
+
+impl FromStr for CacheMode {
+    type Err = Error;
+
+    /// Parse "read_through", "record" or "replay", e.g. from an environment variable
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "read_through" | "readthrough" => Ok(CacheMode::ReadThrough),
+            "record" | "record_only" => Ok(CacheMode::RecordOnly),
+            "replay" | "replay_only" => Ok(CacheMode::ReplayOnly),
+            other => Err(Error::msg(format!("Unknown cache mode '{}', expected read_through, record or replay", other))),
+        }
+    }
+}
+
+// Completion stored in a response cache
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CachedCompletion {
+    pub content: String,
+    // Tool calls and other provider fields of the AI message
+    #[serde(default)]
+    pub additional_kwargs: HashMap<String, Value>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    pub model_name: String,
+    // Unix timestamp in seconds, used for the TTL
+    pub created_at: u64,
+}
+
+impl CachedCompletion {
+    pub fn from_completion(completion: &ChatCompletion) -> Self {
+        let (content, additional_kwargs) = match &completion.message {
+            ChatMessage::AIMessage(content)
+            | ChatMessage::System(content)
+            | ChatMessage::Human(content)
+            | ChatMessage::ToolMessage(content) => (content.content.clone(), content.additional_kwargs.clone()),
+        };
+        Self {
+            content,
+            additional_kwargs,
+            usage: completion.usage.clone(),
+            model_name: completion.model_name.clone(),
+            created_at: unix_now(),
+        }
+    }
+
+    pub fn to_completion(&self) -> ChatCompletion {
+        ChatCompletion {
+            message: ChatMessage::AIMessage(ChatMessageContent {
+                content: self.content.clone(),
+                name: None,
+                additional_kwargs: self.additional_kwargs.clone(),
+            }),
+            usage: self.usage.clone(),
+            model_name: self.model_name.clone(),
+        }
+    }
+
+    fn is_expired(&self, ttl: Option<Duration>) -> bool {
+        ttl.is_some_and(|ttl| unix_now().saturating_sub(self.created_at) > ttl.as_secs())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// Storage of cached completions
+pub trait ResponseCache: Send + Sync {
+    // Get the entry of a key, expired entries are removed and reported as missing
+    fn get(&self, key: String) -> Pin<Box<dyn Future<Output = Result<Option<CachedCompletion>, Error>> + Send + '_>>;
+
+    // Store an entry, evicting the oldest entries above the size cap
+    fn put(&self, key: String, entry: CachedCompletion) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+
+    // Remove every entry
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+}
+
+/// Cache storing one pretty-printed JSON file per key, suitable for fixtures committed to the repository
+#[derive(Clone, Debug)]
+pub struct DiskResponseCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    max_entries: Option<usize>,
+}
+
+impl DiskResponseCache {
+    /// Create a cache in a directory, created on the first write
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: None,
+            max_entries: None,
+        }
+    }
+
+    /// Entries older than the TTL are ignored and removed (default: never expire)
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Maximum number of entries, the oldest ones are evicted (default: unlimited)
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    // Cached entries with their last write time
+    async fn entries(&self) -> Result<Vec<(PathBuf, SystemTime)>, Error> {
+        let mut entries = Vec::new();
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let modified = entry.metadata().await?.modified().unwrap_or(UNIX_EPOCH);
+            entries.push((path, modified));
+        }
+        Ok(entries)
+    }
+
+    async fn evict(&self) -> Result<(), Error> {
+        let Some(max_entries) = self.max_entries else {
+            return Ok(());
+        };
+        let mut entries = self.entries().await?;
+        if entries.len() <= max_entries {
+            return Ok(());
+        }
+        entries.sort_by_key(|(_, modified)| *modified);
+        let excess = entries.len() - max_entries;
+        for (path, _) in entries.into_iter().take(excess) {
+            tokio::fs::remove_file(&path).await.ok();
+        }
+        Ok(())
+    }
+}
+
+impl ResponseCache for DiskResponseCache {
+    fn get(&self, key: String) -> Pin<Box<dyn Future<Output = Result<Option<CachedCompletion>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let path = self.entry_path(&key);
+            let data = match tokio::fs::read(&path).await {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let cached: CachedCompletion = serde_json::from_slice(&data)?;
+            if cached.is_expired(self.ttl) {
+                tokio::fs::remove_file(&path).await.ok();
+                return Ok(None);
+            }
+            Ok(Some(cached))
+        })
+    }
+
+    fn put(&self, key: String, entry: CachedCompletion) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Write then rename so that readers never see a partial file
+            let path = self.entry_path(&key);
+            let temp_path = self.dir.join(format!("{}.json.tmp", key));
+            tokio::fs::write(&temp_path, serde_json::to_vec_pretty(&entry)?).await?;
+            tokio::fs::rename(&temp_path, &path).await?;
+            self.evict().await
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            for (path, _) in self.entries().await? {
+                tokio::fs::remove_file(&path).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Cache stored in a SQLite database
+#[cfg(feature = "sqlite")]
+pub struct SqliteResponseCache {
+    connection: Mutex<rusqlite::Connection>,
+    ttl: Option<Duration>,
+    max_entries: Option<usize>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteResponseCache {
+    /// Open or create the database file
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    /// Database living in memory, for tests
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: rusqlite::Connection) -> Result<Self, Error> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS chat_cache (
+                key TEXT PRIMARY KEY,
+                entry TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS chat_cache_created_at ON chat_cache (created_at);",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            ttl: None,
+            max_entries: None,
+        })
+    }
+
+    /// Entries older than the TTL are ignored and removed (default: never expire)
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Maximum number of entries, the oldest ones are evicted (default: unlimited)
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Queries are short local writes, they run on the calling task
+#[cfg(feature = "sqlite")]
+impl ResponseCache for SqliteResponseCache {
+    fn get(&self, key: String) -> Pin<Box<dyn Future<Output = Result<Option<CachedCompletion>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            use rusqlite::OptionalExtension;
+            let connection = self.connection();
+            let entry: Option<String> = connection
+                .query_row("SELECT entry FROM chat_cache WHERE key = ?1", [&key], |row| row.get(0))
+                .optional()?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+            let cached: CachedCompletion = serde_json::from_str(&entry)?;
+            if cached.is_expired(self.ttl) {
+                connection.execute("DELETE FROM chat_cache WHERE key = ?1", [&key])?;
+                return Ok(None);
+            }
+            Ok(Some(cached))
+        })
+    }
+
+    fn put(&self, key: String, entry: CachedCompletion) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            let connection = self.connection();
+            connection.execute(
+                "INSERT OR REPLACE INTO chat_cache (key, entry, created_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![key, serde_json::to_string(&entry)?, entry.created_at as i64],
+            )?;
+            if let Some(max_entries) = self.max_entries {
+                connection.execute(
+                    "DELETE FROM chat_cache WHERE key NOT IN (
+                        SELECT key FROM chat_cache ORDER BY created_at DESC, rowid DESC LIMIT ?1
+                    )",
+                    [max_entries as i64],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.connection().execute("DELETE FROM chat_cache", [])?;
+            Ok(())
+        })
+    }
+}
+
+// Identifying parameters that do not change the answer of the model
+const TRANSPORT_PARAMS: &[&str] = &["base_url"];
+
+// Remove the transport parameters at every level, wrappers such as the router nest the params of their models
+fn strip_transport_params(value: &mut Value) {
+    match value {
+        Value::Object(params) => {
+            for name in TRANSPORT_PARAMS {
+                params.remove(*name);
+            }
+            params.values_mut().for_each(strip_transport_params);
+        },
+        Value::Array(values) => values.iter_mut().for_each(strip_transport_params),
+        _ => {},
+    }
+}
+
+/// ChatModel answering from a response cache
+/// Keys are a SHA-256 of the messages, the tool definitions and the identifying parameters of the model
+/// (model name, temperature, additional parameters, not the base URL), so changing any of them misses the cache.
+#[derive(Clone)]
+pub struct CachedChatModel {
+    model: Arc<dyn ChatModel>,
+    cache: Arc<dyn ResponseCache>,
+    mode: CacheMode,
+}
+
+impl CachedChatModel {
+    pub fn new(model: Arc<dyn ChatModel>, cache: Arc<dyn ResponseCache>) -> Self {
+        Self {
+            model,
+            cache,
+            mode: CacheMode::default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    /// Cache key of a request, independent of the endpoint the model is served from
+    pub fn cache_key(&self, messages: &[ChatMessage], tools: &[ToolDefinition]) -> String {
+        let messages: Vec<Value> = messages.iter().map(|message| {
+            let (role, content) = match message {
+                ChatMessage::System(content) => ("system", content),
+                ChatMessage::Human(content) => ("human", content),
+                ChatMessage::AIMessage(content) => ("ai", content),
+                ChatMessage::ToolMessage(content) => ("tool", content),
+            };
+            json!({
+                "role": role,
+                "content": content.content,
+                "name": content.name,
+                "additional_kwargs": content.additional_kwargs,
+            })
+        }).collect();
+        let tools: Vec<Value> = tools.iter().map(|tool| {
+            json!({"name": tool.name, "description": tool.description, "parameters": tool.parameters})
+        }).collect();
+        // Transport-only parameters are left out, recordings replay against any endpoint or proxy
+        let mut model_params = self.model.identifying_params();
+        strip_transport_params(&mut model_params);
+        // serde_json objects keep their keys sorted, so the serialization is stable
+        let request = json!({
+            "model": model_params,
+            "messages": messages,
+            "tools": tools,
+        });
+        format!("{:x}", Sha256::digest(request.to_string().as_bytes()))
+    }
+
+    // Entry of the key, None in record-only mode
+    async fn lookup(&self, key: &str) -> Result<Option<CachedCompletion>, Error> {
+        if self.mode == CacheMode::RecordOnly {
+            return Ok(None);
+        }
+        let cached = self.cache.get(key.to_string()).await?;
+        if cached.is_none() && self.mode == CacheMode::ReplayOnly {
+            return Err(Error::msg(format!("No cached response for key {} (replay-only mode)", key)));
+        }
+        Ok(cached)
+    }
+
+    async fn complete(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let key = self.cache_key(&messages, &tools);
+        if let Some(cached) = self.lookup(&key).await? {
+            return Ok(cached.to_completion());
+        }
+        let completion = self.model.invoke_with_tools(messages, tools).await?;
+        self.cache.put(key, CachedCompletion::from_completion(&completion)).await?;
+        Ok(completion)
+    }
+
+    async fn complete_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let key = self.cache_key(&messages, &tools);
+        if let Some(cached) = self.lookup(&key).await? {
+            let chunk = ChatCompletionChunk::from_completion(cached.to_completion());
+            let stream: ChatCompletionStream = Box::pin(futures::stream::once(async move { Ok(chunk) }));
+            return Ok(stream);
+        }
+
+        // Chunks are passed through and the completion is stored once the stream has ended without error
+        let stream = self.model.stream_with_tools(messages, tools).await?;
+        let cache = self.cache.clone();
+        let accumulator = Arc::new(Mutex::new(Some(ChatCompletionAccumulator::new())));
+        let recorder = accumulator.clone();
+        let chunks = stream.inspect(move |chunk| {
+            let mut accumulator = recorder.lock().unwrap_or_else(|e| e.into_inner());
+            match chunk {
+                Ok(chunk) => if let Some(accumulator) = accumulator.as_mut() {
+                    accumulator.push(chunk);
+                },
+                Err(_) => *accumulator = None,
+            }
+        });
+        let store = futures::stream::once(async move {
+            let accumulator = accumulator.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(accumulator) = accumulator {
+                let completion = accumulator.finish();
+                if let Err(e) = cache.put(key, CachedCompletion::from_completion(&completion)).await {
+                    log::warn!("Failed to store the streamed response: {}", e);
+                }
+            }
+            None
+        }).filter_map(futures::future::ready);
+        Ok(Box::pin(chunks.chain(store)))
+    }
+}
+
+impl ChatModel for CachedChatModel {
+    fn model_name(&self) -> Option<&str> {
+        self.model.model_name()
+    }
+
+    fn base_url(&self) -> String {
+        self.model.base_url()
+    }
+
+    fn supports_tool_calling(&self) -> bool {
+        self.model.supports_tool_calling()
+    }
+
+    fn identifying_params(&self) -> Value {
+        self.model.identifying_params()
+    }
+
+    // Cache hits do not reach the model, only misses are reported
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        let model = self.model.with_callbacks(callbacks)?;
+        Some(Arc::new(Self {
+            model,
+            cache: self.cache.clone(),
+            mode: self.mode,
+        }))
+    }
+
+    fn invoke(&self, messages: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, Vec::new()))
+    }
+
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, tools))
+    }
+
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(self.complete_stream(messages, tools))
+    }
+}
+
+// Implement Runnable so that the cached model can be composed with prompts
+impl Runnable<Vec<ChatMessage>, ChatCompletion> for CachedChatModel {
+    fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            model.complete(input, Vec::new()).await
+        })
+    }
+
+    fn astream(
+        &self,
+        input: Vec<ChatMessage>,
+    ) -> Pin<Box<dyn Future<Output = Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            let stream: Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send> = match model.complete_stream(input, Vec::new()).await {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(ChatCompletionChunk::into_completion))),
+                Err(e) => Box::new(futures::stream::once(async move { Err(e) })),
+            };
+            stream
+        })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, ChatCompletion> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Model answering "answer N" for its Nth call
+    struct CountingModel {
+        calls: AtomicUsize,
+    }
+
+    impl ChatModel for CountingModel {
+        fn model_name(&self) -> Option<&str> {
+            Some("counting")
+        }
+
+        fn invoke(&self, _messages: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+            Box::pin(async move {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(CachedCompletion {
+                    content: format!("answer {}", call),
+                    additional_kwargs: HashMap::new(),
+                    usage: None,
+                    model_name: "counting".to_string(),
+                    created_at: 0,
+                }.to_completion())
+            })
+        }
+    }
+
+    fn human(text: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage::Human(ChatMessageContent {
+            content: text.to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })]
+    }
+
+    fn content(completion: ChatCompletion) -> String {
+        match completion.message {
+            ChatMessage::AIMessage(content) => content.content,
+            _ => panic!("Expected AI message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = Arc::new(CountingModel { calls: AtomicUsize::new(0) });
+        let cache = Arc::new(DiskResponseCache::new(dir.path()).with_max_entries(2));
+        let cached = CachedChatModel::new(model.clone(), cache.clone());
+
+        assert_eq!(content(ChatModel::invoke(&cached, human("hi")).await.unwrap()), "answer 1");
+        assert_eq!(content(ChatModel::invoke(&cached, human("hi")).await.unwrap()), "answer 1");
+        assert_eq!(model.calls.load(Ordering::SeqCst), 1);
+
+        // Streams are recorded too
+        let mut stream = ChatModel::stream(&cached, human("stream")).await.unwrap();
+        while stream.next().await.is_some() {}
+        let replay = cached.clone().with_mode(CacheMode::ReplayOnly);
+        assert_eq!(content(ChatModel::invoke(&replay, human("stream")).await.unwrap()), "answer 2");
+        let Err(error) = ChatModel::invoke(&replay, human("unknown")).await else { panic!("Expected a cache miss") };
+        assert!(error.to_string().contains("replay-only"));
+
+        // Record-only refreshes the entry, the size cap evicts the oldest entries
+        let record = cached.clone().with_mode(CacheMode::RecordOnly);
+        assert_eq!(content(ChatModel::invoke(&record, human("hi")).await.unwrap()), "answer 3");
+        assert_eq!(content(ChatModel::invoke(&replay, human("hi")).await.unwrap()), "answer 3");
+        ChatModel::invoke(&cached, human("third")).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // The key depends on the tools and the model parameters
+        let tool = ToolDefinition::new("get_weather".to_string(), "Get the weather".to_string(), json!({"type": "object"}));
+        assert_ne!(cached.cache_key(&human("hi"), &[]), cached.cache_key(&human("hi"), &[tool]));
+        let key = |base_url: &str, model_name: &str| {
+            let model = crate::OpenAIChatModel::new("key".to_string(), Some(base_url.to_string())).with_model(model_name.to_string());
+            CachedChatModel::new(Arc::new(model), cache.clone()).cache_key(&human("hi"), &[])
+        };
+        assert_eq!(key("https://api.openai.com/v1", "gpt-4o"), key("http://localhost:8080/v1", "gpt-4o"));
+        assert_ne!(key("https://api.openai.com/v1", "gpt-4o"), key("https://api.openai.com/v1", "gpt-4o-mini"));
+        let router_key = |base_url: &str| {
+            let model = crate::OpenAIChatModel::new("key".to_string(), Some(base_url.to_string())).with_model("gpt-4o".to_string());
+            let router = crate::RouterChatModel::new().with_model("primary", Arc::new(model));
+            CachedChatModel::new(Arc::new(router), cache.clone()).cache_key(&human("hi"), &[])
+        };
+        assert_eq!(router_key("https://api.openai.com/v1"), router_key("http://localhost:8080/v1"));
+        assert_eq!("replay".parse::<CacheMode>().unwrap(), CacheMode::ReplayOnly);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_cache_ttl_and_size_cap() {
+        let cache = SqliteResponseCache::in_memory().unwrap()
+            .with_ttl(Duration::from_secs(60))
+            .with_max_entries(2);
+        let entry = |content: &str, created_at: u64| CachedCompletion {
+            content: content.to_string(),
+            additional_kwargs: HashMap::new(),
+            usage: None,
+            model_name: "counting".to_string(),
+            created_at,
+        };
+
+        cache.put("expired".to_string(), entry("old", unix_now() - 120)).await.unwrap();
+        assert!(cache.get("expired".to_string()).await.unwrap().is_none());
+
+        for key in ["a", "b", "c"] {
+            cache.put(key.to_string(), entry(key, unix_now())).await.unwrap();
+        }
+        assert!(cache.get("a".to_string()).await.unwrap().is_none());
+        assert_eq!(cache.get("c".to_string()).await.unwrap().unwrap().content, "c");
+        cache.clear().await.unwrap();
+        assert!(cache.get("b".to_string()).await.unwrap().is_none());
+    }
+}
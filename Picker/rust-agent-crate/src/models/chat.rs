@@ -1,6 +1,13 @@
 // Chat model interface and related structure definitions
 use anyhow::Error;
-use crate::models::message::{ChatMessage, TokenUsage};
+use futures::stream::Stream;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::callbacks::{CallbackManager, CallbackRunManager};
+use crate::models::message::{ChatMessage, ChatMessageContent, TokenUsage, ToolCall};
 
 // Simplified chat completion structure
 pub struct ChatCompletion {
@@ -9,6 +16,257 @@ pub struct ChatCompletion {
     pub model_name: String,
 }
 
+// Error response of a model API, callers can downcast it to decide whether to retry or fail over
+#[derive(Clone, Debug)]
+pub struct ModelApiError {
+    // HTTP status code
+    pub status: u16,
+    // Response body
+    pub message: String,
+    // Delay requested by the Retry-After header
+    pub retry_after: Option<Duration>,
+}
+
+impl ModelApiError {
+    /// Read the status, body and Retry-After header of a failed response
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = parse_retry_after(response.headers());
+        let message = response.text().await.unwrap_or_default();
+        Self { status, message, retry_after }
+    }
+
+    /// Rate limits, timeouts and server errors (including 529 overloaded) are worth retrying
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.status, 408 | 409 | 429) || self.status >= 500
+    }
+
+    /// Whether the request was refused because the messages do not fit in the context window
+    pub fn is_context_length_exceeded(&self) -> bool {
+        if !matches!(self.status, 400 | 413 | 422) {
+            return false;
+        }
+        let message = self.message.to_lowercase();
+        [
+            "context_length_exceeded",
+            "maximum context length",
+            "context window",
+            "prompt is too long",
+            "exceeds the available context size",
+        ].iter().any(|pattern| message.contains(pattern))
+    }
+}
+
+impl std::fmt::Display for ModelApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match reqwest::StatusCode::from_u16(self.status) {
+            Ok(status) => write!(f, "API request failed: {} - {}", status, self.message),
+            Err(_) => write!(f, "API request failed: {} - {}", self.status, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ModelApiError {}
+
+// Parse retry-after-ms (OpenAI, Azure) or Retry-After in seconds or as an HTTP date
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers.get("retry-after-ms").and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+// Tool definition passed to models that support native tool calling
+#[derive(Clone, Debug)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    // JSON Schema describing the tool arguments
+    pub parameters: Value,
+}
+
+impl ToolDefinition {
+    pub fn new(name: String, description: String, parameters: Value) -> Self {
+        Self {
+            name,
+            description,
+            parameters,
+        }
+    }
+}
+
+// Partial tool call received while streaming, chunks with the same index belong to the same call
+#[derive(Clone, Debug, Default)]
+pub struct ToolCallChunk {
+    pub index: usize,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments: String,
+}
+
+// Incremental chunk of a streaming chat completion
+#[derive(Clone, Debug, Default)]
+pub struct ChatCompletionChunk {
+    // Content delta (token) of this chunk
+    pub content: String,
+    pub tool_call_chunks: Vec<ToolCallChunk>,
+    pub finish_reason: Option<String>,
+    // Usually only present in the last chunk
+    pub usage: Option<TokenUsage>,
+    pub model_name: Option<String>,
+}
+
+impl ChatCompletionChunk {
+    /// Convert a complete response into a single chunk, used by models without streaming support
+    pub fn from_completion(completion: ChatCompletion) -> Self {
+        let (content, tool_calls) = match completion.message {
+            ChatMessage::AIMessage(content) => {
+                let tool_calls = content.tool_calls();
+                (content.content, tool_calls)
+            },
+            ChatMessage::System(content) | ChatMessage::Human(content) | ChatMessage::ToolMessage(content) => (content.content, Vec::new()),
+        };
+
+        Self {
+            content,
+            tool_call_chunks: tool_calls.into_iter().enumerate().map(|(index, call)| ToolCallChunk {
+                index,
+                id: Some(call.id),
+                name: Some(call.name),
+                arguments: call.arguments,
+            }).collect(),
+            finish_reason: None,
+            usage: completion.usage,
+            model_name: Some(completion.model_name),
+        }
+    }
+
+    /// Convert the chunk into a partial completion (used by Runnable::astream)
+    pub fn into_completion(self) -> ChatCompletion {
+        let mut accumulator = ChatCompletionAccumulator::new();
+        accumulator.push(&self);
+        accumulator.finish()
+    }
+}
+
+// Stream of chat completion chunks
+pub type ChatCompletionStream = Pin<Box<dyn Stream<Item = Result<ChatCompletionChunk, Error>> + Send>>;
+
+// Merge streamed chunks back into a complete ChatCompletion
+#[derive(Default)]
+pub struct ChatCompletionAccumulator {
+    content: String,
+    tool_calls: BTreeMap<usize, ToolCall>,
+    usage: Option<TokenUsage>,
+    model_name: Option<String>,
+}
+
+impl ChatCompletionAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk to the accumulated completion
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) {
+        self.content.push_str(&chunk.content);
+
+        for tool_call_chunk in &chunk.tool_call_chunks {
+            let tool_call = self.tool_calls.entry(tool_call_chunk.index).or_insert_with(|| ToolCall {
+                id: String::new(),
+                name: String::new(),
+                arguments: String::new(),
+            });
+            if let Some(id) = &tool_call_chunk.id {
+                tool_call.id = id.clone();
+            }
+            if let Some(name) = &tool_call_chunk.name {
+                tool_call.name.push_str(name);
+            }
+            tool_call.arguments.push_str(&tool_call_chunk.arguments);
+        }
+
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage.clone();
+        }
+        if chunk.model_name.is_some() {
+            self.model_name = chunk.model_name.clone();
+        }
+    }
+
+    /// Get the content accumulated so far
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Build the complete ChatCompletion
+    pub fn finish(self) -> ChatCompletion {
+        let mut additional_kwargs = HashMap::new();
+        if !self.tool_calls.is_empty() {
+            let tool_calls = self.tool_calls.values().map(|call| call.to_openai_value()).collect();
+            additional_kwargs.insert("tool_calls".to_string(), Value::Array(tool_calls));
+        }
+
+        ChatCompletion {
+            message: ChatMessage::AIMessage(ChatMessageContent {
+                content: self.content,
+                name: None,
+                additional_kwargs,
+            }),
+            usage: self.usage,
+            model_name: self.model_name.unwrap_or_else(|| "unknown".to_string()),
+        }
+    }
+}
+
+// Format messages as "role: content" prompts for callback handlers
+pub(crate) fn messages_to_prompts(messages: &[ChatMessage]) -> Vec<String> {
+    messages.iter().map(|message| match message {
+        ChatMessage::System(content) => format!("system: {}", content.content),
+        ChatMessage::Human(content) => format!("user: {}", content.content),
+        ChatMessage::AIMessage(content) => format!("assistant: {}", content.content),
+        ChatMessage::ToolMessage(content) => format!("tool: {}", content.content),
+    }).collect()
+}
+
+// Report tokens, errors and the end of a streaming LLM run to the run manager
+pub(crate) fn stream_with_run_manager(stream: ChatCompletionStream, run_manager: CallbackRunManager, model_name: String) -> ChatCompletionStream {
+    // (inner stream, run manager, usage, model name, finished)
+    let state = (stream, run_manager, None::<TokenUsage>, model_name, false);
+    Box::pin(futures::stream::unfold(state, |(mut stream, run_manager, mut usage, mut model_name, finished)| async move {
+        use futures::stream::StreamExt;
+        if finished {
+            return None;
+        }
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                if !chunk.content.is_empty() {
+                    run_manager.on_llm_new_token(&chunk.content);
+                }
+                if chunk.usage.is_some() {
+                    usage = chunk.usage.clone();
+                }
+                if let Some(name) = &chunk.model_name {
+                    model_name = name.clone();
+                }
+                Some((Ok(chunk), (stream, run_manager, usage, model_name, false)))
+            },
+            Some(Err(e)) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Some((Err(e), (stream, run_manager, usage, model_name, true)))
+            },
+            None => {
+                run_manager.on_llm_end(&model_name, usage.as_ref());
+                None
+            },
+        }
+    }))
+}
+
 // Chat model interface
 pub trait ChatModel: Send + Sync {
     // Basic model information
@@ -20,12 +278,53 @@ pub trait ChatModel: Send + Sync {
     fn base_url(&self) -> String {
         "https://api.openai.com/v1".to_string()
     }
-    
+
+    // Whether the model accepts tool definitions and returns structured tool calls
+    fn supports_tool_calling(&self) -> bool {
+        false
+    }
+
+    // Parameters identifying the model configuration (model, temperature, ...), part of response cache keys
+    fn identifying_params(&self) -> Value {
+        serde_json::json!({
+            "model_name": self.model_name(),
+            "base_url": self.base_url(),
+        })
+    }
+
+    // Copy of the model whose runs are also reported to the given callbacks, e.g. as children of an agent run
+    // Models without callbacks return None and are used as they are
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        let _callbacks = callbacks;
+        None
+    }
+
     // Core method: handle chat messages
-    fn invoke(&self, messages: Vec<ChatMessage>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+    fn invoke(&self, messages: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
         let _messages = messages;
         Box::pin(async move {
             Err(Error::msg("The model does not implement the invoke method"))
         })
     }
-}
\ No newline at end of file
+
+    // Handle chat messages with tool definitions, models without native tool calling ignore the tools
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        let _tools = tools;
+        self.invoke(messages)
+    }
+
+    // Stream chat messages, the default implementation yields the complete response as a single chunk
+    fn stream(&self, messages: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        self.stream_with_tools(messages, Vec::new())
+    }
+
+    // Stream chat messages with tool definitions
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let completion = self.invoke_with_tools(messages, tools).await?;
+            let chunk = ChatCompletionChunk::from_completion(completion);
+            let stream: ChatCompletionStream = Box::pin(futures::stream::once(async move { Ok(chunk) }));
+            Ok(stream)
+        })
+    }
+}
@@ -1,5 +1,5 @@
 // Message type definitions
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use serde_json::Value;
 
@@ -12,6 +12,16 @@ pub struct ChatMessageContent {
     pub additional_kwargs: HashMap<String, Value>,
 }
 
+impl ChatMessageContent {
+    /// Get the tool calls stored in additional_kwargs["tool_calls"] (OpenAI format)
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        match self.additional_kwargs.get("tool_calls") {
+            Some(Value::Array(calls)) => calls.iter().filter_map(ToolCall::from_openai_value).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 // Simplified message type system (aligned with langchain-core)
 #[derive(Clone, Debug)]
 pub enum ChatMessage {
@@ -21,10 +31,47 @@ pub enum ChatMessage {
     ToolMessage(ChatMessageContent),
 }
 
+// Tool call requested by the model
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    // Arguments as a JSON encoded string, as returned by the model
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// Parse a tool call from the OpenAI `tool_calls` entry format
+    pub fn from_openai_value(value: &Value) -> Option<Self> {
+        let function = value.get("function")?;
+        let name = function.get("name")?.as_str()?.to_string();
+        // Some providers return arguments as an object instead of a string
+        let arguments = match function.get("arguments") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) | None => "{}".to_string(),
+            Some(other) => other.to_string(),
+        };
+        let id = value.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        Some(Self { id, name, arguments })
+    }
+
+    /// Convert to the OpenAI `tool_calls` entry format
+    pub fn to_openai_value(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "arguments": self.arguments,
+            }
+        })
+    }
+}
+
 // Token usage statistics
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TokenUsage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
     pub total_tokens: usize,
-}
\ No newline at end of file
+}
@@ -2,8 +2,23 @@
 mod chat;
 mod message;
 mod openai;
+mod anthropic;
+mod ollama;
+mod router;
+mod cache;
+mod tokenizer;
+mod registry;
+pub(crate) mod sse;
 
 // Re-export module content
-pub use chat::{ChatModel, ChatCompletion};
-pub use message::{ChatMessage, ChatMessageContent, TokenUsage};
-pub use openai::OpenAIChatModel;
\ No newline at end of file
+pub use chat::{ChatModel, ModelApiError, ChatCompletion, ChatCompletionAccumulator, ChatCompletionChunk, ChatCompletionStream, ToolCallChunk, ToolDefinition};
+pub use message::{ChatMessage, ChatMessageContent, TokenUsage, ToolCall};
+pub use openai::OpenAIChatModel;
+pub use anthropic::{AnthropicChatModel, ANTHROPIC_API_VERSION};
+pub use ollama::{LocalModelInfo, LocalModelProvider, LocalServerKind, ModelCapabilities, OllamaChatModel};
+pub use router::{CircuitState, RouteHealth, RouterChatModel};
+pub use cache::{CacheMode, CachedChatModel, CachedCompletion, DiskResponseCache, ResponseCache};
+#[cfg(feature = "sqlite")]
+pub use cache::SqliteResponseCache;
+pub use tokenizer::{trim_messages, BpeEncoding, BpeTokenizer, HeuristicTokenizer, Tokenizer};
+pub use registry::{ModelProfile, ModelRegistry};
\ No newline at end of file
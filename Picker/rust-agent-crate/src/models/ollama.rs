@@ -0,0 +1,853 @@
+// Local model providers: Ollama native API and llama.cpp server
+use super::chat::{messages_to_prompts, stream_with_run_manager, ChatCompletion, ChatCompletionChunk, ChatCompletionStream, ChatModel, ModelApiError, ToolCallChunk, ToolDefinition};
+use super::message::{ChatMessage, TokenUsage};
+use super::openai::OpenAIChatModel;
+use crate::callbacks::{CallbackHandler, CallbackManager};
+use crate::core::Runnable;
+use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+// Kind of local inference server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalServerKind {
+    // Ollama, native API under /api and OpenAI-compatible API under /v1
+    Ollama,
+    // llama.cpp server (llama-server), OpenAI-compatible API under /v1
+    LlamaCpp,
+}
+
+// Model installed on a local server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LocalModelInfo {
+    pub name: String,
+    // Size on disk in bytes
+    pub size: Option<u64>,
+    pub family: Option<String>,
+    // e.g. "8.0B"
+    pub parameter_size: Option<String>,
+    // e.g. "Q4_K_M"
+    pub quantization_level: Option<String>,
+}
+
+// Features supported by a local model
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelCapabilities {
+    // Whether the model accepts tool definitions and returns structured tool calls
+    pub tool_calling: bool,
+    // Whether the model accepts images
+    pub vision: bool,
+    pub context_length: Option<usize>,
+}
+
+// Entry of GET /api/tags
+#[derive(Deserialize)]
+struct OllamaTagsModel {
+    name: String,
+    size: Option<u64>,
+    #[serde(default)]
+    details: Option<OllamaModelDetails>,
+}
+
+#[derive(Deserialize)]
+struct OllamaModelDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+// Response of POST /api/show
+#[derive(Deserialize)]
+struct OllamaShowResponse {
+    #[serde(default)]
+    template: String,
+    // Reported by Ollama 0.6.4 and later
+    capabilities: Option<Vec<String>>,
+    #[serde(default)]
+    model_info: HashMap<String, Value>,
+}
+
+impl OllamaShowResponse {
+    fn to_capabilities(&self) -> ModelCapabilities {
+        let (tool_calling, vision) = match &self.capabilities {
+            Some(capabilities) => (
+                capabilities.iter().any(|c| c == "tools"),
+                capabilities.iter().any(|c| c == "vision"),
+            ),
+            // Older servers: templates of tool capable models render the tool list
+            None => (self.template.contains(".Tools"), false),
+        };
+        // Keys are prefixed with the architecture, e.g. "llama.context_length"
+        let context_length = self.model_info.iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .map(|length| length as usize);
+        ModelCapabilities { tool_calling, vision, context_length }
+    }
+}
+
+/// Turn a failed response into a ModelApiError
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    if !response.status().is_success() {
+        return Err(Error::new(ModelApiError::from_response(response).await));
+    }
+    Ok(response)
+}
+
+// Ollama or llama.cpp server running on the local machine, lists models and detects their capabilities
+#[derive(Clone)]
+pub struct LocalModelProvider {
+    client: Client,
+    kind: LocalServerKind,
+    base_url: String,
+}
+
+impl LocalModelProvider {
+    /// Ollama server, base_url defaults to http://localhost:11434
+    pub fn ollama(base_url: Option<String>) -> Self {
+        Self::new(LocalServerKind::Ollama, base_url.unwrap_or_else(|| "http://localhost:11434".to_string()))
+    }
+
+    /// llama.cpp server, base_url defaults to http://localhost:8080
+    pub fn llama_cpp(base_url: Option<String>) -> Self {
+        Self::new(LocalServerKind::LlamaCpp, base_url.unwrap_or_else(|| "http://localhost:8080".to_string()))
+    }
+
+    fn new(kind: LocalServerKind, base_url: String) -> Self {
+        Self {
+            client: Client::new(),
+            kind,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Get the server kind
+    pub fn kind(&self) -> LocalServerKind {
+        self.kind
+    }
+
+    /// Get base URL, without the /v1 suffix
+    pub fn base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    /// List the models available on the server
+    pub async fn list_models(&self) -> Result<Vec<LocalModelInfo>, Error> {
+        match self.kind {
+            LocalServerKind::Ollama => {
+                let response = check_response(self.client.get(format!("{}/api/tags", self.base_url)).send().await?).await?;
+                let body: Value = response.json().await?;
+                let models: Vec<OllamaTagsModel> = serde_json::from_value(body["models"].clone()).unwrap_or_default();
+                Ok(models.into_iter().map(|model| {
+                    let details = model.details;
+                    LocalModelInfo {
+                        name: model.name,
+                        size: model.size,
+                        family: details.as_ref().and_then(|d| d.family.clone()),
+                        parameter_size: details.as_ref().and_then(|d| d.parameter_size.clone()),
+                        quantization_level: details.as_ref().and_then(|d| d.quantization_level.clone()),
+                    }
+                }).collect())
+            },
+            LocalServerKind::LlamaCpp => {
+                // llama.cpp serves the model it was started with
+                let response = check_response(self.client.get(format!("{}/v1/models", self.base_url)).send().await?).await?;
+                let body: Value = response.json().await?;
+                Ok(body["data"].as_array().into_iter().flatten()
+                    .filter_map(|model| model["id"].as_str())
+                    .map(|name| LocalModelInfo {
+                        name: name.to_string(),
+                        ..Default::default()
+                    })
+                    .collect())
+            },
+        }
+    }
+
+    /// Detect the capabilities of a model
+    pub async fn detect_capabilities(&self, model: &str) -> Result<ModelCapabilities, Error> {
+        match self.kind {
+            LocalServerKind::Ollama => {
+                let request = self.client.post(format!("{}/api/show", self.base_url)).json(&json!({"model": model}));
+                let response: OllamaShowResponse = check_response(request.send().await?).await?.json().await?;
+                Ok(response.to_capabilities())
+            },
+            LocalServerKind::LlamaCpp => {
+                let response = check_response(self.client.get(format!("{}/props", self.base_url)).send().await?).await?;
+                let props: Value = response.json().await?;
+                // Tool calls need the server to be started with --jinja, which reports the template capabilities
+                let tool_calling = match props["chat_template_caps"]["supports_tool_calls"].as_bool() {
+                    Some(supported) => supported,
+                    None => props["chat_template_caps"]["supports_tools"].as_bool().unwrap_or(false),
+                };
+                Ok(ModelCapabilities {
+                    tool_calling,
+                    vision: props["modalities"]["vision"].as_bool().unwrap_or(false),
+                    context_length: props["default_generation_settings"]["n_ctx"].as_u64().map(|n| n as usize),
+                })
+            },
+        }
+    }
+
+    /// Create an OpenAI-compatible model for the local server, native tool calling follows the detected capabilities
+    /// Models without tool calling use the JSON protocol of the agent prompt instead.
+    pub async fn chat_model(&self, model: &str) -> Result<OpenAIChatModel, Error> {
+        let capabilities = self.detect_capabilities(model).await?;
+        // Local servers do not check the API key
+        Ok(OpenAIChatModel::new("local".to_string(), Some(format!("{}/v1", self.base_url)))
+            .with_model(model.to_string())
+            .with_tool_calling(capabilities.tool_calling))
+    }
+}
+
+// Ollama native chat API (/api/chat) model
+#[derive(Clone)]
+pub struct OllamaChatModel {
+    client: Client,
+    base_url: String,
+    model_name: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    context_length: Option<u32>,
+    keep_alive: Option<String>,
+    additional_options: HashMap<String, Value>,
+    // Explicit setting, takes precedence over the detected capabilities
+    tool_calling: Option<bool>,
+    // Shared between clones so that detection and fallbacks are remembered
+    capabilities: Arc<RwLock<Option<ModelCapabilities>>>,
+    callback_manager: CallbackManager,
+}
+
+impl OllamaChatModel {
+    /// Create a new Ollama chat model instance, base_url defaults to http://localhost:11434
+    pub fn new(base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()).trim_end_matches('/').to_string(),
+            model_name: None,
+            temperature: None,
+            max_tokens: None,
+            context_length: None,
+            keep_alive: None,
+            additional_options: HashMap::new(),
+            tool_calling: None,
+            capabilities: Arc::new(RwLock::new(None)),
+            callback_manager: CallbackManager::new(),
+        }
+    }
+
+    /// Get model name
+    pub fn model_name(&self) -> Option<&String> {
+        self.model_name.as_ref()
+    }
+
+    /// Get base URL
+    pub fn base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    /// Get temperature parameter
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// Get maximum number of tokens
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens
+    }
+
+    /// Set model name, e.g. "qwen3:8b"
+    pub fn with_model(mut self, model_name: String) -> Self {
+        self.model_name = Some(model_name);
+        self
+    }
+
+    /// Set temperature parameter
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set maximum number of tokens (num_predict)
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set the context window size (num_ctx), Ollama defaults to a small window
+    pub fn with_context_length(mut self, context_length: u32) -> Self {
+        self.context_length = Some(context_length);
+        self
+    }
+
+    /// Set how long the model stays loaded after a request, e.g. "10m" or "-1"
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Add additional model options, e.g. top_k or seed
+    pub fn with_additional_option(mut self, key: String, value: Value) -> Self {
+        self.additional_options.insert(key, value);
+        self
+    }
+
+    /// Enable or disable native tool calling, overriding the detected capabilities
+    pub fn with_tool_calling(mut self, enabled: bool) -> Self {
+        self.tool_calling = Some(enabled);
+        self
+    }
+
+    /// Add a callback handler, notified of LLM start, new tokens, end and errors
+    pub fn with_callback_handler(mut self, handler: Arc<dyn CallbackHandler>) -> Self {
+        self.callback_manager.add_handler(handler);
+        self
+    }
+
+    /// Set the callback manager, runs of the model become children of its parent run
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+
+    /// Get the callback manager
+    pub fn callback_manager(&self) -> &CallbackManager {
+        &self.callback_manager
+    }
+
+    /// List the models installed on the server
+    pub async fn list_models(&self) -> Result<Vec<LocalModelInfo>, Error> {
+        LocalModelProvider::ollama(Some(self.base_url.clone())).list_models().await
+    }
+
+    /// Detect the capabilities of the model, the result is kept for supports_tool_calling
+    pub async fn detect_capabilities(&self) -> Result<ModelCapabilities, Error> {
+        let model_name = self.model_name.clone().ok_or_else(|| Error::msg("No model name set"))?;
+        let capabilities = LocalModelProvider::ollama(Some(self.base_url.clone())).detect_capabilities(&model_name).await?;
+        *self.capabilities.write().unwrap() = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// Get the detected capabilities
+    pub fn capabilities(&self) -> Option<ModelCapabilities> {
+        self.capabilities.read().unwrap().clone()
+    }
+
+    /// Whether tool definitions are sent, unknown models are tried and fall back when the server refuses tools
+    fn tools_enabled(&self) -> bool {
+        self.tool_calling.unwrap_or_else(|| self.capabilities().is_none_or(|c| c.tool_calling))
+    }
+
+    /// Remember that the model does not support tools
+    fn disable_tool_calling(&self) {
+        let mut capabilities = self.capabilities.write().unwrap();
+        capabilities.get_or_insert_with(ModelCapabilities::default).tool_calling = false;
+    }
+
+    /// Convert chat messages to the /api/chat message format
+    /// Tool messages name the tool instead of the call id, the name is looked up in the preceding tool calls.
+    fn convert_messages(messages: Vec<ChatMessage>) -> Vec<Value> {
+        let mut tool_names: HashMap<String, String> = HashMap::new();
+        messages.into_iter().map(|message| match message {
+            ChatMessage::System(content) => json!({"role": "system", "content": content.content}),
+            ChatMessage::Human(content) => json!({"role": "user", "content": content.content}),
+            ChatMessage::AIMessage(content) => {
+                let tool_calls = content.tool_calls();
+                let mut message = json!({"role": "assistant", "content": content.content});
+                if !tool_calls.is_empty() {
+                    message["tool_calls"] = tool_calls.into_iter().map(|tool_call| {
+                        tool_names.insert(tool_call.id.clone(), tool_call.name.clone());
+                        let arguments: Value = serde_json::from_str(&tool_call.arguments).unwrap_or_else(|_| json!({}));
+                        json!({"function": {"name": tool_call.name, "arguments": arguments}})
+                    }).collect();
+                }
+                message
+            },
+            ChatMessage::ToolMessage(content) => {
+                let mut message = json!({"role": "tool", "content": content.content});
+                let tool_name = content.name.clone().or_else(|| {
+                    content.additional_kwargs.get("tool_call_id")
+                        .and_then(|id| id.as_str())
+                        .and_then(|id| tool_names.get(id).cloned())
+                });
+                if let Some(tool_name) = tool_name {
+                    message["tool_name"] = json!(tool_name);
+                }
+                message
+            },
+        }).collect()
+    }
+
+    /// Build the chat request body
+    fn build_request_body(&self, messages: Vec<ChatMessage>, tools: &[ToolDefinition], stream: bool) -> Value {
+        let mut request_body = json!({
+            "model": self.model_name.clone().unwrap_or_default(),
+            "messages": Self::convert_messages(messages),
+            "stream": stream,
+        });
+
+        let mut options = serde_json::Map::new();
+        if let Some(temp) = self.temperature {
+            options.insert("temperature".to_string(), json!(temp));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            options.insert("num_predict".to_string(), json!(max_tokens));
+        }
+        if let Some(context_length) = self.context_length {
+            options.insert("num_ctx".to_string(), json!(context_length));
+        }
+        for (key, value) in &self.additional_options {
+            options.insert(key.clone(), value.clone());
+        }
+        if !options.is_empty() {
+            request_body["options"] = Value::Object(options);
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            request_body["keep_alive"] = json!(keep_alive);
+        }
+
+        if !tools.is_empty() {
+            let tools_json: Vec<Value> = tools.iter().map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    }
+                })
+            }).collect();
+            request_body["tools"] = Value::Array(tools_json);
+        }
+
+        request_body
+    }
+
+    /// Send a chat request, when the model refuses tools it is marked as such and the request is sent again without them
+    async fn send_request(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>, stream: bool) -> Result<reqwest::Response, Error> {
+        let api_url = format!("{}/api/chat", self.base_url);
+        let tools = if self.tools_enabled() { tools } else { Vec::new() };
+
+        let request_body = self.build_request_body(messages.clone(), &tools, stream);
+        let response = self.client.post(&api_url).json(&request_body).send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let error = ModelApiError::from_response(response).await;
+        // e.g. {"error":"registry.ollama.ai/library/gemma3:4b does not support tools"}
+        if !tools.is_empty() && error.message.contains("does not support tools") {
+            log::warn!("Model {} does not support tools, falling back to the prompt-based protocol", request_body["model"]);
+            self.disable_tool_calling();
+            let request_body = self.build_request_body(messages, &[], stream);
+            return check_response(self.client.post(&api_url).json(&request_body).send().await?).await;
+        }
+        Err(Error::new(error))
+    }
+
+    /// Send a chat request, reporting the LLM run to the callback manager
+    async fn complete(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_completion(messages, tools).await {
+            Ok(completion) => {
+                run_manager.on_llm_end(&completion.model_name, completion.usage.as_ref());
+                Ok(completion)
+            },
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a chat request and convert the response
+    async fn request_completion(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let response: Value = self.send_request(messages, tools, false).await?.json().await?;
+        if let Some(error) = response["error"].as_str() {
+            return Err(Error::msg(format!("API request failed: {}", error)));
+        }
+        let chunk = OllamaStreamState::default().chunk(&response);
+        let mut completion = chunk.into_completion();
+        if let (ChatMessage::AIMessage(content), Some(done_reason)) = (&mut completion.message, response["done_reason"].as_str()) {
+            content.additional_kwargs.insert("done_reason".to_string(), json!(done_reason));
+        }
+        Ok(completion)
+    }
+
+    /// Send a streaming chat request, reporting the LLM run to the callback manager
+    async fn complete_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_stream(messages, tools).await {
+            Ok(stream) => Ok(stream_with_run_manager(stream, run_manager, model_name)),
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a streaming chat request, the response is one JSON object per line
+    async fn request_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let response = self.send_request(messages, tools, true).await?;
+        let stream = json_lines(response.bytes_stream())
+            .scan(OllamaStreamState::default(), |state, line| {
+                let chunk = line.and_then(|line| {
+                    let data: Value = serde_json::from_str(&line)
+                        .map_err(|e| Error::msg(format!("Failed to parse stream line: {} - {}", e, line)))?;
+                    match data["error"].as_str() {
+                        Some(error) => Err(Error::msg(format!("Stream error: {}", error))),
+                        None => Ok(state.chunk(&data)),
+                    }
+                });
+                futures::future::ready(Some(chunk))
+            });
+        Ok(Box::pin(stream))
+    }
+}
+
+// State carried between the lines of a streamed response
+#[derive(Default)]
+struct OllamaStreamState {
+    // Number of tool calls seen so far, tool calls arrive complete and need distinct indexes
+    tool_calls: usize,
+}
+
+impl OllamaStreamState {
+    // Convert a response object (a whole response or one streamed line) into a chunk
+    fn chunk(&mut self, data: &Value) -> ChatCompletionChunk {
+        let message = &data["message"];
+        let mut chunk = ChatCompletionChunk {
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            model_name: data["model"].as_str().map(|s| s.to_string()),
+            ..Default::default()
+        };
+
+        // Ollama does not always return call ids, generated ids are unique across the turns of a conversation
+        for tool_call in message["tool_calls"].as_array().into_iter().flatten() {
+            let index = self.tool_calls;
+            self.tool_calls += 1;
+            let function = &tool_call["function"];
+            let arguments = match &function["arguments"] {
+                Value::String(s) => s.clone(),
+                Value::Null => "{}".to_string(),
+                other => other.to_string(),
+            };
+            chunk.tool_call_chunks.push(ToolCallChunk {
+                index,
+                id: Some(tool_call["id"].as_str().map(|s| s.to_string()).unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()))),
+                name: function["name"].as_str().map(|s| s.to_string()),
+                arguments,
+            });
+        }
+
+        if data["done"].as_bool().unwrap_or(false) {
+            chunk.finish_reason = data["done_reason"].as_str().map(|s| s.to_string());
+            let prompt_tokens = data["prompt_eval_count"].as_u64().unwrap_or(0) as usize;
+            let completion_tokens = data["eval_count"].as_u64().unwrap_or(0) as usize;
+            chunk.usage = Some(TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            });
+        }
+        chunk
+    }
+}
+
+/// Decode a byte stream into lines, empty lines are skipped
+fn json_lines<S, B, E>(bytes: S) -> impl Stream<Item = Result<String, Error>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send + Unpin + 'static,
+    B: AsRef<[u8]>,
+    E: Into<Error>,
+{
+    let state = (bytes, Vec::new(), VecDeque::new(), false);
+    futures::stream::unfold(state, |(mut bytes, mut buffer, mut lines, mut done)| async move {
+        loop {
+            if let Some(line) = lines.pop_front() {
+                return Some((Ok(line), (bytes, buffer, lines, done)));
+            }
+            if done {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(chunk.as_ref());
+                    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..pos + 1).collect();
+                        let line = String::from_utf8_lossy(&line).trim().to_string();
+                        if !line.is_empty() {
+                            lines.push_back(line);
+                        }
+                    }
+                },
+                Some(Err(e)) => {
+                    return Some((Err(e.into()), (bytes, buffer, lines, true)));
+                },
+                None => {
+                    // Flush the last line if the stream ended without a new line
+                    done = true;
+                    let rest = String::from_utf8_lossy(&buffer).trim().to_string();
+                    buffer.clear();
+                    if !rest.is_empty() {
+                        lines.push_back(rest);
+                    }
+                },
+            }
+        }
+    })
+}
+
+impl ChatModel for OllamaChatModel {
+    fn model_name(&self) -> Option<&str> {
+        self.model_name.as_deref()
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url.to_string()
+    }
+
+    // Unknown models use the prompt-based JSON protocol until detect_capabilities reports tool support
+    fn supports_tool_calling(&self) -> bool {
+        self.tool_calling.unwrap_or_else(|| self.capabilities().is_some_and(|c| c.tool_calling))
+    }
+
+    fn identifying_params(&self) -> Value {
+        json!({
+            "model_name": self.model_name,
+            "base_url": self.base_url,
+            "temperature": self.temperature,
+            "max_tokens": self.max_tokens,
+            "context_length": self.context_length,
+            "additional_options": self.additional_options,
+        })
+    }
+
+    // Runs are reported to the callbacks of the caller, the handlers attached to the model are kept
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        Some(Arc::new(self.clone().with_callback_manager(callbacks.merge(&self.callback_manager))))
+    }
+
+    fn invoke(&self, messages: Vec<ChatMessage>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, Vec::new()))
+    }
+
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, tools))
+    }
+
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(self.complete_stream(messages, tools))
+    }
+}
+
+// Implement Runnable so that the model can be composed with prompts, astream yields incremental deltas
+impl Runnable<Vec<ChatMessage>, ChatCompletion> for OllamaChatModel {
+    fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            model.complete(input, Vec::new()).await
+        })
+    }
+
+    fn astream(
+        &self,
+        input: Vec<ChatMessage>,
+    ) -> Pin<Box<dyn std::future::Future<Output = Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            let stream: Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send> = match model.complete_stream(input, Vec::new()).await {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(ChatCompletionChunk::into_completion))),
+                Err(e) => Box::new(futures::stream::once(async move { Err(e) })),
+            };
+            stream
+        })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, ChatCompletion> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::{ChatMessageContent, ToolCall};
+    use axum::{http::StatusCode, response::IntoResponse, routing::{get, post}, Json, Router};
+    use std::sync::Mutex;
+
+    fn message(content: &str, kwargs: HashMap<String, Value>) -> ChatMessageContent {
+        ChatMessageContent {
+            content: content.to_string(),
+            name: None,
+            additional_kwargs: kwargs,
+        }
+    }
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_local_model_listing_and_capabilities() {
+        let app = Router::new()
+            .route("/api/tags", get(|| async {
+                Json(json!({"models": [
+                    {"name": "qwen3:8b", "size": 5200000000u64, "details": {"family": "qwen3", "parameter_size": "8.2B", "quantization_level": "Q4_K_M"}},
+                    {"name": "gemma:2b", "size": 1700000000u64}
+                ]}))
+            }))
+            .route("/api/show", post(|Json(body): Json<Value>| async move {
+                match body["model"].as_str() {
+                    Some("qwen3:8b") => Json(json!({"capabilities": ["completion", "tools"], "model_info": {"qwen3.context_length": 40960}})),
+                    // Servers older than 0.6.4 only return the template
+                    _ => Json(json!({"template": "{{ .System }}{{ .Prompt }}"})),
+                }
+            }))
+            .route("/v1/models", get(|| async { Json(json!({"object": "list", "data": [{"id": "Llama-3.2-3B-Q4_K_M.gguf"}]})) }))
+            .route("/props", get(|| async {
+                Json(json!({"default_generation_settings": {"n_ctx": 8192}, "chat_template_caps": {"supports_tools": true, "supports_tool_calls": true}}))
+            }));
+        let base_url = serve(app).await;
+
+        let ollama = LocalModelProvider::ollama(Some(base_url.clone()));
+        let models = ollama.list_models().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].parameter_size.as_deref(), Some("8.2B"));
+        assert_eq!(models[1].family, None);
+
+        let capabilities = ollama.detect_capabilities("qwen3:8b").await.unwrap();
+        assert_eq!(capabilities, ModelCapabilities { tool_calling: true, vision: false, context_length: Some(40960) });
+        assert!(!ollama.detect_capabilities("gemma:2b").await.unwrap().tool_calling);
+
+        // The agent model falls back to the JSON protocol for models without tools
+        let model = ollama.chat_model("gemma:2b").await.unwrap();
+        assert_eq!(model.base_url(), &format!("{}/v1", base_url));
+        assert!(!ChatModel::supports_tool_calling(&model));
+        assert!(ChatModel::supports_tool_calling(&ollama.chat_model("qwen3:8b").await.unwrap()));
+
+        let llama_cpp = LocalModelProvider::llama_cpp(Some(format!("{}/", base_url)));
+        assert_eq!(llama_cpp.list_models().await.unwrap()[0].name, "Llama-3.2-3B-Q4_K_M.gguf");
+        let capabilities = llama_cpp.detect_capabilities("Llama-3.2-3B-Q4_K_M.gguf").await.unwrap();
+        assert!(capabilities.tool_calling);
+        assert_eq!(capabilities.context_length, Some(8192));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_chat_falls_back_without_tools() {
+        let requests: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = Router::new().route("/api/chat", post(move |Json(body): Json<Value>| {
+            let recorded = recorded.clone();
+            async move {
+                recorded.lock().unwrap().push(body.clone());
+                if body.get("tools").is_some() {
+                    return (StatusCode::BAD_REQUEST, Json(json!({"error": "registry.ollama.ai/library/gemma:2b does not support tools"}))).into_response();
+                }
+                Json(json!({
+                    "model": "gemma:2b",
+                    "message": {"role": "assistant", "content": "{\"content\": \"Hello\"}"},
+                    "done": true,
+                    "done_reason": "stop",
+                    "prompt_eval_count": 12,
+                    "eval_count": 4
+                })).into_response()
+            }
+        }));
+        let base_url = serve(app).await;
+
+        let model = OllamaChatModel::new(Some(base_url))
+            .with_model("gemma:2b".to_string())
+            .with_context_length(8192);
+        assert!(!model.supports_tool_calling());
+        let tools = vec![ToolDefinition::new("get_weather".to_string(), "Get the weather".to_string(), json!({"type": "object"}))];
+        let messages = vec![ChatMessage::Human(message("Hi", HashMap::new()))];
+        let completion = model.invoke_with_tools(messages.clone(), tools.clone()).await.unwrap();
+        assert_eq!(completion.usage.unwrap().total_tokens, 16);
+        match completion.message {
+            ChatMessage::AIMessage(content) => {
+                assert_eq!(content.content, "{\"content\": \"Hello\"}");
+                assert_eq!(content.additional_kwargs["done_reason"], "stop");
+            },
+            _ => panic!("Expected AI message"),
+        }
+        assert_eq!(model.capabilities().map(|c| c.tool_calling), Some(false));
+
+        // Clones share the fallback, later requests do not send tools
+        model.clone().invoke_with_tools(messages, tools).await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["options"]["num_ctx"], 8192);
+        assert!(requests[1].get("tools").is_none() && requests[2].get("tools").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_streaming_tool_calls() {
+        let requests: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let lines = [
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": "Let me "}, "done": false}),
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": "check."}, "done": false}),
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": "", "tool_calls": [{"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}]}, "done": false}),
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "stop", "prompt_eval_count": 30, "eval_count": 9}),
+        ];
+        let body = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+        let app = Router::new().route("/api/chat", post(move |Json(request): Json<Value>| {
+            let body = body.clone();
+            let recorded = recorded.clone();
+            async move {
+                recorded.lock().unwrap().push(request);
+                ([("content-type", "application/x-ndjson")], body)
+            }
+        }));
+        let base_url = serve(app).await;
+
+        let model = OllamaChatModel::new(Some(base_url)).with_model("qwen3:8b".to_string()).with_tool_calling(true);
+        let tools = vec![ToolDefinition::new("get_weather".to_string(), "Get the weather".to_string(), json!({"type": "object"}))];
+
+        // The tool result names the tool of the preceding call
+        let mut ai_kwargs = HashMap::new();
+        ai_kwargs.insert("tool_calls".to_string(), json!([ToolCall { id: "call_0".to_string(), name: "get_time".to_string(), arguments: "{}".to_string() }.to_openai_value()]));
+        let mut tool_kwargs = HashMap::new();
+        tool_kwargs.insert("tool_call_id".to_string(), json!("call_0"));
+        let messages = vec![
+            ChatMessage::Human(message("Time and weather in Paris?", HashMap::new())),
+            ChatMessage::AIMessage(message("", ai_kwargs)),
+            ChatMessage::ToolMessage(message("12:00", tool_kwargs)),
+        ];
+        let mut stream = model.stream_with_tools(messages, tools).await.unwrap();
+        let mut accumulator = crate::models::ChatCompletionAccumulator::new();
+        while let Some(chunk) = stream.next().await {
+            accumulator.push(&chunk.unwrap());
+        }
+        assert_eq!(accumulator.content(), "Let me check.");
+        let completion = accumulator.finish();
+        assert_eq!(completion.usage.unwrap().total_tokens, 39);
+        let tool_calls = match completion.message {
+            ChatMessage::AIMessage(content) => content.tool_calls(),
+            _ => panic!("Expected AI message"),
+        };
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!((tool_calls[0].name.as_str(), tool_calls[0].arguments.as_str()), ("get_weather", "{\"city\":\"Paris\"}"));
+        // The generated id does not repeat the id of the previous turn
+        assert!(tool_calls[0].id.starts_with("call_") && tool_calls[0].id != "call_0", "{}", tool_calls[0].id);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["stream"], true);
+        assert_eq!(requests[0]["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(requests[0]["messages"][1]["tool_calls"][0], json!({"function": {"name": "get_time", "arguments": {}}}));
+        assert_eq!(requests[0]["messages"][2], json!({"role": "tool", "content": "12:00", "tool_name": "get_time"}));
+    }
+}
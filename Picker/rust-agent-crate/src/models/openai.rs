@@ -1,19 +1,29 @@
 // OpenAI model implementation - based on LangChain design
-use super::chat::{ChatCompletion, ChatModel};
+use super::chat::{messages_to_prompts, stream_with_run_manager, ChatCompletion, ChatCompletionChunk, ChatCompletionStream, ChatModel, ModelApiError, ToolCallChunk, ToolDefinition};
 use super::message::{ChatMessage, ChatMessageContent, TokenUsage};
+use super::sse::sse_events;
+use crate::callbacks::{CallbackHandler, CallbackManager};
+use crate::core::Runnable;
 use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::HashMap;
-use log::info;
+use std::pin::Pin;
+use std::sync::Arc;
+use log::debug;
 #[derive(Serialize, Deserialize, Clone)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    // Assistant messages carrying tool calls may have null content
+    #[serde(default)]
+    content: Option<String>,
     name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<serde_json::Value>>,
 }
 
 // Token usage details structure - referencing LangChain's InputTokenDetails and OutputTokenDetails
@@ -75,6 +85,42 @@ struct OpenAIChoice {
     finish_reason: String,
 }
 
+// Streaming response chunk structure
+#[derive(Deserialize)]
+struct OpenAIStreamResponse {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    #[serde(default)]
+    delta: OpenAIStreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenAIStreamDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCallDelta>>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAIFunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
 // API type enumeration - supporting traditional Chat Completions API and new Responses API
 #[derive(Debug, Clone, Copy)]
 enum OpenAIApiType {
@@ -94,6 +140,8 @@ pub struct OpenAIChatModel {
     api_type: OpenAIApiType,
     additional_headers: HashMap<String, String>,
     additional_params: HashMap<String, serde_json::Value>,
+    tool_calling: bool,
+    callback_manager: CallbackManager,
 }
 
 impl OpenAIChatModel {
@@ -109,6 +157,8 @@ impl OpenAIChatModel {
             api_type: OpenAIApiType::ChatCompletions,
             additional_headers: HashMap::new(),
             additional_params: HashMap::new(),
+            tool_calling: true,
+            callback_manager: CallbackManager::new(),
         }
     }
 
@@ -122,6 +172,11 @@ impl OpenAIChatModel {
         &self.base_url
     }
 
+    /// Get API key, shared with OpenAIEmbeddings::from_chat_model
+    pub(crate) fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
     /// Get temperature parameter
     pub fn temperature(&self) -> Option<f32> {
         self.temperature
@@ -168,6 +223,29 @@ impl OpenAIChatModel {
         self
     }
 
+    /// Enable or disable native tool calling (for OpenAI-compatible servers without tools support)
+    pub fn with_tool_calling(mut self, enabled: bool) -> Self {
+        self.tool_calling = enabled;
+        self
+    }
+
+    /// Add a callback handler, notified of LLM start, new tokens, end and errors
+    pub fn with_callback_handler(mut self, handler: Arc<dyn CallbackHandler>) -> Self {
+        self.callback_manager.add_handler(handler);
+        self
+    }
+
+    /// Set the callback manager, runs of the model become children of its parent run
+    pub fn with_callback_manager(mut self, callback_manager: CallbackManager) -> Self {
+        self.callback_manager = callback_manager;
+        self
+    }
+
+    /// Get the callback manager
+    pub fn callback_manager(&self) -> &CallbackManager {
+        &self.callback_manager
+    }
+
     /// Build request payload - referencing LangChain's _get_request_payload method
     fn _get_request_payload(&self, messages: &[OpenAIMessage]) -> Result<serde_json::Value, Error> {
         Ok(serde_json::json!({"messages": messages}))
@@ -221,6 +299,272 @@ impl OpenAIChatModel {
             _ => Ok(ChatMessage::AIMessage(chat_content)),
         }
     }
+
+    /// Convert chat messages to the OpenAI request message format
+    fn convert_messages(messages: Vec<ChatMessage>) -> Vec<OpenAIMessage> {
+        messages
+            .into_iter()
+            .map(|msg| match msg {
+                ChatMessage::System(content) => OpenAIMessage {
+                    role: "system".to_string(),
+                    content: Some(content.content),
+                    name: content.name,
+                    tool_call_id: None,
+                    tool_calls: None,
+                },
+                ChatMessage::Human(content) => OpenAIMessage {
+                    role: "user".to_string(),
+                    content: Some(content.content),
+                    name: content.name,
+                    tool_call_id: None,
+                    tool_calls: None,
+                },
+                ChatMessage::AIMessage(content) => {
+                    // Replay tool calls made by the model so that following tool messages can refer to them
+                    let tool_calls = match content.additional_kwargs.get("tool_calls") {
+                        Some(serde_json::Value::Array(calls)) if !calls.is_empty() => Some(calls.clone()),
+                        _ => None,
+                    };
+                    let message_content = if tool_calls.is_some() && content.content.is_empty() {
+                        None
+                    } else {
+                        Some(content.content)
+                    };
+                    OpenAIMessage {
+                        role: "assistant".to_string(),
+                        content: message_content,
+                        name: content.name,
+                        tool_call_id: None,
+                        tool_calls,
+                    }
+                },
+                ChatMessage::ToolMessage(content) => {
+                    debug!("Converting tool message: role=tool");
+                    // Add tool_call_id for tool messages, it must match the id of the originating tool call
+                    let tool_call_id = content.additional_kwargs.get("tool_call_id")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string());
+                    OpenAIMessage {
+                        role: "tool".to_string(),
+                        content: Some(content.content),
+                        name: content.name,
+                        tool_call_id,
+                        tool_calls: None,
+                    }
+                },
+            })
+            .collect()
+    }
+
+    /// Build the chat completions request body
+    fn build_request_body(&self, messages: Vec<ChatMessage>, tools: &[ToolDefinition]) -> serde_json::Value {
+        let openai_messages = Self::convert_messages(messages);
+
+        // Build request body
+        let mut request_body = serde_json::json!({
+            "messages": openai_messages,
+            "model": self.model_name.clone().unwrap_or("".to_string()),
+        });
+
+        // Add optional parameters
+        if let Some(temp) = self.temperature {
+            request_body["temperature"] = serde_json::json!(temp);
+        }
+        if let Some(max) = self.max_tokens {
+            request_body["max_tokens"] = serde_json::json!(max);
+        }
+
+        // Add tool definitions in the OpenAI function calling format
+        if self.tool_calling && !tools.is_empty() {
+            let tools_json: Vec<serde_json::Value> = tools.iter().map(|tool| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    }
+                })
+            }).collect();
+            request_body["tools"] = serde_json::Value::Array(tools_json);
+        }
+
+        // Add additional parameters
+        for (key, value) in &self.additional_params {
+            request_body[key] = value.clone();
+        }
+
+        request_body
+    }
+
+    /// Send a chat completions request and check the response status
+    async fn send_request(&self, request_body: &serde_json::Value) -> Result<reqwest::Response, Error> {
+        // Build complete API path, concatenating base_url with specific endpoint
+        let api_url = format!("{}/chat/completions", self.base_url);
+
+        // Build request
+        let mut request = self.client.post(&api_url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json");
+
+        // Add additional request headers
+        for (key, value) in &self.additional_headers {
+            request = request.header(key, value);
+        }
+
+        // Send request
+        let response = request.json(request_body).send().await?;
+
+        // Check response status
+        if !response.status().is_success() {
+            return Err(Error::new(ModelApiError::from_response(response).await));
+        }
+
+        Ok(response)
+    }
+
+    /// Send a chat completions request, reporting the LLM run to the callback manager
+    async fn complete(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_completion(messages, tools).await {
+            Ok(completion) => {
+                run_manager.on_llm_end(&completion.model_name, completion.usage.as_ref());
+                Ok(completion)
+            },
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a chat completions request and convert the response
+    async fn request_completion(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletion, Error> {
+        let request_body = self.build_request_body(messages, &tools);
+        let response = self.send_request(&request_body).await?;
+
+        // Parse response
+        let response: OpenAIResponse = response.json().await?;
+
+        // Handle response
+        let message = match response.choices.first() {
+            Some(choice) => {
+                if choice.message.role != "assistant" {
+                    return Err(Error::msg(format!("Unexpected message role: {}", choice.message.role)));
+                }
+                &choice.message
+            },
+            None => {
+                // Try to use output field (Responses API)
+                match response.output.as_ref().and_then(|outputs| outputs.first()) {
+                    Some(choice) => &choice.message,
+                    None => return Err(Error::msg("No choices or output returned from API")),
+                }
+            },
+        };
+
+        // Keep structured tool calls so that agents don't need to parse them out of the text
+        let mut additional_kwargs = HashMap::new();
+        if let Some(tool_calls) = &message.tool_calls {
+            if !tool_calls.is_empty() {
+                additional_kwargs.insert("tool_calls".to_string(), serde_json::Value::Array(tool_calls.clone()));
+            }
+        }
+
+        let chat_message = ChatMessage::AIMessage(ChatMessageContent {
+            content: message.content.clone().unwrap_or_default(),
+            name: message.name.clone(),
+            additional_kwargs,
+        });
+
+        // Convert usage statistics
+        let usage = response.usage.as_ref().map(|openai_usage| self._create_usage_metadata(openai_usage));
+
+        let model_name_str = response.model.as_deref().unwrap_or("unknown");
+        Ok(ChatCompletion {
+            message: chat_message,
+            usage,
+            model_name: model_name_str.to_string(),
+        })
+    }
+
+    /// Send a streaming chat completions request, reporting the LLM run to the callback manager
+    async fn complete_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let model_name = self.model_name.clone().unwrap_or_default();
+        let run_manager = self.callback_manager.on_llm_start(&model_name, &messages_to_prompts(&messages));
+        match self.request_stream(messages, tools).await {
+            Ok(stream) => Ok(stream_with_run_manager(stream, run_manager, model_name)),
+            Err(e) => {
+                run_manager.on_llm_error(&model_name, &e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// Send a streaming chat completions request and convert SSE events into chunks
+    async fn request_stream(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Result<ChatCompletionStream, Error> {
+        let mut request_body = self.build_request_body(messages, &tools);
+        request_body["stream"] = serde_json::json!(true);
+        // Without it the streamed chunks carry no usage, it comes in a last chunk with no choices
+        request_body["stream_options"] = serde_json::json!({"include_usage": true});
+        let response = self.send_request(&request_body).await?;
+
+        let stream = sse_events(response.bytes_stream()).filter_map(|event| {
+            async move {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => return Some(Err(e)),
+                };
+
+                // The stream is terminated by a [DONE] message
+                let data = event.data.trim();
+                if data.is_empty() || data == "[DONE]" {
+                    return None;
+                }
+
+                let response: OpenAIStreamResponse = match serde_json::from_str(data) {
+                    Ok(response) => response,
+                    Err(e) => return Some(Err(Error::msg(format!("Failed to parse stream chunk: {} - {}", e, data)))),
+                };
+
+                let mut chunk = ChatCompletionChunk {
+                    model_name: response.model,
+                    usage: response.usage.map(|usage| TokenUsage {
+                        prompt_tokens: usage.prompt_tokens,
+                        completion_tokens: usage.completion_tokens,
+                        total_tokens: usage.total_tokens,
+                    }),
+                    ..Default::default()
+                };
+
+                for choice in response.choices {
+                    if let Some(content) = choice.delta.content {
+                        chunk.content.push_str(&content);
+                    }
+                    for tool_call in choice.delta.tool_calls.unwrap_or_default() {
+                        let (name, arguments) = match tool_call.function {
+                            Some(function) => (function.name, function.arguments.unwrap_or_default()),
+                            None => (None, String::new()),
+                        };
+                        chunk.tool_call_chunks.push(ToolCallChunk {
+                            index: tool_call.index,
+                            id: tool_call.id,
+                            name,
+                            arguments,
+                        });
+                    }
+                    if choice.finish_reason.is_some() {
+                        chunk.finish_reason = choice.finish_reason;
+                    }
+                }
+
+                Some(Ok(chunk))
+            }
+        });
+
+        Ok(Box::pin(stream))
+    }
 }
 
 impl ChatModel for OpenAIChatModel {
@@ -232,155 +576,218 @@ impl ChatModel for OpenAIChatModel {
         self.base_url.to_string()
     }
 
+    fn supports_tool_calling(&self) -> bool {
+        self.tool_calling
+    }
+
+    fn identifying_params(&self) -> serde_json::Value {
+        serde_json::json!({
+            "model_name": self.model_name,
+            "base_url": self.base_url,
+            "temperature": self.temperature,
+            "max_tokens": self.max_tokens,
+            "additional_params": self.additional_params,
+        })
+    }
+
+    // Runs are reported to the callbacks of the caller, the handlers attached to the model are kept
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        Some(Arc::new(self.clone().with_callback_manager(callbacks.merge(&self.callback_manager))))
+    }
+
     fn invoke(&self, messages: Vec<ChatMessage>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
-        let messages = messages;
-        let client = self.client.clone();
-        let api_key = self.api_key.clone();
-        let base_url = self.base_url.clone();
-        let model_name = self.model_name.clone();
-        let temperature = self.temperature;
-        let max_tokens = self.max_tokens;
-        let additional_headers = self.additional_headers.clone();
-        let additional_params = self.additional_params.clone();
+        Box::pin(self.complete(messages, Vec::new()))
+    }
+
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.complete(messages, tools))
+    }
 
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(self.complete_stream(messages, tools))
+    }
+}
+
+// Implement Runnable so that the model can be composed with prompts, astream yields incremental deltas
+impl Runnable<Vec<ChatMessage>, ChatCompletion> for OpenAIChatModel {
+    fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<ChatCompletion, Error>> + Send>> {
+        let model = self.clone();
         Box::pin(async move {
-            // Convert message format
-            let openai_messages: Vec<OpenAIMessage> = messages
-                .into_iter()
-                .map(|msg| match msg {
-                    ChatMessage::System(content) => OpenAIMessage {
-                        role: "system".to_string(),
-                        content: content.content,
-                        name: content.name,
-                        tool_call_id: None,
-                    },
-                    ChatMessage::Human(content) => OpenAIMessage {
-                        role: "user".to_string(),
-                        content: content.content,
-                        name: content.name,
-                        tool_call_id: None,
-                    },
-                    ChatMessage::AIMessage(content) => OpenAIMessage {
-                        role: "assistant".to_string(),
-                        content: content.content,
-                        name: content.name,
-                        tool_call_id: None,
-                    },
-                    ChatMessage::ToolMessage(content) => {
-                        info!("Converting tool message: role=tool, content={}", content.content);
-                        // Add tool_call_id for tool messages
-                        let tool_call_id = content.additional_kwargs.get("tool_call_id")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("default_tool_call_id").to_string();
-                        OpenAIMessage {
-                            role: "tool".to_string(),
-                            content: content.content,
-                            name: content.name,
-                            tool_call_id: Some(tool_call_id),
-                        }
-                    },
-                })
-                .collect();
+            model.complete(input, Vec::new()).await
+        })
+    }
 
-            // Build request body
-            let mut request_body = serde_json::json!({
-                "messages": openai_messages,
-                "model": model_name.clone().unwrap_or("".to_string()),
-            });
+    fn astream(
+        &self,
+        input: Vec<ChatMessage>,
+    ) -> Pin<Box<dyn std::future::Future<Output = Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            let stream: Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send> = match model.complete_stream(input, Vec::new()).await {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(ChatCompletionChunk::into_completion))),
+                Err(e) => Box::new(futures::stream::once(async move { Err(e) })),
+            };
+            stream
+        })
+    }
 
-            // Add optional parameters
-            if let Some(temp) = temperature {
-                request_body["temperature"] = serde_json::json!(temp);
-            }
-            if let Some(max) = max_tokens {
-                request_body["max_tokens"] = serde_json::json!(max);
-            }
-            
-            // Add additional parameters
-            for (key, value) in additional_params {
-                request_body[key] = value;
-            }
+    fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, ChatCompletion> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
 
-            // Build complete API path, concatenating base_url with specific endpoint
-            let api_url = format!("{}/chat/completions", base_url);
-            
-            // Build request
-            let mut request = client.post(&api_url)
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json");
-
-            // Add additional request headers
-            for (key, value) in additional_headers {
-                request = request.header(key, value);
-            }
-            
-            // Send request
-            let response = request.json(&request_body).send().await?;
-            
-            // Check response status
-            let status = response.status();
-            if !status.is_success() {
-                let error_text = response.text().await?;
-                return Err(Error::msg(format!("API request failed: {} - {}", status, error_text)));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use std::sync::{Arc, Mutex};
+    use futures::stream::StreamExt;
+
+    // Start a mock chat completions endpoint that records request bodies and returns the given response
+    async fn start_mock_server(response: serde_json::Value) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = (requests.clone(), response);
+        let app = Router::new()
+            .route("/chat/completions", post(|State((requests, response)): State<(Arc<Mutex<Vec<serde_json::Value>>>, serde_json::Value)>, Json(body): Json<serde_json::Value>| async move {
+                requests.lock().unwrap().push(body);
+                Json(response)
+            }))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", address), requests)
+    }
 
-            // Parse response
-            let response: OpenAIResponse = response.json().await?;
-
-            // Handle response
-            let chat_message = match response.choices.first() {
-                Some(choice) => {
-                    let message = &choice.message;
-                    match message.role.as_str() {
-                        "assistant" => ChatMessage::AIMessage(ChatMessageContent {
-                            content: message.content.clone(),
-                            name: message.name.clone(),
-                            additional_kwargs: HashMap::new(),
-                        }),
-                        _ => {
-                            return Err(Error::msg(format!("Unexpected message role: {}", message.role)));
-                        }
-                    }
-                },
-                None => {
-                    // Try to use output field (Responses API)
-                    match &response.output {
-                        Some(outputs) => {
-                            match outputs.first() {
-                                Some(choice) => {
-                                    let message = &choice.message;
-                                    ChatMessage::AIMessage(ChatMessageContent {
-                                        content: message.content.clone(),
-                                        name: message.name.clone(),
-                                        additional_kwargs: HashMap::new(),
-                                    })
-                                },
-                                None => return Err(Error::msg("No output returned from API")),
-                            }
-                        },
-                        None => return Err(Error::msg("No choices or output returned from API")),
-                    }
-                },
-            };
+    #[tokio::test]
+    async fn test_native_tool_calling() {
+        let (base_url, requests) = start_mock_server(serde_json::json!({
+            "model": "mock-model",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "tool_calls",
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_abc",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Beijing\"}"}
+                    }]
+                }
+            }]
+        })).await;
+
+        let model = OpenAIChatModel::new("test-key".to_string(), Some(base_url)).with_model("mock-model".to_string());
+        let tools = vec![ToolDefinition::new(
+            "get_weather".to_string(),
+            "Get weather".to_string(),
+            serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+        )];
+        let messages = vec![ChatMessage::Human(ChatMessageContent {
+            content: "Weather in Beijing?".to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })];
 
-            // Convert usage statistics
-            let usage = match &response.usage {
-                Some(openai_usage) => {
-                    Some(TokenUsage {
-                        prompt_tokens: openai_usage.prompt_tokens,
-                        completion_tokens: openai_usage.completion_tokens,
-                        total_tokens: openai_usage.total_tokens,
-                    })
-                },
-                None => None,
-            };
+        let completion = model.invoke_with_tools(messages, tools).await.unwrap();
+        let content = match completion.message {
+            ChatMessage::AIMessage(content) => content,
+            _ => panic!("Expected AI message"),
+        };
+        let tool_calls = content.tool_calls();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "call_abc");
+        assert_eq!(tool_calls[0].name, "get_weather");
+        assert_eq!(tool_calls[0].arguments, "{\"city\":\"Beijing\"}");
+
+        // Send the tool result back and check it references the tool call
+        let mut tool_kwargs = HashMap::new();
+        tool_kwargs.insert("tool_call_id".to_string(), serde_json::json!("call_abc"));
+        let follow_up = vec![
+            ChatMessage::AIMessage(content.clone()),
+            ChatMessage::ToolMessage(ChatMessageContent {
+                content: "Sunny".to_string(),
+                name: None,
+                additional_kwargs: tool_kwargs,
+            }),
+        ];
+        ChatModel::invoke(&model, follow_up).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["tools"][0]["type"], "function");
+        assert_eq!(requests[0]["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(requests[0]["tools"][0]["function"]["parameters"]["properties"]["city"]["type"], "string");
+        assert!(requests[1].get("tools").is_none());
+        assert_eq!(requests[1]["messages"][0]["tool_calls"][0]["id"], "call_abc");
+        assert_eq!(requests[1]["messages"][1]["role"], "tool");
+        assert_eq!(requests[1]["messages"][1]["tool_call_id"], "call_abc");
+    }
 
-            let model_name_str = response.model.as_deref().unwrap_or("unknown");
-            Ok(ChatCompletion {
-                message: chat_message,
-                usage,
-                model_name: model_name_str.to_string(),
-            })
-        })
+    // Callback handler collecting streamed tokens
+    struct TokenCollector {
+        tokens: Mutex<Vec<String>>,
+    }
+
+    impl CallbackHandler for TokenCollector {
+        fn on_llm_new_token(&self, _run: &crate::callbacks::RunInfo, token: &str) {
+            self.tokens.lock().unwrap().push(token.to_string());
+        }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_streaming_chat_completion() {
+        let body = [
+            r#"{"model":"mock-model","choices":[{"index":0,"delta":{"role":"assistant","content":"Hel"},"finish_reason":null}]}"#,
+            r#"{"model":"mock-model","choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":null}]}"#,
+            r#"{"model":"mock-model","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":"{\"city\":"}}]},"finish_reason":null}]}"#,
+            r#"{"model":"mock-model","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]},"finish_reason":"tool_calls"}],"usage":null}"#,
+            r#"{"model":"mock-model","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":3,"total_tokens":8}}"#,
+            "[DONE]",
+        ].iter().map(|data| format!("data: {}\r\n\r\n", data)).collect::<String>();
+
+        let app = Router::new().route("/chat/completions", post(move |Json(request): Json<serde_json::Value>| {
+            let body = body.clone();
+            async move {
+                assert_eq!(request["stream"], true);
+                assert_eq!(request["stream_options"]["include_usage"], true);
+                ([("content-type", "text/event-stream")], body)
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let collector = Arc::new(TokenCollector { tokens: Mutex::new(Vec::new()) });
+        let model = OpenAIChatModel::new("test-key".to_string(), Some(format!("http://{}", address)))
+            .with_callback_handler(collector.clone());
+        let messages = vec![ChatMessage::Human(ChatMessageContent {
+            content: "Hi".to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })];
+
+        let mut stream = ChatModel::stream(&model, messages).await.unwrap();
+        let mut accumulator = crate::models::ChatCompletionAccumulator::new();
+        while let Some(chunk) = stream.next().await {
+            accumulator.push(&chunk.unwrap());
+        }
+        assert_eq!(accumulator.content(), "Hello");
+        assert_eq!(*collector.tokens.lock().unwrap(), vec!["Hel".to_string(), "lo".to_string()]);
+
+        let completion = accumulator.finish();
+        assert_eq!(completion.model_name, "mock-model");
+        assert_eq!(completion.usage.unwrap().total_tokens, 8);
+        let tool_calls = match completion.message {
+            ChatMessage::AIMessage(content) => content.tool_calls(),
+            _ => panic!("Expected AI message"),
+        };
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].arguments, "{\"city\":\"Paris\"}");
+    }
+}
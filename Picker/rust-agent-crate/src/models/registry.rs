@@ -0,0 +1,205 @@
+// Registry of model context windows and tokenizers
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::Result;
+use log::info;
+
+use super::tokenizer::{BpeEncoding, BpeTokenizer, HeuristicTokenizer, Tokenizer};
+
+/// Limits of a model family
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelProfile {
+    /// Maximum number of prompt and completion tokens
+    pub context_window: usize,
+    /// Maximum number of completion tokens, reserved when fitting prompts into the context window
+    pub max_output_tokens: usize,
+    /// BPE vocabulary of the model, None when the model uses its own tokenizer
+    pub encoding: Option<BpeEncoding>,
+}
+
+impl ModelProfile {
+    /// Create a profile without a known BPE vocabulary
+    pub fn new(context_window: usize, max_output_tokens: usize) -> Self {
+        Self { context_window, max_output_tokens, encoding: None }
+    }
+
+    /// Set the BPE vocabulary used by the model
+    pub fn with_encoding(mut self, encoding: BpeEncoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Tokens available for the prompt once the completion is reserved
+    pub fn prompt_budget(&self) -> usize {
+        self.context_window.saturating_sub(self.max_output_tokens)
+    }
+}
+
+/// Maps model names to context windows and tokenizers
+///
+/// Profiles are registered by name prefix and the longest matching prefix wins, so "gpt-4o-mini"
+/// uses the "gpt-4o" profile while "gpt-4" keeps its own. Provider prefixes such as "openai/" and
+/// Ollama tags such as ":8b" do not prevent a match. Models without a loaded vocabulary are counted
+/// with the HeuristicTokenizer.
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    // Name prefix and profile, sorted by descending prefix length
+    profiles: Vec<(String, ModelProfile)>,
+    tokenizers: HashMap<BpeEncoding, Arc<dyn Tokenizer>>,
+    default_tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        use BpeEncoding::{Cl100kBase, O200kBase};
+
+        let mut registry = Self::empty();
+        for (prefix, context_window, max_output_tokens, encoding) in [
+            ("gpt-3.5-turbo", 16_385, 4_096, Some(Cl100kBase)),
+            ("gpt-4", 8_192, 4_096, Some(Cl100kBase)),
+            ("gpt-4-32k", 32_768, 4_096, Some(Cl100kBase)),
+            ("gpt-4-turbo", 128_000, 4_096, Some(Cl100kBase)),
+            ("gpt-4o", 128_000, 16_384, Some(O200kBase)),
+            ("gpt-4.1", 1_047_576, 32_768, Some(O200kBase)),
+            ("gpt-5", 400_000, 128_000, Some(O200kBase)),
+            ("o1", 200_000, 100_000, Some(O200kBase)),
+            ("o1-mini", 128_000, 65_536, Some(O200kBase)),
+            ("o3", 200_000, 100_000, Some(O200kBase)),
+            ("o4-mini", 200_000, 100_000, Some(O200kBase)),
+            ("claude", 200_000, 8_192, None),
+            ("deepseek-chat", 128_000, 8_192, None),
+            ("deepseek-reasoner", 128_000, 32_768, None),
+            ("gemini-1.5", 1_048_576, 8_192, None),
+            ("gemini-2", 1_048_576, 8_192, None),
+            ("qwen", 32_768, 8_192, None),
+            ("llama3", 8_192, 2_048, None),
+            ("llama3.1", 131_072, 4_096, None),
+            ("llama3.2", 131_072, 4_096, None),
+            ("llama3.3", 131_072, 4_096, None),
+            ("mistral", 32_768, 4_096, None),
+            ("glm-4", 128_000, 4_096, None),
+        ] {
+            let profile = ModelProfile { context_window, max_output_tokens, encoding };
+            registry.register(prefix, profile);
+        }
+        registry
+    }
+}
+
+impl ModelRegistry {
+    /// Create a registry with the built-in profiles of common models
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry without any profile
+    pub fn empty() -> Self {
+        Self {
+            profiles: Vec::new(),
+            tokenizers: HashMap::new(),
+            default_tokenizer: Arc::new(HeuristicTokenizer),
+        }
+    }
+
+    /// Register a profile for models whose name starts with the prefix, replacing an existing one
+    pub fn register(&mut self, prefix: impl Into<String>, profile: ModelProfile) {
+        let prefix = prefix.into().to_lowercase();
+        self.profiles.retain(|(existing, _)| *existing != prefix);
+        self.profiles.push((prefix, profile));
+        self.profiles.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    }
+
+    /// Register a profile, see register
+    pub fn with_profile(mut self, prefix: impl Into<String>, profile: ModelProfile) -> Self {
+        self.register(prefix, profile);
+        self
+    }
+
+    /// Use the tokenizer for models of the encoding
+    pub fn with_tokenizer(mut self, encoding: BpeEncoding, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizers.insert(encoding, tokenizer);
+        self
+    }
+
+    /// Use the tokenizer for models without a loaded vocabulary
+    pub fn with_default_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.default_tokenizer = tokenizer;
+        self
+    }
+
+    /// Load `cl100k_base.tiktoken` and `o200k_base.tiktoken` from a directory, missing files are skipped
+    pub fn with_encodings_from_dir(mut self, dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        for encoding in [BpeEncoding::Cl100kBase, BpeEncoding::O200kBase] {
+            if !dir.join(encoding.file_name()).exists() {
+                continue;
+            }
+            let tokenizer = BpeTokenizer::from_dir(dir, encoding)?;
+            info!("Loaded {} vocabulary with {} tokens", encoding.name(), tokenizer.vocab_size());
+            self.tokenizers.insert(encoding, Arc::new(tokenizer));
+        }
+        Ok(self)
+    }
+
+    /// Profile of the model, None for unknown models
+    pub fn profile(&self, model_name: &str) -> Option<&ModelProfile> {
+        let name = Self::normalize(model_name);
+        self.profiles
+            .iter()
+            .find(|(prefix, _)| name.starts_with(prefix.as_str()))
+            .map(|(_, profile)| profile)
+    }
+
+    /// Context window of the model, None for unknown models
+    pub fn context_window(&self, model_name: &str) -> Option<usize> {
+        self.profile(model_name).map(|profile| profile.context_window)
+    }
+
+    /// Tokenizer of the model, the default tokenizer when its vocabulary is not loaded
+    pub fn tokenizer_for(&self, model_name: &str) -> Arc<dyn Tokenizer> {
+        self.profile(model_name)
+            .and_then(|profile| profile.encoding)
+            .and_then(|encoding| self.tokenizers.get(&encoding))
+            .cloned()
+            .unwrap_or_else(|| self.default_tokenizer.clone())
+    }
+
+    // "openai/gpt-4o" and "GPT-4o" both become "gpt-4o"
+    fn normalize(model_name: &str) -> String {
+        let name = model_name.rsplit('/').next().unwrap_or(model_name);
+        name.trim().to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let registry = ModelRegistry::new();
+        assert_eq!(registry.context_window("gpt-4"), Some(8_192));
+        assert_eq!(registry.context_window("gpt-4o-mini"), Some(128_000));
+        assert_eq!(registry.context_window("openai/GPT-4.1-nano"), Some(1_047_576));
+        assert_eq!(registry.context_window("llama3.1:8b"), Some(131_072));
+        assert_eq!(registry.context_window("unknown-model"), None);
+
+        let registry = registry.with_profile("my-model", ModelProfile::new(4_096, 1_024));
+        assert_eq!(registry.profile("my-model-v2").unwrap().prompt_budget(), 3_072);
+    }
+
+    #[test]
+    fn test_tokenizer_for_falls_back_to_default() {
+        let registry = ModelRegistry::new();
+        assert_eq!(registry.tokenizer_for("gpt-4o").name(), "heuristic");
+
+        let mut ranks: HashMap<Vec<u8>, u32> = (0..=255u8).map(|byte| (vec![byte], byte as u32)).collect();
+        ranks.insert(b"ab".to_vec(), 256);
+        let tokenizer = BpeTokenizer::from_ranks("o200k_base", ranks, BpeEncoding::O200kBase.pattern(), HashMap::new()).unwrap();
+        let registry = registry.with_tokenizer(BpeEncoding::O200kBase, Arc::new(tokenizer));
+        assert_eq!(registry.tokenizer_for("gpt-4o").name(), "o200k_base");
+        assert_eq!(registry.tokenizer_for("gpt-4").name(), "heuristic");
+        assert_eq!(registry.tokenizer_for("claude-sonnet-4").name(), "heuristic");
+    }
+}
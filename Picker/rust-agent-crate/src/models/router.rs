@@ -0,0 +1,632 @@
+// Chat model routing requests over several providers with retries, circuit breakers and fallbacks
+use super::chat::{ChatCompletion, ChatCompletionChunk, ChatCompletionStream, ChatModel, ModelApiError, ToolDefinition};
+use super::message::ChatMessage;
+use crate::callbacks::CallbackManager;
+use crate::core::Runnable;
+use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
+use log::warn;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// State of the circuit breaker of a route
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    // Requests are sent to the model
+    Closed,
+    // The model failed too often and is skipped until the reset timeout has passed
+    Open,
+    // The reset timeout has passed, the next request is a trial deciding whether the circuit closes again
+    HalfOpen,
+}
+
+// Health of a route
+#[derive(Clone, Debug)]
+pub struct RouteHealth {
+    pub name: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+// Mutable circuit breaker state of a route
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    // Set while the circuit is open or half-open
+    open_until: Option<Instant>,
+    // A half-open trial request is running
+    trial_in_flight: bool,
+}
+
+// Admission of a request by the circuit breaker
+// A half-open trial is released when the permit is dropped without a recorded result,
+// e.g. when the request future is cancelled, so that the circuit does not stay open for good
+struct BreakerPermit {
+    breaker: Arc<Mutex<BreakerState>>,
+    trial: bool,
+}
+
+impl Drop for BreakerPermit {
+    fn drop(&mut self) {
+        if self.trial {
+            self.breaker.lock().unwrap_or_else(|e| e.into_inner()).trial_in_flight = false;
+        }
+    }
+}
+
+// Model of the router
+#[derive(Clone)]
+struct Route {
+    name: String,
+    model: Arc<dyn ChatModel>,
+    // Only used when the request does not fit in the context window of the previous models
+    context_fallback: bool,
+    breaker: Arc<Mutex<BreakerState>>,
+}
+
+/// ChatModel over several models, tried in order
+/// Rate limits (429), timeouts and server errors are retried with exponential backoff and jitter,
+/// honoring Retry-After, before falling back to the next model. Each model has a circuit breaker:
+/// after `failure_threshold` failed requests in a row it is skipped for `reset_timeout`.
+/// Requests exceeding the context window go to the models added with `with_context_fallback`.
+/// Streams are routed when they are opened, errors after the first chunk are returned as is.
+#[derive(Clone)]
+pub struct RouterChatModel {
+    routes: Vec<Route>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    failure_threshold: u32,
+    reset_timeout: Duration,
+}
+
+impl Default for RouterChatModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RouterChatModel {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(20),
+            failure_threshold: 3,
+            reset_timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Add a model, models are tried in the order they were added
+    pub fn with_model(mut self, name: impl Into<String>, model: Arc<dyn ChatModel>) -> Self {
+        self.push_route(name.into(), model, false);
+        self
+    }
+
+    /// Add a larger context model, only used when a request exceeds the context window of the previous models
+    pub fn with_context_fallback(mut self, name: impl Into<String>, model: Arc<dyn ChatModel>) -> Self {
+        self.push_route(name.into(), model, true);
+        self
+    }
+
+    /// Retries of a model before falling back to the next one (default 2)
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Backoff before the first retry and upper bound of the backoff (default 500ms and 20s)
+    /// A Retry-After longer than the upper bound makes the router fall back instead of waiting.
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Failed requests in a row after which the circuit of a model opens (default 3)
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Time an open circuit waits before a trial request (default 30s)
+    pub fn with_reset_timeout(mut self, reset_timeout: Duration) -> Self {
+        self.reset_timeout = reset_timeout;
+        self
+    }
+
+    fn push_route(&mut self, name: String, model: Arc<dyn ChatModel>, context_fallback: bool) {
+        self.routes.push(Route {
+            name,
+            model,
+            context_fallback,
+            breaker: Arc::new(Mutex::new(BreakerState::default())),
+        });
+    }
+
+    /// Health of every model, in routing order
+    pub fn health(&self) -> Vec<RouteHealth> {
+        let now = Instant::now();
+        self.routes.iter().map(|route| {
+            let breaker = route.breaker.lock().unwrap_or_else(|e| e.into_inner());
+            let state = match breaker.open_until {
+                None => CircuitState::Closed,
+                Some(until) if now < until => CircuitState::Open,
+                Some(_) => CircuitState::HalfOpen,
+            };
+            RouteHealth {
+                name: route.name.clone(),
+                state,
+                consecutive_failures: breaker.consecutive_failures,
+                last_error: breaker.last_error.clone(),
+            }
+        }).collect()
+    }
+
+    // Whether a request may be sent to the route, open circuits let a single trial through after the reset timeout
+    fn try_acquire(&self, route: &Route) -> Option<BreakerPermit> {
+        let mut breaker = route.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        let trial = match breaker.open_until {
+            None => false,
+            Some(until) if Instant::now() < until => return None,
+            Some(_) if breaker.trial_in_flight => return None,
+            Some(_) => {
+                breaker.trial_in_flight = true;
+                true
+            },
+        };
+        Some(BreakerPermit { breaker: route.breaker.clone(), trial })
+    }
+
+    // The model answered, even if the answer is an error caused by the request
+    fn record_success(&self, route: &Route, mut permit: BreakerPermit) {
+        permit.trial = false;
+        let mut breaker = route.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures = 0;
+        breaker.open_until = None;
+        breaker.trial_in_flight = false;
+    }
+
+    fn record_failure(&self, route: &Route, mut permit: BreakerPermit, error: &Error) {
+        permit.trial = false;
+        let mut breaker = route.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures += 1;
+        breaker.last_error = Some(error.to_string());
+        if breaker.trial_in_flight || breaker.consecutive_failures >= self.failure_threshold {
+            warn!("Model '{}' is skipped for {:?}: {}", route.name, self.reset_timeout, error);
+            breaker.open_until = Some(Instant::now() + self.reset_timeout);
+            breaker.trial_in_flight = false;
+        }
+    }
+
+    // Delay before the next attempt, None when the error is not worth retrying on the same model
+    fn retry_delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_retries || !is_retryable(error) {
+            return None;
+        }
+        if let Some(retry_after) = error.downcast_ref::<ModelApiError>().and_then(|e| e.retry_after) {
+            // Waiting longer than the backoff bound is worse than trying the next model
+            return (retry_after <= self.max_backoff).then_some(retry_after);
+        }
+        // Exponential backoff with jitter in [delay / 2, delay]
+        let delay = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_backoff);
+        let jitter = std::collections::hash_map::RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        Some(delay.mul_f64(0.5 + jitter / 2.0))
+    }
+
+    /// Run a request on the routes in order, retrying and falling back as described on the type
+    async fn route<T, F, Fut>(&self, request: F) -> Result<T, Error>
+    where
+        F: Fn(Arc<dyn ChatModel>) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut failures = Vec::new();
+        let mut last_error = None;
+        let mut context_exceeded = false;
+
+        for route in &self.routes {
+            if route.context_fallback != context_exceeded {
+                continue;
+            }
+            let Some(permit) = self.try_acquire(route) else {
+                failures.push(format!("{}: circuit open", route.name));
+                continue;
+            };
+
+            let mut attempt = 0;
+            let error = loop {
+                let error = match request(route.model.clone()).await {
+                    Ok(output) => {
+                        self.record_success(route, permit);
+                        return Ok(output);
+                    },
+                    Err(error) => error,
+                };
+                match self.retry_delay(attempt, &error) {
+                    Some(delay) => {
+                        warn!("Model '{}' failed, retrying in {:?}: {}", route.name, delay, error);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    },
+                    None => break error,
+                }
+            };
+
+            if is_context_length_exceeded(&error) {
+                // The model works, the request is too large for it
+                self.record_success(route, permit);
+                context_exceeded = true;
+            } else if is_provider_failure(&error) {
+                self.record_failure(route, permit, &error);
+            } else {
+                self.record_success(route, permit);
+            }
+            warn!("Model '{}' failed, trying the next model: {}", route.name, error);
+            failures.push(format!("{}: {}", route.name, error));
+            last_error = Some(error);
+        }
+
+        let summary = format!("All models failed ({})", failures.join("; "));
+        Err(match last_error {
+            // Keep the last error so that callers can still downcast it
+            Some(error) => error.context(summary),
+            None => Error::msg(summary),
+        })
+    }
+}
+
+// Rate limits, server errors and connection problems
+fn is_retryable(error: &Error) -> bool {
+    if let Some(api_error) = error.downcast_ref::<ModelApiError>() {
+        return api_error.is_retryable();
+    }
+    error.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_timeout() || e.is_connect())
+}
+
+fn is_context_length_exceeded(error: &Error) -> bool {
+    error.downcast_ref::<ModelApiError>().is_some_and(|e| e.is_context_length_exceeded())
+}
+
+// Errors caused by the request itself (400, 404, 422...) do not count against the circuit breaker
+fn is_provider_failure(error: &Error) -> bool {
+    match error.downcast_ref::<ModelApiError>() {
+        Some(api_error) => api_error.is_retryable() || matches!(api_error.status, 401..=403),
+        None => true,
+    }
+}
+
+impl ChatModel for RouterChatModel {
+    // Name of the first model
+    fn model_name(&self) -> Option<&str> {
+        self.routes.first().and_then(|route| route.model.model_name())
+    }
+
+    fn base_url(&self) -> String {
+        self.routes.first().map(|route| route.model.base_url()).unwrap_or_default()
+    }
+
+    // Native tool calling is only used when every model supports it, the JSON protocol works with all of them
+    fn supports_tool_calling(&self) -> bool {
+        !self.routes.is_empty() && self.routes.iter().all(|route| route.model.supports_tool_calling())
+    }
+
+    // Parameters of every model, in routing order
+    fn identifying_params(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.routes.iter().map(|route| {
+            serde_json::json!({
+                "name": route.name,
+                "context_fallback": route.context_fallback,
+                "model": route.model.identifying_params(),
+            })
+        }).collect())
+    }
+
+    // Every route gets the callbacks, the circuit breakers stay shared with this router
+    fn with_callbacks(&self, callbacks: &CallbackManager) -> Option<Arc<dyn ChatModel>> {
+        let mut router = self.clone();
+        for route in &mut router.routes {
+            if let Some(model) = route.model.with_callbacks(callbacks) {
+                route.model = model;
+            }
+        }
+        Some(Arc::new(router))
+    }
+
+    fn invoke(&self, messages: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        self.invoke_with_tools(messages, Vec::new())
+    }
+
+    fn invoke_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+        Box::pin(self.route(move |model| {
+            let messages = messages.clone();
+            let tools = tools.clone();
+            async move { model.invoke_with_tools(messages, tools).await }
+        }))
+    }
+
+    fn stream_with_tools(&self, messages: Vec<ChatMessage>, tools: Vec<ToolDefinition>) -> Pin<Box<dyn Future<Output = Result<ChatCompletionStream, Error>> + Send + '_>> {
+        Box::pin(self.route(move |model| {
+            let messages = messages.clone();
+            let tools = tools.clone();
+            async move { model.stream_with_tools(messages, tools).await }
+        }))
+    }
+}
+
+// Implement Runnable so that the router can be composed with prompts, astream yields incremental deltas
+impl Runnable<Vec<ChatMessage>, ChatCompletion> for RouterChatModel {
+    fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            ChatModel::invoke(&model, input).await
+        })
+    }
+
+    fn astream(
+        &self,
+        input: Vec<ChatMessage>,
+    ) -> Pin<Box<dyn Future<Output = Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send>> + Send>> {
+        let model = self.clone();
+        Box::pin(async move {
+            let stream: Box<dyn Stream<Item = Result<ChatCompletion, Error>> + Send> = match ChatModel::stream(&model, input).await {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(ChatCompletionChunk::into_completion))),
+                Err(e) => Box::new(futures::stream::once(async move { Err(e) })),
+            };
+            stream
+        })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, ChatCompletion> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChatMessageContent, OpenAIChatModel};
+    use axum::{http::StatusCode, response::IntoResponse, routing::post, Json, Router};
+    use serde_json::json;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Model answering with scripted results, then with its name
+    struct MockModel {
+        name: String,
+        errors: Mutex<VecDeque<ModelApiError>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockModel {
+        fn new(name: &str, errors: Vec<(u16, &str)>) -> Arc<Self> {
+            Arc::new(Self {
+                name: name.to_string(),
+                errors: Mutex::new(errors.into_iter().map(|(status, message)| ModelApiError {
+                    status,
+                    message: message.to_string(),
+                    retry_after: None,
+                }).collect()),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl ChatModel for MockModel {
+        fn invoke(&self, _messages: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+            Box::pin(async move {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                if let Some(error) = self.errors.lock().unwrap().pop_front() {
+                    return Err(Error::new(error));
+                }
+                Ok(ChatCompletion {
+                    message: ChatMessage::AIMessage(ChatMessageContent {
+                        content: self.name.clone(),
+                        name: None,
+                        additional_kwargs: HashMap::new(),
+                    }),
+                    usage: None,
+                    model_name: self.name.clone(),
+                })
+            })
+        }
+    }
+
+    fn hello() -> Vec<ChatMessage> {
+        vec![ChatMessage::Human(ChatMessageContent {
+            content: "Hello".to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        })]
+    }
+
+    #[tokio::test]
+    async fn test_router_retries_falls_back_and_opens_circuit() {
+        let primary = MockModel::new("primary", vec![(429, "rate limited"), (503, "overloaded"), (500, "down"), (403, "forbidden")]);
+        let secondary = MockModel::new("secondary", vec![(401, "invalid api key")]);
+        let tertiary = MockModel::new("tertiary", vec![]);
+        let router = RouterChatModel::new()
+            .with_model("primary", primary.clone())
+            .with_model("secondary", secondary.clone())
+            .with_model("tertiary", tertiary.clone())
+            .with_max_retries(2)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(5))
+            .with_failure_threshold(1)
+            .with_reset_timeout(Duration::from_millis(50));
+
+        // 429 and 503 are retried, the third failure falls back, 401 is not retried
+        let completion = ChatModel::invoke(&router, hello()).await.unwrap();
+        assert_eq!(completion.model_name, "tertiary");
+        assert_eq!((primary.calls(), secondary.calls(), tertiary.calls()), (3, 1, 1));
+        let health = router.health();
+        assert_eq!(health[0].state, CircuitState::Open);
+        assert_eq!(health[1].last_error.as_deref(), Some("API request failed: 401 Unauthorized - invalid api key"));
+        assert_eq!(health[2].state, CircuitState::Closed);
+
+        // Open circuits are skipped
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "tertiary");
+        assert_eq!((primary.calls(), secondary.calls()), (3, 1));
+
+        // After the reset timeout a failed trial opens the circuit again, a successful one closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(router.health()[0].state, CircuitState::HalfOpen);
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "secondary");
+        assert_eq!(primary.calls(), 4);
+        assert_eq!(router.health()[0].state, CircuitState::Open);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "primary");
+        assert_eq!(router.health()[0].state, CircuitState::Closed);
+    }
+
+    // Model failing once, then hanging on the next request and answering the ones after
+    struct HangingModel {
+        calls: AtomicUsize,
+    }
+
+    impl ChatModel for HangingModel {
+        fn invoke(&self, _messages: Vec<ChatMessage>) -> Pin<Box<dyn Future<Output = Result<ChatCompletion, Error>> + Send + '_>> {
+            Box::pin(async move {
+                match self.calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Error::new(ModelApiError { status: 500, message: "down".to_string(), retry_after: None })),
+                    1 => std::future::pending().await,
+                    _ => MockModel::new("hanging", vec![]).invoke(hello()).await,
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_cancelled_trial_releases_circuit() {
+        let primary = Arc::new(HangingModel { calls: AtomicUsize::new(0) });
+        let router = RouterChatModel::new()
+            .with_model("primary", primary.clone())
+            .with_model("backup", MockModel::new("backup", vec![]))
+            .with_max_retries(0)
+            .with_failure_threshold(1)
+            .with_reset_timeout(Duration::from_millis(20));
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "backup");
+        assert_eq!(router.health()[0].state, CircuitState::Open);
+
+        // The half-open trial is dropped before the model answers
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(tokio::time::timeout(Duration::from_millis(20), ChatModel::invoke(&router, hello())).await.is_err());
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+
+        // The next request is a new trial instead of being skipped
+        assert_eq!(router.health()[0].state, CircuitState::HalfOpen);
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "hanging");
+        assert_eq!(router.health()[0].state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_router_context_length_failover() {
+        let small = MockModel::new("small", vec![(400, "{\"error\": {\"code\": \"context_length_exceeded\"}}")]);
+        let backup = MockModel::new("backup", vec![]);
+        let large = MockModel::new("large", vec![(413, "prompt is too long: 250000 tokens > 200000 maximum")]);
+        let router = RouterChatModel::new()
+            .with_model("small", small.clone())
+            .with_context_fallback("large", large.clone())
+            .with_model("backup", backup.clone());
+
+        // Models of the same chain are skipped, the request goes to the larger context model
+        let Err(error) = ChatModel::invoke(&router, hello()).await else { panic!("Expected an error") };
+        assert_eq!((small.calls(), large.calls(), backup.calls()), (1, 1, 0));
+        assert!(error.downcast_ref::<ModelApiError>().is_some_and(|e| e.is_context_length_exceeded()));
+        assert!(error.to_string().starts_with("All models failed (small: API request failed: 400 Bad Request"));
+        // Context errors do not count against the circuit breaker
+        assert!(router.health().iter().all(|health| health.consecutive_failures == 0));
+
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "small");
+    }
+
+    #[tokio::test]
+    async fn test_router_honors_retry_after() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route("/chat/completions", post(move |Json(_body): Json<serde_json::Value>| {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "1")], "slow down").into_response();
+                }
+                Json(json!({
+                    "model": "gpt-mock",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]
+                })).into_response()
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let model = OpenAIChatModel::new("key".to_string(), Some(format!("http://{}", address)));
+        let Err(error) = ChatModel::invoke(&model, hello()).await else { panic!("Expected an error") };
+        let api_error = error.downcast_ref::<ModelApiError>().unwrap();
+        assert_eq!((api_error.status, api_error.retry_after), (429, Some(Duration::from_secs(1))));
+        assert!(api_error.is_retryable());
+
+        // Retry-After takes precedence over the backoff
+        calls.store(0, Ordering::SeqCst);
+        let router = RouterChatModel::new()
+            .with_model("openai", Arc::new(model))
+            .with_backoff(Duration::from_millis(1), Duration::from_secs(2));
+        let started = Instant::now();
+        assert_eq!(ChatModel::invoke(&router, hello()).await.unwrap().model_name, "gpt-mock");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    // Records the parent run of every model run
+    #[derive(Default)]
+    struct LlmRunRecorder {
+        parents: Mutex<Vec<Option<uuid::Uuid>>>,
+    }
+
+    impl crate::callbacks::CallbackHandler for LlmRunRecorder {
+        fn on_llm_start(&self, run: &crate::callbacks::RunInfo, _model_name: &str, _prompts: &[String]) {
+            self.parents.lock().unwrap().push(run.parent_run_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_reach_wrapped_models() {
+        let app = Router::new().route("/chat/completions", post(|Json(_body): Json<serde_json::Value>| async {
+            Json(json!({
+                "model": "gpt-mock",
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]
+            }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        // Handlers attached to the model and the callbacks of the caller both see the run of the routed model
+        let model_recorder = Arc::new(LlmRunRecorder::default());
+        let model = OpenAIChatModel::new("key".to_string(), Some(format!("http://{}", address)))
+            .with_callback_handler(model_recorder.clone());
+        let router = RouterChatModel::new().with_model("openai", Arc::new(model));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(crate::models::DiskResponseCache::new(dir.path()));
+        let cached = crate::models::CachedChatModel::new(Arc::new(router), cache);
+
+        let recorder = Arc::new(LlmRunRecorder::default());
+        let agent_run = CallbackManager::new().with_handler(recorder.clone()).on_chain_start("agent");
+        let model = cached.with_callbacks(&agent_run.get_child()).unwrap();
+        assert_eq!(model.invoke(hello()).await.unwrap().model_name, "gpt-mock");
+        assert_eq!(*recorder.parents.lock().unwrap(), vec![Some(agent_run.run_id())]);
+        assert_eq!(model_recorder.parents.lock().unwrap().len(), 1);
+    }
+}
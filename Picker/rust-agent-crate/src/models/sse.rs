@@ -0,0 +1,90 @@
+// Server-Sent Events decoding for streaming HTTP responses
+use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
+use std::collections::VecDeque;
+
+// Single Server-Sent Event
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+// Parse one event block (lines between blank lines)
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent::default();
+    let mut data_lines = Vec::new();
+
+    for line in block.lines() {
+        // Lines starting with a colon are comments (keep-alive)
+        if line.is_empty() || line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => event.event = Some(value.to_string()),
+            "data" => data_lines.push(value.to_string()),
+            "id" => event.id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    if data_lines.is_empty() && event.event.is_none() {
+        return None;
+    }
+    event.data = data_lines.join("\n");
+    Some(event)
+}
+
+// Move complete event blocks out of the buffer
+fn drain_events(buffer: &mut Vec<u8>, events: &mut VecDeque<SseEvent>) {
+    while let Some(pos) = buffer.windows(2).position(|w| w == b"\n\n") {
+        let block: Vec<u8> = buffer.drain(..pos + 2).collect();
+        if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+            events.push_back(event);
+        }
+    }
+}
+
+/// Decode a byte stream into Server-Sent Events
+pub(crate) fn sse_events<S, B, E>(bytes: S) -> impl Stream<Item = Result<SseEvent, Error>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send + Unpin + 'static,
+    B: AsRef<[u8]>,
+    E: Into<Error>,
+{
+    let state = (bytes, Vec::new(), VecDeque::new(), false);
+    futures::stream::unfold(state, |(mut bytes, mut buffer, mut events, mut done)| async move {
+        loop {
+            if let Some(event) = events.pop_front() {
+                return Some((Ok(event), (bytes, buffer, events, done)));
+            }
+            if done {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    // Normalize CRLF line endings so that events are always separated by "\n\n"
+                    buffer.extend(chunk.as_ref().iter().filter(|b| **b != b'\r'));
+                    drain_events(&mut buffer, &mut events);
+                },
+                Some(Err(e)) => {
+                    return Some((Err(e.into()), (bytes, buffer, events, true)));
+                },
+                None => {
+                    // Flush the last event if the stream ended without a blank line
+                    done = true;
+                    let rest = String::from_utf8_lossy(&buffer).to_string();
+                    buffer.clear();
+                    if let Some(event) = parse_event(&rest) {
+                        events.push_back(event);
+                    }
+                },
+            }
+        }
+    })
+}
@@ -0,0 +1,388 @@
+// Tokenizers used to count tokens against model context windows
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use anyhow::{anyhow, Context, Error, Result};
+use base64::Engine;
+use fancy_regex::Regex;
+
+use super::message::ChatMessage;
+use crate::memory::utils::estimate_text_tokens;
+
+// Pre-tokenizer of cl100k_base (gpt-4, gpt-3.5-turbo, text-embedding-3)
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+// Pre-tokenizer of o200k_base (gpt-4o, gpt-4.1, o-series)
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+// Tokens added by the chat format for every message, and once to prime the reply
+const TOKENS_PER_MESSAGE: usize = 3;
+const TOKENS_PER_NAME: usize = 1;
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Counts tokens of texts and chat messages
+pub trait Tokenizer: Send + Sync + fmt::Debug {
+    // Name of the tokenizer, e.g. "cl100k_base"
+    fn name(&self) -> &str;
+
+    // Number of tokens of the text
+    fn count_tokens(&self, text: &str) -> usize;
+
+    // Number of prompt tokens of the messages, including the overhead of the chat format
+    fn count_message_tokens(&self, messages: &[ChatMessage]) -> usize {
+        let mut count = TOKENS_PER_REPLY;
+        for message in messages {
+            let (role, content) = match message {
+                ChatMessage::System(content) => ("system", content),
+                ChatMessage::Human(content) => ("user", content),
+                ChatMessage::AIMessage(content) => ("assistant", content),
+                ChatMessage::ToolMessage(content) => ("tool", content),
+            };
+            count += TOKENS_PER_MESSAGE + self.count_tokens(role) + self.count_tokens(&content.content);
+            if let Some(name) = &content.name {
+                count += TOKENS_PER_NAME + self.count_tokens(name);
+            }
+            for tool_call in content.tool_calls() {
+                count += self.count_tokens(&tool_call.name) + self.count_tokens(&tool_call.arguments);
+            }
+        }
+        count
+    }
+}
+
+/// Drop the oldest messages until they fit in max_tokens, like LangChain's trim_messages with the "last" strategy
+/// A leading system message is always kept and the remaining messages start with a human message.
+pub fn trim_messages(mut messages: Vec<ChatMessage>, max_tokens: usize, tokenizer: &dyn Tokenizer) -> Vec<ChatMessage> {
+    let mut total = tokenizer.count_message_tokens(&messages);
+    if total <= max_tokens {
+        return messages;
+    }
+    let overhead = tokenizer.count_message_tokens(&[]);
+    let start = usize::from(matches!(messages.first(), Some(ChatMessage::System(_))));
+    let mut end = start;
+    while end < messages.len() && (total > max_tokens || !matches!(messages[end], ChatMessage::Human(_))) {
+        total -= tokenizer.count_message_tokens(std::slice::from_ref(&messages[end])) - overhead;
+        end += 1;
+    }
+    messages.drain(start..end);
+    messages
+}
+
+/// Character based estimate (1 token per CJK character, 4 other characters per token)
+/// Used when no vocabulary is available for a model.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        // Any non-empty text is at least one token
+        match estimate_text_tokens(text) {
+            0 if !text.is_empty() => 1,
+            count => count,
+        }
+    }
+}
+
+/// Byte pair encodings published by OpenAI in the tiktoken format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BpeEncoding {
+    Cl100kBase,
+    O200kBase,
+}
+
+impl BpeEncoding {
+    /// Name of the encoding, e.g. "cl100k_base"
+    pub fn name(&self) -> &'static str {
+        match self {
+            BpeEncoding::Cl100kBase => "cl100k_base",
+            BpeEncoding::O200kBase => "o200k_base",
+        }
+    }
+
+    /// File name of the vocabulary, e.g. "cl100k_base.tiktoken"
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            BpeEncoding::Cl100kBase => "cl100k_base.tiktoken",
+            BpeEncoding::O200kBase => "o200k_base.tiktoken",
+        }
+    }
+
+    /// Regular expression splitting text into pieces before the merges
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            BpeEncoding::Cl100kBase => CL100K_PATTERN,
+            BpeEncoding::O200kBase => O200K_PATTERN,
+        }
+    }
+
+    /// Special tokens of the encoding, only used when decoding
+    pub fn special_tokens(&self) -> HashMap<String, u32> {
+        let tokens: &[(&str, u32)] = match self {
+            BpeEncoding::Cl100kBase => &[
+                ("<|endoftext|>", 100257),
+                ("<|fim_prefix|>", 100258),
+                ("<|fim_middle|>", 100259),
+                ("<|fim_suffix|>", 100260),
+                ("<|endofprompt|>", 100276),
+            ],
+            BpeEncoding::O200kBase => &[
+                ("<|endoftext|>", 199999),
+                ("<|endofprompt|>", 200018),
+            ],
+        };
+        tokens.iter().map(|(token, rank)| (token.to_string(), *rank)).collect()
+    }
+}
+
+impl std::str::FromStr for BpeEncoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cl100k_base" => Ok(BpeEncoding::Cl100kBase),
+            "o200k_base" => Ok(BpeEncoding::O200kBase),
+            other => Err(anyhow!("Unknown BPE encoding: {}", other)),
+        }
+    }
+}
+
+/// Byte pair encoding tokenizer compatible with tiktoken vocabularies
+///
+/// Vocabularies are loaded from local files (one `base64(token) rank` pair per line),
+/// e.g. `cl100k_base.tiktoken` and `o200k_base.tiktoken`, nothing is downloaded.
+pub struct BpeTokenizer {
+    name: String,
+    encoder: HashMap<Vec<u8>, u32>,
+    decoder: HashMap<u32, Vec<u8>>,
+    pattern: Regex,
+}
+
+impl fmt::Debug for BpeTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BpeTokenizer")
+            .field("name", &self.name)
+            .field("vocab_size", &self.decoder.len())
+            .finish()
+    }
+}
+
+impl BpeTokenizer {
+    /// Build a tokenizer from merge ranks, a pre-tokenizer pattern and special tokens
+    /// Every single byte must have a rank so that any text can be encoded.
+    pub fn from_ranks(
+        name: impl Into<String>,
+        ranks: HashMap<Vec<u8>, u32>,
+        pattern: &str,
+        special_tokens: HashMap<String, u32>,
+    ) -> Result<Self> {
+        let name = name.into();
+        if let Some(byte) = (0..=255u8).find(|byte| !ranks.contains_key(&vec![*byte])) {
+            return Err(anyhow!("Vocabulary {} has no rank for byte 0x{:02x}", name, byte));
+        }
+        let pattern = Regex::new(pattern).with_context(|| format!("Invalid pre-tokenizer pattern of {}", name))?;
+
+        let mut decoder: HashMap<u32, Vec<u8>> = ranks.iter().map(|(bytes, rank)| (*rank, bytes.clone())).collect();
+        for (token, rank) in special_tokens {
+            decoder.insert(rank, token.into_bytes());
+        }
+
+        Ok(Self { name, encoder: ranks, decoder, pattern })
+    }
+
+    /// Parse a vocabulary in the tiktoken format
+    pub fn from_tiktoken(data: &str, encoding: BpeEncoding) -> Result<Self> {
+        let mut ranks = HashMap::new();
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line.split_once(' ')
+                .ok_or_else(|| anyhow!("Invalid line {} in {} vocabulary", index + 1, encoding.name()))?;
+            let token = base64::engine::general_purpose::STANDARD.decode(token)
+                .with_context(|| format!("Invalid token on line {} in {} vocabulary", index + 1, encoding.name()))?;
+            let rank: u32 = rank.parse()
+                .with_context(|| format!("Invalid rank on line {} in {} vocabulary", index + 1, encoding.name()))?;
+            ranks.insert(token, rank);
+        }
+        Self::from_ranks(encoding.name(), ranks, encoding.pattern(), encoding.special_tokens())
+    }
+
+    /// Load a vocabulary file in the tiktoken format
+    pub fn from_file(path: impl AsRef<Path>, encoding: BpeEncoding) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {} vocabulary from {}", encoding.name(), path.display()))?;
+        Self::from_tiktoken(&data, encoding)
+    }
+
+    /// Load `<encoding>.tiktoken` from a directory
+    pub fn from_dir(dir: impl AsRef<Path>, encoding: BpeEncoding) -> Result<Self> {
+        Self::from_file(dir.as_ref().join(encoding.file_name()), encoding)
+    }
+
+    /// Number of tokens in the vocabulary, including special tokens
+    pub fn vocab_size(&self) -> usize {
+        self.decoder.len()
+    }
+
+    /// Encode text into token ids, special tokens are encoded as ordinary text
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        let mut end = 0;
+        for piece in self.pattern.find_iter(text) {
+            let Ok(piece) = piece else {
+                // Backtracking limit exceeded, merge the rest as a single piece
+                break;
+            };
+            end = piece.end();
+            self.encode_piece(piece.as_str().as_bytes(), &mut tokens);
+        }
+        if end < text.len() {
+            self.encode_piece(&text.as_bytes()[end..], &mut tokens);
+        }
+        tokens
+    }
+
+    /// Decode token ids into text, invalid UTF-8 sequences are replaced
+    pub fn decode(&self, tokens: &[u32]) -> Result<String> {
+        let mut bytes = Vec::new();
+        for token in tokens {
+            let token_bytes = self.decoder.get(token)
+                .ok_or_else(|| anyhow!("Unknown token {} for {}", token, self.name))?;
+            bytes.extend_from_slice(token_bytes);
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn encode_piece(&self, piece: &[u8], tokens: &mut Vec<u32>) {
+        match self.encoder.get(piece) {
+            Some(rank) => tokens.push(*rank),
+            None => tokens.extend(self.byte_pair_encode(piece)),
+        }
+    }
+
+    // Merge the lowest ranked adjacent pair until no pair is in the vocabulary
+    fn byte_pair_encode(&self, piece: &[u8]) -> Vec<u32> {
+        let mut boundaries: Vec<usize> = (0..=piece.len()).collect();
+        while boundaries.len() > 2 {
+            let best = (0..boundaries.len() - 2)
+                .filter_map(|i| self.encoder.get(&piece[boundaries[i]..boundaries[i + 2]]).map(|rank| (*rank, i)))
+                .min();
+            match best {
+                Some((_, i)) => {
+                    boundaries.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+        boundaries
+            .windows(2)
+            // Single bytes are always ranked, see from_ranks
+            .filter_map(|window| self.encoder.get(&piece[window[0]..window[1]]).copied())
+            .collect()
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ChatMessageContent;
+
+    // Byte level vocabulary with a few merges: "he", "ll", "hell", "hello", " w", " wor"
+    fn small_tokenizer() -> BpeTokenizer {
+        let mut ranks: HashMap<Vec<u8>, u32> = (0..=255u8).map(|byte| (vec![byte], byte as u32)).collect();
+        for (rank, token) in ["he", "ll", "hell", "hello", " w", "or", " wor"].iter().enumerate() {
+            ranks.insert(token.as_bytes().to_vec(), 256 + rank as u32);
+        }
+        let special = HashMap::from([("<|endoftext|>".to_string(), 1000)]);
+        BpeTokenizer::from_ranks("test", ranks, CL100K_PATTERN, special).unwrap()
+    }
+
+    #[test]
+    fn test_bpe_merges_and_round_trip() {
+        let tokenizer = small_tokenizer();
+        let tokens = tokenizer.encode("hello world");
+        // "hello" is a single token, " world" merges into " wor" + "l" + "d"
+        assert_eq!(tokens, vec![259, 262, b'l' as u32, b'd' as u32]);
+        assert_eq!(tokenizer.decode(&tokens).unwrap(), "hello world");
+        assert_eq!(tokenizer.decode(&[1000]).unwrap(), "<|endoftext|>");
+
+        // Multi-byte characters fall back to bytes and still round trip
+        let text = "héllo, 世界!";
+        assert_eq!(tokenizer.decode(&tokenizer.encode(text)).unwrap(), text);
+        assert_eq!(tokenizer.count_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_tiktoken_format_and_missing_bytes() {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let mut data: String = (0..=255u8).map(|byte| format!("{} {}\n", engine.encode([byte]), byte)).collect();
+        data.push_str(&format!("{} 256\n", engine.encode("ab")));
+        let tokenizer = BpeTokenizer::from_tiktoken(&data, BpeEncoding::O200kBase).unwrap();
+        assert_eq!(tokenizer.name(), "o200k_base");
+        assert_eq!(tokenizer.encode("abc"), vec![256, b'c' as u32]);
+
+        let incomplete = format!("{} 0\n", engine.encode("a"));
+        assert!(BpeTokenizer::from_tiktoken(&incomplete, BpeEncoding::Cl100kBase).is_err());
+    }
+
+    #[test]
+    fn test_count_message_tokens() {
+        let tokenizer = small_tokenizer();
+        let message = |content: &str| ChatMessage::Human(ChatMessageContent {
+            content: content.to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        });
+        // 3 for the reply, 3 per message, "user" is 4 bytes without merges
+        assert_eq!(tokenizer.count_message_tokens(&[message("hello")]), 3 + 3 + 4 + 1);
+        assert_eq!(HeuristicTokenizer.count_tokens("hi"), 1);
+        assert_eq!(HeuristicTokenizer.count_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_trim_messages_keeps_system_and_starts_on_human() {
+        let content = |text: &str| ChatMessageContent {
+            content: text.to_string(),
+            name: None,
+            additional_kwargs: HashMap::new(),
+        };
+        let messages = vec![
+            ChatMessage::System(content("system prompt")),
+            ChatMessage::Human(content("first question")),
+            ChatMessage::AIMessage(content("first answer")),
+            ChatMessage::Human(content("second question")),
+            ChatMessage::AIMessage(content("second answer")),
+        ];
+        let tokenizer = HeuristicTokenizer;
+        let total = tokenizer.count_message_tokens(&messages);
+        assert_eq!(trim_messages(messages.clone(), total, &tokenizer).len(), 5);
+
+        // Dropping only the first question would leave an answer first, so the answer goes too
+        let trimmed = trim_messages(messages.clone(), total - 1, &tokenizer);
+        assert_eq!(trimmed.len(), 3);
+        assert!(matches!(&trimmed[0], ChatMessage::System(c) if c.content == "system prompt"));
+        assert!(matches!(&trimmed[1], ChatMessage::Human(c) if c.content == "second question"));
+
+        assert_eq!(trim_messages(messages, 0, &tokenizer).len(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+// Chat prompt template implementation
+use anyhow::Error;
+use std::collections::HashMap;
+use std::pin::Pin;
+use crate::core::Runnable;
+use crate::models::{ChatMessage, ChatMessageContent};
+use crate::prompt::PromptTemplate;
+
+/// Template of one or more chat messages
+#[derive(Clone, Debug)]
+pub enum MessagePromptTemplate {
+    System(PromptTemplate),
+    Human(PromptTemplate),
+    AI(PromptTemplate),
+    /// Fixed messages inserted as they are
+    Messages(Vec<ChatMessage>),
+    /// Example conversations formatted with the example prompt (aligned with langchain FewShotChatMessagePromptTemplate)
+    FewShot {
+        examples: Vec<HashMap<String, String>>,
+        example_prompt: Box<ChatPromptTemplate>,
+    },
+}
+
+impl MessagePromptTemplate {
+    /// Create a message template from a role ("system", "human"/"user" or "ai"/"assistant") and a template string
+    pub fn from_role(role: &str, template: &str) -> Result<Self, Error> {
+        let prompt = PromptTemplate::new(template)?;
+        match role {
+            "system" => Ok(MessagePromptTemplate::System(prompt)),
+            "human" | "user" => Ok(MessagePromptTemplate::Human(prompt)),
+            "ai" | "assistant" => Ok(MessagePromptTemplate::AI(prompt)),
+            _ => Err(Error::msg(format!("Unsupported message role: {}", role))),
+        }
+    }
+
+    /// Create a few-shot block, every example is formatted with the example prompt
+    pub fn few_shot(examples: Vec<HashMap<String, String>>, example_prompt: ChatPromptTemplate) -> Self {
+        MessagePromptTemplate::FewShot {
+            examples,
+            example_prompt: Box::new(example_prompt),
+        }
+    }
+
+    // Variables required by this message template
+    fn input_variables(&self) -> Vec<String> {
+        match self {
+            MessagePromptTemplate::System(prompt)
+            | MessagePromptTemplate::Human(prompt)
+            | MessagePromptTemplate::AI(prompt) => prompt.input_variables(),
+            MessagePromptTemplate::Messages(_) | MessagePromptTemplate::FewShot { .. } => Vec::new(),
+        }
+    }
+
+    // Apply partial variables to string templates
+    fn partial(&self, values: &HashMap<String, String>) -> Self {
+        match self {
+            MessagePromptTemplate::System(prompt) => MessagePromptTemplate::System(prompt.partial(values.clone())),
+            MessagePromptTemplate::Human(prompt) => MessagePromptTemplate::Human(prompt.partial(values.clone())),
+            MessagePromptTemplate::AI(prompt) => MessagePromptTemplate::AI(prompt.partial(values.clone())),
+            other => other.clone(),
+        }
+    }
+
+    fn format_messages(&self, inputs: &HashMap<String, String>) -> Result<Vec<ChatMessage>, Error> {
+        let content = |prompt: &PromptTemplate| -> Result<ChatMessageContent, Error> {
+            Ok(ChatMessageContent {
+                content: prompt.format(inputs)?,
+                name: None,
+                additional_kwargs: HashMap::new(),
+            })
+        };
+
+        match self {
+            MessagePromptTemplate::System(prompt) => Ok(vec![ChatMessage::System(content(prompt)?)]),
+            MessagePromptTemplate::Human(prompt) => Ok(vec![ChatMessage::Human(content(prompt)?)]),
+            MessagePromptTemplate::AI(prompt) => Ok(vec![ChatMessage::AIMessage(content(prompt)?)]),
+            MessagePromptTemplate::Messages(messages) => Ok(messages.clone()),
+            MessagePromptTemplate::FewShot { examples, example_prompt } => {
+                let mut messages = Vec::new();
+                for example in examples {
+                    messages.extend(example_prompt.format_messages(example)?);
+                }
+                Ok(messages)
+            },
+        }
+    }
+}
+
+/// Prompt template producing a list of chat messages (aligned with langchain-core ChatPromptTemplate)
+#[derive(Clone, Debug, Default)]
+pub struct ChatPromptTemplate {
+    messages: Vec<MessagePromptTemplate>,
+}
+
+impl ChatPromptTemplate {
+    /// Create a template from message templates
+    pub fn new(messages: Vec<MessagePromptTemplate>) -> Self {
+        Self { messages }
+    }
+
+    /// Create a template from (role, template) pairs
+    pub fn from_messages(messages: &[(&str, &str)]) -> Result<Self, Error> {
+        let messages = messages.iter()
+            .map(|(role, template)| MessagePromptTemplate::from_role(role, template))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self::new(messages))
+    }
+
+    /// Append a message template
+    pub fn with_message(mut self, message: MessagePromptTemplate) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Fill a variable in advance in every message template
+    pub fn with_partial(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut values = HashMap::new();
+        values.insert(name.into(), value.into());
+        self.partial(values)
+    }
+
+    /// Fill several variables in advance in every message template
+    pub fn partial(&self, values: HashMap<String, String>) -> Self {
+        Self {
+            messages: self.messages.iter().map(|message| message.partial(&values)).collect(),
+        }
+    }
+
+    /// Get the variables that must be provided when formatting
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        for message in &self.messages {
+            for name in message.input_variables() {
+                if !variables.contains(&name) {
+                    variables.push(name);
+                }
+            }
+        }
+        variables
+    }
+
+    /// Format all message templates
+    pub fn format_messages(&self, inputs: &HashMap<String, String>) -> Result<Vec<ChatMessage>, Error> {
+        let mut messages = Vec::new();
+        for message in &self.messages {
+            messages.extend(message.format_messages(inputs)?);
+        }
+        Ok(messages)
+    }
+}
+
+// Formatted messages can be piped directly into a chat model
+impl Runnable<HashMap<String, String>, Vec<ChatMessage>> for ChatPromptTemplate {
+    fn invoke(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn std::future::Future<Output = Result<Vec<ChatMessage>, Error>> + Send>> {
+        let result = self.format_messages(&inputs);
+        Box::pin(async move { result })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<HashMap<String, String>, Vec<ChatMessage>> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::pipe;
+
+    fn inputs(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn contents(messages: &[ChatMessage]) -> Vec<String> {
+        messages.iter().map(|message| match message {
+            ChatMessage::System(c) => format!("system: {}", c.content),
+            ChatMessage::Human(c) => format!("human: {}", c.content),
+            ChatMessage::AIMessage(c) => format!("ai: {}", c.content),
+            ChatMessage::ToolMessage(c) => format!("tool: {}", c.content),
+        }).collect()
+    }
+
+    #[test]
+    fn test_chat_prompt_with_few_shot_examples() {
+        let example_prompt = ChatPromptTemplate::from_messages(&[("human", "{q}"), ("ai", "{a}")]).unwrap();
+        let examples = vec![inputs(&[("q", "2+2"), ("a", "4")])];
+
+        let prompt = ChatPromptTemplate::from_messages(&[("system", "You are {role}.")])
+            .unwrap()
+            .with_message(MessagePromptTemplate::few_shot(examples, example_prompt))
+            .with_message(MessagePromptTemplate::from_role("user", "{question}").unwrap())
+            .with_partial("role", "a calculator");
+
+        assert_eq!(prompt.input_variables(), vec!["question".to_string()]);
+        let messages = prompt.format_messages(&inputs(&[("question", "3+3")])).unwrap();
+        assert_eq!(contents(&messages), vec![
+            "system: You are a calculator.",
+            "human: 2+2",
+            "ai: 4",
+            "human: 3+3",
+        ]);
+        assert!(prompt.format_messages(&HashMap::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_chat_prompt() {
+        // Stands in for a chat model consuming the formatted messages
+        struct CountMessages;
+        impl Runnable<Vec<ChatMessage>, usize> for CountMessages {
+            fn invoke(&self, input: Vec<ChatMessage>) -> Pin<Box<dyn std::future::Future<Output = Result<usize, Error>> + Send>> {
+                Box::pin(async move { Ok(input.len()) })
+            }
+
+            fn clone_to_owned(&self) -> Box<dyn Runnable<Vec<ChatMessage>, usize> + Send + Sync> {
+                Box::new(CountMessages)
+            }
+        }
+
+        let prompt = ChatPromptTemplate::from_messages(&[("system", "Be brief."), ("human", "Translate {text}")]).unwrap();
+        let chain = pipe(prompt, CountMessages);
+        assert_eq!(chain.invoke(inputs(&[("text", "hello")])).await.unwrap(), 2);
+    }
+}
@@ -1,5 +1,7 @@
 // Prompt template module definition
+mod chat;
 mod template;
 
 // Re-export module content
-pub use template::PromptTemplate;
\ No newline at end of file
+pub use chat::{ChatPromptTemplate, MessagePromptTemplate};
+pub use template::{FewShotPromptTemplate, PromptTemplate};
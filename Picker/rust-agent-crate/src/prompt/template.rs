@@ -1,17 +1,289 @@
 // Prompt template implementation
 use anyhow::Error;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::pin::Pin;
+use crate::core::Runnable;
 
-// Prompt template interface
-pub trait PromptTemplate: Send + Sync {
-    // Get template input variable names
-    fn input_variables(&self) -> Vec<String> {
-        unimplemented!();
+// Parsed piece of a template
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+// Parse a template with {var} placeholders, {{ and }} are literal braces
+fn parse_template(template: &str) -> Result<Vec<Segment>, Error> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            },
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(Error::msg(format!("Unclosed variable {{{} in prompt template", name))),
+                    }
+                }
+                let name = name.trim().to_string();
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(Error::msg(format!("Invalid variable name {{{}}} in prompt template", name)));
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Variable(name));
+            },
+            '}' => return Err(Error::msg("Single '}' in prompt template, use '}}' for a literal brace")),
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+/// String prompt template with {var} placeholders (aligned with langchain-core PromptTemplate)
+#[derive(Clone, Debug, Default)]
+pub struct PromptTemplate {
+    template: String,
+    segments: Vec<Segment>,
+    partial_variables: HashMap<String, String>,
+}
+
+impl PromptTemplate {
+    /// Parse a template, variables are written as {name}
+    pub fn new(template: impl Into<String>) -> Result<Self, Error> {
+        let template = template.into();
+        let segments = parse_template(&template)?;
+        Ok(Self {
+            template,
+            segments,
+            partial_variables: HashMap::new(),
+        })
+    }
+
+    /// Parse a template and check that it uses exactly the declared input variables
+    pub fn with_input_variables(template: impl Into<String>, input_variables: &[&str]) -> Result<Self, Error> {
+        let prompt = Self::new(template)?;
+        let declared: BTreeSet<String> = input_variables.iter().map(|s| s.to_string()).collect();
+        let used: BTreeSet<String> = prompt.input_variables().into_iter().collect();
+
+        let missing: Vec<&String> = used.difference(&declared).collect();
+        if !missing.is_empty() {
+            return Err(Error::msg(format!("Prompt template uses undeclared variables: {:?}", missing)));
+        }
+        let extra: Vec<&String> = declared.difference(&used).collect();
+        if !extra.is_empty() {
+            return Err(Error::msg(format!("Declared variables are not used in the prompt template: {:?}", extra)));
+        }
+        Ok(prompt)
+    }
+
+    /// Fill a variable in advance, it is no longer required when formatting
+    pub fn with_partial(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.partial_variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Fill several variables in advance
+    pub fn partial(&self, values: HashMap<String, String>) -> Self {
+        let mut prompt = self.clone();
+        prompt.partial_variables.extend(values);
+        prompt
+    }
+
+    /// Get the raw template string
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Get the variables that must be provided when formatting, in order of first use
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Variable(name) = segment {
+                if !self.partial_variables.contains_key(name) && !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            }
+        }
+        variables
+    }
+
+    /// Format the template, inputs that are not used by the template are ignored
+    pub fn format(&self, inputs: &HashMap<String, String>) -> Result<String, Error> {
+        let missing: Vec<String> = self.input_variables().into_iter()
+            .filter(|name| !inputs.contains_key(name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::msg(format!("Missing prompt variables: {:?}", missing)));
+        }
+
+        let mut output = String::with_capacity(self.template.len());
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Variable(name) => {
+                    let value = inputs.get(name).or_else(|| self.partial_variables.get(name));
+                    output.push_str(value.map(|s| s.as_str()).unwrap_or_default());
+                },
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Runnable<HashMap<String, String>, String> for PromptTemplate {
+    fn invoke(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send>> {
+        let result = self.format(&inputs);
+        Box::pin(async move { result })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<HashMap<String, String>, String> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// Prompt template with formatted examples between a prefix and a suffix (aligned with langchain FewShotPromptTemplate)
+#[derive(Clone, Debug)]
+pub struct FewShotPromptTemplate {
+    examples: Vec<HashMap<String, String>>,
+    example_prompt: PromptTemplate,
+    prefix: Option<PromptTemplate>,
+    suffix: PromptTemplate,
+    example_separator: String,
+}
+
+impl FewShotPromptTemplate {
+    /// Create a template formatting every example with the example prompt, followed by the suffix
+    pub fn new(examples: Vec<HashMap<String, String>>, example_prompt: PromptTemplate, suffix: PromptTemplate) -> Self {
+        Self {
+            examples,
+            example_prompt,
+            prefix: None,
+            suffix,
+            example_separator: "\n\n".to_string(),
+        }
+    }
+
+    /// Set the template placed before the examples
+    pub fn with_prefix(mut self, prefix: PromptTemplate) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// Set the separator between prefix, examples and suffix
+    pub fn with_example_separator(mut self, separator: impl Into<String>) -> Self {
+        self.example_separator = separator.into();
+        self
+    }
+
+    /// Get the variables of prefix and suffix
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut variables = self.prefix.as_ref().map(|p| p.input_variables()).unwrap_or_default();
+        for name in self.suffix.input_variables() {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+        variables
+    }
+
+    /// Format prefix, examples and suffix
+    pub fn format(&self, inputs: &HashMap<String, String>) -> Result<String, Error> {
+        let mut pieces = Vec::new();
+        if let Some(prefix) = &self.prefix {
+            pieces.push(prefix.format(inputs)?);
+        }
+        for example in &self.examples {
+            pieces.push(self.example_prompt.format(example)?);
+        }
+        pieces.push(self.suffix.format(inputs)?);
+        Ok(pieces.join(&self.example_separator))
+    }
+}
+
+impl Runnable<HashMap<String, String>, String> for FewShotPromptTemplate {
+    fn invoke(&self, inputs: HashMap<String, String>) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send>> {
+        let result = self.format(&inputs);
+        Box::pin(async move { result })
+    }
+
+    fn clone_to_owned(&self) -> Box<dyn Runnable<HashMap<String, String>, String> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
     }
-    
-    // Format template
-    fn format(&self, inputs: HashMap<String, String>) -> Result<String, Error> {
-        let _inputs = inputs;
-        unimplemented!();
+
+    #[test]
+    fn test_format_and_escaping() {
+        let prompt = PromptTemplate::new("Hello {name}, reply as {{\"content\": \"{name}\"}}").unwrap();
+        assert_eq!(prompt.input_variables(), vec!["name".to_string()]);
+        assert_eq!(
+            prompt.format(&inputs(&[("name", "Bob"), ("unused", "x")])).unwrap(),
+            "Hello Bob, reply as {\"content\": \"Bob\"}"
+        );
+        assert!(prompt.format(&HashMap::new()).is_err());
+        assert!(PromptTemplate::new("Hello {name").is_err());
+        assert!(PromptTemplate::new("Hello {first name}").is_err());
+    }
+
+    #[test]
+    fn test_declared_variables_and_partials() {
+        assert!(PromptTemplate::with_input_variables("{a} {b}", &["a"]).is_err());
+        assert!(PromptTemplate::with_input_variables("{a}", &["a", "b"]).is_err());
+
+        let prompt = PromptTemplate::with_input_variables("{greeting}, {name}!", &["greeting", "name"])
+            .unwrap()
+            .with_partial("greeting", "Hi");
+        assert_eq!(prompt.input_variables(), vec!["name".to_string()]);
+        assert_eq!(prompt.format(&inputs(&[("name", "Ann")])).unwrap(), "Hi, Ann!");
+    }
+
+    #[test]
+    fn test_few_shot_prompt() {
+        let examples = vec![inputs(&[("q", "1+1"), ("a", "2")]), inputs(&[("q", "2+2"), ("a", "4")])];
+        let prompt = FewShotPromptTemplate::new(
+            examples,
+            PromptTemplate::new("Q: {q}\nA: {a}").unwrap(),
+            PromptTemplate::new("Q: {question}\nA:").unwrap(),
+        ).with_prefix(PromptTemplate::new("Answer the questions.").unwrap());
+
+        assert_eq!(prompt.input_variables(), vec!["question".to_string()]);
+        assert_eq!(
+            prompt.format(&inputs(&[("question", "3+3")])).unwrap(),
+            "Answer the questions.\n\nQ: 1+1\nA: 2\n\nQ: 2+2\nA: 4\n\nQ: 3+3\nA:"
+        );
+    }
+
+    #[test]
+    fn test_default_react_prompts() {
+        let values = inputs(&[("system_prompt", "Be helpful."), ("tool_descriptions", "- get_weather: Get weather\n")]);
+        let prompt = PromptTemplate::new(crate::agents::DEFAULT_REACT_JSON_PROMPT).unwrap().format(&values).unwrap();
+        assert!(prompt.starts_with("Be helpful.\n"));
+        assert!(prompt.contains("- get_weather: Get weather"));
+        assert!(prompt.contains("{\"call_tool\": {\"name\": \"Tool Name\""));
+        assert!(PromptTemplate::new(crate::agents::DEFAULT_REACT_PROMPT).unwrap().format(&values).is_ok());
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,9 @@
 // Tools module definition
+mod schema;
 mod tool;
 mod utils;
 
 // Re-export module content
+pub use schema::{default_input_schema, validate_json_schema, SchemaViolation, ToolValidationError};
 pub use tool::{Tool, Toolkit, ExampleTool, ExampleToolkit};
 pub use utils::{find_matching_tool_index, parse_model_output};
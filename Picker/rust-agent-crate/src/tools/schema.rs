@@ -0,0 +1,214 @@
+// JSON Schema support for tool arguments
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Schema accepting any JSON object, used by tools that don't declare their arguments
+pub fn default_input_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {}
+    })
+}
+
+// Single schema violation, path is a JSON pointer to the offending value
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SchemaViolation {
+    pub path: String,
+    pub message: String,
+}
+
+// Arguments rejected by the tool input schema, returned before the tool is called
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ToolValidationError {
+    pub tool: String,
+    pub violations: Vec<SchemaViolation>,
+}
+
+impl fmt::Display for ToolValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Written for the model, so that it can correct the arguments and call the tool again
+        write!(f, "Invalid arguments for tool {}:", self.tool)?;
+        for violation in &self.violations {
+            let path = if violation.path.is_empty() { "(root)" } else { violation.path.as_str() };
+            write!(f, " {}: {};", path, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ToolValidationError {}
+
+/// Validate a value against a JSON Schema
+/// Supported keywords: type, enum, const, required, properties, additionalProperties, items,
+/// minimum, maximum, exclusiveMinimum, exclusiveMaximum, minLength, maxLength, minItems, maxItems,
+/// allOf, anyOf and oneOf. Unknown keywords are ignored.
+pub fn validate_json_schema(schema: &Value, value: &Value) -> Vec<SchemaViolation> {
+    let mut violations = Vec::new();
+    validate_at(schema, value, "", &mut violations);
+    violations
+}
+
+fn violation(violations: &mut Vec<SchemaViolation>, path: &str, message: String) {
+    violations.push(SchemaViolation {
+        path: path.to_string(),
+        message,
+    });
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        // Floats with an integral value such as 1.0 are accepted as integers
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, violations: &mut Vec<SchemaViolation>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // `false` rejects everything, `true` and other values accept everything
+        Value::Bool(false) => {
+            violation(violations, path, "no value is allowed here".to_string());
+            return;
+        },
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(t, value)) {
+            violation(violations, path, format!("expected {}, got {}", types.join(" or "), type_name(value)));
+            // Remaining keywords would only repeat the type mismatch
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violation(violations, path, format!("must be one of {}", Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violation(violations, path, format!("must be {}", expected));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(|n| n.as_str()) {
+                    if !object.contains_key(name) {
+                        violation(violations, &format!("{}/{}", path, name), "is required".to_string());
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property_value) in object {
+                let property_path = format!("{}/{}", path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    Some(property_schema) => validate_at(property_schema, property_value, &property_path, violations),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => violation(violations, &property_path, "unknown property".to_string()),
+                        Some(additional) => validate_at(additional, property_value, &property_path, violations),
+                        None => {},
+                    },
+                }
+            }
+        },
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}/{}", path, i), violations);
+                }
+            }
+            if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+                if (items.len() as u64) < min {
+                    violation(violations, path, format!("must contain at least {} items", min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+                if (items.len() as u64) > max {
+                    violation(violations, path, format!("must contain at most {} items", max));
+                }
+            }
+        },
+        Value::String(s) => {
+            let length = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+                if length < min {
+                    violation(violations, path, format!("must be at least {} characters long", min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+                if length > max {
+                    violation(violations, path, format!("must be at most {} characters long", max));
+                }
+            }
+        },
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64()) {
+                if n < min {
+                    violation(violations, path, format!("must be >= {}", min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64()) {
+                if n > max {
+                    violation(violations, path, format!("must be <= {}", max));
+                }
+            }
+            if let Some(min) = schema.get("exclusiveMinimum").and_then(|m| m.as_f64()) {
+                if n <= min {
+                    violation(violations, path, format!("must be > {}", min));
+                }
+            }
+            if let Some(max) = schema.get("exclusiveMaximum").and_then(|m| m.as_f64()) {
+                if n >= max {
+                    violation(violations, path, format!("must be < {}", max));
+                }
+            }
+        },
+        _ => {},
+    }
+
+    if let Some(Value::Array(schemas)) = schema.get("allOf") {
+        for sub_schema in schemas {
+            validate_at(sub_schema, value, path, violations);
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+        if !schemas.iter().any(|s| validate_json_schema(s, value).is_empty()) {
+            violation(violations, path, "does not match any of the allowed schemas".to_string());
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        let matching = schemas.iter().filter(|s| validate_json_schema(s, value).is_empty()).count();
+        if matching != 1 {
+            violation(violations, path, format!("must match exactly one schema, matched {}", matching));
+        }
+    }
+}
@@ -1,6 +1,9 @@
 // Tool interface and implementation
 use anyhow::Error;
+use serde_json::Value;
 use std::pin::Pin;
+use crate::callbacks::CallbackManager;
+use crate::tools::default_input_schema;
 
 // Minimal tool interface (aligned with langchain-core)
 pub trait Tool: Send + Sync {
@@ -9,9 +12,32 @@ pub trait Tool: Send + Sync {
     
     fn description(&self) -> &str;
     
+    // JSON Schema of the arguments passed to invoke as a JSON object
+    fn input_schema(&self) -> Value {
+        default_input_schema()
+    }
+    
     // Core execution method
     fn invoke(&self, input: &str) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + '_>>;
     
+    // Invoke reporting a tool run to the callback manager of the caller (used by AgentExecutor)
+    // Tools with callbacks of their own override it so that every event is fired once
+    fn invoke_with_callbacks<'a>(&'a self, input: &'a str, callbacks: &'a CallbackManager) -> Pin<Box<dyn std::future::Future<Output = Result<String, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let run_manager = callbacks.on_tool_start(self.name(), input);
+            match self.invoke(input).await {
+                Ok(output) => {
+                    run_manager.on_tool_end(self.name(), &output);
+                    Ok(output)
+                },
+                Err(e) => {
+                    run_manager.on_tool_error(self.name(), &e.to_string());
+                    Err(e)
+                },
+            }
+        })
+    }
+    
     // Add as_any method to support runtime type checking
     fn as_any(&self) -> &dyn std::any::Any;
 }
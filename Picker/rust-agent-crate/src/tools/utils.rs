@@ -1,11 +1,13 @@
 use crate::tools::Tool;
-use std::boxed::Box;
 use serde_json::Value;
 use std::collections::HashMap;
 use crate::agents::{AgentOutput, AgentAction, AgentFinish};
 
 /// Implement fuzzy matching mechanism for tool names, returns the matching tool name
-pub fn find_matching_tool_index(tools: &[Box<dyn Tool + Send + Sync>], requested_tool: &str) -> Option<String> {
+pub fn find_matching_tool_index<T>(tools: &[T], requested_tool: &str) -> Option<String>
+where
+    T: std::ops::Deref<Target = dyn Tool + Send + Sync>,
+{
     // 1. Exact match - prioritize complete matching
     if let Some(tool) = tools.iter().find(|t| { 
         t.name() == requested_tool 
@@ -71,6 +73,7 @@ pub fn parse_model_output(content: &str) -> Result<AgentOutput, anyhow::Error> {
                     tool_input,
                     log: "Call tool".to_string(),
                     thought: Some("Call tool based on model output".to_string()),
+                    tool_call_id: None,
                 }));
             }
         }
@@ -0,0 +1,250 @@
+// Vector store interface, documents and metadata filters
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::embeddings::{Embedding, Embeddings};
+
+/// Text with metadata, aligned with LangChain's Document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    /// Unique id in the store, assigned on insert when missing
+    pub id: Option<String>,
+    pub page_content: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+}
+
+impl Document {
+    /// Create a document without id and metadata
+    pub fn new(page_content: impl Into<String>) -> Self {
+        Self {
+            id: None,
+            page_content: page_content.into(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Set the id, documents with an existing id are replaced
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Add a metadata value
+    pub fn with_metadata(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.metadata.insert(key.into(), value);
+        self
+    }
+}
+
+/// Document returned by a search with its cosine similarity to the query, higher is closer
+pub type ScoredDocument = (Document, f32);
+
+/// Condition on a single metadata value
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataCondition {
+    Eq(Value),
+    // Also true when the key is missing
+    Ne(Value),
+    In(Vec<Value>),
+    // Timestamp (RFC 3339 string or Unix seconds) at or after the time
+    After(DateTime<Utc>),
+    // Timestamp strictly before the time
+    Before(DateTime<Utc>),
+}
+
+impl MetadataCondition {
+    fn matches(&self, value: Option<&Value>) -> bool {
+        match (self, value) {
+            (MetadataCondition::Ne(expected), value) => value != Some(expected),
+            (_, None) => false,
+            (MetadataCondition::Eq(expected), Some(value)) => value == expected,
+            (MetadataCondition::In(expected), Some(value)) => expected.contains(value),
+            (MetadataCondition::After(time), Some(value)) => parse_timestamp(value).is_some_and(|t| t >= *time),
+            (MetadataCondition::Before(time), Some(value)) => parse_timestamp(value).is_some_and(|t| t < *time),
+        }
+    }
+}
+
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text).ok().map(|t| t.with_timezone(&Utc)),
+        Value::Number(number) => DateTime::from_timestamp(number.as_i64()?, 0),
+        _ => None,
+    }
+}
+
+/// Metadata conditions that must all hold, e.g. a session and a time range
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataFilter {
+    conditions: Vec<(String, MetadataCondition)>,
+}
+
+impl MetadataFilter {
+    /// Create a filter matching every document
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a condition on the metadata key
+    pub fn with_condition(mut self, key: impl Into<String>, condition: MetadataCondition) -> Self {
+        self.conditions.push((key.into(), condition));
+        self
+    }
+
+    /// The value of the key must equal the value
+    pub fn with_eq(self, key: impl Into<String>, value: Value) -> Self {
+        self.with_condition(key, MetadataCondition::Eq(value))
+    }
+
+    /// The value of the key must differ from the value
+    pub fn with_ne(self, key: impl Into<String>, value: Value) -> Self {
+        self.with_condition(key, MetadataCondition::Ne(value))
+    }
+
+    /// The value of the key must be one of the values
+    pub fn with_in(self, key: impl Into<String>, values: Vec<Value>) -> Self {
+        self.with_condition(key, MetadataCondition::In(values))
+    }
+
+    /// The timestamp in the key must be at or after the time
+    pub fn with_after(self, key: impl Into<String>, time: DateTime<Utc>) -> Self {
+        self.with_condition(key, MetadataCondition::After(time))
+    }
+
+    /// The timestamp in the key must be before the time
+    pub fn with_before(self, key: impl Into<String>, time: DateTime<Utc>) -> Self {
+        self.with_condition(key, MetadataCondition::Before(time))
+    }
+
+    /// Combine the conditions of both filters
+    pub fn and(mut self, other: MetadataFilter) -> Self {
+        self.conditions.extend(other.conditions);
+        self
+    }
+
+    /// Whether the metadata satisfies all conditions
+    pub fn matches(&self, metadata: &HashMap<String, Value>) -> bool {
+        self.conditions.iter().all(|(key, condition)| condition.matches(metadata.get(key)))
+    }
+}
+
+// Vector store interface, aligned with LangChain's VectorStore
+pub trait VectorStore: Send + Sync {
+    // Embedding model used for documents and queries
+    fn embeddings(&self) -> Arc<dyn Embeddings>;
+
+    // Store documents with their vectors and return their ids, documents with an existing id are replaced
+    fn add_vectors(&self, documents: Vec<Document>, vectors: Vec<Embedding>) -> Pin<Box<dyn Future<Output = Result<Vec<String>, Error>> + Send + '_>>;
+
+    // Core method: the k documents closest to the vector that match the filter, closest first
+    fn similarity_search_by_vector_with_score(&self, vector: Embedding, k: usize, filter: Option<MetadataFilter>) -> Pin<Box<dyn Future<Output = Result<Vec<ScoredDocument>, Error>> + Send + '_>>;
+
+    // Delete documents by id, unknown ids are ignored
+    fn delete(&self, ids: Vec<String>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+
+    // Delete the documents matching the filter and return how many were deleted
+    fn delete_by_filter(&self, filter: MetadataFilter) -> Pin<Box<dyn Future<Output = Result<usize, Error>> + Send + '_>>;
+
+    // Embed and store documents, return their ids
+    fn add_documents(&self, documents: Vec<Document>) -> Pin<Box<dyn Future<Output = Result<Vec<String>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            if documents.is_empty() {
+                return Ok(Vec::new());
+            }
+            let texts = documents.iter().map(|document| document.page_content.clone()).collect();
+            let vectors = self.embeddings().embed_documents(texts).await?;
+            self.add_vectors(documents, vectors).await
+        })
+    }
+
+    // The k documents closest to the query with their similarity
+    fn similarity_search_with_score(&self, query: String, k: usize, filter: Option<MetadataFilter>) -> Pin<Box<dyn Future<Output = Result<Vec<ScoredDocument>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let vector = self.embeddings().embed_query(query).await?;
+            self.similarity_search_by_vector_with_score(vector, k, filter).await
+        })
+    }
+
+    // The k documents closest to the query
+    fn similarity_search(&self, query: String, k: usize, filter: Option<MetadataFilter>) -> Pin<Box<dyn Future<Output = Result<Vec<Document>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let results = self.similarity_search_with_score(query, k, filter).await?;
+            Ok(results.into_iter().map(|(document, _)| document).collect())
+        })
+    }
+}
+
+/// Assign missing ids and check that there is one vector per document
+pub(crate) fn prepare_documents(documents: Vec<Document>, vectors: &[Embedding]) -> Result<Vec<Document>> {
+    if documents.len() != vectors.len() {
+        return Err(Error::msg(format!("Got {} documents but {} vectors", documents.len(), vectors.len())));
+    }
+    Ok(documents
+        .into_iter()
+        .map(|mut document| {
+            document.id.get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+            document
+        })
+        .collect())
+}
+
+/// Cosine similarity, 0 for vectors of different dimensions or zero vectors
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Score the candidates that match the filter and keep the k best
+pub(crate) fn top_k<'a>(
+    candidates: impl Iterator<Item = (&'a Document, &'a Embedding)>,
+    vector: &[f32],
+    k: usize,
+    filter: Option<&MetadataFilter>,
+) -> Vec<ScoredDocument> {
+    let mut scored: Vec<ScoredDocument> = candidates
+        .filter(|(document, _)| filter.is_none_or(|filter| filter.matches(&document.metadata)))
+        .map(|(document, embedding)| (document.clone(), cosine_similarity(vector, embedding)))
+        .collect();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scored.truncate(k);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_metadata_filter() {
+        let metadata: HashMap<String, Value> = HashMap::from([
+            ("session_id".to_string(), json!("s1")),
+            ("timestamp".to_string(), json!("2025-03-01T10:00:00+00:00")),
+        ]);
+        let march = DateTime::parse_from_rfc3339("2025-03-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        let april = DateTime::parse_from_rfc3339("2025-04-01T00:00:00Z").unwrap().with_timezone(&Utc);
+
+        assert!(MetadataFilter::new().matches(&metadata));
+        assert!(MetadataFilter::new().with_eq("session_id", json!("s1")).with_after("timestamp", march).with_before("timestamp", april).matches(&metadata));
+        assert!(!MetadataFilter::new().with_ne("session_id", json!("s1")).matches(&metadata));
+        assert!(MetadataFilter::new().with_ne("user", json!("bob")).matches(&metadata));
+        assert!(!MetadataFilter::new().with_after("timestamp", april).matches(&metadata));
+        assert!(MetadataFilter::new().with_in("session_id", vec![json!("s0"), json!("s1")]).matches(&metadata));
+        assert!(!MetadataFilter::new().with_eq("missing", json!(1)).matches(&metadata));
+    }
+}
@@ -0,0 +1,119 @@
+// Vector store keeping documents in memory
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use anyhow::{Error, Result};
+use tokio::sync::RwLock;
+
+use super::base::{prepare_documents, top_k, Document, MetadataFilter, ScoredDocument, VectorStore};
+use crate::embeddings::{Embedding, Embeddings};
+
+/// Vector store searching all documents in memory, content is lost when the process exits
+#[derive(Clone)]
+pub struct InMemoryVectorStore {
+    embeddings: Arc<dyn Embeddings>,
+    // Documents in insertion order, ids are always set
+    entries: Arc<RwLock<Vec<(Document, Embedding)>>>,
+}
+
+impl InMemoryVectorStore {
+    /// Create an empty store using the embedding model
+    pub fn new(embeddings: Arc<dyn Embeddings>) -> Self {
+        Self {
+            embeddings,
+            entries: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Number of stored documents
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.len()
+    }
+
+    /// Whether the store is empty
+    pub async fn is_empty(&self) -> bool {
+        self.entries.read().await.is_empty()
+    }
+}
+
+impl VectorStore for InMemoryVectorStore {
+    fn embeddings(&self) -> Arc<dyn Embeddings> {
+        self.embeddings.clone()
+    }
+
+    fn add_vectors(&self, documents: Vec<Document>, vectors: Vec<Embedding>) -> Pin<Box<dyn Future<Output = Result<Vec<String>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let documents = prepare_documents(documents, &vectors)?;
+            let mut entries = self.entries.write().await;
+            let mut ids = Vec::with_capacity(documents.len());
+            for (document, vector) in documents.into_iter().zip(vectors) {
+                ids.push(document.id.clone().unwrap_or_default());
+                match entries.iter_mut().find(|(existing, _)| existing.id == document.id) {
+                    Some(entry) => *entry = (document, vector),
+                    None => entries.push((document, vector)),
+                }
+            }
+            Ok(ids)
+        })
+    }
+
+    fn similarity_search_by_vector_with_score(&self, vector: Embedding, k: usize, filter: Option<MetadataFilter>) -> Pin<Box<dyn Future<Output = Result<Vec<ScoredDocument>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let entries = self.entries.read().await;
+            let candidates = entries.iter().map(|(document, embedding)| (document, embedding));
+            Ok(top_k(candidates, &vector, k, filter.as_ref()))
+        })
+    }
+
+    fn delete(&self, ids: Vec<String>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.entries.write().await.retain(|(document, _)| !document.id.as_ref().is_some_and(|id| ids.contains(id)));
+            Ok(())
+        })
+    }
+
+    fn delete_by_filter(&self, filter: MetadataFilter) -> Pin<Box<dyn Future<Output = Result<usize, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut entries = self.entries.write().await;
+            let count = entries.len();
+            entries.retain(|(document, _)| !filter.matches(&document.metadata));
+            Ok(count - entries.len())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DeterministicFakeEmbedding;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_search_filter_replace_and_delete() {
+        let store = InMemoryVectorStore::new(Arc::new(DeterministicFakeEmbedding::new(32)));
+        let ids = store.add_documents(vec![
+            Document::new("the cat sleeps").with_id("a").with_metadata("session_id", json!("s1")),
+            Document::new("rust borrow checker").with_metadata("session_id", json!("s2")),
+        ]).await.unwrap();
+        assert_eq!(ids[0], "a");
+        assert_eq!(store.len().await, 2);
+
+        // Identical text gives an identical vector
+        let results = store.similarity_search_with_score("rust borrow checker".to_string(), 1, None).await.unwrap();
+        assert_eq!(results[0].0.page_content, "rust borrow checker");
+        assert!((results[0].1 - 1.0).abs() < 1e-5);
+
+        let filter = MetadataFilter::new().with_eq("session_id", json!("s1"));
+        let results = store.similarity_search("rust borrow checker".to_string(), 5, Some(filter)).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id.as_deref(), Some("a"));
+
+        store.add_documents(vec![Document::new("the dog barks").with_id("a")]).await.unwrap();
+        assert_eq!(store.len().await, 2);
+        store.delete(vec!["a".to_string()]).await.unwrap();
+        assert_eq!(store.len().await, 1);
+        let deleted = store.delete_by_filter(MetadataFilter::new().with_eq("session_id", json!("s2"))).await.unwrap();
+        assert_eq!(deleted, 1);
+        assert!(store.is_empty().await);
+    }
+}
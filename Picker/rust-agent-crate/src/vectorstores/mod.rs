@@ -0,0 +1,11 @@
+// Vector store module definition
+mod base;
+mod in_memory;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+// Re-export module content
+pub use base::{Document, MetadataCondition, MetadataFilter, ScoredDocument, VectorStore};
+pub use in_memory::InMemoryVectorStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteVectorStore;
@@ -0,0 +1,228 @@
+// Vector store persisted in a SQLite file
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{Error, Result};
+
+use super::base::{prepare_documents, top_k, Document, MetadataFilter, ScoredDocument, VectorStore};
+use crate::embeddings::{Embedding, Embeddings};
+
+/// Vector store persisted in a SQLite database (requires the `sqlite` feature)
+///
+/// Vectors are stored as little-endian f32 blobs and searched exhaustively, which suits the
+/// size of a conversation memory. Several collections can share one database file.
+pub struct SqliteVectorStore {
+    connection: Mutex<rusqlite::Connection>,
+    embeddings: Arc<dyn Embeddings>,
+    collection: String,
+}
+
+impl SqliteVectorStore {
+    /// Open or create the database file
+    pub fn open(path: impl AsRef<Path>, embeddings: Arc<dyn Embeddings>) -> Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = rusqlite::Connection::open(path)?;
+        // Readers do not block the writer, writers wait for each other
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        Self::from_connection(connection, embeddings)
+    }
+
+    /// Database living in memory, for tests
+    pub fn in_memory(embeddings: Arc<dyn Embeddings>) -> Result<Self> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?, embeddings)
+    }
+
+    fn from_connection(connection: rusqlite::Connection, embeddings: Arc<dyn Embeddings>) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS vector_documents (
+                collection TEXT NOT NULL,
+                id TEXT NOT NULL,
+                content TEXT NOT NULL,
+                metadata TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                PRIMARY KEY (collection, id)
+            );",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            embeddings,
+            collection: "default".to_string(),
+        })
+    }
+
+    /// Use a named collection of the database (default: "default")
+    pub fn with_collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = collection.into();
+        self
+    }
+
+    /// Number of documents in the collection
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self.connection().query_row(
+            "SELECT COUNT(*) FROM vector_documents WHERE collection = ?1",
+            [&self.collection],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Whether the collection is empty
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Embedding {
+    bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+}
+
+// Queries are short local reads and writes, they run on the calling task
+impl VectorStore for SqliteVectorStore {
+    fn embeddings(&self) -> Arc<dyn Embeddings> {
+        self.embeddings.clone()
+    }
+
+    fn add_vectors(&self, documents: Vec<Document>, vectors: Vec<Embedding>) -> Pin<Box<dyn Future<Output = Result<Vec<String>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let documents = prepare_documents(documents, &vectors)?;
+            let mut connection = self.connection();
+            let transaction = connection.transaction()?;
+            let mut ids = Vec::with_capacity(documents.len());
+            for (document, vector) in documents.into_iter().zip(vectors) {
+                let id = document.id.unwrap_or_default();
+                transaction.execute(
+                    "INSERT OR REPLACE INTO vector_documents (collection, id, content, metadata, embedding) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        self.collection,
+                        id,
+                        document.page_content,
+                        serde_json::to_string(&document.metadata)?,
+                        encode_vector(&vector),
+                    ],
+                )?;
+                ids.push(id);
+            }
+            transaction.commit()?;
+            Ok(ids)
+        })
+    }
+
+    fn similarity_search_by_vector_with_score(&self, vector: Embedding, k: usize, filter: Option<MetadataFilter>) -> Pin<Box<dyn Future<Output = Result<Vec<ScoredDocument>, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let entries = {
+                let connection = self.connection();
+                let mut statement = connection.prepare(
+                    "SELECT id, content, metadata, embedding FROM vector_documents WHERE collection = ?1",
+                )?;
+                let rows = statement.query_map([&self.collection], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Vec<u8>>(3)?))
+                })?;
+                let mut entries = Vec::new();
+                for row in rows {
+                    let (id, content, metadata, embedding) = row?;
+                    let document = Document {
+                        id: Some(id),
+                        page_content: content,
+                        metadata: serde_json::from_str(&metadata)?,
+                    };
+                    entries.push((document, decode_vector(&embedding)));
+                }
+                entries
+            };
+            let candidates = entries.iter().map(|(document, embedding)| (document, embedding));
+            Ok(top_k(candidates, &vector, k, filter.as_ref()))
+        })
+    }
+
+    fn delete_by_filter(&self, filter: MetadataFilter) -> Pin<Box<dyn Future<Output = Result<usize, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut connection = self.connection();
+            let transaction = connection.transaction()?;
+            let ids = {
+                let mut statement = transaction.prepare("SELECT id, metadata FROM vector_documents WHERE collection = ?1")?;
+                let rows = statement.query_map([&self.collection], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+                let mut ids = Vec::new();
+                for row in rows {
+                    let (id, metadata) = row?;
+                    if filter.matches(&serde_json::from_str(&metadata)?) {
+                        ids.push(id);
+                    }
+                }
+                ids
+            };
+            for id in &ids {
+                transaction.execute(
+                    "DELETE FROM vector_documents WHERE collection = ?1 AND id = ?2",
+                    rusqlite::params![self.collection, id],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(ids.len())
+        })
+    }
+
+    fn delete(&self, ids: Vec<String>) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut connection = self.connection();
+            let transaction = connection.transaction()?;
+            for id in ids {
+                transaction.execute(
+                    "DELETE FROM vector_documents WHERE collection = ?1 AND id = ?2",
+                    rusqlite::params![self.collection, id],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DeterministicFakeEmbedding;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_persists_documents_across_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vectors.db");
+        let embeddings: Arc<dyn Embeddings> = Arc::new(DeterministicFakeEmbedding::new(16));
+
+        let store = SqliteVectorStore::open(&path, embeddings.clone()).unwrap();
+        let journal_mode: String = store.connection.lock().unwrap().pragma_query_value(None, "journal_mode", |row| row.get(0)).unwrap();
+        assert_eq!(journal_mode, "wal");
+        store.add_documents(vec![
+            Document::new("my favourite colour is green").with_id("m1").with_metadata("session_id", json!("s1")),
+            Document::new("the meeting is on friday").with_id("m2").with_metadata("session_id", json!("s2")),
+        ]).await.unwrap();
+        drop(store);
+
+        let store = SqliteVectorStore::open(&path, embeddings.clone()).unwrap();
+        assert_eq!(store.len().unwrap(), 2);
+        let results = store.similarity_search_with_score("the meeting is on friday".to_string(), 1, None).await.unwrap();
+        assert_eq!(results[0].0.id.as_deref(), Some("m2"));
+        assert_eq!(results[0].0.metadata["session_id"], json!("s2"));
+        assert!((results[0].1 - 1.0).abs() < 1e-5);
+
+        // Collections are separate
+        let other = SqliteVectorStore::open(&path, embeddings).unwrap().with_collection("other");
+        assert!(other.is_empty().unwrap());
+
+        store.delete(vec!["m1".to_string()]).await.unwrap();
+        assert_eq!(store.len().unwrap(), 1);
+    }
+}